@@ -0,0 +1,330 @@
+use crate::domain::ChainId;
+use ethers::abi::{self, Token};
+use ethers::types::{Address, Bytes, U256, U512};
+use ethers::utils::id;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Bridge provider used to move tokens between chains
+pub trait BridgeProvider: Send + Sync {
+    /// Returns provider name (matches `Order::bridge_provider`)
+    fn name(&self) -> &str;
+
+    /// Estimates the fee charged for bridging `amount` of `token`,
+    /// denominated in the bridged token
+    fn estimate_fee(
+        &self,
+        token: Address,
+        amount: U256,
+        source: ChainId,
+        destination: ChainId,
+    ) -> U256;
+
+    /// Estimates time until funds arrive on the destination chain
+    fn estimate_time(&self, source: ChainId, destination: ChainId) -> Duration;
+
+    /// Encodes the bridge deposit call sending `amount` of `token` to
+    /// `recipient` on the destination chain
+    fn encode_deposit(
+        &self,
+        token: Address,
+        amount: U256,
+        recipient: Address,
+        source: ChainId,
+        destination: ChainId,
+    ) -> Bytes;
+}
+
+/// Prepends a function selector to ABI-encoded arguments
+fn encode_call(signature: &str, args: &[Token]) -> Bytes {
+    let mut call_data = id(signature).to_vec();
+    call_data.extend(abi::encode(args));
+    call_data.into()
+}
+
+/// Fee of `fee_bps` on `amount` plus `base_fee`, saturating rather than
+/// overflowing on huge amounts
+fn bps_fee(amount: U256, fee_bps: u32, base_fee: U256) -> U256 {
+    let proportional = amount.full_mul(U256::from(fee_bps)) / U512::from(10000);
+    U256::try_from(proportional).unwrap_or(U256::MAX).saturating_add(base_fee)
+}
+
+/// Across Protocol bridge (relayer-based, fast fills)
+#[derive(Debug, Clone)]
+pub struct AcrossBridge {
+    /// Relayer + LP fee (in basis points)
+    pub fee_bps: u32,
+
+    /// Flat fee covering destination gas, in bridged token units
+    pub base_fee: U256,
+}
+
+impl AcrossBridge {
+    /// Creates a new Across bridge provider
+    pub fn new(fee_bps: u32, base_fee: U256) -> Self {
+        Self { fee_bps, base_fee }
+    }
+}
+
+impl Default for AcrossBridge {
+    fn default() -> Self {
+        Self::new(6, U256::zero()) // 0.06%
+    }
+}
+
+impl BridgeProvider for AcrossBridge {
+    fn name(&self) -> &str {
+        "Across"
+    }
+
+    fn estimate_fee(
+        &self,
+        _token: Address,
+        amount: U256,
+        _source: ChainId,
+        _destination: ChainId,
+    ) -> U256 {
+        bps_fee(amount, self.fee_bps, self.base_fee)
+    }
+
+    fn estimate_time(&self, _source: ChainId, destination: ChainId) -> Duration {
+        // Relayers front the funds, so fills land within a few destination blocks
+        match destination {
+            ChainId::Ethereum => Duration::from_secs(300),
+            _ => Duration::from_secs(120),
+        }
+    }
+
+    fn encode_deposit(
+        &self,
+        token: Address,
+        amount: U256,
+        recipient: Address,
+        _source: ChainId,
+        destination: ChainId,
+    ) -> Bytes {
+        // SpokePool relayer fee is a percentage scaled by 1e18
+        let relayer_fee_pct = U256::from(self.fee_bps) * U256::exp10(14);
+
+        encode_call(
+            "deposit(address,address,uint256,uint256,int64,uint32)",
+            &[
+                Token::Address(recipient),
+                Token::Address(token),
+                Token::Uint(amount),
+                Token::Uint(U256::from(destination.as_u64())),
+                Token::Int(relayer_fee_pct),
+                Token::Uint(U256::zero()), // quote timestamp, set at submission
+            ],
+        )
+    }
+}
+
+/// Hop Protocol bridge (bonder-based AMM bridge)
+#[derive(Debug, Clone)]
+pub struct HopBridge {
+    /// Bonder + AMM fee (in basis points)
+    pub fee_bps: u32,
+
+    /// Flat fee covering destination gas, in bridged token units
+    pub base_fee: U256,
+}
+
+impl HopBridge {
+    /// Creates a new Hop bridge provider
+    pub fn new(fee_bps: u32, base_fee: U256) -> Self {
+        Self { fee_bps, base_fee }
+    }
+}
+
+impl Default for HopBridge {
+    fn default() -> Self {
+        Self::new(20, U256::zero()) // 0.2%
+    }
+}
+
+impl BridgeProvider for HopBridge {
+    fn name(&self) -> &str {
+        "Hop"
+    }
+
+    fn estimate_fee(
+        &self,
+        _token: Address,
+        amount: U256,
+        _source: ChainId,
+        _destination: ChainId,
+    ) -> U256 {
+        bps_fee(amount, self.fee_bps, self.base_fee)
+    }
+
+    fn estimate_time(&self, source: ChainId, _destination: ChainId) -> Duration {
+        // Transfers out of L1 wait for mainnet confirmations before bonding
+        match source {
+            ChainId::Ethereum => Duration::from_secs(600),
+            _ => Duration::from_secs(300),
+        }
+    }
+
+    fn encode_deposit(
+        &self,
+        token: Address,
+        amount: U256,
+        recipient: Address,
+        source: ChainId,
+        destination: ChainId,
+    ) -> Bytes {
+        let chain_id = Token::Uint(U256::from(destination.as_u64()));
+
+        match source {
+            // L1 bridge: no bonder involved
+            ChainId::Ethereum => encode_call(
+                "sendToL2(uint256,address,uint256,uint256,uint256,address,uint256)",
+                &[
+                    chain_id,
+                    Token::Address(recipient),
+                    Token::Uint(amount),
+                    Token::Uint(U256::zero()), // amountOutMin
+                    Token::Uint(U256::zero()), // deadline
+                    Token::Address(Address::zero()), // relayer
+                    Token::Uint(U256::zero()), // relayerFee
+                ],
+            ),
+            // L2 AMM wrapper: bonder fronts funds on the destination
+            _ => {
+                let bonder_fee = self.estimate_fee(token, amount, source, destination);
+                encode_call(
+                    "swapAndSend(uint256,address,uint256,uint256,uint256,uint256,uint256,uint256)",
+                    &[
+                        chain_id,
+                        Token::Address(recipient),
+                        Token::Uint(amount),
+                        Token::Uint(bonder_fee),
+                        Token::Uint(U256::zero()), // amountOutMin
+                        Token::Uint(U256::zero()), // deadline
+                        Token::Uint(U256::zero()), // destinationAmountOutMin
+                        Token::Uint(U256::zero()), // destinationDeadline
+                    ],
+                )
+            }
+        }
+    }
+}
+
+/// Registry of bridge providers keyed by name
+#[derive(Clone, Default)]
+pub struct BridgeRegistry {
+    providers: HashMap<String, Arc<dyn BridgeProvider>>,
+}
+
+impl BridgeRegistry {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry with the built-in providers (Across, Hop)
+    pub fn with_defaults() -> Self {
+        let mut registry = Self::new();
+        registry.register(Arc::new(AcrossBridge::default()));
+        registry.register(Arc::new(HopBridge::default()));
+        registry
+    }
+
+    /// Registers a provider under its name, replacing any existing entry
+    pub fn register(&mut self, provider: Arc<dyn BridgeProvider>) {
+        self.providers.insert(provider.name().to_string(), provider);
+    }
+
+    /// Looks up a provider by name
+    pub fn get(&self, name: &str) -> Option<&Arc<dyn BridgeProvider>> {
+        self.providers.get(name)
+    }
+
+    /// Returns number of registered providers
+    pub fn len(&self) -> usize {
+        self.providers.len()
+    }
+
+    /// Checks if registry has no providers
+    pub fn is_empty(&self) -> bool {
+        self.providers.is_empty()
+    }
+}
+
+impl std::fmt::Debug for BridgeRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BridgeRegistry")
+            .field("providers", &self.providers.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_across_fee_estimation() {
+        let across = AcrossBridge::new(10, U256::from(5));
+        let fee = across.estimate_fee(
+            Address::zero(),
+            U256::from(1_000_000),
+            ChainId::Ethereum,
+            ChainId::Arbitrum,
+        );
+        assert_eq!(fee, U256::from(1005));
+    }
+
+    #[test]
+    fn test_fee_estimation_saturates_on_huge_amounts() {
+        let across = AcrossBridge::new(10, U256::from(5));
+        let fee = across.estimate_fee(Address::zero(), U256::MAX, ChainId::Ethereum, ChainId::Arbitrum);
+        assert_eq!(fee, U256::MAX / 1000 + 5);
+
+        let hop = HopBridge::new(10000, U256::one());
+        let fee = hop.estimate_fee(Address::zero(), U256::MAX, ChainId::Ethereum, ChainId::Arbitrum);
+        assert_eq!(fee, U256::MAX);
+    }
+
+    #[test]
+    fn test_hop_slower_from_mainnet() {
+        let hop = HopBridge::default();
+        let from_l1 = hop.estimate_time(ChainId::Ethereum, ChainId::Optimism);
+        let from_l2 = hop.estimate_time(ChainId::Optimism, ChainId::Arbitrum);
+        assert!(from_l1 > from_l2);
+    }
+
+    #[test]
+    fn test_across_deposit_encoding() {
+        let across = AcrossBridge::default();
+        let token = Address::from_low_u64_be(0xaa);
+        let recipient = Address::from_low_u64_be(0xbb);
+        let amount = U256::from(123456789u64);
+
+        let call_data = across.encode_deposit(
+            token,
+            amount,
+            recipient,
+            ChainId::Ethereum,
+            ChainId::Arbitrum,
+        );
+
+        assert_eq!(call_data.len(), 4 + 6 * 32);
+        assert_eq!(&call_data[..4], &id("deposit(address,address,uint256,uint256,int64,uint32)"));
+        assert_eq!(&call_data[16..36], recipient.as_bytes());
+        assert_eq!(&call_data[48..68], token.as_bytes());
+        assert_eq!(U256::from_big_endian(&call_data[68..100]), amount);
+        assert_eq!(U256::from_big_endian(&call_data[100..132]), U256::from(42161u64));
+    }
+
+    #[test]
+    fn test_registry_lookup_by_name() {
+        let registry = BridgeRegistry::with_defaults();
+        assert_eq!(registry.len(), 2);
+        assert_eq!(registry.get("Across").unwrap().name(), "Across");
+        assert_eq!(registry.get("Hop").unwrap().name(), "Hop");
+        assert!(registry.get("Unknown").is_none());
+    }
+}
@@ -1,7 +1,15 @@
+use ethers::types::Address;
 use serde::{Deserialize, Serialize};
 
+/// Start of the numeric range reserved for non-EVM chains.
+///
+/// EVM chain ids are assigned well below this, so non-EVM networks get
+/// `NON_EVM_CHAIN_ID_BASE + n` without colliding with a real chain id.
+pub const NON_EVM_CHAIN_ID_BASE: u64 = 1 << 63;
+
 /// Supported blockchain networks
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[repr(u64)]
 pub enum ChainId {
     Ethereum = 1,
     Optimism = 10,
@@ -10,6 +18,7 @@ pub enum ChainId {
     Base = 8453,
     Arbitrum = 42161,
     Avalanche = 43114,
+    Solana = NON_EVM_CHAIN_ID_BASE + 1,
 }
 
 impl ChainId {
@@ -23,6 +32,7 @@ impl ChainId {
             ChainId::Base => "Base",
             ChainId::Arbitrum => "Arbitrum",
             ChainId::Avalanche => "Avalanche",
+            ChainId::Solana => "Solana",
         }
     }
     
@@ -36,12 +46,28 @@ impl ChainId {
             ChainId::Base => "ETH",
             ChainId::Arbitrum => "ETH",
             ChainId::Avalanche => "AVAX",
+            ChainId::Solana => "SOL",
         }
     }
     
+    /// Returns the canonical wrapped native token (WETH, WMATIC, ...), or
+    /// `None` for chains without one
+    pub fn wrapped_native_token(&self) -> Option<Address> {
+        let address = match self {
+            ChainId::Ethereum => "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2",
+            ChainId::Optimism | ChainId::Base => "0x4200000000000000000000000000000000000006",
+            ChainId::BinanceSmartChain => "0xbb4CdB9CBd36B01bD1cBaEBF2De08d9173bc095c",
+            ChainId::Polygon => "0x0d500B1d8E8eF31E21C99d1Db9A6444d3ADf1270",
+            ChainId::Arbitrum => "0x82aF49447D8a07e3bd95BD0d56f35241523fBab1",
+            ChainId::Avalanche => "0xB31f66AA3C1e785363F0875A1B74E27b85FD66c7",
+            ChainId::Solana => return None,
+        };
+        address.parse().ok()
+    }
+    
     /// Checks if chain is EVM compatible
     pub fn is_evm(&self) -> bool {
-        true // All currently supported chains are EVM
+        !matches!(self, ChainId::Solana)
     }
     
     /// Returns typical block time in seconds
@@ -54,6 +80,7 @@ impl ChainId {
             ChainId::Base => 2,
             ChainId::Arbitrum => 1,
             ChainId::Avalanche => 2,
+            ChainId::Solana => 1, // ~400ms slots, rounded up
         }
     }
     
@@ -72,6 +99,7 @@ impl ChainId {
             8453 => Some(ChainId::Base),
             42161 => Some(ChainId::Arbitrum),
             43114 => Some(ChainId::Avalanche),
+            id if id == ChainId::Solana as u64 => Some(ChainId::Solana),
             _ => None,
         }
     }
@@ -126,6 +154,18 @@ mod tests {
         assert_eq!(ChainId::Ethereum.block_time(), 12);
         assert_eq!(ChainId::Arbitrum.block_time(), 1);
     }
+    
+    #[test]
+    fn test_non_evm_chain() {
+        assert!(!ChainId::Solana.is_evm());
+        assert_eq!(ChainId::Solana.native_token(), "SOL");
+        
+        let id = ChainId::Solana.as_u64();
+        assert!(id > NON_EVM_CHAIN_ID_BASE);
+        assert_eq!(ChainId::from_u64(id), Some(ChainId::Solana));
+        // The reserved base itself is not a chain
+        assert_eq!(ChainId::from_u64(NON_EVM_CHAIN_ID_BASE), None);
+    }
 }
 
 #[cfg(test)]
@@ -143,6 +183,7 @@ mod extra_chains_tests {
             ChainId::Base,
             ChainId::Arbitrum,
             ChainId::Avalanche,
+            ChainId::Solana,
         ];
         for &c in &all {
             let id = c.as_u64();
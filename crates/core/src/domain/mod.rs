@@ -1,7 +1,9 @@
 pub mod orders;
 pub mod tokens;
 pub mod chains;
+pub mod order_book;
 
-pub use orders::{Order, OrderStatus, OrderType};
-pub use tokens::{Token, TokenAmount};
+pub use orders::{domain_separator, Order, OrderId, OrderStatus, OrderType};
+pub use tokens::{infer_decimals, is_native_token, Token, TokenAmount, TokenRegistry, NATIVE_TOKEN};
 pub use chains::{ChainId, SupportedChain};
+pub use order_book::OrderBook;
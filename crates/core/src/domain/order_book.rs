@@ -0,0 +1,155 @@
+use super::orders::{Order, OrderId};
+use ethers::types::Address;
+use std::collections::HashMap;
+
+/// Orders indexed by id and by directed token pair
+#[derive(Debug, Clone, Default)]
+pub struct OrderBook {
+    /// Orders keyed by id
+    orders: HashMap<OrderId, Order>,
+
+    /// (sell token, buy token) -> order ids, in insertion order
+    pair_index: HashMap<(Address, Address), Vec<OrderId>>,
+}
+
+impl OrderBook {
+    /// Creates an empty order book
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts an order, returning the order it replaced (same id), if any
+    pub fn insert(&mut self, order: Order) -> Option<Order> {
+        let replaced = self.orders.insert(order.id, order.clone());
+
+        if let Some(old) = &replaced {
+            if let Some(ids) = self.pair_index.get_mut(&(old.sell_token, old.buy_token)) {
+                ids.retain(|id| *id != old.id);
+            }
+        }
+
+        self.pair_index
+            .entry((order.sell_token, order.buy_token))
+            .or_default()
+            .push(order.id);
+
+        replaced
+    }
+
+    /// Looks up an order by id
+    pub fn get(&self, id: &OrderId) -> Option<&Order> {
+        self.orders.get(id)
+    }
+
+    /// Returns orders selling `sell_token` for `buy_token`
+    pub fn orders_for_pair(&self, sell_token: Address, buy_token: Address) -> Vec<&Order> {
+        self.pair_index
+            .get(&(sell_token, buy_token))
+            .map(|ids| ids.iter().filter_map(|id| self.orders.get(id)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Iterates over all orders (in no particular order)
+    pub fn iter(&self) -> impl Iterator<Item = &Order> {
+        self.orders.values()
+    }
+
+    /// Returns number of orders
+    pub fn len(&self) -> usize {
+        self.orders.len()
+    }
+
+    /// Checks if the book has no orders
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+}
+
+impl FromIterator<Order> for OrderBook {
+    fn from_iter<I: IntoIterator<Item = Order>>(iter: I) -> Self {
+        let mut book = Self::new();
+        for order in iter {
+            book.insert(order);
+        }
+        book
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{OrderStatus, OrderType};
+    use ethers::types::U256;
+
+    fn order(id: u8, sell_token: u64, buy_token: u64) -> Order {
+        Order {
+            id: OrderId([id; 32]),
+            owner: Address::zero(),
+            sell_token: Address::from_low_u64_be(sell_token),
+            buy_token: Address::from_low_u64_be(buy_token),
+            sell_amount: U256::from(1000),
+            buy_amount: U256::from(2000),
+            valid_to: u32::MAX,
+            fee_amount: U256::zero(),
+            kind: OrderType::Sell,
+            partially_fillable: false,
+            status: OrderStatus::Open,
+            source_chain: None,
+            destination_chain: None,
+            bridge_provider: None,
+            filled_amount: U256::zero(),
+            signature: vec![],
+        }
+    }
+
+    #[test]
+    fn test_insert_and_get() {
+        let mut book = OrderBook::new();
+        assert!(book.insert(order(1, 1, 2)).is_none());
+        assert!(book.insert(order(2, 2, 1)).is_none());
+
+        assert_eq!(book.len(), 2);
+        assert_eq!(book.get(&OrderId([1u8; 32])).unwrap().sell_token, Address::from_low_u64_be(1));
+        assert!(book.get(&OrderId([3u8; 32])).is_none());
+        assert_eq!(book.iter().count(), 2);
+    }
+
+    #[test]
+    fn test_pair_index_is_directed() {
+        let book: OrderBook = vec![order(1, 1, 2), order(2, 2, 1), order(3, 1, 2), order(4, 1, 3)]
+            .into_iter()
+            .collect();
+
+        let a_to_b: Vec<_> = book
+            .orders_for_pair(Address::from_low_u64_be(1), Address::from_low_u64_be(2))
+            .iter()
+            .map(|o| o.id)
+            .collect();
+        assert_eq!(a_to_b, vec![OrderId([1u8; 32]), OrderId([3u8; 32])]);
+
+        let b_to_a = book.orders_for_pair(Address::from_low_u64_be(2), Address::from_low_u64_be(1));
+        assert_eq!(b_to_a.len(), 1);
+        assert_eq!(b_to_a[0].id, OrderId([2u8; 32]));
+
+        assert!(book
+            .orders_for_pair(Address::from_low_u64_be(3), Address::from_low_u64_be(1))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_reinsert_moves_pair() {
+        let mut book = OrderBook::new();
+        book.insert(order(1, 1, 2));
+        let replaced = book.insert(order(1, 1, 3));
+
+        assert_eq!(replaced.unwrap().buy_token, Address::from_low_u64_be(2));
+        assert_eq!(book.len(), 1);
+        assert!(book
+            .orders_for_pair(Address::from_low_u64_be(1), Address::from_low_u64_be(2))
+            .is_empty());
+        assert_eq!(
+            book.orders_for_pair(Address::from_low_u64_be(1), Address::from_low_u64_be(3)).len(),
+            1
+        );
+    }
+}
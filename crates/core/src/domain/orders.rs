@@ -1,8 +1,21 @@
 use serde::{Deserialize, Serialize};
-use ethers::types::{Address, U256};
-use super::tokens::TokenAmount;
+use ethers::abi::{self, Token};
+use ethers::types::{Address, Signature, H256, U256, U512};
+use ethers::utils::keccak256;
 use super::chains::ChainId;
 
+/// EIP-712 type string of a GPv2 order
+const ORDER_TYPE: &str = "Order(address sellToken,address buyToken,address receiver,uint256 sellAmount,uint256 buyAmount,uint32 validTo,bytes32 appData,uint256 feeAmount,string kind,bool partiallyFillable,string sellTokenBalance,string buyTokenBalance)";
+
+/// EIP-712 type string of the signing domain
+const DOMAIN_TYPE: &str = "EIP712Domain(string name,string version,uint256 chainId,address verifyingContract)";
+
+/// GPv2Settlement contract, deployed at the same address on every chain
+pub const SETTLEMENT_CONTRACT: [u8; 20] = [
+    0x90, 0x08, 0xd1, 0x9f, 0x58, 0xaa, 0xbd, 0x9e, 0xd0, 0xd6,
+    0x09, 0x71, 0x56, 0x5a, 0xa8, 0x51, 0x05, 0x60, 0xab, 0x41,
+];
+
 /// Represents a CoW Protocol order
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Order {
@@ -47,6 +60,16 @@ pub struct Order {
     
     /// Bridge provider for cross-chain orders
     pub bridge_provider: Option<String>,
+    
+    /// Amount already filled, in the sell token for sell orders and the
+    /// buy token for buy orders
+    #[serde(default)]
+    pub filled_amount: U256,
+    
+    /// Owner's 65-byte ECDSA signature over the order's EIP-712 digest
+    /// (empty if unsigned)
+    #[serde(default)]
+    pub signature: Vec<u8>,
 }
 
 /// Order unique identifier
@@ -79,7 +102,128 @@ pub enum OrderStatus {
     Expired,
 }
 
+/// Computes the EIP-712 domain separator of the settlement contract on `chain`
+pub fn domain_separator(chain: ChainId) -> [u8; 32] {
+    keccak256(abi::encode(&[
+        Token::FixedBytes(keccak256(DOMAIN_TYPE).to_vec()),
+        Token::FixedBytes(keccak256("Gnosis Protocol").to_vec()),
+        Token::FixedBytes(keccak256("v2").to_vec()),
+        Token::Uint(U256::from(chain.as_u64())),
+        Token::Address(Address::from(SETTLEMENT_CONTRACT)),
+    ]))
+}
+
 impl Order {
+    /// Creates an open order from a quantity and a limit price in buy token
+    /// per sell token (the ratio `limit_price` returns).
+    ///
+    /// Sell orders sell `quantity` for at least `quantity * limit_price`; buy
+    /// orders buy `quantity` for at most `quantity / limit_price`. The
+    /// derived amount is rounded in the owner's favor, so the order's limit
+    /// is never worse than the price asked. The id is left as a placeholder
+    /// and the owner unset.
+    pub fn from_limit_price(
+        sell_token: Address,
+        buy_token: Address,
+        quantity: U256,
+        limit_price: f64,
+        kind: OrderType,
+    ) -> Result<Self, String> {
+        if !limit_price.is_finite() || limit_price <= 0.0 {
+            return Err(format!("Limit price must be positive, got {}", limit_price));
+        }
+        
+        // Price as a fixed-point ratio of 1e18
+        let scale = U256::exp10(18);
+        let price = U256::from((limit_price * 1e18).round() as u128);
+        if price.is_zero() {
+            return Err(format!("Limit price {} is below 1e-18", limit_price));
+        }
+        let overflow = |_| "Derived amount overflows".to_string();
+        let (sell_amount, buy_amount) = match kind {
+            OrderType::Sell => {
+                let buy = (quantity.full_mul(price) + U512::from(scale) - U512::one()) / U512::from(scale);
+                (quantity, U256::try_from(buy).map_err(overflow)?)
+            }
+            OrderType::Buy => {
+                let sell = quantity.full_mul(scale) / U512::from(price);
+                (U256::try_from(sell).map_err(overflow)?, quantity)
+            }
+        };
+        
+        Ok(Self {
+            id: OrderId([0u8; 32]),
+            owner: Address::zero(),
+            sell_token,
+            buy_token,
+            sell_amount,
+            buy_amount,
+            valid_to: u32::MAX,
+            fee_amount: U256::zero(),
+            kind,
+            partially_fillable: false,
+            status: OrderStatus::Open,
+            source_chain: None,
+            destination_chain: None,
+            bridge_provider: None,
+            filled_amount: U256::zero(),
+            signature: vec![],
+        })
+    }
+    
+    /// Computes the EIP-712 digest users sign for this order.
+    ///
+    /// Unlike `id`, which callers may leave as a placeholder, this depends
+    /// only on the order parameters, so identical submissions hash the same.
+    /// The order is signed on its source chain (Ethereum if unset), with the
+    /// owner as receiver, empty app data and ERC20 balances. The owner is not
+    /// part of the digest; a full CoW order uid also appends owner and
+    /// `valid_to`.
+    pub fn eip712_digest(&self) -> OrderId {
+        let chain = self.source_chain.unwrap_or(ChainId::Ethereum);
+        OrderId(self.signing_digest(domain_separator(chain)))
+    }
+    
+    /// Computes the EIP-712 digest of this order under `domain_separator`
+    pub fn signing_digest(&self, domain_separator: [u8; 32]) -> [u8; 32] {
+        let kind = match self.kind {
+            OrderType::Sell => "sell",
+            OrderType::Buy => "buy",
+        };
+        let struct_hash = keccak256(abi::encode(&[
+            Token::FixedBytes(keccak256(ORDER_TYPE).to_vec()),
+            Token::Address(self.sell_token),
+            Token::Address(self.buy_token),
+            Token::Address(Address::zero()), // receiver: owner
+            Token::Uint(self.sell_amount),
+            Token::Uint(self.buy_amount),
+            Token::Uint(U256::from(self.valid_to)),
+            Token::FixedBytes(vec![0u8; 32]), // appData
+            Token::Uint(self.fee_amount),
+            Token::FixedBytes(keccak256(kind).to_vec()),
+            Token::Bool(self.partially_fillable),
+            Token::FixedBytes(keccak256("erc20").to_vec()),
+            Token::FixedBytes(keccak256("erc20").to_vec()),
+        ]));
+        
+        let mut message = Vec::with_capacity(66);
+        message.extend_from_slice(b"\x19\x01");
+        message.extend_from_slice(&domain_separator);
+        message.extend_from_slice(&struct_hash);
+        keccak256(message)
+    }
+    
+    /// Checks that `signature` recovers to `owner` over the order's digest
+    /// under `domain_separator`. Missing or malformed signatures fail.
+    pub fn verify_signature(&self, domain_separator: [u8; 32]) -> bool {
+        let Ok(signature) = Signature::try_from(self.signature.as_slice()) else {
+            return false;
+        };
+        
+        let digest = H256::from(self.signing_digest(domain_separator));
+        matches!(signature.recover(digest), Ok(signer) if signer == self.owner)
+    }
+    
     /// Validates order parameters
     pub fn validate(&self) -> Result<(), String> {
         if self.sell_amount.is_zero() {
@@ -98,6 +242,14 @@ impl Order {
             return Err("Valid_to timestamp must be set".to_string());
         }
         
+        let filled_side = match self.kind {
+            OrderType::Sell => self.sell_amount,
+            OrderType::Buy => self.buy_amount,
+        };
+        if self.filled_amount > filled_side {
+            return Err("Filled amount exceeds order amount".to_string());
+        }
+        
         // Cross-chain validation
         if self.is_cross_chain() {
             if self.source_chain.is_none() || self.destination_chain.is_none() {
@@ -109,9 +261,65 @@ impl Order {
             }
         }
         
+        // Settlement happens in an EVM contract, so the sell side must be EVM
+        if let Some(chain) = self.source_chain {
+            if !chain.is_evm() {
+                return Err(format!("Source chain {} is not EVM-compatible", chain.name()));
+            }
+        }
+        
         Ok(())
     }
     
+    /// Returns the sell and buy amounts left to fill.
+    ///
+    /// The side `filled_amount` is counted in shrinks by it; the other side
+    /// shrinks pro rata, rounded in the user's favour so the limit price
+    /// never loosens.
+    pub fn remaining_amounts(&self) -> (U256, U256) {
+        let scale = |amount: U256, numerator: U256, denominator: U256, round_up: bool| {
+            if denominator.is_zero() {
+                return U256::zero();
+            }
+            let product = amount.full_mul(numerator);
+            let denominator = U512::from(denominator);
+            let mut scaled = product / denominator;
+            if round_up && !(product % denominator).is_zero() {
+                scaled += U512::one();
+            }
+            U256::try_from(scaled).unwrap_or(U256::MAX)
+        };
+        
+        match self.kind {
+            OrderType::Sell => {
+                let sell = self.sell_amount.saturating_sub(self.filled_amount);
+                (sell, scale(self.buy_amount, sell, self.sell_amount, true))
+            }
+            OrderType::Buy => {
+                let buy = self.buy_amount.saturating_sub(self.filled_amount);
+                (scale(self.sell_amount, buy, self.buy_amount, false), buy)
+            }
+        }
+    }
+    
+    /// Returns the sell amount actually swapped: the fee is taken out of
+    /// the sell amount and kept by the settlement
+    pub fn sell_amount_after_fee(&self) -> U256 {
+        self.sell_amount.saturating_sub(self.fee_amount)
+    }
+    
+    /// Returns a copy of the order sized to its remaining amounts, with
+    /// nothing filled
+    pub fn remaining(&self) -> Order {
+        let (sell_amount, buy_amount) = self.remaining_amounts();
+        Order {
+            sell_amount,
+            buy_amount,
+            filled_amount: U256::zero(),
+            ..self.clone()
+        }
+    }
+    
     /// Checks if order is cross-chain
     pub fn is_cross_chain(&self) -> bool {
         self.source_chain.is_some() && self.destination_chain.is_some()
@@ -140,6 +348,16 @@ impl Order {
             OrderType::Sell => price >= self.limit_price(),
         }
     }
+    
+    /// Checks if order can be filled at `price` at time `now`: it must be
+    /// open, unexpired, have nonzero amounts and accept the price
+    pub fn is_fillable(&self, price: f64, now: u32) -> bool {
+        self.status == OrderStatus::Open
+            && !self.is_expired(now)
+            && !self.sell_amount.is_zero()
+            && !self.buy_amount.is_zero()
+            && self.can_fill_at_price(price)
+    }
 }
 
 #[cfg(test)]
@@ -154,7 +372,7 @@ mod tests {
             buy_token: Address::from_low_u64_be(2),
             sell_amount: U256::from(1000),
             buy_amount: U256::from(2000),
-            valid_to: 9999999999,
+            valid_to: 4_000_000_000,
             fee_amount: U256::from(10),
             kind: OrderType::Sell,
             partially_fillable: false,
@@ -162,9 +380,58 @@ mod tests {
             source_chain: None,
             destination_chain: None,
             bridge_provider: None,
+            filled_amount: U256::zero(),
+            signature: vec![],
         }
     }
     
+    #[test]
+    fn test_eip712_digest_ignores_placeholder_id() {
+        let order = create_test_order();
+        
+        let mut resubmitted = order.clone();
+        resubmitted.id = OrderId([9u8; 32]);
+        resubmitted.status = OrderStatus::Pending;
+        assert_eq!(order.eip712_digest(), resubmitted.eip712_digest());
+        
+        let mut different = order.clone();
+        different.sell_amount += U256::one();
+        assert_ne!(order.eip712_digest(), different.eip712_digest());
+        
+        let mut other_chain = order.clone();
+        other_chain.source_chain = Some(ChainId::Arbitrum);
+        assert_ne!(order.eip712_digest(), other_chain.eip712_digest());
+    }
+    
+    #[test]
+    fn test_verify_signature() {
+        use ethers::signers::{LocalWallet, Signer};
+        
+        let wallet: LocalWallet = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+            .parse()
+            .unwrap();
+        let separator = domain_separator(ChainId::Ethereum);
+        
+        let mut order = create_test_order();
+        order.owner = wallet.address();
+        assert!(!order.verify_signature(separator));
+        
+        let digest = H256::from(order.signing_digest(separator));
+        order.signature = wallet.sign_hash(digest).unwrap().to_vec();
+        assert!(order.verify_signature(separator));
+        
+        // Any change to the signed parameters invalidates the signature
+        let mut tampered = order.clone();
+        tampered.buy_amount -= U256::one();
+        assert!(!tampered.verify_signature(separator));
+        
+        // As does another domain or owner
+        assert!(!order.verify_signature(domain_separator(ChainId::Arbitrum)));
+        let mut forged = order.clone();
+        forged.owner = Address::from_low_u64_be(1);
+        assert!(!forged.verify_signature(separator));
+    }
+    
     #[test]
     fn test_order_validation_success() {
         let order = create_test_order();
@@ -191,11 +458,82 @@ mod tests {
         assert_eq!(order.limit_price(), 2.0);
     }
     
+    #[test]
+    fn test_from_limit_price() {
+        let sell_token = Address::from_low_u64_be(1);
+        let buy_token = Address::from_low_u64_be(2);
+        let quantity = U256::from(100);
+        
+        let sell = Order::from_limit_price(sell_token, buy_token, quantity, 2.0, OrderType::Sell).unwrap();
+        assert_eq!((sell.sell_amount, sell.buy_amount), (U256::from(100), U256::from(200)));
+        assert_eq!(sell.limit_price(), 2.0);
+        assert!(sell.validate().is_ok());
+        
+        let buy = Order::from_limit_price(sell_token, buy_token, quantity, 2.0, OrderType::Buy).unwrap();
+        assert_eq!((buy.sell_amount, buy.buy_amount), (U256::from(50), U256::from(100)));
+        assert_eq!(buy.limit_price(), 2.0);
+        
+        // Rounding never leaves the owner worse off than the price
+        let sell = Order::from_limit_price(sell_token, buy_token, quantity, 1.005, OrderType::Sell).unwrap();
+        assert_eq!(sell.buy_amount, U256::from(101));
+        let buy = Order::from_limit_price(sell_token, buy_token, quantity, 3.0, OrderType::Buy).unwrap();
+        assert_eq!(buy.sell_amount, U256::from(33));
+        
+        assert!(Order::from_limit_price(sell_token, buy_token, quantity, 0.0, OrderType::Sell).is_err());
+        assert!(Order::from_limit_price(sell_token, buy_token, quantity, f64::NAN, OrderType::Buy).is_err());
+    }
+    
     #[test]
     fn test_is_expired() {
         let order = create_test_order();
         assert!(!order.is_expired(1000));
-        assert!(order.is_expired(10000000000));
+        assert!(order.is_expired(u32::MAX));
+    }
+    
+    #[test]
+    fn test_is_fillable() {
+        let order = create_test_order();
+        assert!(order.is_fillable(2.0, 1000));
+        
+        let mut cancelled = create_test_order();
+        cancelled.status = OrderStatus::Cancelled;
+        assert!(!cancelled.is_fillable(2.0, 1000));
+        
+        assert!(!order.is_fillable(2.0, u32::MAX)); // Expired
+        
+        let mut zero_sell = create_test_order();
+        zero_sell.sell_amount = U256::zero();
+        assert!(!zero_sell.is_fillable(2.0, 1000));
+        
+        let mut zero_buy = create_test_order();
+        zero_buy.buy_amount = U256::zero();
+        assert!(!zero_buy.is_fillable(2.0, 1000));
+        
+        assert!(!order.is_fillable(1.5, 1000)); // Below sell limit
+    }
+    
+    #[test]
+    fn test_remaining_amounts() {
+        let mut order = create_test_order(); // Sells 1000 for 2000
+        order.status = OrderStatus::PartiallyFilled;
+        order.filled_amount = U256::from(400);
+        assert_eq!(order.remaining_amounts(), (U256::from(600), U256::from(1200)));
+        
+        // Buy side rounds up for sell orders so the limit never loosens
+        order.filled_amount = U256::from(333);
+        assert_eq!(order.remaining_amounts(), (U256::from(667), U256::from(1334)));
+        
+        let mut buy_order = create_test_order();
+        buy_order.kind = OrderType::Buy;
+        buy_order.filled_amount = U256::from(500);
+        assert_eq!(buy_order.remaining_amounts(), (U256::from(750), U256::from(1500)));
+        
+        let remaining = order.remaining();
+        assert_eq!(remaining.sell_amount, U256::from(667));
+        assert!(remaining.filled_amount.is_zero());
+        
+        order.filled_amount = U256::from(1001);
+        assert!(order.validate().is_err());
     }
     
     #[test]
@@ -208,6 +546,15 @@ mod tests {
         order.bridge_provider = Some("Across".to_string());
         assert!(order.validate().is_ok());
     }
+    
+    #[test]
+    fn test_non_evm_source_chain_rejected() {
+        let mut order = create_test_order();
+        order.source_chain = Some(ChainId::Solana);
+        order.destination_chain = Some(ChainId::Ethereum);
+        order.bridge_provider = Some("Across".to_string());
+        assert!(order.validate().is_err());
+    }
 }
 
 #[cfg(test)]
@@ -231,6 +578,8 @@ mod extra_orders_tests {
             source_chain: None,
             destination_chain: None,
             bridge_provider: None,
+            filled_amount: U256::zero(),
+            signature: vec![],
         }
     }
 
@@ -1,6 +1,18 @@
 use serde::{Deserialize, Serialize};
-use ethers::types::{Address, U256};
+use ethers::types::{Address, U256, U512};
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use super::chains::ChainId;
+use tracing::warn;
+
+/// Sentinel address standing for a chain's native token (ETH, MATIC, ...)
+/// in order sell/buy token fields
+pub const NATIVE_TOKEN: [u8; 20] = [0xee; 20];
+
+/// Checks if `token` is the native-token sentinel rather than an ERC20
+pub fn is_native_token(token: Address) -> bool {
+    token == Address::from(NATIVE_TOKEN)
+}
 
 /// Represents a token on a specific chain
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -21,7 +33,11 @@ pub struct Token {
     pub decimals: u8,
 }
 
-/// Token amount with decimal awareness
+/// Token amount with decimal awareness.
+///
+/// The derived `PartialEq`/`Eq` are structural: 1.00 with 2 decimals and
+/// 1.0000 with 4 decimals are unequal. Use [`TokenAmount::value_eq`] and
+/// [`TokenAmount::value_cmp`] to compare the values they represent.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub struct TokenAmount {
     /// Raw amount in smallest unit
@@ -55,6 +71,36 @@ impl TokenAmount {
         self.raw.is_zero()
     }
     
+    /// Checks if both amounts represent the same value, whatever their
+    /// decimals
+    pub fn value_eq(&self, other: &TokenAmount) -> bool {
+        self.value_cmp(other) == Ordering::Equal
+    }
+    
+    /// Compares the values both amounts represent, scaling them to a common
+    /// number of decimals first
+    pub fn value_cmp(&self, other: &TokenAmount) -> Ordering {
+        // Zero needs no scaling, however many decimals the other side has
+        if self.is_zero() || other.is_zero() {
+            return (!self.is_zero()).cmp(&!other.is_zero());
+        }
+        
+        let decimals = self.decimals.max(other.decimals);
+        let scale = |amount: &TokenAmount| {
+            U512::from(10)
+                .checked_pow(U512::from(decimals - amount.decimals))?
+                .checked_mul(U512::from(amount.raw))
+        };
+        
+        match (scale(self), scale(other)) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            // Only the side with fewer decimals is scaled up, so overflowing
+            // puts it beyond anything the other side's raw amount can hold
+            (None, _) => Ordering::Greater,
+            (_, None) => Ordering::Less,
+        }
+    }
+    
     /// Adds two token amounts (must have same decimals)
     pub fn checked_add(&self, other: &TokenAmount) -> Option<TokenAmount> {
         if self.decimals != other.decimals {
@@ -100,7 +146,49 @@ impl TokenAmount {
     }
 }
 
+/// Infers token decimals from well-known symbols.
+///
+/// Heuristic only: tokens can reuse symbols with different decimals, so
+/// prefer on-chain `decimals()` whenever it is available.
+pub fn infer_decimals(symbol: &str) -> Option<u8> {
+    match symbol.to_ascii_uppercase().as_str() {
+        "USDC" | "USDT" | "USDC.E" | "PYUSD" => Some(6),
+        "WBTC" | "CBBTC" => Some(8),
+        "ETH" | "WETH" | "DAI" | "USDS" | "FRAX" | "LINK" | "UNI" | "AAVE" | "COW" | "GNO"
+        | "MATIC" | "WMATIC" | "POL" | "BNB" | "WBNB" | "AVAX" | "WAVAX" | "ARB" | "OP" => {
+            Some(18)
+        }
+        _ => None,
+    }
+}
+
 impl Token {
+    /// Creates a token, falling back to symbol-based decimals inference
+    /// when `decimals` is not provided.
+    ///
+    /// Returns `None` if decimals are missing and can't be inferred.
+    pub fn with_inferred_decimals(
+        address: Address,
+        chain_id: ChainId,
+        symbol: String,
+        name: String,
+        decimals: Option<u8>,
+    ) -> Option<Self> {
+        let decimals = match decimals {
+            Some(decimals) => decimals,
+            None => {
+                let inferred = infer_decimals(&symbol)?;
+                warn!(
+                    "Decimals not provided for {} ({:?}), inferred {} from symbol",
+                    symbol, address, inferred
+                );
+                inferred
+            }
+        };
+        
+        Some(Self::new(address, chain_id, symbol, name, decimals))
+    }
+    
     /// Creates a new token
     pub fn new(
         address: Address,
@@ -124,6 +212,60 @@ impl Token {
     }
 }
 
+/// Decimals of known tokens, for converting raw amounts into whole tokens
+#[derive(Debug, Clone, Default)]
+pub struct TokenRegistry {
+    /// Token address -> decimals
+    decimals: HashMap<Address, u8>,
+}
+
+impl TokenRegistry {
+    /// Decimals assumed for tokens missing from the registry
+    pub const DEFAULT_DECIMALS: u8 = 18;
+    
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Registers `token` as having `decimals`
+    pub fn with_decimals(mut self, token: Address, decimals: u8) -> Self {
+        self.decimals.insert(token, decimals);
+        self
+    }
+    
+    /// Registers a token's decimals
+    pub fn register(&mut self, token: &Token) {
+        self.decimals.insert(token.address, token.decimals);
+    }
+    
+    /// Returns `token`'s decimals, or `DEFAULT_DECIMALS` if unknown
+    pub fn decimals(&self, token: Address) -> u8 {
+        self.decimals.get(&token).copied().unwrap_or(Self::DEFAULT_DECIMALS)
+    }
+    
+    /// Returns the raw amount making up one whole `token`
+    pub fn unit(&self, token: Address) -> f64 {
+        10f64.powi(self.decimals(token) as i32)
+    }
+    
+    /// Converts a raw amount of `token` into whole tokens, saturating
+    /// amounts beyond 128 bits
+    pub fn to_units(&self, token: Address, amount: U256) -> f64 {
+        amount.min(U256::from(u128::MAX)).as_u128() as f64 / self.unit(token)
+    }
+}
+
+impl FromIterator<Token> for TokenRegistry {
+    fn from_iter<I: IntoIterator<Item = Token>>(tokens: I) -> Self {
+        let mut registry = Self::new();
+        for token in tokens {
+            registry.register(&token);
+        }
+        registry
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -134,6 +276,79 @@ mod tests {
         assert_eq!(amount.to_decimal(), 1.5);
     }
     
+    #[test]
+    fn test_value_comparison_across_decimals() {
+        let two_decimals = TokenAmount::new(U256::from(100), 2);
+        let four_decimals = TokenAmount::new(U256::from(10000), 4);
+        assert_ne!(two_decimals, four_decimals);
+        assert!(two_decimals.value_eq(&four_decimals));
+        assert_eq!(two_decimals.value_cmp(&four_decimals), Ordering::Equal);
+        
+        // 1.01 (2 decimals) > 1.0099 (4 decimals)
+        let larger = TokenAmount::new(U256::from(101), 2);
+        let smaller = TokenAmount::new(U256::from(10099), 4);
+        assert_eq!(larger.value_cmp(&smaller), Ordering::Greater);
+        assert_eq!(smaller.value_cmp(&larger), Ordering::Less);
+        
+        assert!(TokenAmount::new(U256::zero(), 0).value_eq(&TokenAmount::new(U256::zero(), 255)));
+        let tiny = TokenAmount::new(U256::one(), 255);
+        let one = TokenAmount::new(U256::one(), 0);
+        assert_eq!(one.value_cmp(&tiny), Ordering::Greater);
+        assert_eq!(tiny.value_cmp(&one), Ordering::Less);
+    }
+    
+    #[test]
+    fn test_infer_decimals() {
+        assert_eq!(infer_decimals("USDC"), Some(6));
+        assert_eq!(infer_decimals("usdt"), Some(6));
+        assert_eq!(infer_decimals("WBTC"), Some(8));
+        assert_eq!(infer_decimals("WETH"), Some(18));
+        assert_eq!(infer_decimals("NOTATOKEN"), None);
+    }
+    
+    #[test]
+    fn test_with_inferred_decimals_prefers_explicit() {
+        let explicit = Token::with_inferred_decimals(
+            Address::zero(),
+            ChainId::Ethereum,
+            "USDC".to_string(),
+            "USD Coin".to_string(),
+            Some(18),
+        )
+        .unwrap();
+        assert_eq!(explicit.decimals, 18);
+        
+        let inferred = Token::with_inferred_decimals(
+            Address::zero(),
+            ChainId::Ethereum,
+            "USDC".to_string(),
+            "USD Coin".to_string(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(inferred.decimals, 6);
+        
+        assert!(Token::with_inferred_decimals(
+            Address::zero(),
+            ChainId::Ethereum,
+            "XYZ".to_string(),
+            "Unknown".to_string(),
+            None,
+        )
+        .is_none());
+    }
+    
+    #[test]
+    fn test_token_registry_scales_by_decimals() {
+        let usdc = Address::from_low_u64_be(1);
+        let weth = Address::from_low_u64_be(2);
+        let registry = TokenRegistry::new().with_decimals(usdc, 6);
+        
+        assert_eq!(registry.to_units(usdc, U256::from(2_500_000u64)), 2.5);
+        assert_eq!(registry.decimals(weth), TokenRegistry::DEFAULT_DECIMALS);
+        assert_eq!(registry.to_units(weth, U256::exp10(18)), 1.0);
+    }
+    
     #[test]
     fn test_token_amount_addition() {
         let a = TokenAmount::new(U256::from(100), 18);
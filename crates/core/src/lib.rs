@@ -2,10 +2,12 @@ pub mod domain;
 pub mod solver;
 pub mod settlement;
 pub mod math;
+pub mod bridge;
 
-pub use solver::{Solver, SolverConfig, Solution};
+pub use solver::{Solver, SolverConfig, SolverConfigBuilder, Solution};
 pub use domain::{Order, Token, ChainId, OrderStatus};
 pub use settlement::{Settlement, SettlementPlan};
+pub use bridge::{BridgeProvider, BridgeRegistry};
 
 /// Core result type for solver operations
 pub type Result<T> = std::result::Result<T, Error>;
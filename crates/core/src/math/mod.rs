@@ -1,4 +1,30 @@
-use ethers::types::U256;
+use ethers::types::{U256, U512};
+
+/// Marginal price of a constant product pool: output tokens per input
+/// token for an infinitesimal trade, before fees.
+///
+/// Returns 0.0 when `reserve_in` is zero. Read off [`spot_price_x18`], so
+/// reserves beyond `u128` are safe.
+pub fn spot_price(reserve_in: U256, reserve_out: U256) -> f64 {
+    u256_to_f64(spot_price_x18(reserve_in, reserve_out)) / 1e18
+}
+
+/// Like [`spot_price`], in fixed point scaled by 1e18, rounding down and
+/// saturating at `U256::MAX`
+pub fn spot_price_x18(reserve_in: U256, reserve_out: U256) -> U256 {
+    if reserve_in.is_zero() {
+        return U256::zero();
+    }
+    
+    U256::try_from(reserve_out.full_mul(U256::exp10(18)) / U512::from(reserve_in)).unwrap_or(U256::MAX)
+}
+
+/// Converts `value` to the nearest `f64` without panicking above
+/// `u128::MAX`, keeping the top 128 bits
+pub fn u256_to_f64(value: U256) -> f64 {
+    let shift = value.bits().saturating_sub(128);
+    (value >> shift).as_u128() as f64 * 2f64.powi(shift as i32)
+}
 
 /// Calculates price impact for a swap
 pub fn calculate_price_impact(
@@ -20,7 +46,7 @@ pub fn calculate_price_impact(
     let new_reserve_out = k / new_reserve_in;
     
     let amount_out = reserve_out_f - new_reserve_out;
-    let expected_price = reserve_out_f / reserve_in_f;
+    let expected_price = spot_price(reserve_in, reserve_out);
     let actual_price = amount_out / amount_in_f;
     
     ((expected_price - actual_price) / expected_price).abs()
@@ -50,6 +76,37 @@ pub fn calculate_amm_output(
     numerator.checked_div(denominator)
 }
 
+/// Calculates output amount for constant product AMM when the tokens
+/// charge a fee on transfer.
+///
+/// `transfer_fee_bps_in` is deducted from `amount_in` before it reaches
+/// the pool and `transfer_fee_bps_out` from the pool's output on its way to
+/// the recipient. With both at zero this is exactly `calculate_amm_output`.
+pub fn calculate_amm_output_with_transfer_fees(
+    amount_in: U256,
+    reserve_in: U256,
+    reserve_out: U256,
+    fee_bps: u32,
+    transfer_fee_bps_in: u32,
+    transfer_fee_bps_out: u32,
+) -> Option<U256> {
+    let received_by_pool = deduct_bps(amount_in, transfer_fee_bps_in)?;
+    let sent_by_pool = calculate_amm_output(received_by_pool, reserve_in, reserve_out, fee_bps)?;
+    deduct_bps(sent_by_pool, transfer_fee_bps_out)
+}
+
+/// Deducts `bps` basis points from `amount`, rounding the deduction down.
+///
+/// Returns `None` for more than 10000 bps or on overflow.
+pub fn deduct_bps(amount: U256, bps: u32) -> Option<U256> {
+    if bps > 10000 {
+        return None;
+    }
+    
+    let fee = amount.checked_mul(U256::from(bps))? / U256::from(10000);
+    amount.checked_sub(fee)
+}
+
 /// Calculates required input for desired output (constant product AMM)
 pub fn calculate_amm_input(
     amount_out: U256,
@@ -87,29 +144,113 @@ pub fn calculate_optimal_split(
     vec![split_amount; num_paths]
 }
 
-/// Calculates geometric mean price
+/// Calculates geometric mean price.
+///
+/// Returns 0.0 for an empty slice, a zero price, or any negative or
+/// non-finite price.
 pub fn geometric_mean_price(prices: &[f64]) -> f64 {
-    if prices.is_empty() {
+    if prices.is_empty() || prices.iter().any(|p| !p.is_finite() || *p <= 0.0) {
         return 0.0;
     }
     
-    let product: f64 = prices.iter().product();
-    product.powf(1.0 / prices.len() as f64)
+    // Average in log space so large products don't overflow
+    let log_mean = prices.iter().map(|p| p.ln()).sum::<f64>() / prices.len() as f64;
+    log_mean.exp()
 }
 
-/// Calculates weighted average price
+/// Calculates weighted average price from `(price, weight)` pairs.
+///
+/// Returns 0.0 for an empty slice, a zero total weight, any negative or
+/// non-finite price or weight, or if the sums overflow.
 pub fn weighted_average_price(prices: &[(f64, f64)]) -> f64 {
     if prices.is_empty() {
         return 0.0;
     }
     
+    let valid = prices
+        .iter()
+        .all(|(p, w)| p.is_finite() && w.is_finite() && *p >= 0.0 && *w >= 0.0);
+    if !valid {
+        return 0.0;
+    }
+    
     let total_weight: f64 = prices.iter().map(|(_, w)| w).sum();
     if total_weight == 0.0 {
         return 0.0;
     }
     
     let weighted_sum: f64 = prices.iter().map(|(p, w)| p * w).sum();
-    weighted_sum / total_weight
+    let average = weighted_sum / total_weight;
+    if average.is_finite() { average } else { 0.0 }
+}
+
+/// Calculates weighted average price from `(price, weight)` pairs in
+/// integer arithmetic.
+///
+/// Sums are kept in 512 bits. If the weights total more than 256 bits they
+/// are all shifted down by the same number of bits first, which keeps the
+/// weighted sum in range and their ratios intact up to rounding. The
+/// division rounds down, so the result never exceeds the exact average and
+/// is less than 1 wei below it (before any weight scaling). Returns zero for
+/// an empty slice or a zero total weight.
+pub fn weighted_average_price_u256(prices: &[(U256, U256)]) -> U256 {
+    let total_weight = prices
+        .iter()
+        .fold(U512::zero(), |total, (_, weight)| total + U512::from(*weight));
+    let shift = total_weight.bits().saturating_sub(256);
+    
+    let (weighted_sum, total_weight) = prices.iter().fold(
+        (U512::zero(), U512::zero()),
+        |(sum, total), (price, weight)| {
+            let weight = *weight >> shift;
+            (sum + price.full_mul(weight), total + U512::from(weight))
+        },
+    );
+    
+    if total_weight.is_zero() {
+        return U256::zero();
+    }
+    
+    // The average never exceeds the largest price, so it fits in 256 bits
+    U256::try_from(weighted_sum / total_weight).unwrap_or(U256::MAX)
+}
+
+/// Calculates median price (robust to outliers)
+pub fn median_price(prices: &[f64]) -> f64 {
+    if prices.is_empty() {
+        return 0.0;
+    }
+    
+    let mut sorted = prices.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    
+    // Same element for odd lengths, the two middle elements for even
+    let lower = sorted[(sorted.len() - 1) / 2];
+    let upper = sorted[sorted.len() / 2];
+    (lower + upper) / 2.0
+}
+
+/// Calculates mean price after dropping `trim_fraction` of the prices from
+/// each end (e.g. 0.1 drops the lowest and highest 10%).
+///
+/// `trim_fraction` is clamped to [0, 0.5); at least one price is always kept.
+pub fn trimmed_mean_price(prices: &[f64], trim_fraction: f64) -> f64 {
+    if prices.is_empty() {
+        return 0.0;
+    }
+    
+    let mut sorted = prices.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    
+    let fraction = if trim_fraction.is_finite() {
+        trim_fraction.clamp(0.0, 0.499)
+    } else {
+        0.0
+    };
+    let trim = ((sorted.len() as f64) * fraction).floor() as usize;
+    let kept = &sorted[trim..sorted.len() - trim];
+    
+    kept.iter().sum::<f64>() / kept.len() as f64
 }
 
 #[cfg(test)]
@@ -146,10 +287,111 @@ mod tests {
         assert!((mean - 2.0).abs() < 0.01);
     }
     
+    #[test]
+    fn test_median_ignores_outlier() {
+        let prices = vec![1.0, 1.1, 0.9, 1.05, 100.0];
+        let mean = prices.iter().sum::<f64>() / prices.len() as f64;
+        
+        assert_eq!(median_price(&prices), 1.05);
+        assert!(mean > 20.0);
+        assert_eq!(median_price(&[1.0, 3.0]), 2.0);
+        assert_eq!(median_price(&[]), 0.0);
+        assert_eq!(median_price(&[7.0]), 7.0);
+    }
+    
+    #[test]
+    fn test_trimmed_mean() {
+        let prices = vec![0.01, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 100.0];
+        
+        assert!((trimmed_mean_price(&prices, 0.1) - 1.0).abs() < 1e-12);
+        assert!(trimmed_mean_price(&prices, 0.0) > 10.0);
+        assert_eq!(trimmed_mean_price(&[], 0.1), 0.0);
+        assert_eq!(trimmed_mean_price(&[5.0], 0.4), 5.0);
+    }
+    
     #[test]
     fn test_weighted_average() {
         let prices = vec![(100.0, 1.0), (200.0, 2.0)];
         let avg = weighted_average_price(&prices);
         assert!((avg - 166.67).abs() < 0.1);
     }
+    
+    #[test]
+    fn test_non_finite_inputs_return_zero() {
+        assert_eq!(weighted_average_price(&[(100.0, f64::INFINITY), (200.0, 1.0)]), 0.0);
+        assert_eq!(weighted_average_price(&[(f64::NAN, 1.0)]), 0.0);
+        assert_eq!(weighted_average_price(&[(100.0, -1.0), (200.0, 2.0)]), 0.0);
+        assert_eq!(weighted_average_price(&[(f64::MAX, 2.0), (f64::MAX, 2.0)]), 0.0);
+        assert_eq!(geometric_mean_price(&[-1.0, 4.0]), 0.0);
+        assert_eq!(geometric_mean_price(&[f64::NAN]), 0.0);
+        assert!((geometric_mean_price(&[1e200, 1e200]) - 1e200).abs() / 1e200 < 1e-9);
+    }
+    
+    #[test]
+    fn test_weighted_average_u256() {
+        let prices = vec![(U256::from(100), U256::from(1)), (U256::from(200), U256::from(2))];
+        // 500 / 3 = 166.67, rounded down
+        assert_eq!(weighted_average_price_u256(&prices), U256::from(166));
+        assert_eq!(weighted_average_price_u256(&[]), U256::zero());
+        assert_eq!(weighted_average_price_u256(&[(U256::from(5), U256::zero())]), U256::zero());
+        
+        // Products far beyond 256 bits
+        let large = vec![(U256::MAX, U256::MAX), (U256::MAX - 2, U256::MAX)];
+        assert_eq!(weighted_average_price_u256(&large), U256::MAX - 1);
+    }
+    
+    #[test]
+    fn test_amm_output_with_transfer_fees() {
+        let amount_in = U256::from(1_000_000);
+        let reserve_in = U256::from(100_000_000);
+        let reserve_out = U256::from(100_000_000);
+        
+        let plain = calculate_amm_output(amount_in, reserve_in, reserve_out, 30).unwrap();
+        
+        // Zero transfer fees reproduce the plain output
+        assert_eq!(
+            calculate_amm_output_with_transfer_fees(amount_in, reserve_in, reserve_out, 30, 0, 0),
+            Some(plain)
+        );
+        
+        // 1% on the way in and out
+        let taxed = calculate_amm_output_with_transfer_fees(amount_in, reserve_in, reserve_out, 30, 100, 100)
+            .unwrap();
+        let expected = deduct_bps(
+            calculate_amm_output(U256::from(990_000), reserve_in, reserve_out, 30).unwrap(),
+            100,
+        )
+        .unwrap();
+        assert_eq!(taxed, expected);
+        assert!(taxed < plain * 99 / 100);
+        
+        assert_eq!(deduct_bps(U256::from(100), 10001), None);
+    }
+    
+    #[test]
+    fn test_spot_price() {
+        let reserve_in = U256::from(1_000_000);
+        let reserve_out = U256::from(2_000_000);
+        
+        assert_eq!(spot_price(reserve_in, reserve_out), 2.0);
+        assert_eq!(spot_price_x18(reserve_in, reserve_out), U256::exp10(18) * 2);
+        
+        // No input reserve: no price
+        assert_eq!(spot_price(U256::zero(), reserve_out), 0.0);
+        assert_eq!(spot_price_x18(U256::zero(), reserve_out), U256::zero());
+        
+        // Dust input reserve against a huge output one saturates
+        assert_eq!(spot_price_x18(U256::one(), U256::MAX), U256::MAX);
+        
+        // Reserves beyond u128 still price by their ratio
+        assert_eq!(spot_price(U256::exp10(40), U256::exp10(40) * 3), 3.0);
+    }
+    
+    #[test]
+    fn test_u256_to_f64_beyond_u128() {
+        assert_eq!(u256_to_f64(U256::zero()), 0.0);
+        assert_eq!(u256_to_f64(U256::from(u128::MAX)), u128::MAX as f64);
+        assert_eq!(u256_to_f64(U256::exp10(40)), 1e40);
+        assert!((u256_to_f64(U256::MAX) / 2f64.powi(256) - 1.0).abs() < 1e-15);
+    }
 }
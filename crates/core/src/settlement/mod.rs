@@ -1,7 +1,10 @@
 use serde::{Deserialize, Serialize};
-use ethers::types::{Address, U256, Bytes};
-use crate::domain::{OrderId, ChainId};
-use std::collections::HashMap;
+use ethers::types::{Address, U256, U512, I256, Bytes};
+use crate::bridge::BridgeProvider;
+use crate::domain::{is_native_token, Order, OrderId, OrderType, ChainId, NATIVE_TOKEN};
+use crate::domain::orders::SETTLEMENT_CONTRACT;
+use crate::solver::{LiquidityPool, PoolType};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 /// Settlement plan for executing trades
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -12,11 +15,32 @@ pub struct SettlementPlan {
     /// On-chain interactions (AMM swaps, etc.)
     pub interactions: Vec<Interaction>,
     
-    /// Clearing prices per token
+    /// Clearing prices per token, serialized in token order
+    #[serde(serialize_with = "serialize_sorted")]
     pub clearing_prices: HashMap<Address, U256>,
     
     /// Post-hooks for cross-chain operations
     pub post_hooks: Vec<PostHook>,
+    
+    /// Self-offsetting order pairs netted off-chain
+    #[serde(default)]
+    pub internal_transfers: Vec<InternalTransfer>,
+}
+
+/// Converts a token amount to a signed balance change, saturating
+fn signed(amount: U256) -> I256 {
+    I256::try_from(amount).unwrap_or(I256::MAX)
+}
+
+/// Serializes a map with its keys in sorted order, so equal maps always
+/// produce identical output
+fn serialize_sorted<S, K, V>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+    K: Serialize + Ord,
+    V: Serialize,
+{
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
 }
 
 /// Individual trade in settlement
@@ -25,16 +49,31 @@ pub struct Trade {
     /// Order being filled
     pub order_id: OrderId,
     
-    /// Executed sell amount
+    /// Token paid by the user
+    #[serde(default)]
+    pub sell_token: Address,
+    
+    /// Token received by the user
+    #[serde(default)]
+    pub buy_token: Address,
+    
+    /// Executed sell amount, swapped after the fee was taken out
     pub executed_sell_amount: U256,
     
     /// Executed buy amount
     pub executed_buy_amount: U256,
     
-    /// Fee paid
+    /// Fee paid in the sell token, on top of the executed sell amount
     pub fee: U256,
 }
 
+/// Multicall3, deployed at the same address on every chain; wraps
+/// interactions that are allowed to fail
+pub const MULTICALL3: [u8; 20] = [
+    0xca, 0x11, 0xbd, 0xe0, 0x59, 0x77, 0xb3, 0x63, 0x11, 0x67,
+    0x02, 0x88, 0x62, 0xbe, 0x2a, 0x17, 0x39, 0x76, 0xca, 0x11,
+];
+
 /// On-chain interaction (AMM swap, vault operation, etc.)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Interaction {
@@ -49,6 +88,232 @@ pub struct Interaction {
     
     /// Interaction type
     pub interaction_type: InteractionType,
+    
+    /// Tokens moved in and out of the settlement contract, if known
+    #[serde(default)]
+    pub flow: Option<TokenFlow>,
+    
+    /// Chain the interaction executes on; `None` for the settlement's own chain
+    #[serde(default)]
+    pub chain: Option<ChainId>,
+    
+    /// Whether the settlement tolerates this interaction reverting; only
+    /// for interactions nothing else in the settlement depends on
+    #[serde(default)]
+    pub allow_failure: bool,
+}
+
+impl Interaction {
+    /// Builds an ERC20 `approve(spender, amount)` call on `token`
+    pub fn approval(token: Address, spender: Address, amount: U256) -> Self {
+        let mut call_data = ethers::utils::id("approve(address,uint256)").to_vec();
+        call_data.extend(ethers::abi::encode(&[
+            ethers::abi::Token::Address(spender),
+            ethers::abi::Token::Uint(amount),
+        ]));
+        
+        Self {
+            target: token,
+            call_data: call_data.into(),
+            value: U256::zero(),
+            interaction_type: InteractionType::Approval,
+            flow: None,
+            chain: None,
+            allow_failure: false,
+        }
+    }
+    
+    /// Builds a `deposit()` call on `weth` wrapping `amount` of the native
+    /// token, sending the native amount as call value
+    pub fn wrap(weth: Address, amount: U256) -> Self {
+        Self {
+            target: weth,
+            call_data: ethers::utils::id("deposit()").to_vec().into(),
+            value: amount,
+            interaction_type: InteractionType::WrapNative,
+            flow: Some(TokenFlow {
+                token_in: Address::from(NATIVE_TOKEN),
+                amount_in: amount,
+                token_out: weth,
+                amount_out: amount,
+            }),
+            chain: None,
+            allow_failure: false,
+        }
+    }
+    
+    /// Builds a `withdraw(amount)` call on `weth` unwrapping into the
+    /// native token
+    pub fn unwrap(weth: Address, amount: U256) -> Self {
+        let mut call_data = ethers::utils::id("withdraw(uint256)").to_vec();
+        call_data.extend(ethers::abi::encode(&[ethers::abi::Token::Uint(amount)]));
+        
+        Self {
+            target: weth,
+            call_data: call_data.into(),
+            value: U256::zero(),
+            interaction_type: InteractionType::UnwrapNative,
+            flow: Some(TokenFlow {
+                token_in: weth,
+                amount_in: amount,
+                token_out: Address::from(NATIVE_TOKEN),
+                amount_out: amount,
+            }),
+            chain: None,
+            allow_failure: false,
+        }
+    }
+    
+    /// Builds an ERC-3156 `flashLoan` of `amount` of `token` from `lender`,
+    /// paid to the settlement contract. It must be repaid, with
+    /// `flash_loan_repayment`, later in the same settlement.
+    pub fn flash_loan(lender: Address, token: Address, amount: U256) -> Self {
+        let mut call_data = ethers::utils::id("flashLoan(address,address,uint256,bytes)").to_vec();
+        call_data.extend(ethers::abi::encode(&[
+            ethers::abi::Token::Address(Address::from(SETTLEMENT_CONTRACT)),
+            ethers::abi::Token::Address(token),
+            ethers::abi::Token::Uint(amount),
+            ethers::abi::Token::Bytes(vec![]),
+        ]));
+        
+        Self {
+            target: lender,
+            call_data: call_data.into(),
+            value: U256::zero(),
+            interaction_type: InteractionType::FlashLoan,
+            flow: Some(TokenFlow {
+                token_in: token,
+                amount_in: U256::zero(),
+                token_out: token,
+                amount_out: amount,
+            }),
+            chain: None,
+            allow_failure: false,
+        }
+    }
+    
+    /// Builds a `transfer(lender, amount)` of `token` repaying a flash loan,
+    /// including any loan fee in `amount`
+    pub fn flash_loan_repayment(lender: Address, token: Address, amount: U256) -> Self {
+        let mut call_data = ethers::utils::id("transfer(address,uint256)").to_vec();
+        call_data.extend(ethers::abi::encode(&[
+            ethers::abi::Token::Address(lender),
+            ethers::abi::Token::Uint(amount),
+        ]));
+        
+        Self {
+            target: token,
+            call_data: call_data.into(),
+            value: U256::zero(),
+            interaction_type: InteractionType::FlashLoan,
+            flow: Some(TokenFlow {
+                token_in: token,
+                amount_in: amount,
+                token_out: token,
+                amount_out: U256::zero(),
+            }),
+            chain: None,
+            allow_failure: false,
+        }
+    }
+    
+    /// Marks the interaction as allowed to fail without reverting the
+    /// settlement
+    pub fn allowing_failure(mut self) -> Self {
+        self.allow_failure = true;
+        self
+    }
+    
+    /// Checks if the settlement depends on this interaction succeeding:
+    /// anything moving tokens, approving them or wrapping them. Only custom
+    /// calls with no token flow, such as reward claims, may fail.
+    pub fn is_revert_critical(&self) -> bool {
+        self.interaction_type != InteractionType::Custom || self.flow.is_some()
+    }
+    
+    /// Returns the `(target, value, call_data)` the settlement contract
+    /// calls: the interaction itself, or if it's allowed to fail, a
+    /// Multicall3 `aggregate3Value` call that tolerates it reverting
+    pub fn to_call(&self) -> (Address, U256, Bytes) {
+        if !self.allow_failure {
+            return (self.target, self.value, self.call_data.clone());
+        }
+        
+        let mut call_data = ethers::utils::id("aggregate3Value((address,bool,uint256,bytes)[])").to_vec();
+        call_data.extend(ethers::abi::encode(&[ethers::abi::Token::Array(vec![
+            ethers::abi::Token::Tuple(vec![
+                ethers::abi::Token::Address(self.target),
+                ethers::abi::Token::Bool(true),
+                ethers::abi::Token::Uint(self.value),
+                ethers::abi::Token::Bytes(self.call_data.to_vec()),
+            ]),
+        ])]));
+        (Address::from(MULTICALL3), self.value, call_data.into())
+    }
+    
+    /// Returns the lender, token and amount of a flash loan (`true`) or
+    /// repayment (`false`), or `None` for any other interaction
+    fn flash_loan_leg(&self) -> Option<(bool, Address, Address, U256)> {
+        if self.interaction_type != InteractionType::FlashLoan {
+            return None;
+        }
+        
+        let flow = self.flow.as_ref()?;
+        if flow.amount_out > flow.amount_in {
+            Some((true, self.target, flow.token_out, flow.amount_out))
+        } else if self.call_data.len() >= 36 {
+            let lender = Address::from_slice(&self.call_data[16..36]);
+            Some((false, lender, flow.token_in, flow.amount_in))
+        } else {
+            None
+        }
+    }
+    
+    /// Estimates gas used by this interaction: swaps cost the default for
+    /// their pool type, wraps and flash loans their own fixed cost, anything
+    /// else `SettlementPlan::INTERACTION_GAS`
+    pub fn estimate_gas(&self) -> u64 {
+        let pool_type = match self.interaction_type {
+            InteractionType::UniswapV2Swap => PoolType::UniswapV2,
+            InteractionType::UniswapV3Swap => PoolType::UniswapV3,
+            InteractionType::BalancerSwap => PoolType::Balancer,
+            InteractionType::CurveSwap => PoolType::Curve,
+            InteractionType::WrapNative => return SettlementPlan::WRAP_GAS,
+            InteractionType::UnwrapNative => return SettlementPlan::UNWRAP_GAS,
+            InteractionType::FlashLoan => {
+                return match self.flash_loan_leg() {
+                    Some((false, ..)) => SettlementPlan::TRANSFER_GAS,
+                    _ => SettlementPlan::FLASH_LOAN_GAS,
+                };
+            }
+            InteractionType::Approval | InteractionType::Custom => return SettlementPlan::INTERACTION_GAS,
+        };
+        LiquidityPool::default_gas_cost(pool_type)
+    }
+    
+    /// Checks if this is an approval of `spender` on `token`
+    pub fn approves(&self, token: Address, spender: Address) -> bool {
+        self.interaction_type == InteractionType::Approval
+            && self.target == token
+            && self.call_data.len() >= 36
+            && Address::from_slice(&self.call_data[16..36]) == spender
+    }
+}
+
+/// Token movement caused by an interaction, seen from the settlement contract
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TokenFlow {
+    /// Token sent out of the settlement contract
+    pub token_in: Address,
+    
+    /// Amount sent out
+    pub amount_in: U256,
+    
+    /// Token received by the settlement contract
+    pub token_out: Address,
+    
+    /// Amount received
+    pub amount_out: U256,
 }
 
 /// Type of on-chain interaction
@@ -69,6 +334,16 @@ pub enum InteractionType {
     /// ERC20 approval
     Approval,
     
+    /// WETH-style `deposit()` of the native token
+    WrapNative,
+    
+    /// WETH-style `withdraw(amount)` into the native token
+    UnwrapNative,
+    
+    /// Flash loan taken out, or its repayment; the direction is given by
+    /// the interaction's token flow
+    FlashLoan,
+    
     /// Custom interaction
     Custom,
 }
@@ -98,7 +373,107 @@ pub struct PostHook {
     pub recipient: Address,
 }
 
+impl PostHook {
+    /// Builds a post-hook with bridge deposit call data encoded by `provider`
+    pub fn build(
+        provider: &dyn BridgeProvider,
+        bridge_contract: Address,
+        source_chain: ChainId,
+        destination_chain: ChainId,
+        intermediate_token: Address,
+        amount: U256,
+        recipient: Address,
+    ) -> Result<Self, String> {
+        if source_chain == destination_chain {
+            return Err(format!(
+                "Post-hook source and destination chains must differ (both {})",
+                source_chain.name()
+            ));
+        }
+        
+        if amount.is_zero() {
+            return Err("Post-hook bridge amount must be greater than zero".to_string());
+        }
+        
+        // Call data is EVM ABI and the recipient is a 20-byte address
+        for chain in [source_chain, destination_chain] {
+            if !chain.is_evm() {
+                return Err(format!("Post-hooks require EVM chains, got {}", chain.name()));
+            }
+        }
+        
+        let call_data = provider.encode_deposit(
+            intermediate_token,
+            amount,
+            recipient,
+            source_chain,
+            destination_chain,
+        );
+        
+        Ok(Self {
+            bridge_contract,
+            call_data,
+            source_chain,
+            destination_chain,
+            intermediate_token,
+            amount,
+            recipient,
+        })
+    }
+}
+
+/// Pair of same-owner orders that exactly offset each other.
+///
+/// Netted off-chain: produces no trade and no interaction.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct InternalTransfer {
+    /// Owner of both orders
+    pub owner: Address,
+    
+    /// Order selling `token_a` for `token_b`
+    pub order_a: OrderId,
+    
+    /// Order selling `token_b` for `token_a`
+    pub order_b: OrderId,
+    
+    /// First token
+    pub token_a: Address,
+    
+    /// Second token
+    pub token_b: Address,
+    
+    /// Amount of `token_a` netted
+    pub amount_a: U256,
+    
+    /// Amount of `token_b` netted
+    pub amount_b: U256,
+}
+
 impl Settlement {
+    /// Base transaction gas
+    pub const BASE_GAS: u64 = 21000;
+    
+    /// Gas per trade
+    pub const TRADE_GAS: u64 = 50000;
+    
+    /// Gas per interaction
+    pub const INTERACTION_GAS: u64 = 100000;
+    
+    /// Gas per post-hook
+    pub const POST_HOOK_GAS: u64 = 150000;
+    
+    /// Gas to wrap the native token
+    pub const WRAP_GAS: u64 = 45000;
+    
+    /// Gas to unwrap into the native token
+    pub const UNWRAP_GAS: u64 = 35000;
+    
+    /// Gas to take out a flash loan, including the lender's callback
+    pub const FLASH_LOAN_GAS: u64 = 80000;
+    
+    /// Gas for a plain ERC20 transfer, such as a flash loan repayment
+    pub const TRANSFER_GAS: u64 = 30000;
+    
     /// Creates a new empty settlement
     pub fn new() -> Self {
         Self::default()
@@ -109,43 +484,549 @@ impl Settlement {
         self.trades.push(trade);
     }
     
+    /// Keeps only the trades for which `keep` returns true, dropping the
+    /// clearing prices of tokens no remaining trade uses
+    pub fn retain_trades(&mut self, keep: impl FnMut(&Trade) -> bool) {
+        self.trades.retain(keep);
+        let traded: HashSet<Address> = self
+            .trades
+            .iter()
+            .flat_map(|trade| [trade.sell_token, trade.buy_token])
+            .collect();
+        self.clearing_prices.retain(|token, _| traded.contains(token));
+    }
+    
     /// Adds an interaction to the settlement
     pub fn add_interaction(&mut self, interaction: Interaction) {
         self.interactions.push(interaction);
     }
     
+    /// Adds an approval of `spender` on `token` unless the settlement
+    /// already has one; returns whether an approval was added
+    pub fn ensure_approval(&mut self, token: Address, spender: Address, amount: U256) -> bool {
+        self.ensure_approval_on(None, token, spender, amount)
+    }
+    
+    /// Like [`Self::ensure_approval`], for interactions executing on `chain`
+    /// (`None` for the settlement's own chain)
+    pub fn ensure_approval_on(
+        &mut self,
+        chain: Option<ChainId>,
+        token: Address,
+        spender: Address,
+        amount: U256,
+    ) -> bool {
+        if self
+            .interactions
+            .iter()
+            .any(|i| i.chain == chain && i.approves(token, spender))
+        {
+            return false;
+        }
+        
+        let mut approval = Interaction::approval(token, spender, amount);
+        approval.chain = chain;
+        self.interactions.push(approval);
+        true
+    }
+    
+    /// Removes interactions made redundant by other orders' fills.
+    ///
+    /// Approvals of the same spender on the same token are merged into the
+    /// first, raised to the largest amount, so it still precedes every swap
+    /// spending it. Other exact duplicates (same chain, target, call data
+    /// and value) are dropped unless they move tokens: two identical swaps
+    /// trade twice, so both are kept. Returns the number removed.
+    pub fn dedupe_interactions(&mut self) -> usize {
+        let before = self.interactions.len();
+        let mut approvals: HashMap<(Option<ChainId>, Address, Address), usize> = HashMap::new();
+        let mut seen: HashSet<(Option<ChainId>, Address, Bytes, U256)> = HashSet::new();
+        let mut kept: Vec<Interaction> = Vec::with_capacity(before);
+        
+        for interaction in std::mem::take(&mut self.interactions) {
+            if interaction.interaction_type == InteractionType::Approval && interaction.call_data.len() >= 68 {
+                let spender = Address::from_slice(&interaction.call_data[16..36]);
+                let amount = U256::from_big_endian(&interaction.call_data[36..68]);
+                let key = (interaction.chain, interaction.target, spender);
+                
+                if let Some(&index) = approvals.get(&key) {
+                    let first = &mut kept[index];
+                    if amount > U256::from_big_endian(&first.call_data[36..68]) {
+                        *first = Interaction {
+                            chain: interaction.chain,
+                            ..Interaction::approval(interaction.target, spender, amount)
+                        };
+                    }
+                    continue;
+                }
+                approvals.insert(key, kept.len());
+            } else if interaction.flow.is_none()
+                && !seen.insert((
+                    interaction.chain,
+                    interaction.target,
+                    interaction.call_data.clone(),
+                    interaction.value,
+                ))
+            {
+                continue;
+            }
+            
+            kept.push(interaction);
+        }
+        
+        self.interactions = kept;
+        before - self.interactions.len()
+    }
+    
+    /// Converts native-token legs of the trades through `weth`.
+    ///
+    /// Native tokens sold and bought are netted: a surplus is wrapped
+    /// before any other interaction, a shortfall unwrapped after all of
+    /// them. Returns whether an interaction was added.
+    pub fn wrap_native_legs(&mut self, weth: Address) -> bool {
+        let net: I256 = self
+            .trades
+            .iter()
+            .map(|trade| {
+                let mut net = I256::zero();
+                if is_native_token(trade.sell_token) {
+                    net += signed(trade.executed_sell_amount);
+                }
+                if is_native_token(trade.buy_token) {
+                    net -= signed(trade.executed_buy_amount);
+                }
+                net
+            })
+            .fold(I256::zero(), |total, net| total + net);
+        
+        if net.is_positive() {
+            self.interactions.insert(0, Interaction::wrap(weth, net.into_raw()));
+        } else if net.is_negative() {
+            self.interactions.push(Interaction::unwrap(weth, (-net).into_raw()));
+        } else {
+            return false;
+        }
+        true
+    }
+    
     /// Adds a post-hook for cross-chain
     pub fn add_post_hook(&mut self, post_hook: PostHook) {
         self.post_hooks.push(post_hook);
     }
     
+    /// Adds a netted same-owner order pair
+    pub fn add_internal_transfer(&mut self, transfer: InternalTransfer) {
+        self.internal_transfers.push(transfer);
+    }
+    
     /// Sets clearing price for a token
     pub fn set_clearing_price(&mut self, token: Address, price: U256) {
         self.clearing_prices.insert(token, price);
     }
     
+    /// Prices a trade selling `sell_amount` of `sell_token` for up to
+    /// `available` of `buy_token`, returning the buy amount it executes at
+    /// under the settlement's uniform prices.
+    ///
+    /// Unpriced tokens are priced so the trade gets all of `available`. If
+    /// both are already priced, it gets the amount their rate implies, or
+    /// `None` is returned, changing nothing, when that amount is outside
+    /// `min_buy..=available`.
+    pub fn reconcile_trade_price(
+        &mut self,
+        sell_token: Address,
+        buy_token: Address,
+        sell_amount: U256,
+        min_buy: U256,
+        available: U256,
+    ) -> Option<U256> {
+        match (self.clearing_prices.get(&sell_token).copied(), self.clearing_prices.get(&buy_token).copied()) {
+            (Some(sell_price), Some(buy_price)) => {
+                let implied = U256::try_from(sell_amount.full_mul(sell_price).checked_div(U512::from(buy_price))?).ok()?;
+                (min_buy..=available).contains(&implied).then_some(implied)
+            }
+            (Some(sell_price), None) => {
+                let buy_price = U256::try_from(sell_amount.full_mul(sell_price).checked_div(U512::from(available))?).ok()?;
+                self.clearing_prices.insert(buy_token, buy_price);
+                Some(available)
+            }
+            (None, Some(buy_price)) => {
+                let sell_price = U256::try_from(available.full_mul(buy_price).checked_div(U512::from(sell_amount))?).ok()?;
+                self.clearing_prices.insert(sell_token, sell_price);
+                Some(available)
+            }
+            (None, None) => {
+                self.clearing_prices.insert(sell_token, available);
+                self.clearing_prices.insert(buy_token, sell_amount);
+                Some(available)
+            }
+        }
+    }
+    
+    /// Appends `other`'s trades, interactions, post-hooks and internal
+    /// transfers and merges its clearing prices.
+    ///
+    /// Fails without modifying `self` if both plans price the same token
+    /// differently or settle the same order.
+    pub fn merge(&mut self, other: SettlementPlan) -> Result<(), String> {
+        for (token, price) in &other.clearing_prices {
+            if let Some(existing) = self.clearing_prices.get(token) {
+                if existing != price {
+                    return Err(format!(
+                        "Conflicting clearing prices for {:?}: {} vs {}",
+                        token, existing, price
+                    ));
+                }
+            }
+        }
+        
+        let settled: Vec<OrderId> = self
+            .trades
+            .iter()
+            .map(|t| t.order_id)
+            .chain(self.internal_transfers.iter().flat_map(|t| [t.order_a, t.order_b]))
+            .collect();
+        let other_settled = other
+            .trades
+            .iter()
+            .map(|t| t.order_id)
+            .chain(other.internal_transfers.iter().flat_map(|t| [t.order_a, t.order_b]));
+        for order_id in other_settled {
+            if settled.contains(&order_id) {
+                return Err(format!("Order {:?} is settled by both plans", order_id));
+            }
+        }
+        
+        self.trades.extend(other.trades);
+        self.interactions.extend(other.interactions);
+        self.post_hooks.extend(other.post_hooks);
+        self.internal_transfers.extend(other.internal_transfers);
+        self.clearing_prices.extend(other.clearing_prices);
+        
+        Ok(())
+    }
+    
     /// Validates settlement plan
     pub fn validate(&self) -> Result<(), String> {
-        if self.trades.is_empty() {
+        if self.trades.is_empty() && self.internal_transfers.is_empty() {
             return Err("Settlement must contain at least one trade".to_string());
         }
         
-        // Validate all trades have clearing prices
+        if let Some(interaction) = self.interactions.iter().find(|i| i.allow_failure && i.is_revert_critical()) {
+            return Err(format!(
+                "{:?} interaction on {:?} is revert-critical and can't be allowed to fail",
+                interaction.interaction_type, interaction.target
+            ));
+        }
+        
+        self.check_flash_loans()
+    }
+    
+    /// ABI-encodes the interactions as the `(address,uint256,bytes)[]` the
+    /// settlement contract executes, wrapping those allowed to fail
+    pub fn encode_interactions(&self) -> Bytes {
+        let calls = self
+            .interactions
+            .iter()
+            .map(|interaction| {
+                let (target, value, call_data) = interaction.to_call();
+                ethers::abi::Token::Tuple(vec![
+                    ethers::abi::Token::Address(target),
+                    ethers::abi::Token::Uint(value),
+                    ethers::abi::Token::Bytes(call_data.to_vec()),
+                ])
+            })
+            .collect();
+        ethers::abi::encode(&[ethers::abi::Token::Array(calls)]).into()
+    }
+    
+    /// Validates the settlement, then checks every trade delivers its
+    /// order's minimum: sell orders must receive at least `buy_amount` and
+    /// buy orders pay at most `sell_amount`. Partially fillable orders are
+    /// held to the same bounds in proportion to how much of them is filled.
+    pub fn validate_with_orders(&self, orders: &[Order]) -> Result<(), String> {
+        self.validate()?;
+        
+        let orders: HashMap<OrderId, &Order> = orders.iter().map(|order| (order.id, order)).collect();
         for trade in &self.trades {
-            // Additional validation logic here
+            let order = orders
+                .get(&trade.order_id)
+                .ok_or_else(|| format!("Trade for unknown order {:?}", trade.order_id))?;
+            
+            match order.kind {
+                OrderType::Sell => {
+                    let sold = trade.executed_sell_amount + trade.fee;
+                    let min_received = if order.partially_fillable {
+                        order.buy_amount.full_mul(sold.min(order.sell_amount)) / U512::from(order.sell_amount)
+                    } else {
+                        U512::from(order.buy_amount)
+                    };
+                    if U512::from(trade.executed_buy_amount) < min_received {
+                        return Err(format!(
+                            "Trade for sell order {:?} delivers {} but must deliver at least {}",
+                            trade.order_id, trade.executed_buy_amount, min_received
+                        ));
+                    }
+                }
+                OrderType::Buy => {
+                    let max_paid = if order.partially_fillable {
+                        order.sell_amount.full_mul(trade.executed_buy_amount.min(order.buy_amount))
+                            / U512::from(order.buy_amount)
+                    } else {
+                        U512::from(order.sell_amount)
+                    };
+                    if U512::from(trade.executed_sell_amount) > max_paid {
+                        return Err(format!(
+                            "Trade for buy order {:?} charges {} but may charge at most {}",
+                            trade.order_id, trade.executed_sell_amount, max_paid
+                        ));
+                    }
+                }
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Checks that every flash loan is repaid, in full, by later
+    /// interactions of the settlement, and that nothing is repaid to a
+    /// lender before it lent
+    pub fn check_flash_loans(&self) -> Result<(), String> {
+        let mut outstanding: BTreeMap<(Address, Address), U256> = BTreeMap::new();
+        
+        for interaction in &self.interactions {
+            match interaction.flash_loan_leg() {
+                Some((true, lender, token, amount)) => {
+                    *outstanding.entry((lender, token)).or_insert_with(U256::zero) += amount;
+                }
+                Some((false, lender, token, amount)) => match outstanding.get_mut(&(lender, token)) {
+                    Some(owed) => *owed = owed.saturating_sub(amount),
+                    None => {
+                        return Err(format!(
+                            "Repayment of {:?} to {:?} without a prior flash loan",
+                            token, lender
+                        ));
+                    }
+                },
+                None => {}
+            }
+        }
+        
+        match outstanding.into_iter().find(|(_, owed)| !owed.is_zero()) {
+            Some(((lender, token), owed)) => Err(format!(
+                "Flash loan of {:?} from {:?} is not repaid ({} outstanding)",
+                token, lender, owed
+            )),
+            None => Ok(()),
+        }
+    }
+    
+    /// Checks that neither clearing prices nor trades admit arbitrage.
+    ///
+    /// Amounts are converted the way the settlement contract does it,
+    /// `amount * price_in / price_out` in integer math, so badly scaled prices
+    /// can make a round trip through three tokens gain or lose value. Every
+    /// triple of priced tokens is walked in both directions and the round
+    /// trip must return within `tolerance_bps` of the starting amount.
+    ///
+    /// Trades are checked at the rates they actually execute at: going
+    /// round any cycle of two or three tokens through the best trade on
+    /// each leg must not gain more than `tolerance_bps`, which catches
+    /// trades that don't clear at the uniform prices.
+    pub fn check_price_consistency(&self, tolerance_bps: u32) -> Result<(), String> {
+        let mut tokens: Vec<&Address> = self.clearing_prices.keys().collect();
+        tokens.sort();
+        
+        if let Some(token) = tokens.iter().find(|t| self.clearing_prices[**t].is_zero()) {
+            return Err(format!("Clearing price for {:?} is zero", token));
+        }
+        
+        let start = U256::exp10(18);
+        let tolerance = start * U256::from(tolerance_bps) / U256::from(10000);
+        let convert = |amount: U256, from: &Address, to: &Address| -> Option<U256> {
+            amount
+                .checked_mul(self.clearing_prices[from])?
+                .checked_div(self.clearing_prices[to])
+        };
+        
+        for (i, a) in tokens.iter().enumerate() {
+            for (j, b) in tokens.iter().enumerate().skip(i + 1) {
+                for c in tokens.iter().skip(j + 1) {
+                    for cycle in [[a, b, c], [a, c, b]] {
+                        let round_trip = convert(start, cycle[0], cycle[1])
+                            .and_then(|amount| convert(amount, cycle[1], cycle[2]))
+                            .and_then(|amount| convert(amount, cycle[2], cycle[0]))
+                            .ok_or_else(|| {
+                                format!("Price overflow in cycle {:?} -> {:?} -> {:?}", cycle[0], cycle[1], cycle[2])
+                            })?;
+                        
+                        let deviation = if round_trip > start {
+                            round_trip - start
+                        } else {
+                            start - round_trip
+                        };
+                        
+                        if deviation > tolerance {
+                            return Err(format!(
+                                "Inconsistent prices in cycle {:?} -> {:?} -> {:?}: {} returns {}",
+                                cycle[0], cycle[1], cycle[2], start, round_trip
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+        
+        self.check_trade_cycles(tolerance_bps)
+    }
+    
+    /// Checks that no cycle of two or three tokens through the settlement's
+    /// trades gains more than `tolerance_bps`, comparing the product of the
+    /// bought amounts with that of the sold amounts
+    fn check_trade_cycles(&self, tolerance_bps: u32) -> Result<(), String> {
+        // Best (sell, buy) amounts per directed pair
+        let mut best: BTreeMap<(Address, Address), (U256, U256)> = BTreeMap::new();
+        for trade in self.trades.iter().filter(|t| !t.executed_sell_amount.is_zero()) {
+            let rate = (trade.executed_sell_amount, trade.executed_buy_amount);
+            best.entry((trade.sell_token, trade.buy_token))
+                .and_modify(|current| {
+                    if rate.1.full_mul(current.0) > current.1.full_mul(rate.0) {
+                        *current = rate;
+                    }
+                })
+                .or_insert(rate);
+        }
+        
+        let gains = |legs: &[(U256, U256)]| -> Option<bool> {
+            let mut sold = U512::from(10000u32 + tolerance_bps);
+            let mut bought = U512::from(10000u32);
+            for (sell, buy) in legs {
+                sold = sold.checked_mul(U512::from(*sell))?;
+                bought = bought.checked_mul(U512::from(*buy))?;
+            }
+            Some(bought > sold)
+        };
+        let check = |tokens: &[Address], legs: &[(U256, U256)]| match gains(legs) {
+            Some(false) => Ok(()),
+            Some(true) => Err(format!("Trades admit arbitrage in cycle {:?}", tokens)),
+            None => Err(format!("Trade amounts overflow in cycle {:?}", tokens)),
+        };
+        
+        for (&(a, b), &ab) in &best {
+            if let Some(&ba) = best.get(&(b, a)) {
+                if a < b {
+                    check(&[a, b], &[ab, ba])?;
+                }
+            }
+            for (&(_, c), &bc) in best.range((b, Address::zero())..=(b, Address::repeat_byte(0xff))) {
+                if let Some(&ca) = best.get(&(c, a)) {
+                    // Each cycle once, starting from its smallest token
+                    if a < b && a < c {
+                        check(&[a, b, c], &[ab, bc, ca])?;
+                    }
+                }
+            }
         }
         
         Ok(())
     }
     
+    /// Net token balance change of the settlement contract itself.
+    ///
+    /// Trades pay the sell amount in and the buy amount out; interactions
+    /// with a known [`TokenFlow`] do the reverse. Whatever remains is the
+    /// solver's own inventory change (e.g. captured spread). Internal
+    /// transfers never touch the contract. Tokens that net to zero are omitted.
+    pub fn solver_inventory_delta(&self) -> HashMap<Address, I256> {
+        let mut delta: HashMap<Address, I256> = HashMap::new();
+        let mut apply = |token: Address, change: I256| {
+            *delta.entry(token).or_insert_with(I256::zero) += change;
+        };
+        
+        for trade in &self.trades {
+            apply(trade.sell_token, signed(trade.executed_sell_amount));
+            apply(trade.buy_token, -signed(trade.executed_buy_amount));
+        }
+        
+        for flow in self.interactions.iter().filter_map(|i| i.flow.as_ref()) {
+            apply(flow.token_in, -signed(flow.amount_in));
+            apply(flow.token_out, signed(flow.amount_out));
+        }
+        
+        delta.retain(|_, change| !change.is_zero());
+        delta
+    }
+    
+    /// Checks that the settlement conserves tokens.
+    ///
+    /// Per token, trades pay sell amounts and fees in and buy amounts out,
+    /// interactions with a known [`TokenFlow`] do the reverse, and post-hooks
+    /// pay the bridged amount out. Every balance must end non-negative, so
+    /// the settlement never pays out tokens it didn't receive. Cross-chain
+    /// orders are paid out by the bridge, so their buy side and
+    /// destination-chain interactions aren't counted. Each trade must also
+    /// belong to one of `orders`, swap its tokens and meet its limit price.
+    pub fn check_balances(&self, orders: &[Order]) -> Result<(), String> {
+        let orders: HashMap<OrderId, &Order> = orders.iter().map(|order| (order.id, order)).collect();
+        let mut balances: HashMap<Address, I256> = HashMap::new();
+        let mut apply = |token: Address, change: I256| {
+            *balances.entry(token).or_insert_with(I256::zero) += change;
+        };
+        
+        for trade in &self.trades {
+            let order = orders
+                .get(&trade.order_id)
+                .ok_or_else(|| format!("Trade for unknown order {:?}", trade.order_id))?;
+            
+            if (order.sell_token, order.buy_token) != (trade.sell_token, trade.buy_token) {
+                return Err(format!("Trade for order {:?} swaps the wrong tokens", trade.order_id));
+            }
+            
+            if trade.executed_buy_amount.full_mul(order.sell_amount)
+                < order.buy_amount.full_mul(trade.executed_sell_amount)
+            {
+                return Err(format!("Trade for order {:?} executes below its limit price", trade.order_id));
+            }
+            
+            apply(trade.sell_token, signed(trade.executed_sell_amount) + signed(trade.fee));
+            if !order.is_cross_chain() {
+                apply(trade.buy_token, -signed(trade.executed_buy_amount));
+            }
+        }
+        
+        for interaction in self.interactions.iter().filter(|i| i.chain.is_none()) {
+            if let Some(flow) = &interaction.flow {
+                apply(flow.token_in, -signed(flow.amount_in));
+                apply(flow.token_out, signed(flow.amount_out));
+            }
+        }
+        
+        for hook in &self.post_hooks {
+            apply(hook.intermediate_token, -signed(hook.amount));
+        }
+        
+        // Report the same deficit every time
+        let deficit = balances
+            .into_iter()
+            .filter(|(_, balance)| balance.is_negative())
+            .min_by_key(|(token, _)| *token);
+        
+        match deficit {
+            Some((token, balance)) => Err(format!(
+                "Settlement pays out more {:?} than it receives (net {})",
+                token, balance
+            )),
+            None => Ok(()),
+        }
+    }
+    
     /// Estimates total gas cost
     pub fn estimate_gas(&self) -> u64 {
-        let base_gas = 21000u64;
-        let trade_gas = self.trades.len() as u64 * 50000;
-        let interaction_gas = self.interactions.len() as u64 * 100000;
-        let post_hook_gas = self.post_hooks.len() as u64 * 150000;
+        let trade_gas = self.trades.len() as u64 * Self::TRADE_GAS;
+        let interaction_gas: u64 = self.interactions.iter().map(Interaction::estimate_gas).sum();
+        let post_hook_gas = self.post_hooks.len() as u64 * Self::POST_HOOK_GAS;
         
-        base_gas + trade_gas + interaction_gas + post_hook_gas
+        Self::BASE_GAS + trade_gas + interaction_gas + post_hook_gas
     }
 }
 
@@ -163,6 +1044,76 @@ mod tests {
         assert_eq!(settlement.interactions.len(), 0);
     }
     
+    #[test]
+    fn test_approval_encoding_and_dedup() {
+        let token = Address::from_low_u64_be(1);
+        let spender = Address::from_low_u64_be(2);
+        
+        let approval = Interaction::approval(token, spender, U256::from(1000));
+        assert_eq!(approval.target, token);
+        assert_eq!(approval.interaction_type, InteractionType::Approval);
+        assert_eq!(&approval.call_data[..4], &[0x09, 0x5e, 0xa7, 0xb3]);
+        assert_eq!(U256::from_big_endian(&approval.call_data[36..68]), U256::from(1000));
+        assert!(approval.approves(token, spender));
+        assert!(!approval.approves(spender, token));
+        
+        let mut settlement = Settlement::new();
+        assert!(settlement.ensure_approval(token, spender, U256::MAX));
+        assert!(!settlement.ensure_approval(token, spender, U256::MAX));
+        assert!(settlement.ensure_approval(token, Address::from_low_u64_be(3), U256::MAX));
+        assert_eq!(settlement.interactions.len(), 2);
+    }
+    
+    fn priced_trade(order: u8, sell_token: u64, buy_token: u64) -> SettlementPlan {
+        let mut plan = Settlement::new();
+        plan.add_trade(Trade {
+            order_id: OrderId([order; 32]),
+            sell_token: Address::from_low_u64_be(sell_token),
+            buy_token: Address::from_low_u64_be(buy_token),
+            executed_sell_amount: U256::from(1000),
+            executed_buy_amount: U256::from(2000),
+            fee: U256::zero(),
+        });
+        plan.set_clearing_price(Address::from_low_u64_be(sell_token), U256::from(2000));
+        plan.set_clearing_price(Address::from_low_u64_be(buy_token), U256::from(1000));
+        plan
+    }
+    
+    #[test]
+    fn test_merge_combines_plans() {
+        let mut cow = priced_trade(1, 1, 2);
+        let mut routed = priced_trade(2, 1, 2);
+        routed.add_interaction(Interaction::approval(
+            Address::from_low_u64_be(1),
+            Address::from_low_u64_be(9),
+            U256::MAX,
+        ));
+        routed.set_clearing_price(Address::from_low_u64_be(3), U256::from(500));
+        
+        cow.merge(routed).unwrap();
+        assert_eq!(cow.trades.len(), 2);
+        assert_eq!(cow.interactions.len(), 1);
+        assert_eq!(cow.clearing_prices.len(), 3);
+        assert_eq!(cow.clearing_prices[&Address::from_low_u64_be(3)], U256::from(500));
+    }
+    
+    #[test]
+    fn test_merge_rejects_conflicts() {
+        let mut plan = priced_trade(1, 1, 2);
+        
+        let mut repriced = priced_trade(2, 1, 2);
+        repriced.set_clearing_price(Address::from_low_u64_be(2), U256::from(1001));
+        let err = plan.merge(repriced).unwrap_err();
+        assert!(err.contains("Conflicting clearing prices"));
+        
+        let err = plan.merge(priced_trade(1, 1, 2)).unwrap_err();
+        assert!(err.contains("settled by both plans"));
+        
+        // Failed merges leave the plan untouched
+        assert_eq!(plan.trades.len(), 1);
+        assert_eq!(plan.clearing_prices[&Address::from_low_u64_be(2)], U256::from(1000));
+    }
+    
     #[test]
     fn test_gas_estimation() {
         let mut settlement = Settlement::new();
@@ -170,6 +1121,8 @@ mod tests {
         
         settlement.add_trade(Trade {
             order_id: OrderId([0u8; 32]),
+            sell_token: Address::from_low_u64_be(1),
+            buy_token: Address::from_low_u64_be(2),
             executed_sell_amount: U256::from(1000),
             executed_buy_amount: U256::from(2000),
             fee: U256::from(10),
@@ -177,4 +1130,550 @@ mod tests {
         
         assert!(settlement.estimate_gas() > base_gas);
     }
+    
+    /// Encodes `deposit(address recipient, uint256 amount, uint256 chainId)`
+    struct MockBridge;
+    
+    impl BridgeProvider for MockBridge {
+        fn name(&self) -> &str {
+            "Mock"
+        }
+        
+        fn estimate_fee(&self, _: Address, _: U256, _: ChainId, _: ChainId) -> U256 {
+            U256::zero()
+        }
+        
+        fn estimate_time(&self, _: ChainId, _: ChainId) -> std::time::Duration {
+            std::time::Duration::from_secs(60)
+        }
+        
+        fn encode_deposit(
+            &self,
+            _token: Address,
+            amount: U256,
+            recipient: Address,
+            _source: ChainId,
+            destination: ChainId,
+        ) -> Bytes {
+            let mut call_data = ethers::utils::id("deposit(address,uint256,uint256)").to_vec();
+            call_data.extend(ethers::abi::encode(&[
+                ethers::abi::Token::Address(recipient),
+                ethers::abi::Token::Uint(amount),
+                ethers::abi::Token::Uint(U256::from(destination.as_u64())),
+            ]));
+            call_data.into()
+        }
+    }
+    
+    #[test]
+    fn test_price_consistency() {
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let token_c = Address::from_low_u64_be(3);
+        
+        let mut settlement = SettlementPlan::new();
+        settlement.set_clearing_price(token_a, U256::exp10(18));
+        settlement.set_clearing_price(token_b, U256::exp10(18) * 2000);
+        settlement.set_clearing_price(token_c, U256::exp10(6));
+        assert!(settlement.check_price_consistency(1).is_ok());
+        
+        // Converting into B truncates to nothing, so the cycle loses everything
+        settlement.set_clearing_price(token_a, U256::one());
+        settlement.set_clearing_price(token_b, U256::exp10(30));
+        let err = settlement.check_price_consistency(1).unwrap_err();
+        assert!(err.contains("Inconsistent prices"));
+    }
+    
+    #[test]
+    fn test_trade_cycle_arbitrage() {
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let token_c = Address::from_low_u64_be(3);
+        let trade = |id: u8, sell_token: Address, buy_token: Address, sell: u64, buy: u64| Trade {
+            order_id: OrderId([id; 32]),
+            sell_token,
+            buy_token,
+            executed_sell_amount: U256::from(sell),
+            executed_buy_amount: U256::from(buy),
+            fee: U256::zero(),
+        };
+        
+        // Consistent prices, and trades at 2 B per A, 3 C per B, 1/6 A per C
+        let mut settlement = SettlementPlan::new();
+        settlement.set_clearing_price(token_a, U256::from(6));
+        settlement.set_clearing_price(token_b, U256::from(3));
+        settlement.set_clearing_price(token_c, U256::from(1));
+        settlement.add_trade(trade(1, token_a, token_b, 1000, 2000));
+        settlement.add_trade(trade(2, token_b, token_c, 1000, 3000));
+        settlement.add_trade(trade(3, token_c, token_a, 6000, 1000));
+        assert!(settlement.check_price_consistency(1).is_ok());
+        
+        // A C seller getting more A than the prices give closes a profitable loop
+        settlement.add_trade(trade(4, token_c, token_a, 6000, 1100));
+        let err = settlement.check_price_consistency(1).unwrap_err();
+        assert!(err.contains("Trades admit arbitrage"));
+        
+        // So does a B seller getting more A directly
+        settlement.trades.pop();
+        settlement.add_trade(trade(5, token_b, token_a, 2000, 1100));
+        let err = settlement.check_price_consistency(1).unwrap_err();
+        assert!(err.contains("Trades admit arbitrage"));
+    }
+    
+    fn order(id: u8, sell_token: Address, buy_token: Address, sell_amount: U256, buy_amount: U256) -> Order {
+        Order {
+            id: OrderId([id; 32]),
+            owner: Address::from_low_u64_be(id as u64),
+            sell_token,
+            buy_token,
+            sell_amount,
+            buy_amount,
+            valid_to: u32::MAX,
+            fee_amount: U256::zero(),
+            kind: crate::domain::OrderType::Sell,
+            partially_fillable: false,
+            status: crate::domain::OrderStatus::Open,
+            source_chain: None,
+            destination_chain: None,
+            bridge_provider: None,
+            filled_amount: U256::zero(),
+            signature: vec![],
+        }
+    }
+    
+    #[test]
+    fn test_check_balances() {
+        let token_x = Address::from_low_u64_be(1);
+        let token_y = Address::from_low_u64_be(2);
+        let e18 = U256::exp10(18);
+        
+        // A sells 1 X for 2 Y, B sells 2 Y for 1 X
+        let orders = [
+            order(1, token_x, token_y, e18, e18 * 2),
+            order(2, token_y, token_x, e18 * 2, e18),
+        ];
+        let trade = |order: &Order, sell: U256, buy: U256| Trade {
+            order_id: order.id,
+            sell_token: order.sell_token,
+            buy_token: order.buy_token,
+            executed_sell_amount: sell,
+            executed_buy_amount: buy,
+            fee: U256::zero(),
+        };
+        
+        let mut balanced = SettlementPlan::new();
+        balanced.add_trade(trade(&orders[0], e18, e18 * 2));
+        balanced.add_trade(trade(&orders[1], e18 * 2, e18));
+        assert!(balanced.check_balances(&orders).is_ok());
+        
+        // A is paid more Y than B put in
+        let mut printing = SettlementPlan::new();
+        printing.add_trade(trade(&orders[0], e18, e18 * 3));
+        printing.add_trade(trade(&orders[1], e18 * 2, e18));
+        let err = printing.check_balances(&orders).unwrap_err();
+        assert!(err.contains("pays out more"));
+        
+        // B gives up more Y than its limit allows
+        let mut mispriced = SettlementPlan::new();
+        mispriced.add_trade(trade(&orders[0], e18, e18 * 2));
+        mispriced.add_trade(trade(&orders[1], e18 * 3, e18));
+        let err = mispriced.check_balances(&orders).unwrap_err();
+        assert!(err.contains("below its limit price"));
+        
+        assert!(balanced.check_balances(&orders[..1]).unwrap_err().contains("unknown order"));
+    }
+    
+    #[test]
+    fn test_validate_with_orders_enforces_minimum_received() {
+        let token_x = Address::from_low_u64_be(1);
+        let token_y = Address::from_low_u64_be(2);
+        let e18 = U256::exp10(18);
+        
+        let sell = order(1, token_x, token_y, e18, e18 * 2);
+        let mut buy = order(2, token_y, token_x, e18 * 3, e18);
+        buy.kind = OrderType::Buy;
+        let orders = [sell.clone(), buy.clone()];
+        let settle = |sell_buy: U256, buy_sell: U256| {
+            let mut settlement = SettlementPlan::new();
+            settlement.add_trade(Trade {
+                order_id: sell.id,
+                sell_token: token_x,
+                buy_token: token_y,
+                executed_sell_amount: e18,
+                executed_buy_amount: sell_buy,
+                fee: U256::zero(),
+            });
+            settlement.add_trade(Trade {
+                order_id: buy.id,
+                sell_token: token_y,
+                buy_token: token_x,
+                executed_sell_amount: buy_sell,
+                executed_buy_amount: e18,
+                fee: U256::zero(),
+            });
+            settlement
+        };
+        
+        assert!(settle(e18 * 2, e18 * 3).validate_with_orders(&orders).is_ok());
+        
+        let err = settle(e18 * 2 - 1, e18 * 2).validate_with_orders(&orders).unwrap_err();
+        assert!(err.contains("must deliver at least"));
+        
+        let err = settle(e18 * 2, e18 * 3 + 1).validate_with_orders(&orders).unwrap_err();
+        assert!(err.contains("may charge at most"));
+        
+        // Half-filling a partially fillable sell order halves its minimum
+        let mut partial = sell.clone();
+        partial.partially_fillable = true;
+        let mut settlement = SettlementPlan::new();
+        settlement.add_trade(Trade {
+            order_id: partial.id,
+            sell_token: token_x,
+            buy_token: token_y,
+            executed_sell_amount: e18 / 2,
+            executed_buy_amount: e18,
+            fee: U256::zero(),
+        });
+        assert!(settlement.validate_with_orders(&[partial.clone()]).is_ok());
+        settlement.trades[0].executed_buy_amount -= U256::one();
+        assert!(settlement.validate_with_orders(&[partial]).is_err());
+    }
+    
+    #[test]
+    fn test_dedupe_interactions() {
+        let token = Address::from_low_u64_be(1);
+        let other = Address::from_low_u64_be(2);
+        let spender = Address::from_low_u64_be(9);
+        let swap = |amount: u64| Interaction {
+            target: spender,
+            call_data: Bytes::from(vec![1, 2, 3]),
+            value: U256::zero(),
+            interaction_type: InteractionType::UniswapV2Swap,
+            flow: Some(TokenFlow {
+                token_in: token,
+                amount_in: U256::from(amount),
+                token_out: other,
+                amount_out: U256::from(amount),
+            }),
+            chain: None,
+            allow_failure: false,
+        };
+        
+        let mut settlement = SettlementPlan::new();
+        settlement.add_interaction(Interaction::approval(token, spender, U256::from(100)));
+        settlement.add_interaction(swap(100));
+        settlement.add_interaction(Interaction::approval(token, spender, U256::from(500)));
+        settlement.add_interaction(swap(100));
+        settlement.add_interaction(swap(400));
+        // Same approval on another chain is a separate call
+        let mut bridged = Interaction::approval(token, spender, U256::from(100));
+        bridged.chain = Some(ChainId::Arbitrum);
+        settlement.add_interaction(bridged);
+        
+        assert_eq!(settlement.dedupe_interactions(), 1);
+        assert_eq!(settlement.interactions.len(), 5);
+        
+        // One approval, first and raised to the largest amount
+        let approval = &settlement.interactions[0];
+        assert!(approval.approves(token, spender));
+        assert_eq!(U256::from_big_endian(&approval.call_data[36..68]), U256::from(500));
+        
+        // Every swap is kept, identical ones included
+        let swaps: Vec<_> = settlement.interactions[1..4].iter().map(|i| i.flow.clone()).collect();
+        assert_eq!(swaps, vec![swap(100).flow, swap(100).flow, swap(400).flow]);
+        assert_eq!(settlement.interactions[4].chain, Some(ChainId::Arbitrum));
+        
+        assert_eq!(settlement.dedupe_interactions(), 0);
+    }
+    
+    #[test]
+    fn test_flash_loan_must_be_repaid() {
+        let lender = Address::from_low_u64_be(7);
+        let token = Address::from_low_u64_be(1);
+        let e18 = U256::exp10(18);
+        let trade = Trade {
+            order_id: OrderId([1; 32]),
+            sell_token: token,
+            buy_token: Address::from_low_u64_be(2),
+            executed_sell_amount: e18,
+            executed_buy_amount: e18,
+            fee: U256::zero(),
+        };
+        
+        let mut settlement = SettlementPlan::new();
+        settlement.add_trade(trade);
+        settlement.add_interaction(Interaction::flash_loan(lender, token, e18));
+        assert!(settlement.validate().unwrap_err().contains("not repaid"));
+        
+        // Repaying part of the loan isn't enough
+        let mut partial = settlement.clone();
+        partial.add_interaction(Interaction::flash_loan_repayment(lender, token, e18 / 2));
+        assert!(partial.validate().is_err());
+        
+        // A repayment including the loan fee settles it
+        settlement.add_interaction(Interaction::flash_loan_repayment(lender, token, e18 + e18 / 1000));
+        assert!(settlement.validate().is_ok());
+        assert_eq!(
+            settlement.estimate_gas(),
+            SettlementPlan::BASE_GAS
+                + SettlementPlan::TRADE_GAS
+                + SettlementPlan::FLASH_LOAN_GAS
+                + SettlementPlan::TRANSFER_GAS
+        );
+        
+        // Repaying before borrowing doesn't count
+        settlement.interactions.reverse();
+        assert!(settlement.validate().unwrap_err().contains("without a prior flash loan"));
+    }
+    
+    #[test]
+    fn test_native_sell_is_wrapped() {
+        let eth = Address::from(NATIVE_TOKEN);
+        let weth = ChainId::Ethereum.wrapped_native_token().unwrap();
+        let usdc = Address::from_low_u64_be(2);
+        let e18 = U256::exp10(18);
+        
+        let sell_eth = order(1, eth, usdc, e18, e18 * 2000);
+        let mut settlement = SettlementPlan::new();
+        settlement.add_interaction(Interaction::approval(weth, Address::from_low_u64_be(9), e18));
+        settlement.add_trade(Trade {
+            order_id: sell_eth.id,
+            sell_token: eth,
+            buy_token: usdc,
+            executed_sell_amount: e18,
+            executed_buy_amount: e18 * 2000,
+            fee: U256::zero(),
+        });
+        
+        assert!(settlement.wrap_native_legs(weth));
+        let wrap = &settlement.interactions[0];
+        assert_eq!(wrap.target, weth);
+        assert_eq!(wrap.value, e18);
+        assert_eq!(wrap.interaction_type, InteractionType::WrapNative);
+        assert_eq!(wrap.call_data.to_vec(), ethers::utils::id("deposit()").to_vec());
+        assert_eq!(wrap.flow.as_ref().unwrap().token_out, weth);
+        
+        // Buying the same amount back nets out, so nothing needs wrapping
+        let mut netted = SettlementPlan::new();
+        netted.add_trade(settlement.trades[0].clone());
+        netted.add_trade(Trade {
+            order_id: OrderId([2; 32]),
+            sell_token: usdc,
+            buy_token: eth,
+            executed_sell_amount: e18 * 2000,
+            executed_buy_amount: e18,
+            fee: U256::zero(),
+        });
+        assert!(!netted.wrap_native_legs(weth));
+        assert!(netted.interactions.is_empty());
+    }
+    
+    #[test]
+    fn test_solver_inventory_captures_spread() {
+        let token_x = Address::from_low_u64_be(1);
+        let token_y = Address::from_low_u64_be(2);
+        let e18 = U256::exp10(18);
+        
+        // User sells 1 X for 2 Y; solver swaps the X on an AMM for 2.01 Y
+        let mut settlement = SettlementPlan::new();
+        settlement.add_trade(Trade {
+            order_id: OrderId([1u8; 32]),
+            sell_token: token_x,
+            buy_token: token_y,
+            executed_sell_amount: e18,
+            executed_buy_amount: e18 * 2,
+            fee: U256::zero(),
+        });
+        settlement.add_interaction(Interaction {
+            target: Address::from_low_u64_be(0x100),
+            call_data: Bytes::default(),
+            value: U256::zero(),
+            interaction_type: InteractionType::UniswapV2Swap,
+            flow: Some(TokenFlow {
+                token_in: token_x,
+                amount_in: e18,
+                token_out: token_y,
+                amount_out: e18 * 201 / 100,
+            }),
+            chain: None,
+            allow_failure: false,
+        });
+        
+        let delta = settlement.solver_inventory_delta();
+        assert_eq!(delta.len(), 1);
+        assert_eq!(delta[&token_y], I256::try_from(e18 / 100).unwrap());
+    }
+    
+    #[test]
+    fn test_post_hook_call_data_slots() {
+        let recipient = Address::from_low_u64_be(0xbeef);
+        let amount = U256::from(5_000_000u64);
+        
+        let hook = PostHook::build(
+            &MockBridge,
+            Address::from_low_u64_be(0xb1),
+            ChainId::Ethereum,
+            ChainId::Arbitrum,
+            Address::from_low_u64_be(0xaa),
+            amount,
+            recipient,
+        )
+        .unwrap();
+        
+        let data = &hook.call_data;
+        assert_eq!(data.len(), 4 + 3 * 32);
+        // Addresses are left-padded to 32 bytes
+        assert!(data[4..16].iter().all(|b| *b == 0));
+        assert_eq!(&data[16..36], recipient.as_bytes());
+        assert_eq!(U256::from_big_endian(&data[36..68]), amount);
+        assert_eq!(U256::from_big_endian(&data[68..100]), U256::from(42161u64));
+    }
+    
+    #[test]
+    fn test_post_hook_rejects_same_chain_and_zero_amount() {
+        let build = |destination, amount| {
+            PostHook::build(
+                &MockBridge,
+                Address::zero(),
+                ChainId::Ethereum,
+                destination,
+                Address::zero(),
+                amount,
+                Address::zero(),
+            )
+        };
+        
+        assert!(build(ChainId::Ethereum, U256::from(1)).is_err());
+        assert!(build(ChainId::Arbitrum, U256::zero()).is_err());
+        assert!(build(ChainId::Solana, U256::from(1)).is_err());
+        assert!(build(ChainId::Arbitrum, U256::from(1)).is_ok());
+    }
+    
+    #[test]
+    fn test_optional_interaction_wrapped_in_multicall() {
+        let claim = Interaction {
+            target: Address::from_low_u64_be(0x200),
+            call_data: Bytes::from(vec![0xde, 0xad, 0xbe, 0xef]),
+            value: U256::zero(),
+            interaction_type: InteractionType::Custom,
+            flow: None,
+            chain: None,
+            allow_failure: false,
+        };
+        
+        let (target, _, call_data) = claim.to_call();
+        assert_eq!(target, claim.target);
+        assert_eq!(call_data, claim.call_data);
+        
+        let optional = claim.clone().allowing_failure();
+        let (target, _, call_data) = optional.to_call();
+        assert_eq!(target, Address::from(MULTICALL3));
+        assert_eq!(&call_data[..4], &ethers::utils::id("aggregate3Value((address,bool,uint256,bytes)[])"));
+        
+        let plan = |interaction| {
+            let mut settlement = SettlementPlan::new();
+            settlement.add_trade(Trade {
+                order_id: OrderId([1u8; 32]),
+                sell_token: Address::from_low_u64_be(0x10),
+                buy_token: Address::from_low_u64_be(0x11),
+                executed_sell_amount: U256::one(),
+                executed_buy_amount: U256::one(),
+                fee: U256::zero(),
+            });
+            settlement.add_interaction(interaction);
+            settlement
+        };
+        let required_plan = plan(claim);
+        let optional_plan = plan(optional);
+        assert!(optional_plan.validate().is_ok());
+        assert_ne!(required_plan.encode_interactions(), optional_plan.encode_interactions());
+    }
+    
+    #[test]
+    fn test_revert_critical_interaction_cannot_be_optional() {
+        let token = Address::from_low_u64_be(0x10);
+        let trade = Trade {
+            order_id: OrderId([1u8; 32]),
+            sell_token: token,
+            buy_token: Address::from_low_u64_be(0x11),
+            executed_sell_amount: U256::one(),
+            executed_buy_amount: U256::one(),
+            fee: U256::zero(),
+        };
+        let mut settlement = SettlementPlan::new();
+        settlement.add_trade(trade.clone());
+        settlement.add_interaction(Interaction::approval(token, Address::from_low_u64_be(0x20), U256::MAX).allowing_failure());
+        assert!(settlement.validate().is_err());
+        
+        // A custom call moving tokens backs a trade just like a swap
+        let mut settlement = SettlementPlan::new();
+        settlement.add_trade(trade);
+        settlement.add_interaction(Interaction {
+            target: Address::from_low_u64_be(0x100),
+            call_data: Bytes::default(),
+            value: U256::zero(),
+            interaction_type: InteractionType::Custom,
+            flow: Some(TokenFlow {
+                token_in: token,
+                amount_in: U256::one(),
+                token_out: Address::from_low_u64_be(0x11),
+                amount_out: U256::one(),
+            }),
+            chain: None,
+            allow_failure: true,
+        });
+        assert!(settlement.validate().is_err());
+    }
+    
+    #[test]
+    fn test_trade_price_reconciled_with_existing_prices() {
+        let token_x = Address::from_low_u64_be(1);
+        let token_y = Address::from_low_u64_be(2);
+        let token_z = Address::from_low_u64_be(3);
+        let amount = |n: u64| U256::from(n);
+        
+        // First trade prices both tokens: 10 X for 20 Y
+        let mut settlement = SettlementPlan::new();
+        assert_eq!(settlement.reconcile_trade_price(token_x, token_y, amount(10), amount(20), amount(20)), Some(amount(20)));
+        
+        // Another X -> Y trade executes at the same rate, keeping any extra
+        assert_eq!(settlement.reconcile_trade_price(token_x, token_y, amount(5), amount(9), amount(12)), Some(amount(10)));
+        // A rate below the limit or above what's available is rejected
+        assert_eq!(settlement.reconcile_trade_price(token_x, token_y, amount(5), amount(11), amount(12)), None);
+        assert_eq!(settlement.reconcile_trade_price(token_x, token_y, amount(5), amount(9), amount(9)), None);
+        
+        // A new token is priced against the existing one
+        assert_eq!(settlement.reconcile_trade_price(token_y, token_z, amount(20), amount(5), amount(5)), Some(amount(5)));
+        assert_eq!(settlement.clearing_prices[&token_z], amount(40));
+        assert_eq!(settlement.clearing_prices[&token_x], amount(20));
+    }
+    
+    #[test]
+    fn test_retain_trades_drops_unused_prices() {
+        let token_x = Address::from_low_u64_be(1);
+        let token_y = Address::from_low_u64_be(2);
+        let token_z = Address::from_low_u64_be(3);
+        let trade = |id: u8, sell_token: Address, buy_token: Address| Trade {
+            order_id: OrderId([id; 32]),
+            sell_token,
+            buy_token,
+            executed_sell_amount: U256::one(),
+            executed_buy_amount: U256::one(),
+            fee: U256::zero(),
+        };
+        
+        let mut settlement = SettlementPlan::new();
+        settlement.add_trade(trade(1, token_x, token_y));
+        settlement.add_trade(trade(2, token_y, token_z));
+        for token in [token_x, token_y, token_z] {
+            settlement.set_clearing_price(token, U256::one());
+        }
+        
+        // Only the Y -> Z trade remains, so X is no longer priced
+        settlement.retain_trades(|t| t.order_id != OrderId([1; 32]));
+        assert_eq!(settlement.trades.len(), 1);
+        assert!(!settlement.clearing_prices.contains_key(&token_x));
+        assert!(settlement.clearing_prices.contains_key(&token_y));
+        assert!(settlement.clearing_prices.contains_key(&token_z));
+    }
 }
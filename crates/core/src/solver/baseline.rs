@@ -0,0 +1,144 @@
+use super::scoring::{ScoringPolicy, SurplusMinusGasPolicy};
+use super::{settlement_chain, AuctionContext, RoutingEngine, Solver, SolverConfig, Solution};
+use crate::domain::{Order, OrderStatus, TokenRegistry};
+use crate::settlement::SettlementPlan;
+use async_trait::async_trait;
+use tracing::{debug, info, warn};
+
+/// AMM-only solver that routes every order independently.
+///
+/// Ignores CoW matching entirely, so it serves as a benchmark for how much
+/// surplus matching adds on top of plain routing.
+pub struct BaselineSolver {
+    config: SolverConfig,
+    routing: RoutingEngine,
+    tokens: TokenRegistry,
+}
+
+impl BaselineSolver {
+    /// Creates a baseline solver routing through the given pools, with
+    /// minimum outputs bounded by the config's `max_slippage`
+    pub fn new(config: SolverConfig, routing: RoutingEngine) -> Self {
+        let routing = routing.with_max_slippage(config.max_slippage);
+        Self {
+            config,
+            routing,
+            tokens: TokenRegistry::default(),
+        }
+    }
+
+    /// Sets the token decimals surplus is scaled by; unregistered tokens
+    /// are assumed to have 18
+    pub fn with_token_registry(mut self, tokens: TokenRegistry) -> Self {
+        self.tokens = tokens;
+        self
+    }
+
+    /// Returns the routing engine used for fills
+    pub fn routing(&self) -> &RoutingEngine {
+        &self.routing
+    }
+
+    /// Checks if an order can be filled in this batch
+    fn is_fillable(order: &Order, now: u32) -> bool {
+        if order.status != OrderStatus::Open {
+            debug!("Skipping non-open order: {:?}", order.id);
+            return false;
+        }
+
+        if order.is_expired(now) {
+            debug!("Skipping expired order: {:?}", order.id);
+            return false;
+        }
+
+        if let Err(e) = order.validate() {
+            warn!("Skipping invalid order {:?}: {}", order.id, e);
+            return false;
+        }
+
+        true
+    }
+}
+
+#[async_trait]
+impl Solver for BaselineSolver {
+    async fn solve(&self, orders: Vec<Order>, ctx: &AuctionContext) -> crate::Result<Option<Solution>> {
+        info!("Starting baseline solver with {} orders", orders.len());
+
+        let now = ctx.now();
+
+        let mut settlement = SettlementPlan::default();
+        let mut order_surplus = Vec::new();
+        let mut dropped_orders = Vec::new();
+
+        for order in orders.iter().filter(|o| Self::is_fillable(o, now)) {
+            if ctx.cancellation.is_cancelled() {
+                info!("Baseline solve cancelled, leaving remaining orders unrouted");
+                break;
+            }
+            if self.routing.fill_order(order, &mut settlement).is_none() {
+                dropped_orders.push(order.id);
+                continue;
+            }
+
+            // The trade may execute below the route output at prices set by
+            // earlier fills
+            let executed_buy = settlement.trades.last().map_or(order.buy_amount, |t| t.executed_buy_amount);
+            let surplus = self.tokens.to_units(order.buy_token, executed_buy - order.buy_amount);
+            order_surplus.push((order.id, surplus));
+        }
+
+        if !ctx.cancellation.is_cancelled() {
+            self.config.unroutable_policy.apply(&dropped_orders)?;
+        }
+
+        if settlement.trades.is_empty() {
+            info!("No routable orders");
+            return Ok(None);
+        }
+
+        let gas_cost = settlement.estimate_gas();
+        let surplus = order_surplus.iter().map(|(_, surplus)| surplus).sum();
+
+        let mut solution = Solution {
+            orders: settlement.trades.iter().map(|t| t.order_id).collect(),
+            settlement,
+            gas_cost,
+            surplus,
+            surplus_token: None,
+            order_surplus,
+            dropped_orders,
+            gas_excluded_orders: vec![],
+            overflow_orders: vec![],
+            chain: settlement_chain(&orders),
+            valid_for_block: ctx.block_number,
+            score: 0.0,
+        };
+        solution.score = SurplusMinusGasPolicy.score(&solution, ctx);
+
+        if !solution.is_profitable(self.config.min_profit_threshold) {
+            warn!(
+                "Baseline solution not profitable: score={}, threshold={}",
+                solution.score, self.config.min_profit_threshold
+            );
+            return Ok(None);
+        }
+
+        info!(
+            "Baseline solution: {} orders, surplus={:.4}, score={:.4}",
+            solution.orders.len(),
+            solution.surplus,
+            solution.score
+        );
+
+        Ok(Some(solution))
+    }
+
+    fn name(&self) -> &str {
+        "Baseline"
+    }
+
+    fn config(&self) -> &SolverConfig {
+        &self.config
+    }
+}
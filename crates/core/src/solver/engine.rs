@@ -1,14 +1,176 @@
-use super::{Solver, SolverConfig, Solution, AuctionContext};
-use crate::domain::{Order, OrderStatus};
-use crate::settlement::SettlementPlan;
+use super::{settlement_chain, Solver, SolverConfig, Solution, AuctionContext, SurplusDistribution};
+use super::scoring::{ScoringPolicy, SurplusMinusGasPolicy};
+use super::liquidity::{build_routing_engine, LiquiditySource};
+use super::{PricingEngine, RoutingEngine, RoutingError};
+use crate::bridge::BridgeRegistry;
+use crate::domain::orders::SETTLEMENT_CONTRACT;
+use crate::domain::{domain_separator, ChainId, Order, OrderBook, OrderId, OrderStatus, OrderType, TokenRegistry};
+use crate::math::u256_to_f64;
+use crate::settlement::{Interaction, InternalTransfer, PostHook, SettlementPlan, Trade};
 use async_trait::async_trait;
+use ethers::types::{Address, U256, U512};
+use futures::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tracing::{debug, info, warn};
 
+/// Maximum round-trip drift allowed across clearing prices (in basis points)
+const PRICE_CONSISTENCY_TOLERANCE_BPS: u32 = 1;
+
+/// Orders prepared for settlement, shared across solve stages
+struct PreparedBatch {
+    /// Orders that passed validation and deduplication, up to
+    /// `max_batch_size`
+    valid_orders: Vec<Order>,
+
+    /// Valid orders past `max_batch_size`, left out of the batch
+    overflow_orders: Vec<OrderId>,
+
+    /// Valid orders left after netting
+    matchable_orders: Vec<Order>,
+
+    /// Netted self-offsetting pairs
+    internal_transfers: Vec<InternalTransfer>,
+
+    /// CoW matches as indexes into `matchable_orders`
+    matches: Vec<(usize, usize)>,
+}
+
+/// Progress of a streaming solve
+struct StreamState {
+    /// Orders not yet prepared
+    orders: Option<Vec<Order>>,
+
+    /// Prepared batch, once available
+    batch: Option<PreparedBatch>,
+
+    /// Next stage to evaluate
+    stage: usize,
+
+    /// Best score yielded so far
+    best_score: f64,
+
+    /// Auction the orders are solved in
+    ctx: AuctionContext,
+}
+
+/// Why a solve produced no solution
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SolveRejectReason {
+    /// No order survived validation
+    NoValidOrders,
+
+    /// Valid orders exist but none could be matched or routed
+    NothingSettled,
+
+    /// The solution's score is below `min_profit_threshold`
+    NotProfitable,
+}
+
+/// What settling a set of matches would produce, computed without
+/// building the final `SettlementPlan`
+#[derive(Debug, Clone, Default)]
+pub struct SettlementPreview {
+    /// Trades the matches would execute
+    pub trades: Vec<Trade>,
+
+    /// Interactions the trades need; direct CoW matches need none
+    pub interactions: Vec<Interaction>,
+
+    /// Uniform clearing prices the trades execute at
+    pub clearing_prices: HashMap<Address, U256>,
+
+    /// Matches left out for conflicting with earlier clearing prices
+    pub skipped_matches: Vec<(usize, usize)>,
+
+    /// Gas the settlement would cost
+    pub estimated_gas: u64,
+
+    /// Surplus the trades would give their orders
+    pub surplus: f64,
+}
+
+impl SettlementPreview {
+    /// Builds the settlement plan this preview describes
+    pub fn into_plan(self) -> SettlementPlan {
+        SettlementPlan {
+            trades: self.trades,
+            interactions: self.interactions,
+            clearing_prices: self.clearing_prices,
+            ..SettlementPlan::default()
+        }
+    }
+}
+
+/// Counts and outcome of each stage of a solve, for debugging
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SolveTrace {
+    /// Orders left after validation and deduplication
+    pub valid_orders: usize,
+
+    /// Valid orders left out for exceeding `max_batch_size`
+    #[serde(default)]
+    pub overflow_orders: usize,
+
+    /// CoW matches found
+    pub matches_found: usize,
+
+    /// Matches still settled after the inclusion gate and gas budget
+    pub matches_selected: usize,
+
+    /// Surplus of the candidate solution
+    pub surplus: f64,
+
+    /// Score of the candidate solution
+    pub score: f64,
+
+    /// Why no solution was returned, if none was
+    pub reject_reason: Option<SolveRejectReason>,
+
+    /// Whether the solve was cancelled before it finished
+    #[serde(default)]
+    pub cancelled: bool,
+}
+
+/// Why an order was left out of a batch before solving
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RejectReason {
+    /// Order is cancelled, filled or otherwise not open
+    NotOpen,
+
+    /// Order's `valid_to` has passed
+    Expired,
+
+    /// Sell or buy amount is zero
+    ZeroAmount,
+
+    /// Partially filled order with nothing left to fill
+    FullyFilled,
+
+    /// Order sells and buys the same token
+    SameToken,
+
+    /// Order's signature doesn't recover to its owner
+    InvalidSignature,
+}
+
 /// Main solver engine implementing batch auction logic
 pub struct SolverEngine {
     config: SolverConfig,
     name: String,
+    bridges: BridgeRegistry,
+    /// Chain -> token bridged out of / into that chain for cross-chain orders
+    bridge_tokens: HashMap<ChainId, Address>,
+    /// (provider name, source chain) -> bridge deposit contract
+    bridge_contracts: HashMap<(String, ChainId), Address>,
+    liquidity_sources: Vec<Arc<dyn LiquiditySource>>,
+    scoring: Box<dyn ScoringPolicy>,
+    /// Token decimals for valuing raw amounts
+    tokens: TokenRegistry,
+    /// Oracle CoW fills are compared with AMM quotes against, if any
+    reference_oracle: Option<PricingEngine>,
 }
 
 impl SolverEngine {
@@ -17,43 +179,200 @@ impl SolverEngine {
         Self {
             config,
             name: "CoWSolverEngine".to_string(),
+            bridges: BridgeRegistry::with_defaults(),
+            bridge_tokens: HashMap::new(),
+            bridge_contracts: HashMap::new(),
+            liquidity_sources: Vec::new(),
+            scoring: Box::new(SurplusMinusGasPolicy),
+            tokens: TokenRegistry::default(),
+            reference_oracle: None,
+        }
+    }
+
+    /// Sets the token decimals surplus and bridge costs are scaled by;
+    /// unregistered tokens are assumed to have 18
+    pub fn with_token_registry(mut self, tokens: TokenRegistry) -> Self {
+        self.tokens = tokens;
+        self
+    }
+
+    /// Compares each CoW-matched order's price with what AMM routing would
+    /// give it, valued at `oracle`'s external prices, and drops matches
+    /// that leave either order worse off so both are routed instead
+    pub fn with_reference_oracle(mut self, oracle: PricingEngine) -> Self {
+        self.reference_oracle = Some(oracle);
+        self
+    }
+
+    /// Replaces the bridge providers used to cost cross-chain orders
+    pub fn with_bridge_registry(mut self, bridges: BridgeRegistry) -> Self {
+        self.bridges = bridges;
+        self
+    }
+
+    /// Sets the intermediate token cross-chain orders are bridged through on
+    /// `chain`; a chain without one can't be bridged to or from
+    pub fn with_bridge_token(mut self, chain: ChainId, token: Address) -> Self {
+        self.bridge_tokens.insert(chain, token);
+        self
+    }
+
+    /// Sets the deposit contract of bridge `provider` on `chain`
+    pub fn with_bridge_contract(mut self, provider: &str, chain: ChainId, contract: Address) -> Self {
+        self.bridge_contracts.insert((provider.to_string(), chain), contract);
+        self
+    }
+
+    /// Adds a source of AMM liquidity for routing orders matching can't fill
+    pub fn with_liquidity_source(mut self, source: Arc<dyn LiquiditySource>) -> Self {
+        self.liquidity_sources.push(source);
+        self
+    }
+
+    /// Replaces the objective used to score solutions
+    pub fn with_scoring_policy(mut self, scoring: Box<dyn ScoringPolicy>) -> Self {
+        self.scoring = scoring;
+        self
+    }
+
+    /// Validates and filters orders before solving at `now`
+    fn validate_orders(&self, orders: &[Order], now: u32) -> Vec<Order> {
+        self.validate_orders_detailed(orders, now).0
+    }
+
+    /// Validates orders at `now`, returning the valid ones and why each
+    /// other order was rejected
+    pub fn validate_orders_detailed(
+        &self,
+        orders: &[Order],
+        now: u32,
+    ) -> (Vec<Order>, Vec<(OrderId, RejectReason)>) {
+        let mut valid = Vec::new();
+        let mut rejected = Vec::new();
+
+        for order in orders {
+            match self.reject_reason(order, now) {
+                Some(reason) => {
+                    debug!("Skipping order {:?}: {:?}", order.id, reason);
+                    rejected.push((order.id, reason));
+                }
+                None => valid.push(order.clone()),
+            }
+        }
+
+        (valid, rejected)
+    }
+
+    /// Returns why an order can't be solved at `now`, if it can't
+    fn reject_reason(&self, order: &Order, now: u32) -> Option<RejectReason> {
+        if !matches!(order.status, OrderStatus::Open | OrderStatus::PartiallyFilled) {
+            return Some(RejectReason::NotOpen);
+        }
+
+        if order.is_expired(now) {
+            return Some(RejectReason::Expired);
+        }
+
+        if order.sell_amount.is_zero() || order.buy_amount.is_zero() {
+            return Some(RejectReason::ZeroAmount);
+        }
+
+        let (remaining_sell, remaining_buy) = order.remaining_amounts();
+        if remaining_sell.is_zero() || remaining_buy.is_zero() {
+            return Some(RejectReason::FullyFilled);
+        }
+
+        if order.sell_token == order.buy_token {
+            return Some(RejectReason::SameToken);
         }
+
+        if self.config.verify_signatures {
+            let chain = order.source_chain.unwrap_or(ChainId::Ethereum);
+            if !order.verify_signature(domain_separator(chain)) {
+                return Some(RejectReason::InvalidSignature);
+            }
+        }
+
+        None
     }
 
-    /// Validates and filters orders before solving
-    fn validate_orders(&self, orders: &[Order]) -> Vec<Order> {
+    /// Drops orders submitted more than once.
+    ///
+    /// Duplicates are identified by owner and EIP-712 digest rather than
+    /// `Order::id`, which may be a placeholder. The first submission is kept.
+    fn dedupe_orders(&self, orders: Vec<Order>) -> Vec<Order> {
+        let mut seen = HashSet::new();
+
         orders
-            .iter()
+            .into_iter()
             .filter(|order| {
-                // Filter out invalid or expired orders
-                if order.status != OrderStatus::Open {
-                    debug!("Skipping non-open order: {:?}", order.id);
-                    return false;
+                let digest = order.eip712_digest();
+                if seen.insert((order.owner, digest)) {
+                    true
+                } else {
+                    warn!("Dropping duplicate order {:?} (digest {:?})", order.id, digest);
+                    false
                 }
+            })
+            .collect()
+    }
 
-                // Check if order is expired
-                if let Some(valid_to) = order.valid_to {
-                    let now = std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs() as u32;
-                    
-                    if valid_to < now {
-                        debug!("Skipping expired order: {:?}", order.id);
-                        return false;
-                    }
+    /// Nets out pairs of same-owner orders that exactly offset each other
+    /// (A->B and B->A for the same amounts). Settling both on-chain would
+    /// only burn gas, so they become internal transfers instead.
+    ///
+    /// Partially filled orders are compared, and transferred, on the amounts
+    /// they have left to fill. Returns the remaining orders and the netted
+    /// pairs.
+    fn net_self_offsetting_orders(&self, orders: &[Order]) -> (Vec<Order>, Vec<InternalTransfer>) {
+        let mut netted = vec![false; orders.len()];
+        let mut transfers = Vec::new();
+
+        for (i, order_a) in orders.iter().enumerate() {
+            if netted[i] {
+                continue;
+            }
+            let (sell_a, buy_a) = order_a.remaining_amounts();
+
+            for (j, order_b) in orders.iter().enumerate().skip(i + 1) {
+                if netted[j] {
+                    continue;
                 }
+                let (sell_b, buy_b) = order_b.remaining_amounts();
 
-                // Validate amounts are non-zero
-                if order.sell_amount.is_zero() || order.buy_amount.is_zero() {
-                    warn!("Skipping order with zero amounts: {:?}", order.id);
-                    return false;
+                let offsets = order_a.owner == order_b.owner
+                    && order_a.sell_token == order_b.buy_token
+                    && order_a.buy_token == order_b.sell_token
+                    && !sell_a.is_zero()
+                    && sell_a == buy_b
+                    && buy_a == sell_b;
+
+                if offsets {
+                    debug!("Netting self-offsetting orders: {:?} <-> {:?}", order_a.id, order_b.id);
+                    netted[i] = true;
+                    netted[j] = true;
+                    transfers.push(InternalTransfer {
+                        owner: order_a.owner,
+                        order_a: order_a.id,
+                        order_b: order_b.id,
+                        token_a: order_a.sell_token,
+                        token_b: order_a.buy_token,
+                        amount_a: sell_a,
+                        amount_b: buy_a,
+                    });
+                    break;
                 }
+            }
+        }
 
-                true
-            })
-            .cloned()
-            .collect()
+        let remaining = orders
+            .iter()
+            .zip(netted)
+            .filter(|(_, netted)| !netted)
+            .map(|(order, _)| order.clone())
+            .collect();
+
+        (remaining, transfers)
     }
 
     /// Attempts to find CoW (Coincidence of Wants) matches
@@ -66,11 +385,31 @@ impl SolverEngine {
 
         // Simple CoW matching: find orders that can be matched directly
         for (i, order_a) in orders.iter().enumerate() {
+            // Cross-chain orders settle in legs around a bridge, never against
+            // a counterparty
+            if order_a.is_cross_chain() {
+                continue;
+            }
+
             for (j, order_b) in orders.iter().enumerate().skip(i + 1) {
+                if order_b.is_cross_chain() {
+                    continue;
+                }
+
                 // Check if orders can be matched (sell token of A = buy token of B and vice versa)
                 if order_a.sell_token == order_b.buy_token
                     && order_a.buy_token == order_b.sell_token
                 {
+                    // Same owner on both sides is a wash trade; exact
+                    // offsets were already netted as internal transfers
+                    if order_a.owner == order_b.owner {
+                        warn!(
+                            "Rejecting self-trade {:?} <-> {:?} by {:?}",
+                            order_a.id, order_b.id, order_a.owner
+                        );
+                        continue;
+                    }
+
                     // Check if price conditions are compatible
                     if self.is_price_compatible(order_a, order_b) {
                         debug!("Found CoW match: {:?} <-> {:?}", order_a.id, order_b.id);
@@ -94,146 +433,746 @@ impl SolverEngine {
         // order_a's limit price <= order_b's limit price (when normalized)
         
         // This is a simplified check - real implementation would use precise decimal math
-        let price_a = order_a.buy_amount.as_u128() as f64 / order_a.sell_amount.as_u128() as f64;
-        let price_b = order_b.sell_amount.as_u128() as f64 / order_b.buy_amount.as_u128() as f64;
+        let price_a = u256_to_f64(order_a.buy_amount) / u256_to_f64(order_a.sell_amount);
+        let price_b = u256_to_f64(order_b.sell_amount) / u256_to_f64(order_b.buy_amount);
         
         // Allow some tolerance for matching
-        let tolerance = 1.0 + self.config.max_slippage / 100.0;
+        let tolerance = 1.0 + self.config.match_price_tolerance / 100.0;
         price_a <= price_b * tolerance
     }
 
-    /// Builds settlement plan from matched orders
+    /// Builds settlement plan from matched orders, as previewed by
+    /// [`Self::plan_settlement`]
     async fn build_settlement(
         &self,
         orders: &[Order],
         matches: Vec<(usize, usize)>,
     ) -> crate::Result<SettlementPlan> {
-        let mut settlement = SettlementPlan::default();
+        Ok(self.plan_settlement(orders, &matches)?.into_plan())
+    }
+
+    /// Previews the settlement of matched orders without building it, so
+    /// callers can inspect its trades, gas and surplus before committing.
+    ///
+    /// Clearing prices are uniform across the settlement: each token has one
+    /// price and every trade executes at the ratio of its tokens' prices. A
+    /// match whose tokens are already priced by earlier matches executes at
+    /// the rate those prices imply if both orders' limits allow it, and is
+    /// left out otherwise.
+    pub fn plan_settlement(
+        &self,
+        orders: &[Order],
+        matches: &[(usize, usize)],
+    ) -> crate::Result<SettlementPreview> {
+        let mut preview = SettlementPreview::default();
 
         // For each match, create trades
-        for (i, j) in matches {
+        for &(i, j) in matches {
             let order_a = &orders[i];
             let order_b = &orders[j];
 
+            // Orders may have been cancelled or filled since intake
+            Self::ensure_settleable(order_a)?;
+            Self::ensure_settleable(order_b)?;
+
             // Calculate clearing price (uniform price for both orders)
-            // Use the geometric mean of the two limit prices
-            let clearing_price = self.calculate_clearing_price(order_a, order_b);
+            let Some(clearing_price) = Self::reconcile_clearing_price(
+                &mut preview.clearing_prices,
+                order_a,
+                order_b,
+                self.calculate_clearing_price(order_a, order_b),
+            ) else {
+                debug!(
+                    "Skipping match {:?} <-> {:?}: conflicts with clearing prices of earlier matches",
+                    order_a.id, order_b.id
+                );
+                preview.skipped_matches.push((i, j));
+                continue;
+            };
 
-            // Add clearing prices to settlement
-            settlement.set_clearing_price(order_a.sell_token, clearing_price);
-            settlement.set_clearing_price(order_a.buy_token, clearing_price);
+            // Create trades for both orders, executed at the clearing price
+            // (buy token of A per sell token of A, scaled by 1e18)
+            let scale = U256::exp10(18);
+            let executed = Self::execute_at_rate(order_a, clearing_price, scale)
+                .zip(Self::execute_at_rate(order_b, scale, clearing_price));
+            let Some(((executed_sell_a, executed_buy_a), (executed_sell_b, executed_buy_b))) = executed else {
+                debug!(
+                    "Skipping match {:?} <-> {:?}: clearing price misses a limit",
+                    order_a.id, order_b.id
+                );
+                preview.skipped_matches.push((i, j));
+                continue;
+            };
 
-            // Create trades for both orders
-            // In a real implementation, this would calculate exact fill amounts
-            settlement.add_trade(crate::settlement::Trade {
+            preview.trades.push(Trade {
                 order_id: order_a.id,
-                executed_sell_amount: order_a.sell_amount,
-                executed_buy_amount: order_a.buy_amount,
+                sell_token: order_a.sell_token,
+                buy_token: order_a.buy_token,
+                executed_sell_amount: executed_sell_a,
+                executed_buy_amount: executed_buy_a,
                 fee: order_a.fee_amount,
             });
 
-            settlement.add_trade(crate::settlement::Trade {
+            preview.trades.push(Trade {
                 order_id: order_b.id,
-                executed_sell_amount: order_b.sell_amount,
-                executed_buy_amount: order_b.buy_amount,
+                sell_token: order_b.sell_token,
+                buy_token: order_b.buy_token,
+                executed_sell_amount: executed_sell_b,
+                executed_buy_amount: executed_buy_b,
                 fee: order_b.fee_amount,
             });
         }
 
-        // If AMM routing is enabled, add AMM interactions for unmatched orders
-        if self.config.enable_amm_routing {
-            // TODO: Implement AMM routing logic
-            debug!("AMM routing not yet implemented");
+        preview.estimated_gas = SettlementPlan::BASE_GAS
+            + preview.trades.len() as u64 * SettlementPlan::TRADE_GAS
+            + preview.interactions.iter().map(Interaction::estimate_gas).sum::<u64>();
+        preview.surplus = self
+            .trades_surplus(orders, &preview.trades, &preview.clearing_prices)
+            .iter()
+            .map(|(_, surplus)| surplus)
+            .sum();
+
+        Ok(preview)
+    }
+
+    /// Prices A's tokens in `prices` so that they clear at `rate` (A's
+    /// buy token per sell token, scaled by 1e18), returning the rate the
+    /// match executes at.
+    ///
+    /// A token priced by an earlier match keeps its price. If both are
+    /// priced, the rate they imply is used instead, or `None` returned when
+    /// it breaks either order's limit.
+    fn reconcile_clearing_price(
+        prices: &mut HashMap<Address, U256>,
+        order_a: &Order,
+        order_b: &Order,
+        rate: U256,
+    ) -> Option<U256> {
+        let scale = U256::exp10(18);
+
+        match (prices.get(&order_a.sell_token).copied(), prices.get(&order_a.buy_token).copied()) {
+            (Some(sell_price), Some(buy_price)) => {
+                let implied = U256::try_from(sell_price.full_mul(scale).checked_div(U512::from(buy_price))?).ok()?;
+                let within_limits = implied.full_mul(order_a.sell_amount) >= order_a.buy_amount.full_mul(scale)
+                    && implied.full_mul(order_b.buy_amount) <= order_b.sell_amount.full_mul(scale);
+                (implied == rate || within_limits).then_some(implied)
+            }
+            (Some(sell_price), None) => {
+                let buy_price = U256::try_from(sell_price.full_mul(scale).checked_div(U512::from(rate))?).ok()?;
+                prices.insert(order_a.buy_token, buy_price);
+                Some(rate)
+            }
+            (None, Some(buy_price)) => {
+                let sell_price = U256::try_from(rate.full_mul(buy_price) / U512::from(scale)).ok()?;
+                prices.insert(order_a.sell_token, sell_price);
+                Some(rate)
+            }
+            (None, None) => {
+                prices.insert(order_a.sell_token, rate);
+                prices.insert(order_a.buy_token, scale);
+                Some(rate)
+            }
+        }
+    }
+
+    /// Builds a routing engine over pools fetched from the configured
+    /// liquidity sources for `orders`' tokens, fresh as of the auction block
+    async fn build_routing(&self, orders: &[Order], ctx: &AuctionContext) -> crate::Result<RoutingEngine> {
+        let mut tokens: Vec<_> = orders
+            .iter()
+            .flat_map(|o| [o.sell_token, o.buy_token])
+            .chain(self.bridge_tokens.values().copied())
+            .collect();
+        tokens.sort();
+        tokens.dedup();
+
+        let mut routing = build_routing_engine(&self.liquidity_sources, &tokens)
+            .await?
+            .with_max_slippage(self.config.max_slippage);
+        if let Some(max_staleness) = self.config.max_pool_staleness {
+            routing = routing.with_max_pool_staleness(ctx.block_number, max_staleness);
         }
 
-        Ok(settlement)
+        Ok(routing)
     }
 
-    /// Calculates uniform clearing price for matched orders
-    fn calculate_clearing_price(&self, order_a: &Order, order_b: &Order) -> ethers::types::U256 {
-        // Simplified clearing price calculation
-        // Real implementation would use more sophisticated price discovery
-        
-        // Use geometric mean of the two limit prices
-        let price_a = order_a.buy_amount.as_u128() as f64 / order_a.sell_amount.as_u128() as f64;
-        let price_b = order_b.sell_amount.as_u128() as f64 / order_b.buy_amount.as_u128() as f64;
-        
-        let clearing_price = (price_a * price_b).sqrt();
-        
-        // Convert back to U256 (simplified)
-        ethers::types::U256::from((clearing_price * 1e18) as u128)
+    /// Fills orders without a trade in `settlement` through AMM routes.
+    ///
+    /// Returns orders whose fill was undone because it would push the
+    /// settlement past `max_settlement_gas`. Stops early, keeping the
+    /// orders routed so far, once the auction is cancelled.
+    fn route_unmatched_orders(
+        &self,
+        orders: &[Order],
+        settlement: &mut SettlementPlan,
+        routing: &RoutingEngine,
+        ctx: &AuctionContext,
+    ) -> Vec<OrderId> {
+        let matched: HashSet<OrderId> = settlement.trades.iter().map(|t| t.order_id).collect();
+        let mut gas_excluded = Vec::new();
+        for order in orders.iter().filter(|o| !matched.contains(&o.id)) {
+            if ctx.cancellation.is_cancelled() {
+                info!("Solve cancelled, leaving remaining orders unrouted");
+                break;
+            }
+            let before = settlement.clone();
+
+            let filled = if order.is_cross_chain() {
+                match self.fill_cross_chain_order(order, routing, settlement) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        debug!("Can't bridge order {:?}: {}", order.id, e);
+                        false
+                    }
+                }
+            } else {
+                match routing.fill_order(order, settlement) {
+                    // The swap may execute up to max_slippage below its quote,
+                    // which must still meet the order's limit
+                    Some(route) if route.min_output_amount < order.buy_amount => {
+                        info!(
+                            "Dropping order {:?}: output {} after {}% slippage is below limit {}",
+                            order.id, route.min_output_amount, self.config.max_slippage, order.buy_amount
+                        );
+                        *settlement = before;
+                        continue;
+                    }
+                    route => route.is_some(),
+                }
+            };
+            if !filled {
+                continue;
+            }
+
+            if settlement.estimate_gas() > self.config.max_settlement_gas {
+                debug!("Routing order {:?} would exceed the settlement gas budget", order.id);
+                *settlement = before;
+                gas_excluded.push(order.id);
+                continue;
+            }
+
+            debug!("Routed order {:?} through {}", order.id, if order.is_cross_chain() { "bridge" } else { "AMM" });
+        }
+
+        gas_excluded
     }
 
-    /// Calculates total surplus generated by solution
-    fn calculate_surplus(&self, orders: &[Order], settlement: &SettlementPlan) -> f64 {
-        let mut total_surplus = 0.0;
+    /// Fills a cross-chain order in three legs: a source-chain swap from the
+    /// sell token into the source bridge token, a post-hook bridging it to
+    /// the destination chain, and a destination-chain swap into the buy token.
+    ///
+    /// Swaps are skipped when the order already sells or buys the bridge
+    /// token. The bridge fee is deducted before the destination swap, whose
+    /// slippage-protected minimum output must still meet the order's buy
+    /// amount, and is what the trade records. Destination interactions are
+    /// tagged with their chain. Nothing is added to `settlement` on error.
+    fn fill_cross_chain_order(
+        &self,
+        order: &Order,
+        routing: &RoutingEngine,
+        settlement: &mut SettlementPlan,
+    ) -> crate::Result<()> {
+        let (Some(source), Some(destination)) = (order.source_chain, order.destination_chain) else {
+            return Err(crate::Error::InvalidOrder(
+                "Cross-chain order must specify both chains".to_string(),
+            ));
+        };
 
-        for trade in &settlement.trades {
-            // Find corresponding order
-            if let Some(order) = orders.iter().find(|o| o.id == trade.order_id) {
-                // Surplus = (executed_buy_amount - expected_buy_amount)
-                // This is simplified - real calculation would be more complex
-                let executed = trade.executed_buy_amount.as_u128() as f64;
-                let expected = order.buy_amount.as_u128() as f64;
-                
-                if executed > expected {
-                    total_surplus += (executed - expected) / 1e18; // Convert from wei
+        let provider_name = order
+            .bridge_provider
+            .as_deref()
+            .ok_or_else(|| crate::Error::InvalidOrder("Cross-chain order has no bridge provider".to_string()))?;
+        let provider = self
+            .bridges
+            .get(provider_name)
+            .ok_or_else(|| crate::Error::BridgeError(format!("Unknown bridge provider '{}'", provider_name)))?;
+
+        let bridge_token = |chain: ChainId| {
+            self.bridge_tokens
+                .get(&chain)
+                .copied()
+                .ok_or_else(|| crate::Error::BridgeError(format!("Chain {} is not supported", chain.name())))
+        };
+        let source_token = bridge_token(source)?;
+        let destination_token = bridge_token(destination)?;
+        let contract = self
+            .bridge_contracts
+            .get(&(provider_name.to_string(), source))
+            .copied()
+            .ok_or_else(|| {
+                crate::Error::BridgeError(format!("No {} contract on {}", provider_name, source.name()))
+            })?;
+
+        let no_route = |from: Address, to: Address, e: RoutingError| {
+            crate::Error::InsufficientLiquidity(format!("No route from {:?} to {:?}: {}", from, to, e))
+        };
+
+        // Source leg, swapping the sell amount after fee; bridge only what
+        // the swap is guaranteed to deliver
+        let swapped = order.sell_amount_after_fee();
+        let source_route = if order.sell_token == source_token {
+            None
+        } else {
+            let route = routing
+                .find_best_route(order.sell_token, source_token, swapped)
+                .map_err(|e| no_route(order.sell_token, source_token, e))?;
+            Some(route)
+        };
+        let bridged_amount = source_route
+            .as_ref()
+            .map_or(swapped, |route| route.min_output_amount);
+
+        let bridge_fee = provider.estimate_fee(source_token, bridged_amount, source, destination);
+        let arriving = bridged_amount.saturating_sub(bridge_fee);
+        if arriving.is_zero() {
+            return Err(crate::Error::BridgeError(format!(
+                "Bridge fee {} consumes the bridged amount {}",
+                bridge_fee, bridged_amount
+            )));
+        }
+
+        // Destination leg, held to what the swap is guaranteed to deliver
+        let destination_route = if order.buy_token == destination_token {
+            None
+        } else {
+            let route = routing
+                .find_best_route(destination_token, order.buy_token, arriving)
+                .map_err(|e| no_route(destination_token, order.buy_token, e))?;
+            Some(route)
+        };
+        let output = destination_route
+            .as_ref()
+            .map_or(arriving, |route| route.min_output_amount);
+        if output < order.buy_amount {
+            return Err(crate::Error::InsufficientLiquidity(format!(
+                "Bridged output {} below limit {}",
+                output, order.buy_amount
+            )));
+        }
+
+        // Funds land at the settlement contract when they still need swapping
+        let recipient = if destination_route.is_some() {
+            Address::from(SETTLEMENT_CONTRACT)
+        } else {
+            order.owner
+        };
+        let post_hook = PostHook::build(
+            provider.as_ref(),
+            contract,
+            source,
+            destination,
+            source_token,
+            bridged_amount,
+            recipient,
+        )
+        .map_err(crate::Error::BridgeError)?;
+
+        // Bridged funds go straight to the owner, so the trade must get
+        // exactly what arrives
+        if settlement
+            .reconcile_trade_price(order.sell_token, order.buy_token, swapped, output, output)
+            .is_none()
+        {
+            return Err(crate::Error::SettlementFailed(format!(
+                "Bridged rate for order {:?} conflicts with the settlement's clearing prices",
+                order.id
+            )));
+        }
+        settlement.add_trade(Trade {
+            order_id: order.id,
+            sell_token: order.sell_token,
+            buy_token: order.buy_token,
+            executed_sell_amount: swapped,
+            executed_buy_amount: output,
+            fee: order.fee_amount,
+        });
+
+        if let Some(route) = &source_route {
+            for interaction in routing.build_interactions(route, swapped) {
+                if let Some(flow) = &interaction.flow {
+                    settlement.ensure_approval(flow.token_in, interaction.target, U256::MAX);
+                }
+                settlement.add_interaction(interaction);
+            }
+        }
+        // The bridge deposit pulls the intermediate token from the settlement contract
+        settlement.ensure_approval(source_token, contract, U256::MAX);
+        settlement.add_post_hook(post_hook);
+
+        if let Some(route) = &destination_route {
+            for mut interaction in routing.build_interactions(route, arriving) {
+                interaction.chain = Some(destination);
+                if let Some(flow) = &interaction.flow {
+                    settlement.ensure_approval_on(Some(destination), flow.token_in, interaction.target, U256::MAX);
                 }
+                settlement.add_interaction(interaction);
             }
         }
 
-        total_surplus
+        Ok(())
     }
-}
 
-#[async_trait]
-impl Solver for SolverEngine {
-    async fn solve(&self, orders: Vec<Order>) -> crate::Result<Option<Solution>> {
-        info!("Starting solver with {} orders", orders.len());
+    /// Drops trades whose marginal surplus doesn't cover their marginal gas.
+    ///
+    /// Each order's marginal contribution is its surplus minus the gas of its
+    /// trade at the auction's gas price, both in reference tokens. Both sides
+    /// of a CoW match settle against each other, so a match is kept or
+    /// dropped as a unit based on the sum of its orders.
+    fn apply_inclusion_gate(
+        &self,
+        orders: &[Order],
+        matches: &[(usize, usize)],
+        settlement: &mut SettlementPlan,
+        ctx: &AuctionContext,
+    ) {
+        let order_surplus: HashMap<OrderId, f64> = self
+            .calculate_order_surplus(orders, settlement)
+            .into_iter()
+            .collect();
+        let chain = settlement_chain(orders).unwrap_or(ChainId::Ethereum);
+        let marginal_gas_cost = self.gas_value(SettlementPlan::TRADE_GAS, ctx, chain, &settlement.clearing_prices);
+
+        let mut excluded = HashSet::new();
+        for &(i, j) in matches {
+            let contribution: f64 = [orders[i].id, orders[j].id]
+                .iter()
+                .map(|id| order_surplus.get(id).copied().unwrap_or(0.0) - marginal_gas_cost)
+                .sum();
+
+            if contribution < 0.0 {
+                debug!(
+                    "Excluding match {:?} <-> {:?}: marginal contribution {:.6}",
+                    orders[i].id, orders[j].id, contribution
+                );
+                excluded.insert(orders[i].id);
+                excluded.insert(orders[j].id);
+            }
+        }
+
+        settlement.retain_trades(|trade| !excluded.contains(&trade.order_id));
+    }
+
+    /// Drops matches that would give either order a worse price than the
+    /// best AMM route for the same trade.
+    ///
+    /// Prices are compared as value received per value paid at `oracle`'s
+    /// external prices. Orders whose tokens the oracle doesn't price, or
+    /// that no route fills, keep their match.
+    fn apply_amm_comparison(
+        &self,
+        orders: &[Order],
+        matches: &[(usize, usize)],
+        settlement: &mut SettlementPlan,
+        routing: &RoutingEngine,
+        oracle: &PricingEngine,
+    ) {
+        let mut excluded = HashSet::new();
+        for &(i, j) in matches {
+            let beaten = [&orders[i], &orders[j]].into_iter().find(|order| {
+                let Some(trade) = settlement.trades.iter().find(|t| t.order_id == order.id) else {
+                    return false;
+                };
+                // The AMM fill of the same trade: same input for sell
+                // orders, same output for buy orders
+                let (sell, buy) = (trade.executed_sell_amount, trade.executed_buy_amount);
+                let amm_fill = match order.kind {
+                    OrderType::Sell => routing
+                        .find_best_route(order.sell_token, order.buy_token, sell)
+                        .map(|route| (sell, route.output_amount)),
+                    OrderType::Buy => routing
+                        .find_best_route_for_exact_out(order.sell_token, order.buy_token, buy)
+                        .map(|(amount_in, _)| (amount_in, buy)),
+                };
+                let Ok((amm_sell, amm_buy)) = amm_fill else {
+                    return false;
+                };
+
+                let cow = self.oracle_rate(oracle, order, sell, buy);
+                let amm = self.oracle_rate(oracle, order, amm_sell, amm_buy);
+                match (cow, amm) {
+                    (Some(cow), Some(amm)) if amm > cow => {
+                        debug!("AMM beats CoW price for order {:?}: {:.6} vs {:.6}", order.id, amm, cow);
+                        true
+                    }
+                    _ => false,
+                }
+            });
+
+            if beaten.is_some() {
+                excluded.insert(orders[i].id);
+                excluded.insert(orders[j].id);
+            }
+        }
+
+        settlement.retain_trades(|trade| !excluded.contains(&trade.order_id));
+    }
+
+    /// Values `buy` of `order`'s buy token per `sell` of its sell token at
+    /// `oracle`'s external prices, or `None` if either token is unpriced
+    fn oracle_rate(&self, oracle: &PricingEngine, order: &Order, sell: U256, buy: U256) -> Option<f64> {
+        let sell_price = oracle.external_price(order.sell_token)?;
+        let buy_price = oracle.external_price(order.buy_token)?;
+        let paid = self.tokens.to_units(order.sell_token, sell) * u256_to_f64(sell_price);
+        let received = self.tokens.to_units(order.buy_token, buy) * u256_to_f64(buy_price);
+        (paid > 0.0).then(|| received / paid)
+    }
+
+    /// Drops matches until the settlement fits in `max_settlement_gas`,
+    /// keeping those with the most surplus per unit of gas first.
+    ///
+    /// Returns the orders of dropped matches.
+    fn apply_gas_budget(
+        &self,
+        orders: &[Order],
+        matches: &[(usize, usize)],
+        settlement: &mut SettlementPlan,
+    ) -> Vec<OrderId> {
+        let budget = self.config.max_settlement_gas;
+        if settlement.estimate_gas() <= budget {
+            return Vec::new();
+        }
+
+        let order_surplus: HashMap<OrderId, f64> = self
+            .calculate_order_surplus(orders, settlement)
+            .into_iter()
+            .collect();
+        let settled: HashSet<OrderId> = settlement.trades.iter().map(|t| t.order_id).collect();
+
+        // Only matches that survived earlier gates compete for the budget
+        let match_gas = 2 * SettlementPlan::TRADE_GAS;
+        let mut candidates: Vec<(OrderId, OrderId, f64)> = matches
+            .iter()
+            .map(|&(i, j)| (orders[i].id, orders[j].id))
+            .filter(|(a, b)| settled.contains(a) && settled.contains(b))
+            .map(|(a, b)| {
+                let surplus = order_surplus.get(&a).copied().unwrap_or(0.0)
+                    + order_surplus.get(&b).copied().unwrap_or(0.0);
+                (a, b, surplus / match_gas as f64)
+            })
+            .collect();
+        candidates.sort_by(|x, y| y.2.partial_cmp(&x.2).unwrap_or(std::cmp::Ordering::Equal));
+
+        let mut gas_used = settlement.estimate_gas() - candidates.len() as u64 * match_gas;
+        let mut excluded = Vec::new();
+        for (a, b, efficiency) in candidates {
+            if gas_used + match_gas <= budget {
+                gas_used += match_gas;
+            } else {
+                debug!(
+                    "Excluding match {:?} <-> {:?} for gas: {:.9} surplus per gas",
+                    a, b, efficiency
+                );
+                excluded.push(a);
+                excluded.push(b);
+            }
+        }
+
+        settlement.retain_trades(|trade| !excluded.contains(&trade.order_id));
+        excluded
+    }
+
+    /// Validates `orders` at the auction's time, dedupes and nets them, then
+    /// finds CoW matches.
+    ///
+    /// Returns `None` when no valid orders remain.
+    async fn prepare_batch(&self, orders: Vec<Order>, ctx: &AuctionContext) -> Option<PreparedBatch> {
+        // Validate and filter orders; partially filled orders go on with
+        // their remaining amounts
+        let mut valid_orders: Vec<Order> = self
+            .dedupe_orders(self.validate_orders(&orders, ctx.now()))
+            .iter()
+            .map(Order::remaining)
+            .collect();
 
-        // Validate and filter orders
-        let valid_orders = self.validate_orders(&orders);
-        
         if valid_orders.is_empty() {
-            info!("No valid orders to solve");
-            return Ok(None);
+            return None;
+        }
+
+        // Oversized batches keep only their highest-priority orders:
+        // earliest deadline first if enabled, else largest sell volume
+        let batch_size = self.config.max_batch_size.unwrap_or(usize::MAX);
+        if self.config.prioritize_expiring {
+            valid_orders.sort_by_key(|order| order.valid_to);
+        } else if valid_orders.len() > batch_size {
+            valid_orders.sort_by(|a, b| {
+                let volume_a = self.tokens.to_units(a.sell_token, a.sell_amount);
+                let volume_b = self.tokens.to_units(b.sell_token, b.sell_amount);
+                volume_b.partial_cmp(&volume_a).unwrap_or(std::cmp::Ordering::Equal)
+            });
+        }
+        let overflow_orders: Vec<OrderId> = valid_orders
+            .split_off(batch_size.min(valid_orders.len()))
+            .iter()
+            .map(|order| order.id)
+            .collect();
+        if !overflow_orders.is_empty() {
+            info!(
+                "Leaving out {} orders over max_batch_size {}",
+                overflow_orders.len(),
+                batch_size
+            );
         }
 
         info!("Processing {} valid orders", valid_orders.len());
 
-        // Find CoW matches
-        let matches = self.find_cow_matches(&valid_orders).await;
+        // Net out self-offsetting orders before matching
+        let (matchable_orders, internal_transfers) = self.net_self_offsetting_orders(&valid_orders);
+
+        let matches = self.find_cow_matches(&matchable_orders).await;
+
+        Some(PreparedBatch {
+            valid_orders,
+            overflow_orders,
+            matchable_orders,
+            internal_transfers,
+            matches,
+        })
+    }
+
+    /// Checks if unmatched orders can be routed through AMM liquidity
+    fn routes_through_amms(&self) -> bool {
+        self.config.enable_amm_routing && !self.liquidity_sources.is_empty()
+    }
+
+    /// Builds and scores a solution settling the first `match_count` matches
+    /// of `batch`, optionally routing the remaining orders through AMMs.
+    ///
+    /// Returns `None` if nothing settles or the result isn't profitable.
+    async fn solve_prepared(
+        &self,
+        batch: &PreparedBatch,
+        match_count: usize,
+        route_unmatched: bool,
+        ctx: &AuctionContext,
+        trace: &mut SolveTrace,
+    ) -> crate::Result<Option<Solution>> {
+        let valid_orders = &batch.valid_orders;
+        let matchable_orders = &batch.matchable_orders;
+        let matches = &batch.matches[..match_count];
+
+        let routing = if route_unmatched {
+            Some(self.build_routing(matchable_orders, ctx).await?)
+        } else {
+            None
+        };
+
+        // Build settlement plan, keeping only matches that pay for their gas
+        // and give their orders no worse a price than AMMs would
+        let mut settlement = self.build_settlement(matchable_orders, matches.to_vec()).await?;
+        if let (Some(routing), Some(oracle)) = (&routing, &self.reference_oracle) {
+            self.apply_amm_comparison(matchable_orders, matches, &mut settlement, routing, oracle);
+        }
+        self.apply_inclusion_gate(matchable_orders, matches, &mut settlement, ctx);
+        let mut gas_excluded = self.apply_gas_budget(matchable_orders, matches, &mut settlement);
+        trace.matches_selected = matches
+            .iter()
+            .filter(|(i, j)| {
+                [matchable_orders[*i].id, matchable_orders[*j].id]
+                    .iter()
+                    .all(|id| settlement.trades.iter().any(|t| t.order_id == *id))
+            })
+            .count();
+        for transfer in &batch.internal_transfers {
+            settlement.add_internal_transfer(transfer.clone());
+        }
+
+        // Route orders left over by matching through AMM liquidity
+        if let Some(routing) = &routing {
+            gas_excluded.extend(self.route_unmatched_orders(matchable_orders, &mut settlement, routing, ctx));
+        }
 
-        if matches.is_empty() {
-            info!("No CoW matches found");
-            // In a real implementation, we would try AMM routing here
+        // Orders left out for gas aren't unroutable, so the policy skips them
+        trace.cancelled = ctx.cancellation.is_cancelled();
+        let all_ids: Vec<OrderId> = valid_orders
+            .iter()
+            .map(|o| o.id)
+            .filter(|id| !gas_excluded.contains(id))
+            .collect();
+        if settlement.trades.is_empty() && settlement.internal_transfers.is_empty() {
+            info!("No orders could be matched or routed");
+            if !trace.cancelled {
+                self.config.unroutable_policy.apply(&all_ids)?;
+            }
+            trace.reject_reason = Some(SolveRejectReason::NothingSettled);
             return Ok(None);
         }
 
-        // Build settlement plan
-        let settlement = self.build_settlement(&valid_orders, matches).await?;
+        // Valid orders that didn't make it into the settlement
+        let settled: Vec<OrderId> = settlement
+            .trades
+            .iter()
+            .map(|t| t.order_id)
+            .chain(
+                settlement
+                    .internal_transfers
+                    .iter()
+                    .flat_map(|t| [t.order_a, t.order_b]),
+            )
+            .collect();
+        let dropped_orders: Vec<OrderId> = all_ids
+            .into_iter()
+            .filter(|id| !settled.contains(id))
+            .collect();
+        if !dropped_orders.is_empty() {
+            info!("Dropping {} unsettleable orders", dropped_orders.len());
+        }
+        // A match dropped for gas may still have been routed
+        gas_excluded.retain(|id| !settled.contains(id));
+        if !gas_excluded.is_empty() {
+            info!("Leaving out {} orders over the gas budget", gas_excluded.len());
+        }
+        // Orders a cancelled solve never got to weren't found unroutable
+        if !trace.cancelled {
+            self.config.unroutable_policy.apply(&dropped_orders)?;
+        }
+
+        // Native-token legs settle through the chain's wrapped token
+        let chain = settlement_chain(valid_orders).unwrap_or(ChainId::Ethereum);
+        if let Some(weth) = chain.wrapped_native_token() {
+            settlement.wrap_native_legs(weth);
+        }
+
+        let removed = settlement.dedupe_interactions();
+        if removed > 0 {
+            debug!("Removed {} redundant interactions", removed);
+        }
 
         // Validate settlement
-        settlement.validate()
-            .map_err(|e| crate::Error::SettlementFailed(e))?;
+        settlement.validate_with_orders(valid_orders)
+            .map_err(crate::Error::SettlementFailed)?;
+        settlement.check_price_consistency(PRICE_CONSISTENCY_TOLERANCE_BPS)
+            .map_err(crate::Error::SettlementFailed)?;
+        settlement.check_balances(valid_orders)
+            .map_err(crate::Error::SettlementFailed)?;
 
         // Calculate gas cost
         let gas_cost = settlement.estimate_gas();
 
-        // Calculate surplus
-        let surplus = self.calculate_surplus(&valid_orders, &settlement);
+        // Cross-chain trades buy what arrives after the bridge fee, so
+        // surplus is already net of it
+        let surplus = self.calculate_surplus(valid_orders, &settlement);
+        let order_surplus = self.calculate_order_surplus(valid_orders, &settlement);
 
         // Create solution
         let mut solution = Solution {
-            orders: settlement.trades.iter().map(|t| t.order_id).collect(),
+            orders: settled,
             settlement,
             gas_cost,
             surplus,
+            surplus_token: Some(self.config.reference_token).filter(|token| !token.is_zero()),
+            order_surplus,
+            dropped_orders,
+            gas_excluded_orders: gas_excluded,
+            overflow_orders: batch.overflow_orders.clone(),
+            chain: settlement_chain(valid_orders),
+            valid_for_block: ctx.block_number,
             score: 0.0,
         };
 
         // Calculate quality score
-        solution.calculate_score();
+        solution.score = self.scoring.score(&solution, ctx);
+        trace.surplus = solution.surplus;
+        trace.score = solution.score;
 
         // Check if solution is profitable
         if !solution.is_profitable(self.config.min_profit_threshold) {
@@ -241,19 +1180,392 @@ impl Solver for SolverEngine {
                 "Solution not profitable: score={}, threshold={}",
                 solution.score, self.config.min_profit_threshold
             );
+            trace.reject_reason = Some(SolveRejectReason::NotProfitable);
             return Ok(None);
         }
 
-        info!(
-            "Found solution: {} orders, surplus={:.4}, score={:.4}",
-            solution.orders.len(),
-            solution.surplus,
-            solution.score
-        );
-
         Ok(Some(solution))
     }
 
+    /// Solves like [`Solver::solve`], also returning a trace of how many
+    /// orders and matches each stage kept and why no solution was found
+    pub async fn solve_with_trace(
+        &self,
+        orders: Vec<Order>,
+        ctx: &AuctionContext,
+    ) -> crate::Result<(Option<Solution>, SolveTrace)> {
+        info!("Starting solver with {} orders", orders.len());
+        let mut trace = SolveTrace::default();
+
+        let Some(batch) = self.prepare_batch(orders, ctx).await else {
+            info!("No valid orders to solve");
+            trace.reject_reason = Some(SolveRejectReason::NoValidOrders);
+            return Ok((None, trace));
+        };
+        trace.valid_orders = batch.valid_orders.len();
+        trace.overflow_orders = batch.overflow_orders.len();
+        trace.matches_found = batch.matches.len();
+
+        let solution = self
+            .solve_prepared(&batch, batch.matches.len(), self.routes_through_amms(), ctx, &mut trace)
+            .await?;
+
+        if let Some(solution) = &solution {
+            info!(
+                "Found solution: {} orders, surplus={:.4}, score={:.4}",
+                solution.orders.len(),
+                solution.surplus,
+                solution.score
+            );
+        }
+
+        Ok((solution, trace))
+    }
+
+    /// Solves incrementally, yielding each solution that improves on the
+    /// best score so far.
+    ///
+    /// Matches are settled one at a time in discovery order; the final
+    /// candidate is the full solve including AMM routing, so the last item
+    /// scores the same as `solve` would. The stream ends once candidates are
+    /// exhausted, the configured `timeout_ms` has elapsed or the auction is
+    /// cancelled, so the last item yielded is the best found. Candidates that
+    /// fail (e.g. under `UnroutablePolicy::FailBatch`) are skipped.
+    pub fn solve_streaming(
+        &self,
+        orders: Vec<Order>,
+        ctx: AuctionContext,
+    ) -> impl Stream<Item = Solution> + Send + '_ {
+        let deadline = Instant::now() + Duration::from_millis(self.config.timeout_ms);
+        let state = StreamState {
+            orders: Some(orders),
+            batch: None,
+            stage: 0,
+            best_score: f64::NEG_INFINITY,
+            ctx,
+        };
+
+        stream::unfold(state, move |mut state| async move {
+            if let Some(orders) = state.orders.take() {
+                info!("Starting streaming solve with {} orders", orders.len());
+                state.batch = Some(self.prepare_batch(orders, &state.ctx).await?);
+            }
+            let batch = state.batch.as_ref()?;
+
+            // One stage per match prefix, then the full solve
+            while state.stage <= batch.matches.len() {
+                if Instant::now() >= deadline {
+                    info!("Streaming solve timed out after {} stages", state.stage);
+                    return None;
+                }
+                if state.ctx.cancellation.is_cancelled() {
+                    info!("Streaming solve cancelled after {} stages", state.stage);
+                    return None;
+                }
+
+                let (match_count, route_unmatched) = if state.stage < batch.matches.len() {
+                    (state.stage + 1, false)
+                } else {
+                    (batch.matches.len(), self.routes_through_amms())
+                };
+                state.stage += 1;
+
+                let mut trace = SolveTrace::default();
+                let candidate = self
+                    .solve_prepared(batch, match_count, route_unmatched, &state.ctx, &mut trace)
+                    .await;
+                match candidate {
+                    Ok(Some(solution)) if solution.score > state.best_score => {
+                        debug!("Improved solution at stage {}: score={:.4}", state.stage, solution.score);
+                        state.best_score = solution.score;
+                        return Some((solution, state));
+                    }
+                    Ok(_) => {}
+                    Err(e) => debug!("Skipping stage {}: {}", state.stage, e),
+                }
+            }
+
+            None
+        })
+    }
+
+    /// Checks that an order can still be settled
+    fn ensure_settleable(order: &Order) -> crate::Result<()> {
+        match order.status {
+            OrderStatus::Open | OrderStatus::PartiallyFilled => Ok(()),
+            status => Err(crate::Error::InvalidOrder(format!(
+                "Order {:?} is no longer settleable (status: {:?})",
+                order.id, status
+            ))),
+        }
+    }
+
+    /// Executes an order at `rate_num / rate_den` units of its buy token per
+    /// unit of its sell token, returning `(executed_sell, executed_buy)`.
+    ///
+    /// Only the sell amount after fee is swapped; the fee is recorded on
+    /// the trade separately. Sell orders spend all of it, buy orders receive
+    /// exactly their buy amount. The rate is fixed point, so an amount that
+    /// misses the limit by less than one step of it is rounded to the limit.
+    /// Returns `None` when the rate misses a limit by more than that.
+    fn execute_at_rate(order: &Order, rate_num: U256, rate_den: U256) -> Option<(U256, U256)> {
+        let swapped = order.sell_amount_after_fee();
+        if rate_num.is_zero() || rate_den.is_zero() {
+            return None;
+        }
+        let step = |amount: U256| amount / rate_num.min(rate_den) + 1;
+
+        match order.kind {
+            OrderType::Sell => {
+                let executed_buy = U256::try_from(swapped.full_mul(rate_num) / U512::from(rate_den)).ok()?;
+                (executed_buy.saturating_add(step(executed_buy)) >= order.buy_amount)
+                    .then_some((swapped, executed_buy.max(order.buy_amount)))
+            }
+            OrderType::Buy => {
+                let executed_sell = U256::try_from(order.buy_amount.full_mul(rate_den) / U512::from(rate_num)).ok()?;
+                (executed_sell <= swapped.saturating_add(step(executed_sell)))
+                    .then_some((executed_sell.min(swapped), order.buy_amount))
+            }
+        }
+    }
+
+    /// Combined surplus of a matched pair at clearing price `price`
+    /// (buy token of A per sell token of A), in raw units of A's buy token.
+    ///
+    /// Valued in that one token, A gains `sell_a * p - buy_a` and B gains
+    /// `sell_b - buy_b * p` whatever the order kinds: a buy order's saving
+    /// in its sell token is worth the same as a sell order's extra output.
+    fn pair_surplus(order_a: &Order, order_b: &Order, price: f64) -> f64 {
+        let sell_a = u256_to_f64(order_a.sell_amount);
+        let buy_a = u256_to_f64(order_a.buy_amount);
+        let sell_b = u256_to_f64(order_b.sell_amount);
+        let buy_b = u256_to_f64(order_b.buy_amount);
+
+        (sell_a * price - buy_a) + (sell_b - buy_b * price)
+    }
+
+    /// Calculates uniform clearing price for matched orders.
+    ///
+    /// Picks the price in `[price_a, price_b]` splitting surplus as the
+    /// configured `SurplusDistribution` asks. For `MaxTotal` that's the
+    /// price maximizing the pair's combined surplus, which valued in A's
+    /// buy token is linear in the price with slope `sell_a - buy_b`, so the
+    /// optimum is an endpoint. Two sell (or two buy) orders of different
+    /// sizes only both fill in full at one price, so the price within the
+    /// limits closest to it is used instead. Limits are on the sell amounts
+    /// after fee, which is what the orders swap. Falls back to the midpoint
+    /// when the objective is flat or the limits only meet within slippage.
+    /// Prices are picked and clamped in fixed point, so amounts beyond
+    /// `u128` never pass through a float conversion that could panic.
+    fn calculate_clearing_price(&self, order_a: &Order, order_b: &Order) -> U256 {
+        let scale = U256::exp10(18);
+        let swapped_a = order_a.sell_amount_after_fee();
+        let swapped_b = order_b.sell_amount_after_fee();
+        if swapped_a.is_zero() || order_b.buy_amount.is_zero() {
+            return U256::zero();
+        }
+
+        // A needs at least `lowest` to get its buy amount for what it swaps,
+        // and B pays at most `highest` for its buy amount out of what it
+        // swaps, each rounded towards the other's side so that neither
+        // overpays by rounding
+        let lowest = U256::try_from(order_a.buy_amount.full_mul(scale) / U512::from(swapped_a)).unwrap_or(U256::MAX);
+        let highest = U256::try_from(
+            (swapped_b.full_mul(scale) + U512::from(order_b.buy_amount) - 1) / U512::from(order_b.buy_amount),
+        )
+        .unwrap_or(U256::MAX);
+        let midpoint = U256::try_from((U512::from(lowest) + U512::from(highest)) / 2).unwrap_or(U256::MAX);
+
+        if lowest > highest {
+            return midpoint;
+        }
+
+        // Valued in A's buy token, A gains sell_a * p - buy_a and B gains
+        // sell_b - buy_b * p, whatever the order kinds. Sizes are in A's
+        // sell token: sell_a and buy_b.
+        let (sell_a, buy_a) = (order_a.sell_amount, order_a.buy_amount);
+        let (sell_b, buy_b) = (order_b.sell_amount, order_b.buy_amount);
+        match self.config.surplus_distribution {
+            SurplusDistribution::MaxTotal => {}
+            // Both improve on their limit by the same amount per unit traded
+            SurplusDistribution::Proportional => return midpoint,
+            SurplusDistribution::EqualSplit => {
+                let equal = (U512::from(buy_a) + U512::from(sell_b)) * U512::from(scale)
+                    / (U512::from(sell_a) + U512::from(buy_b));
+                return U256::try_from(equal).unwrap_or(U256::MAX).clamp(lowest, highest);
+            }
+            SurplusDistribution::FavorSmaller => {
+                return match sell_a.cmp(&buy_b) {
+                    std::cmp::Ordering::Less => highest,
+                    std::cmp::Ordering::Greater => lowest,
+                    std::cmp::Ordering::Equal => midpoint,
+                };
+            }
+        }
+
+        // Closest to balanced that meets both limits; fees cover what
+        // fixed-point rounding leaves over
+        let balanced = match (order_a.kind, order_b.kind) {
+            (OrderType::Sell, OrderType::Sell) => swapped_b.full_mul(scale) / U512::from(swapped_a),
+            (OrderType::Buy, OrderType::Buy) => order_a.buy_amount.full_mul(scale) / U512::from(order_b.buy_amount),
+            _ => U512::zero(),
+        };
+        if !balanced.is_zero() {
+            return U256::try_from(balanced).unwrap_or(U256::MAX).clamp(lowest, highest);
+        }
+
+        let scored: Vec<(U256, f64)> = [lowest, highest]
+            .into_iter()
+            .map(|rate| (rate, Self::pair_surplus(order_a, order_b, u256_to_f64(rate) / 1e18)))
+            .collect();
+        let best = scored
+            .iter()
+            .copied()
+            .fold((lowest, f64::NEG_INFINITY), |best, candidate| {
+                if candidate.1 > best.1 { candidate } else { best }
+            });
+        let worst = scored.iter().map(|(_, surplus)| *surplus).fold(f64::INFINITY, f64::min);
+
+        // Flat objective: no price is better, so split evenly
+        if best.1 - worst <= best.1.abs().max(1.0) * 1e-9 {
+            debug!("Flat surplus objective, using midpoint price {:.6}", u256_to_f64(midpoint) / 1e18);
+            return midpoint;
+        }
+
+        best.0
+    }
+
+    /// Calculates surplus generated for each traded order, in whole units of
+    /// the configured reference token
+    fn calculate_order_surplus(
+        &self,
+        orders: &[Order],
+        settlement: &SettlementPlan,
+    ) -> Vec<(OrderId, f64)> {
+        self.trades_surplus(orders, &settlement.trades, &settlement.clearing_prices)
+    }
+
+    /// Calculates the surplus each of `trades` gives its order, valued at
+    /// `prices`
+    fn trades_surplus(
+        &self,
+        orders: &[Order],
+        trades: &[Trade],
+        prices: &HashMap<Address, U256>,
+    ) -> Vec<(OrderId, f64)> {
+        let mut order_surplus = Vec::new();
+        let book: OrderBook = orders.iter().cloned().collect();
+
+        for trade in trades {
+            // Find corresponding order
+            if let Some(order) = book.get(&trade.order_id) {
+                // Sell orders gain in the buy token, buy orders save in the sell token
+                let (token, executed, expected) = match order.kind {
+                    OrderType::Sell => (order.buy_token, trade.executed_buy_amount, order.buy_amount),
+                    OrderType::Buy => (order.sell_token, order.sell_amount, trade.executed_sell_amount),
+                };
+
+                let surplus = self.reference_value(token, executed.saturating_sub(expected), prices);
+                order_surplus.push((trade.order_id, surplus));
+            }
+        }
+
+        order_surplus
+    }
+
+    /// Values `amount` of `token` in whole reference tokens at the
+    /// given clearing prices.
+    ///
+    /// Falls back to whole units of `token` when no reference token is
+    /// configured or either token has no clearing price.
+    fn reference_value(&self, token: Address, amount: U256, prices: &HashMap<Address, U256>) -> f64 {
+        let reference = self.config.reference_token;
+
+        match (prices.get(&token), prices.get(&reference)) {
+            (Some(price), Some(reference_price)) if !reference.is_zero() && !reference_price.is_zero() => {
+                let value = U256::try_from(amount.full_mul(*price) / U512::from(*reference_price))
+                    .unwrap_or(U256::MAX);
+                self.tokens.to_units(reference, value)
+            }
+            _ => self.tokens.to_units(token, amount),
+        }
+    }
+
+    /// Values `gas` at the auction's gas price in whole reference tokens,
+    /// through the clearing price of `chain`'s wrapped native token.
+    ///
+    /// Like [`Self::reference_value`], counts ETH one for one when either
+    /// token has no clearing price.
+    fn gas_value(&self, gas: u64, ctx: &AuctionContext, chain: ChainId, prices: &HashMap<Address, U256>) -> f64 {
+        let gas_cost_eth = ctx.gas_cost_eth(gas);
+        match chain.wrapped_native_token() {
+            Some(weth) => self.reference_value(weth, U256::from((gas_cost_eth * 1e18) as u128), prices),
+            None => gas_cost_eth,
+        }
+    }
+
+    /// Updates `solution`'s trades and surplus with amounts realized in
+    /// simulation, and re-scores it with the engine's scoring policy.
+    ///
+    /// `realized` maps order ids to `(executed_sell, executed_buy)`. Each
+    /// order's surplus moves by what it received beyond, and paid below,
+    /// its quoted amounts, valued in reference tokens at the solution's
+    /// clearing prices. The aggregate surplus follows.
+    pub fn recompute_surplus_from_simulation(
+        &self,
+        solution: &mut Solution,
+        realized: &HashMap<OrderId, (U256, U256)>,
+        ctx: &AuctionContext,
+    ) {
+        let prices = solution.settlement.clearing_prices.clone();
+        let signed_value = |token: Address, from: U256, to: U256| {
+            if to >= from {
+                self.reference_value(token, to - from, &prices)
+            } else {
+                -self.reference_value(token, from - to, &prices)
+            }
+        };
+        let mut total_delta = 0.0;
+
+        for trade in &mut solution.settlement.trades {
+            let Some(&(executed_sell, executed_buy)) = realized.get(&trade.order_id) else {
+                warn!("No simulation result for order {:?}", trade.order_id);
+                continue;
+            };
+
+            let delta = signed_value(trade.buy_token, trade.executed_buy_amount, executed_buy)
+                + signed_value(trade.sell_token, executed_sell, trade.executed_sell_amount);
+
+            match solution.order_surplus.iter_mut().find(|(id, _)| *id == trade.order_id) {
+                Some((_, surplus)) => *surplus += delta,
+                None => solution.order_surplus.push((trade.order_id, delta)),
+            }
+
+            trade.executed_sell_amount = executed_sell;
+            trade.executed_buy_amount = executed_buy;
+            total_delta += delta;
+        }
+
+        solution.surplus += total_delta;
+        solution.score = self.scoring.score(solution, ctx);
+    }
+
+    /// Calculates total surplus generated by solution
+    fn calculate_surplus(&self, orders: &[Order], settlement: &SettlementPlan) -> f64 {
+        self.calculate_order_surplus(orders, settlement)
+            .iter()
+            .map(|(_, surplus)| surplus)
+            .sum()
+    }
+}
+
+#[async_trait]
+impl Solver for SolverEngine {
+    async fn solve(&self, orders: Vec<Order>, ctx: &AuctionContext) -> crate::Result<Option<Solution>> {
+        let (solution, _) = self.solve_with_trace(orders, ctx).await?;
+        Ok(solution)
+    }
+
     fn name(&self) -> &str {
         &self.name
     }
@@ -266,9 +1578,15 @@ impl Solver for SolverEngine {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::domain::{OrderId, OrderKind};
+    use crate::domain::OrderType;
     use ethers::types::{Address, U256};
 
+    /// Fixed auction time for validation tests
+    const NOW: u32 = 1_700_000_000;
+
+    /// One whole token at 18 decimals
+    const E18: u128 = 1_000_000_000_000_000_000;
+
     fn create_test_order(
         sell_token: Address,
         buy_token: Address,
@@ -277,20 +1595,29 @@ mod tests {
     ) -> Order {
         Order {
             id: OrderId([0u8; 32]),
-            owner: Address::zero(),
+            owner: sell_token, // Opposing test orders must not be self-trades
             sell_token,
             buy_token,
             sell_amount: U256::from(sell_amount),
             buy_amount: U256::from(buy_amount),
-            valid_to: Some(u32::MAX),
+            valid_to: u32::MAX,
             fee_amount: U256::from(1000),
-            kind: OrderKind::Sell,
+            kind: OrderType::Sell,
             partially_fillable: false,
             status: OrderStatus::Open,
-            chain_id: crate::domain::ChainId::Mainnet,
+            source_chain: None,
+            destination_chain: None,
+            bridge_provider: None,
+            filled_amount: U256::zero(),
+            signature: vec![],
         }
     }
 
+    /// Like [`create_test_order`], with id `[id; 32]`
+    fn order_with_id(id: u8, sell_token: Address, buy_token: Address, sell_amount: u128, buy_amount: u128) -> Order {
+        Order { id: OrderId([id; 32]), ..create_test_order(sell_token, buy_token, sell_amount, buy_amount) }
+    }
+
     #[tokio::test]
     async fn test_solver_engine_creation() {
         let config = SolverConfig::default();
@@ -311,8 +1638,186 @@ mod tests {
             create_test_order(token_a, token_b, 0, 2000), // Invalid: zero sell amount
         ];
 
-        let valid = engine.validate_orders(&orders);
+        let valid = engine.validate_orders(&orders, NOW);
+        assert_eq!(valid.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_validate_orders_reports_reject_reasons() {
+        let engine = SolverEngine::new(SolverConfig::default());
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+
+        let mut orders = vec![
+            order_with_id(1, token_a, token_b, 1000, 2000),
+            order_with_id(2, token_a, token_b, 1000, 2000),
+            order_with_id(3, token_a, token_b, 1000, 2000),
+            order_with_id(4, token_a, token_b, 1000, 0),
+            order_with_id(5, token_a, token_a, 1000, 2000),
+        ];
+        orders[1].status = OrderStatus::Cancelled;
+        orders[2].valid_to = 1;
+
+        let (valid, rejected) = engine.validate_orders_detailed(&orders, NOW);
+        assert_eq!(valid.len(), 1);
+        assert_eq!(valid[0].id, orders[0].id);
+        assert_eq!(
+            rejected,
+            vec![
+                (orders[1].id, RejectReason::NotOpen),
+                (orders[2].id, RejectReason::Expired),
+                (orders[3].id, RejectReason::ZeroAmount),
+                (orders[4].id, RejectReason::SameToken),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_partially_filled_order_matches_remaining_volume() {
+        let engine = SolverEngine::new(SolverConfig::default());
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+
+        // Half of A's 2 -> 4 order is already filled
+        let mut partial = order_with_id(1, token_a, token_b, 2 * E18, 4 * E18);
+        partial.status = OrderStatus::PartiallyFilled;
+        partial.filled_amount = U256::from(E18);
+        let counter = order_with_id(2, token_b, token_a, 3 * E18, E18);
+
+        let solution = engine
+            .solve(vec![partial.clone(), counter], &AuctionContext::default())
+            .await
+            .unwrap()
+            .unwrap();
+        let trade = solution
+            .settlement
+            .trades
+            .iter()
+            .find(|t| t.order_id == partial.id)
+            .unwrap();
+        assert_eq!(trade.executed_sell_amount + trade.fee, U256::from(E18));
+        assert!(trade.executed_buy_amount >= U256::from(2 * E18));
+
+        partial.filled_amount = U256::from(2 * E18);
+        let (valid, rejected) = engine.validate_orders_detailed(&[partial.clone()], NOW);
+        assert!(valid.is_empty());
+        assert_eq!(rejected, vec![(partial.id, RejectReason::FullyFilled)]);
+    }
+
+    #[test]
+    fn test_unsigned_orders_rejected_when_verifying() {
+        use ethers::signers::{LocalWallet, Signer};
+        use ethers::types::H256;
+
+        let wallet: LocalWallet = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+            .parse()
+            .unwrap();
+        let mut signed = create_test_order(Address::from_low_u64_be(1), Address::from_low_u64_be(2), 1000, 2000);
+        signed.owner = wallet.address();
+        let digest = H256::from(signed.signing_digest(domain_separator(ChainId::Ethereum)));
+        signed.signature = wallet.sign_hash(digest).unwrap().to_vec();
+        let mut forged = signed.clone();
+        forged.id = OrderId([2u8; 32]);
+        forged.sell_amount = U256::from(500);
+        let orders = vec![signed.clone(), forged.clone()];
+
+        let engine = SolverEngine::new(SolverConfig::default());
+        assert_eq!(engine.validate_orders_detailed(&orders, NOW).0.len(), 2);
+
+        let config = SolverConfig::builder().verify_signatures(true).build().unwrap();
+        let (valid, rejected) = SolverEngine::new(config).validate_orders_detailed(&orders, NOW);
+        assert_eq!(valid, vec![signed]);
+        assert_eq!(rejected, vec![(forged.id, RejectReason::InvalidSignature)]);
+    }
+
+    #[tokio::test]
+    async fn test_expiry_checked_against_auction_timestamp() {
+        let engine = SolverEngine::new(SolverConfig::default());
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+
+        // Long expired by the wall clock, but live in a replayed auction
+        let mut orders = vec![
+            order_with_id(1, token_a, token_b, E18, 2 * E18),
+            order_with_id(2, token_b, token_a, 3 * E18, E18),
+        ];
+        for order in &mut orders {
+            order.valid_to = 1_000_000;
+        }
+
+        let at_deadline = AuctionContext {
+            timestamp: 1_000_000,
+            ..AuctionContext::default()
+        };
+        let solution = engine.solve(orders.clone(), &at_deadline).await.unwrap().unwrap();
+        assert_eq!(solution.orders.len(), 2);
+
+        let after_deadline = AuctionContext {
+            timestamp: 1_000_001,
+            ..AuctionContext::default()
+        };
+        assert!(engine.solve(orders.clone(), &after_deadline).await.unwrap().is_none());
+
+        let (_, rejected) = engine.validate_orders_detailed(&orders, after_deadline.now());
+        assert!(rejected.iter().all(|(_, reason)| *reason == RejectReason::Expired));
+    }
+
+    #[tokio::test]
+    async fn test_replayed_auction_drops_orders_expired_at_its_timestamp() {
+        let engine = SolverEngine::new(SolverConfig::default());
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+
+        let mut order = create_test_order(token_a, token_b, 1000, 2000);
+        order.valid_to = 4_000_000_000;
+        let orders = vec![order];
+
+        // Still valid by the wall clock, which is only the fallback
+        let (valid, _) = engine.validate_orders_detailed(&orders, AuctionContext::default().now());
         assert_eq!(valid.len(), 1);
+
+        let replay = AuctionContext {
+            timestamp: 4_000_000_001,
+            ..AuctionContext::default()
+        };
+        for _ in 0..2 {
+            let (valid, rejected) = engine.validate_orders_detailed(&orders, replay.now());
+            assert!(valid.is_empty());
+            assert_eq!(rejected, vec![(orders[0].id, RejectReason::Expired)]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_solve_trace_reports_unprofitable_batch() {
+        let engine = SolverEngine::new(SolverConfig {
+            min_profit_threshold: 1.0,
+            ..SolverConfig::default()
+        });
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let orders = vec![
+            order_with_id(1, token_a, token_b, E18, 2 * E18),
+            order_with_id(2, token_b, token_a, 3 * E18, E18),
+        ];
+
+        let (solution, trace) = engine
+            .solve_with_trace(orders, &AuctionContext::default())
+            .await
+            .unwrap();
+        assert!(solution.is_none());
+        assert_eq!(trace.valid_orders, 2);
+        assert_eq!(trace.matches_found, 1);
+        assert_eq!(trace.matches_selected, 1);
+        assert!(trace.score < 1.0);
+        assert_eq!(trace.reject_reason, Some(SolveRejectReason::NotProfitable));
+
+        let (_, trace) = engine.solve_with_trace(vec![], &AuctionContext::default()).await.unwrap();
+        assert_eq!(trace.reject_reason, Some(SolveRejectReason::NoValidOrders));
     }
 
     #[tokio::test]
@@ -333,6 +1838,32 @@ mod tests {
         assert_eq!(matches[0], (0, 1));
     }
 
+    #[tokio::test]
+    async fn test_match_price_tolerance() {
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+
+        // A asks 0.2% more than B offers
+        let orders = vec![
+            create_test_order(token_a, token_b, 1000, 2004),
+            create_test_order(token_b, token_a, 2000, 1000),
+        ];
+
+        let loose = SolverEngine::new(SolverConfig {
+            match_price_tolerance: 0.5,
+            max_slippage: 0.0,
+            ..SolverConfig::default()
+        });
+        assert_eq!(loose.find_cow_matches(&orders).await, vec![(0, 1)]);
+
+        let strict = SolverEngine::new(SolverConfig {
+            match_price_tolerance: 0.0,
+            max_slippage: 5.0,
+            ..SolverConfig::default()
+        });
+        assert!(strict.find_cow_matches(&orders).await.is_empty());
+    }
+
     #[tokio::test]
     async fn test_solve_with_matches() {
         let config = SolverConfig::default();
@@ -341,12 +1872,14 @@ mod tests {
         let token_a = Address::from_low_u64_be(1);
         let token_b = Address::from_low_u64_be(2);
 
+        // The second order pays more than the first asks, leaving surplus to
+        // cover gas and the profit threshold
         let orders = vec![
-            create_test_order(token_a, token_b, 1000000000000000000, 2000000000000000000),
-            create_test_order(token_b, token_a, 2000000000000000000, 1000000000000000000),
+            order_with_id(1, token_a, token_b, E18, 2 * E18),
+            order_with_id(2, token_b, token_a, 22 * E18 / 10, E18),
         ];
 
-        let solution = engine.solve(orders).await.unwrap();
+        let solution = engine.solve(orders, &AuctionContext::default()).await.unwrap();
         assert!(solution.is_some());
 
         let solution = solution.unwrap();
@@ -354,6 +1887,29 @@ mod tests {
         assert!(solution.score >= 0.0);
     }
 
+    #[tokio::test]
+    async fn test_scoring_policy_is_pluggable() {
+        use crate::solver::SurplusOnlyPolicy;
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+
+        let orders = vec![
+            order_with_id(1, token_a, token_b, E18, 15 * E18 / 10),
+            order_with_id(2, token_b, token_a, 2 * E18, E18),
+        ];
+
+        let default = SolverEngine::new(SolverConfig::default());
+        let ctx = AuctionContext::default();
+        let solution = default.solve(orders.clone(), &ctx).await.unwrap().unwrap();
+        assert!(solution.score < solution.surplus);
+
+        let surplus_only = SolverEngine::new(SolverConfig::default())
+            .with_scoring_policy(Box::new(SurplusOnlyPolicy));
+        let solution = surplus_only.solve(orders, &ctx).await.unwrap().unwrap();
+        assert_eq!(solution.score, solution.surplus);
+    }
+
     #[tokio::test]
     async fn test_solve_no_matches() {
         let config = SolverConfig::default();
@@ -368,7 +1924,1190 @@ mod tests {
             create_test_order(token_a, token_c, 1000, 3000),
         ];
 
-        let solution = engine.solve(orders).await.unwrap();
+        let solution = engine.solve(orders, &AuctionContext::default()).await.unwrap();
+        assert!(solution.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_unmatched_orders_routed_through_liquidity_sources() {
+        use crate::solver::{LiquidityPool, PoolType, StaticLiquiditySource};
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+
+        let source = StaticLiquiditySource::new(
+            "static",
+            vec![LiquidityPool {
+                address: Address::from_low_u64_be(0x100),
+                pool_type: PoolType::UniswapV2,
+                token_a,
+                token_b,
+                reserve_a: U256::from(1000 * E18),
+                reserve_b: U256::from(2000 * E18),
+                fee_bps: 30,
+                gas_cost: 100000,
+                concentrated: None,
+                amplification: None,
+                fetched_at_block: 0,
+            }],
+        );
+        let orders = vec![create_test_order(token_a, token_b, E18, 19 * E18 / 10)];
+
+        // Without liquidity there's no counterparty
+        let engine = SolverEngine::new(SolverConfig::default());
+        assert!(engine.solve(orders.clone(), &AuctionContext::default()).await.unwrap().is_none());
+
+        let engine = SolverEngine::new(SolverConfig::default()).with_liquidity_source(Arc::new(source));
+        let solution = engine.solve(orders, &AuctionContext::default()).await.unwrap().unwrap();
+        assert_eq!(solution.settlement.trades.len(), 1);
+        // Approval of the sell token, then the swap
+        assert_eq!(solution.settlement.interactions.len(), 2);
+        assert!(solution.settlement.trades[0].executed_buy_amount > U256::from(19 * E18 / 10));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_solve_returns_best_so_far() {
+        use crate::solver::{CancellationToken, LiquidityPool, PoolType, StaticLiquiditySource, UnroutablePolicy};
+        use futures::StreamExt;
+
+        /// Cancels the solve once its pools are fetched, i.e. mid-solve
+        struct CancellingSource {
+            inner: StaticLiquiditySource,
+            cancellation: CancellationToken,
+        }
+
+        #[async_trait]
+        impl LiquiditySource for CancellingSource {
+            fn name(&self) -> &str {
+                self.inner.name()
+            }
+
+            async fn fetch_pools(&self, tokens: &[Address]) -> crate::Result<Vec<LiquidityPool>> {
+                self.cancellation.cancel();
+                self.inner.fetch_pools(tokens).await
+            }
+        }
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let token_c = Address::from_low_u64_be(3);
+        let token_d = Address::from_low_u64_be(4);
+
+        let pools = StaticLiquiditySource::new(
+            "static",
+            vec![LiquidityPool::with_defaults(
+                Address::from_low_u64_be(0x100),
+                PoolType::UniswapV2,
+                token_c,
+                token_d,
+                U256::from(1000 * E18),
+                U256::from(2000 * E18),
+                30,
+            )],
+        );
+        let orders = vec![
+            order_with_id(1, token_a, token_b, E18, 15 * E18 / 10),
+            order_with_id(2, token_b, token_a, 2 * E18, E18),
+            order_with_id(3, token_c, token_d, E18, 19 * E18 / 10),
+        ];
+        let config = SolverConfig::builder()
+            .unroutable_policy(UnroutablePolicy::FailBatch)
+            .build()
+            .unwrap();
+
+        let engine = SolverEngine::new(config.clone()).with_liquidity_source(Arc::new(pools.clone()));
+        let solution = engine.solve(orders.clone(), &AuctionContext::default()).await.unwrap().unwrap();
+        assert_eq!(solution.settlement.trades.len(), 3);
+
+        let ctx = AuctionContext::default();
+        let engine = SolverEngine::new(config).with_liquidity_source(Arc::new(CancellingSource {
+            inner: pools,
+            cancellation: ctx.cancellation.clone(),
+        }));
+        let (solution, trace) = engine.solve_with_trace(orders.clone(), &ctx).await.unwrap();
+        assert!(trace.cancelled);
+
+        // The CoW match survives; the order left unrouted doesn't fail the batch
+        let solution = solution.unwrap();
+        assert_eq!(solution.orders.len(), 2);
+        assert!(!solution.orders.contains(&orders[2].id));
+
+        // A stream cancelled up front yields nothing further
+        let stream = engine.solve_streaming(orders, ctx);
+        assert_eq!(stream.collect::<Vec<_>>().await.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_order_routed_when_amm_beats_cow_price() {
+        use crate::solver::{LiquidityPool, PoolType, PricingStrategy, StaticLiquiditySource};
+
+        let token_x = Address::from_low_u64_be(1);
+        let token_y = Address::from_low_u64_be(2);
+
+        // The pool gives ~3 Y per X, well above the CoW clearing rate
+        let source = Arc::new(StaticLiquiditySource::new(
+            "static",
+            vec![LiquidityPool::with_defaults(
+                Address::from_low_u64_be(0x100),
+                PoolType::UniswapV2,
+                token_x,
+                token_y,
+                U256::from(1000 * E18),
+                U256::from(3000 * E18),
+                30,
+            )],
+        ));
+        let orders = vec![
+            order_with_id(1, token_x, token_y, E18, 15 * E18 / 10),
+            order_with_id(2, token_y, token_x, 2 * E18, E18),
+        ];
+
+        let engine = SolverEngine::new(SolverConfig::default()).with_liquidity_source(source.clone());
+        let solution = engine.solve(orders.clone(), &AuctionContext::default()).await.unwrap().unwrap();
+        assert_eq!(solution.settlement.trades.len(), 2);
+        assert!(solution.settlement.interactions.is_empty());
+
+        let mut oracle = PricingEngine::new(PricingStrategy::MarketPrice, 0.0);
+        oracle.set_external_price(token_x, U256::from(3 * E18));
+        oracle.set_external_price(token_y, U256::from(E18));
+        let engine = SolverEngine::new(SolverConfig::default())
+            .with_liquidity_source(source.clone())
+            .with_reference_oracle(oracle);
+        let solution = engine.solve(orders.clone(), &AuctionContext::default()).await.unwrap().unwrap();
+
+        // X's seller is routed for ~3 Y; Y's seller can't get 1 X from the
+        // pool and is dropped rather than matched at a worse price for X
+        assert_eq!(solution.settlement.trades.len(), 1);
+        let trade = &solution.settlement.trades[0];
+        assert_eq!(trade.order_id, orders[0].id);
+        assert!(trade.executed_buy_amount > U256::from(29 * E18 / 10));
+        assert_eq!(solution.dropped_orders, vec![orders[1].id]);
+
+        // Only the ratio of oracle prices matters, even beyond u128
+        let mut oracle = PricingEngine::new(PricingStrategy::MarketPrice, 0.0);
+        oracle.set_external_price(token_x, U256::from(3 * E18) * U256::exp10(30));
+        oracle.set_external_price(token_y, U256::from(E18) * U256::exp10(30));
+        let engine = SolverEngine::new(SolverConfig::default())
+            .with_liquidity_source(source)
+            .with_reference_oracle(oracle);
+        let scaled = engine.solve(orders, &AuctionContext::default()).await.unwrap().unwrap();
+        assert_eq!(scaled.settlement.trades.len(), 1);
+        assert_eq!(scaled.settlement.trades[0].order_id, trade.order_id);
+        assert_eq!(scaled.settlement.trades[0].executed_buy_amount, trade.executed_buy_amount);
+        assert_eq!(scaled.dropped_orders, solution.dropped_orders);
+    }
+
+    #[tokio::test]
+    async fn test_route_rejected_when_slippage_breaches_limit() {
+        use crate::solver::{LiquidityPool, PoolType, StaticLiquiditySource};
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+
+        let source = Arc::new(StaticLiquiditySource::new(
+            "static",
+            vec![LiquidityPool::with_defaults(
+                Address::from_low_u64_be(0x100),
+                PoolType::UniswapV2,
+                token_a,
+                token_b,
+                U256::from(1000 * E18),
+                U256::from(2000 * E18),
+                30,
+            )],
+        ));
+        let engine = SolverEngine::new(SolverConfig::default()).with_liquidity_source(source);
+
+        // 1 A routes to ~1.992 B, at worst ~1.982 B after 0.5% slippage
+        let within = vec![create_test_order(token_a, token_b, E18, 198 * E18 / 100)];
+        let solution = engine.solve(within, &AuctionContext::default()).await.unwrap().unwrap();
+        assert_eq!(solution.settlement.trades.len(), 1);
+
+        // Quoted above the limit, but slippage could fill it below
+        let outside = vec![create_test_order(token_a, token_b, E18, 1985 * E18 / 1000)];
+        let (solution, trace) = engine.solve_with_trace(outside, &AuctionContext::default()).await.unwrap();
         assert!(solution.is_none());
+        assert_eq!(trace.reject_reason, Some(SolveRejectReason::NothingSettled));
+    }
+
+    #[tokio::test]
+    async fn test_surplus_scaled_by_token_decimals() {
+        use crate::solver::{LiquidityPool, PoolType, StaticLiquiditySource};
+
+        let token_a = Address::from_low_u64_be(1);
+        let usdc = Address::from_low_u64_be(2);
+        let e6 = 1000000u128;
+
+        let source = Arc::new(StaticLiquiditySource::new(
+            "static",
+            vec![LiquidityPool::with_defaults(
+                Address::from_low_u64_be(0x100),
+                PoolType::UniswapV2,
+                token_a,
+                usdc,
+                U256::from(1000 * E18),
+                U256::from(2000000 * e6),
+                30,
+            )],
+        ));
+        let orders = vec![create_test_order(token_a, usdc, E18, 1980 * e6)];
+
+        // 1 A routes to ~1992 USDC, ~12 USDC over the limit
+        let engine = SolverEngine::new(SolverConfig::default())
+            .with_liquidity_source(source.clone())
+            .with_token_registry(TokenRegistry::new().with_decimals(usdc, 6));
+        let solution = engine.solve(orders.clone(), &AuctionContext::default()).await.unwrap().unwrap();
+        assert!(solution.surplus > 11.0 && solution.surplus < 13.0);
+
+        // Read as an 18-decimal token the same surplus is dust
+        let engine = SolverEngine::new(SolverConfig::default()).with_liquidity_source(source);
+        let (solution, _) = engine.solve_with_trace(orders, &AuctionContext::default()).await.unwrap();
+        assert!(solution.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stale_liquidity_not_routed() {
+        use crate::solver::{LiquidityPool, PoolType, StaticLiquiditySource};
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+
+        let source = Arc::new(StaticLiquiditySource::new(
+            "static",
+            vec![LiquidityPool {
+                fetched_at_block: 90,
+                ..LiquidityPool::with_defaults(
+                    Address::from_low_u64_be(0x100),
+                    PoolType::UniswapV2,
+                    token_a,
+                    token_b,
+                    U256::from(1000 * E18),
+                    U256::from(2000 * E18),
+                    30,
+                )
+            }],
+        ));
+        let orders = vec![create_test_order(token_a, token_b, E18, 19 * E18 / 10)];
+        let ctx = AuctionContext {
+            block_number: 100,
+            ..AuctionContext::default()
+        };
+
+        let engine = SolverEngine::new(SolverConfig::default()).with_liquidity_source(source.clone());
+        assert!(engine.solve(orders.clone(), &ctx).await.unwrap().is_some());
+
+        let config = SolverConfig::builder().max_pool_staleness(3).build().unwrap();
+        let engine = SolverEngine::new(config).with_liquidity_source(source);
+        assert!(engine.solve(orders, &ctx).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_fee_deducted_from_swapped_sell_amount() {
+        use crate::solver::{LiquidityPool, PoolType, StaticLiquiditySource};
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let token_c = Address::from_low_u64_be(3);
+
+        // A matched pair, and an order only a pool can fill
+        let mut orders = vec![
+            order_with_id(1, token_a, token_b, E18, 19 * E18 / 10),
+            order_with_id(2, token_b, token_a, 2 * E18, E18),
+            order_with_id(3, token_a, token_c, E18, E18),
+        ];
+        for order in &mut orders {
+            order.fee_amount = U256::from(E18 / 100);
+        }
+
+        let source = StaticLiquiditySource::new(
+            "static",
+            vec![LiquidityPool::with_defaults(
+                Address::from_low_u64_be(0x100),
+                PoolType::UniswapV2,
+                token_a,
+                token_c,
+                U256::from(1000 * E18),
+                U256::from(2000 * E18),
+                30,
+            )],
+        );
+        let engine = SolverEngine::new(SolverConfig::default()).with_liquidity_source(Arc::new(source));
+        let solution = engine.solve(orders.clone(), &AuctionContext::default()).await.unwrap().unwrap();
+
+        assert_eq!(solution.settlement.trades.len(), 3);
+        for order in &orders {
+            let trade = solution.settlement.trades.iter().find(|t| t.order_id == order.id).unwrap();
+            assert_eq!(trade.executed_sell_amount + trade.fee, order.sell_amount);
+        }
+
+        // The swap only spends what's left after the fee
+        let swap = solution.settlement.interactions.iter().find_map(|i| i.flow.as_ref()).unwrap();
+        assert_eq!(swap.amount_in, orders[2].sell_amount - orders[2].fee_amount);
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_orders_dropped_by_digest() {
+        let engine = SolverEngine::new(SolverConfig::default());
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+
+        // Same order submitted twice under different placeholder ids
+        let original = create_test_order(token_a, token_b, 1000, 2000);
+        let mut resubmitted = original.clone();
+        resubmitted.id = OrderId([7u8; 32]);
+        let other = create_test_order(token_a, token_b, 1000, 2100);
+
+        let deduped = engine.dedupe_orders(vec![original.clone(), resubmitted, other]);
+        assert_eq!(deduped.len(), 2);
+        assert_eq!(deduped[0].id, original.id);
+
+        // Identical parameters from a different owner are a separate order
+        let mut other_owner = original.clone();
+        other_owner.owner = Address::from_low_u64_be(99);
+        assert_eq!(engine.dedupe_orders(vec![original, other_owner]).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_same_owner_counter_order_not_matched() {
+        let engine = SolverEngine::new(SolverConfig::default());
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+
+        // Crossing limits but not an exact offset, so nothing gets netted
+        let mut orders = vec![
+            create_test_order(token_a, token_b, E18, 19 * E18 / 10),
+            create_test_order(token_b, token_a, 2 * E18, E18),
+        ];
+        for order in &mut orders {
+            order.owner = Address::from_low_u64_be(42);
+        }
+
+        assert!(engine.find_cow_matches(&orders).await.is_empty());
+        assert!(engine.solve(orders, &AuctionContext::default()).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_unroutable_policy() {
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let token_c = Address::from_low_u64_be(3);
+
+        let orders = vec![
+            order_with_id(1, token_a, token_b, E18, 19 * E18 / 10),
+            order_with_id(2, token_b, token_a, 2 * E18, E18),
+            order_with_id(3, token_a, token_c, E18, 3 * E18), // No counterparty
+        ];
+
+        let engine = SolverEngine::new(SolverConfig::default());
+        let solution = engine.solve(orders.clone(), &AuctionContext::default()).await.unwrap().unwrap();
+        assert_eq!(solution.orders.len(), 2);
+        assert_eq!(solution.dropped_orders, vec![orders[2].id]);
+
+        let config = SolverConfig {
+            unroutable_policy: crate::solver::UnroutablePolicy::FailBatch,
+            ..SolverConfig::default()
+        };
+        let engine = SolverEngine::new(config);
+        assert!(matches!(
+            engine.solve(orders, &AuctionContext::default()).await,
+            Err(crate::Error::InsufficientLiquidity(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_self_offsetting_orders_are_netted() {
+        let engine = SolverEngine::new(SolverConfig::default());
+
+        let owner = Address::from_low_u64_be(42);
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let token_c = Address::from_low_u64_be(3);
+
+        let mut order_a = order_with_id(1, token_a, token_b, 1000, 2000);
+        order_a.owner = owner;
+        let mut order_b = order_with_id(2, token_b, token_a, 2000, 1000);
+        order_b.owner = owner;
+        let mut unrelated = order_with_id(3, token_a, token_c, 1000, 3000);
+        unrelated.owner = owner;
+
+        let (remaining, transfers) =
+            engine.net_self_offsetting_orders(&[order_a.clone(), order_b.clone(), unrelated]);
+
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].id, OrderId([3u8; 32]));
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].order_a, order_a.id);
+        assert_eq!(transfers[0].order_b, order_b.id);
+
+        let mut settlement = engine.build_settlement(&remaining, vec![]).await.unwrap();
+        for transfer in transfers {
+            settlement.add_internal_transfer(transfer);
+        }
+
+        assert!(settlement.interactions.is_empty());
+        assert!(settlement.trades.is_empty());
+        assert!(settlement.validate().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_different_owners_are_not_netted() {
+        let engine = SolverEngine::new(SolverConfig::default());
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+
+        let mut order_a = create_test_order(token_a, token_b, 1000, 2000);
+        order_a.owner = Address::from_low_u64_be(42);
+        let mut order_b = create_test_order(token_b, token_a, 2000, 1000);
+        order_b.owner = Address::from_low_u64_be(43);
+
+        let (remaining, transfers) = engine.net_self_offsetting_orders(&[order_a, order_b]);
+
+        assert_eq!(remaining.len(), 2);
+        assert!(transfers.is_empty());
+    }
+
+    #[test]
+    fn test_partially_filled_orders_netted_on_remaining_amounts() {
+        let engine = SolverEngine::new(SolverConfig::default());
+
+        let owner = Address::from_low_u64_be(42);
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+
+        // Half of the 1000 A -> 2000 B order is already filled
+        let mut partial = order_with_id(1, token_a, token_b, 1000, 2000);
+        partial.owner = owner;
+        partial.status = OrderStatus::PartiallyFilled;
+        partial.filled_amount = U256::from(500);
+        let mut full_counter = order_with_id(2, token_b, token_a, 2000, 1000);
+        full_counter.owner = owner;
+        let mut remaining_counter = order_with_id(3, token_b, token_a, 1000, 500);
+        remaining_counter.owner = owner;
+
+        // Offsetting the full amounts isn't enough once part is filled
+        let (remaining, transfers) = engine.net_self_offsetting_orders(&[partial.clone(), full_counter]);
+        assert_eq!(remaining.len(), 2);
+        assert!(transfers.is_empty());
+
+        let (remaining, transfers) = engine.net_self_offsetting_orders(&[partial, remaining_counter]);
+        assert!(remaining.is_empty());
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].amount_a, U256::from(500));
+        assert_eq!(transfers[0].amount_b, U256::from(1000));
+    }
+
+    #[tokio::test]
+    async fn test_build_settlement_rejects_cancelled_order() {
+        let config = SolverConfig::default();
+        let engine = SolverEngine::new(config);
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+
+        let mut orders = vec![
+            create_test_order(token_a, token_b, 1000, 2000),
+            create_test_order(token_b, token_a, 2000, 1000),
+        ];
+
+        let matches = engine.find_cow_matches(&orders).await;
+        assert_eq!(matches.len(), 1);
+
+        // Order gets cancelled between matching and settlement
+        orders[1].status = OrderStatus::Cancelled;
+
+        let result = engine.build_settlement(&orders, matches).await;
+        assert!(matches!(result, Err(crate::Error::InvalidOrder(_))));
+    }
+
+    #[tokio::test]
+    async fn test_inclusion_gate_drops_negative_match() {
+        let engine = SolverEngine::new(SolverConfig::default());
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+
+        let orders = vec![
+            order_with_id(1, token_a, token_b, E18, 2 * E18),
+            order_with_id(2, token_b, token_a, 2 * E18, E18),
+            order_with_id(3, token_a, token_b, E18, 2 * E18),
+            order_with_id(4, token_b, token_a, 2 * E18, E18),
+        ];
+
+        // First match fills with surplus, second fills exactly at limits
+        let surplus_fills = [3 * E18, 2 * E18, 2 * E18, E18];
+        let mut settlement = SettlementPlan::default();
+        for (order, buy) in orders.iter().zip(surplus_fills) {
+            settlement.add_trade(crate::settlement::Trade {
+                order_id: order.id,
+                sell_token: order.sell_token,
+                buy_token: order.buy_token,
+                executed_sell_amount: order.sell_amount,
+                executed_buy_amount: U256::from(buy),
+                fee: U256::zero(),
+            });
+        }
+
+        let score = |settlement: &SettlementPlan| {
+            let mut solution = Solution {
+                orders: vec![],
+                settlement: settlement.clone(),
+                gas_cost: settlement.estimate_gas(),
+                surplus: engine.calculate_surplus(&orders, settlement),
+                surplus_token: None,
+                order_surplus: vec![],
+                dropped_orders: vec![],
+                gas_excluded_orders: vec![],
+                overflow_orders: vec![],
+                chain: None,
+                valid_for_block: 0,
+                score: 0.0,
+            };
+            solution.calculate_score();
+            solution.score
+        };
+        let ungated_score = score(&settlement);
+
+        engine.apply_inclusion_gate(&orders, &[(0, 1), (2, 3)], &mut settlement, &AuctionContext::default());
+
+        let kept: Vec<OrderId> = settlement.trades.iter().map(|t| t.order_id).collect();
+        assert_eq!(kept, vec![orders[0].id, orders[1].id]);
+        assert!(score(&settlement) > ungated_score);
+
+        // At 30,000 gwei each trade's gas costs 1.5 tokens, more than
+        // either order of the first match earns
+        let ctx = AuctionContext {
+            gas_price: 30_000,
+            ..AuctionContext::default()
+        };
+        engine.apply_inclusion_gate(&orders, &[(0, 1)], &mut settlement, &ctx);
+        assert!(settlement.trades.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_gas_budget_drops_least_efficient_match() {
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+
+        let orders = vec![
+            order_with_id(1, token_a, token_b, E18, 2 * E18),
+            order_with_id(2, token_b, token_a, 2 * E18, E18),
+            order_with_id(3, token_a, token_b, E18, 2 * E18),
+            order_with_id(4, token_b, token_a, 2 * E18, E18),
+        ];
+
+        // Second match earns twice the surplus of the first for the same gas
+        let fills = [5 * E18 / 2, E18, 3 * E18, E18];
+        let mut settlement = SettlementPlan::default();
+        for (order, buy) in orders.iter().zip(fills) {
+            settlement.add_trade(crate::settlement::Trade {
+                order_id: order.id,
+                sell_token: order.sell_token,
+                buy_token: order.buy_token,
+                executed_sell_amount: order.sell_amount,
+                executed_buy_amount: U256::from(buy),
+                fee: U256::zero(),
+            });
+        }
+        let matches = [(0, 1), (2, 3)];
+
+        // Room for every trade: nothing dropped
+        let roomy = SolverEngine::new(SolverConfig::default());
+        let mut unchanged = settlement.clone();
+        assert!(roomy.apply_gas_budget(&orders, &matches, &mut unchanged).is_empty());
+        assert_eq!(unchanged.trades.len(), 4);
+
+        // Room for one match only
+        let budget = SettlementPlan::BASE_GAS + 3 * SettlementPlan::TRADE_GAS;
+        let config = SolverConfig::builder().max_settlement_gas(budget).build().unwrap();
+        let engine = SolverEngine::new(config);
+        let excluded = engine.apply_gas_budget(&orders, &matches, &mut settlement);
+
+        assert_eq!(excluded, vec![orders[0].id, orders[1].id]);
+        let kept: Vec<OrderId> = settlement.trades.iter().map(|t| t.order_id).collect();
+        assert_eq!(kept, vec![orders[2].id, orders[3].id]);
+        assert!(settlement.estimate_gas() <= budget);
+    }
+
+    struct MockBridge {
+        fee: U256,
+    }
+
+    impl crate::bridge::BridgeProvider for MockBridge {
+        fn name(&self) -> &str {
+            "Mock"
+        }
+
+        fn estimate_fee(
+            &self,
+            _token: Address,
+            _amount: U256,
+            _source: crate::domain::ChainId,
+            _destination: crate::domain::ChainId,
+        ) -> U256 {
+            self.fee
+        }
+
+        fn estimate_time(
+            &self,
+            _source: crate::domain::ChainId,
+            _destination: crate::domain::ChainId,
+        ) -> std::time::Duration {
+            std::time::Duration::from_secs(60)
+        }
+
+        fn encode_deposit(
+            &self,
+            _token: Address,
+            _amount: U256,
+            _recipient: Address,
+            _source: crate::domain::ChainId,
+            _destination: crate::domain::ChainId,
+        ) -> ethers::types::Bytes {
+            ethers::types::Bytes::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bridge_fee_reduces_score() {
+        use crate::solver::{LiquidityPool, PoolType, StaticLiquiditySource};
+
+        let usdc_mainnet = Address::from_low_u64_be(1);
+        let usdc_arbitrum = Address::from_low_u64_be(2);
+
+        // Sells and buys the bridge tokens, so only the bridge stands between
+        let mut order = create_test_order(usdc_mainnet, usdc_arbitrum, E18, E18 / 2);
+        order.source_chain = Some(ChainId::Ethereum);
+        order.destination_chain = Some(ChainId::Arbitrum);
+        order.bridge_provider = Some("Mock".to_string());
+
+        let solve = |fee: u128| {
+            let mut registry = BridgeRegistry::new();
+            registry.register(Arc::new(MockBridge { fee: U256::from(fee) }));
+            let source = StaticLiquiditySource::new(
+                "static",
+                vec![LiquidityPool::with_defaults(
+                    Address::from_low_u64_be(0x100),
+                    PoolType::UniswapV2,
+                    usdc_mainnet,
+                    Address::from_low_u64_be(3),
+                    U256::from(1000 * E18),
+                    U256::from(1000 * E18),
+                    30,
+                )],
+            );
+            let engine = SolverEngine::new(SolverConfig::default())
+                .with_bridge_registry(registry)
+                .with_liquidity_source(Arc::new(source))
+                .with_bridge_token(ChainId::Ethereum, usdc_mainnet)
+                .with_bridge_token(ChainId::Arbitrum, usdc_arbitrum)
+                .with_bridge_contract("Mock", ChainId::Ethereum, Address::from_low_u64_be(0x500));
+            let order = order.clone();
+            async move { engine.solve(vec![order], &AuctionContext::default()).await.unwrap().unwrap() }
+        };
+
+        let free = solve(0).await;
+        let charged = solve(E18 / 10).await;
+
+        // The fee comes out of what arrives, and out of surplus exactly once
+        let arrived = |solution: &Solution| solution.settlement.trades[0].executed_buy_amount;
+        assert_eq!(arrived(&free) - arrived(&charged), U256::from(E18 / 10));
+        assert!((free.surplus - charged.surplus - 0.1).abs() < 1e-9);
+        assert!((free.score - charged.score - 0.1).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_surplus_valued_in_reference_token() {
+        let token_a = Address::from_low_u64_be(1);
+        let usdc = Address::from_low_u64_be(2);
+        let wbtc = Address::from_low_u64_be(3);
+        let weth = Address::from_low_u64_be(4);
+
+        let to_usdc = order_with_id(1, token_a, usdc, E18, 2 * E18);
+        let to_wbtc = order_with_id(2, token_a, wbtc, E18, 2 * E18);
+        let orders = vec![to_usdc, to_wbtc];
+
+        // Both orders get one whole token more than their limit
+        let mut settlement = SettlementPlan::default();
+        for order in &orders {
+            settlement.add_trade(crate::settlement::Trade {
+                order_id: order.id,
+                sell_token: order.sell_token,
+                buy_token: order.buy_token,
+                executed_sell_amount: order.sell_amount,
+                executed_buy_amount: U256::from(3 * E18),
+                fee: order.fee_amount,
+            });
+        }
+        settlement.set_clearing_price(usdc, U256::from(1));
+        settlement.set_clearing_price(wbtc, U256::from(50000));
+        settlement.set_clearing_price(weth, U256::from(2000));
+
+        let unweighted = SolverEngine::new(SolverConfig::default());
+        assert!((unweighted.calculate_surplus(&orders, &settlement) - 2.0).abs() < 1e-9);
+
+        let config = SolverConfig::builder().reference_token(weth).build().unwrap();
+        let engine = SolverEngine::new(config);
+        let order_surplus: HashMap<OrderId, f64> =
+            engine.calculate_order_surplus(&orders, &settlement).into_iter().collect();
+        assert!((order_surplus[&orders[0].id] - 0.0005).abs() < 1e-9);
+        assert!((order_surplus[&orders[1].id] - 25.0).abs() < 1e-9);
+        assert!((engine.calculate_surplus(&orders, &settlement) - 25.0005).abs() < 1e-9);
+
+        // Simulation delivers a tenth of a WBTC less, worth 2.5 WETH
+        let order_surplus = engine.calculate_order_surplus(&orders, &settlement);
+        let mut solution = Solution {
+            orders: orders.iter().map(|o| o.id).collect(),
+            settlement,
+            gas_cost: 100_000,
+            surplus: 25.0005,
+            surplus_token: Some(weth),
+            order_surplus,
+            dropped_orders: vec![],
+            gas_excluded_orders: vec![],
+            overflow_orders: vec![],
+            chain: None,
+            valid_for_block: 0,
+            score: 0.0,
+        };
+        let realized = HashMap::from([(orders[1].id, (orders[1].sell_amount, U256::from(29 * E18 / 10)))]);
+        let ctx = AuctionContext { congestion_factor: Some(3.0), ..Default::default() };
+        let engine = engine.with_scoring_policy(Box::new(SurplusMinusGasPolicy));
+        engine.recompute_surplus_from_simulation(&mut solution, &realized, &ctx);
+        assert!((solution.surplus - 22.5005).abs() < 1e-9);
+        assert!((solution.order_surplus[1].1 - 22.5).abs() < 1e-9);
+        assert!((solution.score - (22.5005 - ctx.gas_cost_eth(100_000))).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_recompute_surplus_from_worse_simulation() {
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let id_a = OrderId([1u8; 32]);
+        let id_b = OrderId([2u8; 32]);
+        let e18 = U256::from(E18);
+
+        // Token B is worth twice token A, the reference token
+        let mut settlement = SettlementPlan::default();
+        settlement.set_clearing_price(token_a, e18);
+        settlement.set_clearing_price(token_b, e18 * 2);
+        settlement.add_trade(Trade {
+            order_id: id_a,
+            sell_token: token_a,
+            buy_token: token_b,
+            executed_sell_amount: e18,
+            executed_buy_amount: e18 * 2,
+            fee: U256::zero(),
+        });
+        settlement.add_trade(Trade {
+            order_id: id_b,
+            sell_token: token_b,
+            buy_token: token_a,
+            executed_sell_amount: e18 * 2,
+            executed_buy_amount: e18,
+            fee: U256::zero(),
+        });
+
+        let mut solution = Solution {
+            orders: vec![id_a, id_b],
+            settlement,
+            gas_cost: 100_000,
+            surplus: 0.5,
+            surplus_token: None,
+            order_surplus: vec![(id_a, 0.3), (id_b, 0.2)],
+            dropped_orders: vec![],
+            gas_excluded_orders: vec![],
+            overflow_orders: vec![],
+            chain: None,
+            valid_for_block: 0,
+            score: 0.0,
+        };
+        let ctx = AuctionContext { congestion_factor: Some(2.0), ..Default::default() };
+        let engine = SolverEngine::new(SolverConfig::builder().reference_token(token_a).build().unwrap());
+        solution.score = SurplusMinusGasPolicy.score(&solution, &ctx);
+        let quoted_score = solution.score;
+
+        // Simulation delivers 0.1 less of A's buy token, and B pays 0.05
+        // more of its sell token for the same buy amount
+        let mut realized = HashMap::new();
+        realized.insert(id_a, (e18, e18 * 2 - e18 / 10));
+        realized.insert(id_b, (e18 * 2 + e18 / 20, e18));
+        engine.recompute_surplus_from_simulation(&mut solution, &realized, &ctx);
+
+        assert!((solution.surplus - 0.2).abs() < 1e-9);
+        assert!((solution.order_surplus[0].1 - 0.1).abs() < 1e-9);
+        assert!((solution.order_surplus[1].1 - 0.1).abs() < 1e-9);
+        assert_eq!(solution.settlement.trades[0].executed_buy_amount, e18 * 2 - e18 / 10);
+        assert_eq!(solution.settlement.trades[1].executed_sell_amount, e18 * 2 + e18 / 20);
+        assert_eq!(solution.score, SurplusMinusGasPolicy.score(&solution, &ctx));
+        assert!(solution.score < quoted_score);
+    }
+
+    #[tokio::test]
+    async fn test_cross_chain_order_split_into_legs() {
+        use crate::settlement::InteractionType;
+        use crate::solver::{LiquidityPool, PoolType, StaticLiquiditySource};
+
+        let sell_token = Address::from_low_u64_be(1);
+        let usdc_mainnet = Address::from_low_u64_be(2);
+        let usdc_arbitrum = Address::from_low_u64_be(3);
+        let buy_token = Address::from_low_u64_be(4);
+        let spoke_pool = Address::from_low_u64_be(0x500);
+
+        let pool = |address: u64, token_a: Address, token_b: Address| LiquidityPool {
+            address: Address::from_low_u64_be(address),
+            pool_type: PoolType::UniswapV2,
+            token_a,
+            token_b,
+            reserve_a: U256::from(1000000 * E18),
+            reserve_b: U256::from(1000000 * E18),
+            fee_bps: 30,
+            gas_cost: 100000,
+            concentrated: None,
+            amplification: None,
+            fetched_at_block: 0,
+        };
+        let source = StaticLiquiditySource::new(
+            "static",
+            vec![pool(0x100, sell_token, usdc_mainnet), pool(0x200, usdc_arbitrum, buy_token)],
+        );
+
+        let mut order = create_test_order(sell_token, buy_token, E18, E18 / 2);
+        order.source_chain = Some(ChainId::Ethereum);
+        order.destination_chain = Some(ChainId::Arbitrum);
+        order.bridge_provider = Some("Mock".to_string());
+
+        let mut registry = BridgeRegistry::new();
+        registry.register(Arc::new(MockBridge {
+            fee: U256::from(E18 / 1000),
+        }));
+        let engine = SolverEngine::new(SolverConfig::default())
+            .with_bridge_registry(registry)
+            .with_liquidity_source(Arc::new(source))
+            .with_bridge_token(ChainId::Ethereum, usdc_mainnet)
+            .with_bridge_token(ChainId::Arbitrum, usdc_arbitrum)
+            .with_bridge_contract("Mock", ChainId::Ethereum, spoke_pool);
+
+        let ctx = AuctionContext::default();
+        let solution = engine.solve(vec![order.clone()], &ctx).await.unwrap().unwrap();
+        let settlement = &solution.settlement;
+
+        assert_eq!(settlement.trades.len(), 1);
+        assert!(settlement.trades[0].executed_buy_amount >= order.buy_amount);
+
+        assert_eq!(settlement.post_hooks.len(), 1);
+        let hook = &settlement.post_hooks[0];
+        assert_eq!(hook.bridge_contract, spoke_pool);
+        assert_eq!(hook.source_chain, ChainId::Ethereum);
+        assert_eq!(hook.destination_chain, ChainId::Arbitrum);
+        assert_eq!(hook.intermediate_token, usdc_mainnet);
+        assert!(settlement
+            .interactions
+            .iter()
+            .any(|i| i.approves(usdc_mainnet, spoke_pool)));
+
+        let swaps: Vec<_> = settlement
+            .interactions
+            .iter()
+            .filter(|i| i.interaction_type == InteractionType::UniswapV2Swap)
+            .collect();
+        assert_eq!(swaps.len(), 2);
+        let source_flow = swaps[0].flow.as_ref().unwrap();
+        assert_eq!(swaps[0].chain, None);
+        assert_eq!((source_flow.token_in, source_flow.token_out), (sell_token, usdc_mainnet));
+        assert!(hook.amount <= source_flow.amount_out); // Bridges the slippage-protected minimum
+        let destination_flow = swaps[1].flow.as_ref().unwrap();
+        assert_eq!(swaps[1].chain, Some(ChainId::Arbitrum));
+        assert_eq!((destination_flow.token_in, destination_flow.token_out), (usdc_arbitrum, buy_token));
+        assert_eq!(destination_flow.amount_in, hook.amount - U256::from(E18 / 1000));
+
+        // The trade is held to what the destination swap guarantees, so a
+        // limit only the expected output meets is rejected
+        let guaranteed = settlement.trades[0].executed_buy_amount;
+        assert!(guaranteed < destination_flow.amount_out);
+        let mut tight = order.clone();
+        tight.buy_amount = (guaranteed + destination_flow.amount_out) / 2;
+        assert!(engine.solve(vec![tight], &ctx).await.unwrap().is_none());
+
+        // Without a bridge token on Arbitrum the destination chain is unsupported
+        let mut registry = BridgeRegistry::new();
+        registry.register(Arc::new(MockBridge { fee: U256::zero() }));
+        let unsupported = SolverEngine::new(SolverConfig::default())
+            .with_bridge_registry(registry)
+            .with_liquidity_source(Arc::new(StaticLiquiditySource::new(
+                "static",
+                vec![pool(0x100, sell_token, usdc_mainnet), pool(0x200, usdc_arbitrum, buy_token)],
+            )))
+            .with_bridge_token(ChainId::Ethereum, usdc_mainnet)
+            .with_bridge_contract("Mock", ChainId::Ethereum, spoke_pool);
+        assert!(unsupported.solve(vec![order], &ctx).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_solve_streaming_scores_non_decreasing() {
+        use futures::StreamExt;
+
+        let engine = SolverEngine::new(SolverConfig::default());
+
+        // Three independent pairs, each with room for surplus
+        let mut orders = Vec::new();
+        for pair in 0..3u8 {
+            let token_x = Address::from_low_u64_be(2 * pair as u64 + 1);
+            let token_y = Address::from_low_u64_be(2 * pair as u64 + 2);
+            orders.push(order_with_id(2 * pair + 1, token_x, token_y, E18, 15 * E18 / 10));
+            orders.push(order_with_id(2 * pair + 2, token_y, token_x, 2 * E18, E18));
+        }
+
+        let solutions: Vec<Solution> = engine
+            .solve_streaming(orders.clone(), AuctionContext::default())
+            .collect()
+            .await;
+        assert!(solutions.len() > 1);
+        for window in solutions.windows(2) {
+            assert!(window[1].score >= window[0].score);
+        }
+
+        let best = engine.solve(orders, &AuctionContext::default()).await.unwrap().unwrap();
+        assert_eq!(solutions.last().unwrap().score, best.score);
+        assert_eq!(best.orders.len(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_near_expiry_orders_matched_first_when_capped() {
+        let token_x = Address::from_low_u64_be(1);
+        let token_y = Address::from_low_u64_be(2);
+        let token_z = Address::from_low_u64_be(3);
+        let token_w = Address::from_low_u64_be(4);
+
+        // The far-expiry pair is submitted first and trades more volume
+        let mut orders = vec![
+            order_with_id(1, token_x, token_y, 2 * E18, 3 * E18),
+            order_with_id(2, token_y, token_x, 4 * E18, 2 * E18),
+            order_with_id(3, token_z, token_w, E18, 15 * E18 / 10),
+            order_with_id(4, token_w, token_z, 2 * E18, E18),
+        ];
+        for (n, order) in orders.iter_mut().enumerate() {
+            order.valid_to = if n < 2 { u32::MAX } else { u32::MAX - 1 };
+        }
+
+        let capped = SolverConfig::builder().max_batch_size(2);
+
+        // Largest volume first by default
+        let engine = SolverEngine::new(capped.clone().build().unwrap());
+        let solution = engine.solve(orders.clone(), &AuctionContext::default()).await.unwrap().unwrap();
+        assert_eq!(solution.orders.len(), 2);
+        assert!(solution.orders.contains(&orders[0].id) && solution.orders.contains(&orders[1].id));
+        assert_eq!(solution.overflow_orders, vec![orders[3].id, orders[2].id]);
+
+        let engine = SolverEngine::new(capped.prioritize_expiring(true).build().unwrap());
+        let (solution, trace) = engine.solve_with_trace(orders.clone(), &AuctionContext::default()).await.unwrap();
+        let solution = solution.unwrap();
+        assert_eq!(solution.orders, vec![orders[2].id, orders[3].id]);
+        assert_eq!(solution.overflow_orders, vec![orders[0].id, orders[1].id]);
+        assert!(solution.dropped_orders.is_empty());
+        assert_eq!(trace.valid_orders, 2);
+        assert_eq!(trace.overflow_orders, 2);
+    }
+
+    #[tokio::test]
+    async fn test_matches_sharing_tokens_clear_at_uniform_prices() {
+        let engine = SolverEngine::new(SolverConfig::default());
+
+        let token_x = Address::from_low_u64_be(1);
+        let token_y = Address::from_low_u64_be(2);
+
+        let mut orders = vec![
+            // Clears at 2 Y per X
+            order_with_id(1, token_x, token_y, E18, 2 * E18),
+            order_with_id(2, token_y, token_x, 2 * E18, E18),
+            // Would clear anywhere in 1.5-2.5, so 2 is accepted
+            order_with_id(3, token_x, token_y, E18, 15 * E18 / 10),
+            order_with_id(4, token_y, token_x, 25 * E18 / 10, E18),
+            // Needs at least 3, which 2 can't give
+            order_with_id(5, token_x, token_y, E18, 3 * E18),
+            order_with_id(6, token_y, token_x, 4 * E18, E18),
+        ];
+        for order in &mut orders {
+            // The first pair only clears at exactly 2 when nothing is taken as fee
+            order.fee_amount = U256::zero();
+        }
+
+        let settlement = engine
+            .build_settlement(&orders, vec![(0, 1), (2, 3), (4, 5)])
+            .await
+            .unwrap();
+        let traded: Vec<OrderId> = settlement.trades.iter().map(|t| t.order_id).collect();
+        assert_eq!(traded, orders[..4].iter().map(|o| o.id).collect::<Vec<_>>());
+
+        // Every trade executes at the one ratio of the tokens' prices, up
+        // to rounding
+        let prices = &settlement.clearing_prices;
+        assert_eq!(prices[&token_x], prices[&token_y] * 2);
+        for trade in &settlement.trades {
+            let paid = trade.executed_sell_amount * prices[&trade.sell_token];
+            let received = trade.executed_buy_amount * prices[&trade.buy_token];
+            let diff = if paid > received { paid - received } else { received - paid };
+            assert!(diff * U256::exp10(12) < paid);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_settlement_preview_matches_built_settlement() {
+        let engine = SolverEngine::new(SolverConfig::default());
+
+        let token_x = Address::from_low_u64_be(1);
+        let token_y = Address::from_low_u64_be(2);
+
+        let orders = vec![
+            order_with_id(1, token_x, token_y, E18, 15 * E18 / 10),
+            order_with_id(2, token_y, token_x, 25 * E18 / 10, E18),
+            // Conflicts with the clearing price of the first match
+            order_with_id(3, token_x, token_y, E18, 3 * E18),
+            order_with_id(4, token_y, token_x, 4 * E18, E18),
+        ];
+        let matches = vec![(0, 1), (2, 3)];
+
+        let preview = engine.plan_settlement(&orders, &matches).unwrap();
+        assert_eq!(preview.skipped_matches, vec![(2, 3)]);
+        assert!(preview.surplus > 0.0);
+
+        let settlement = engine.build_settlement(&orders, matches).await.unwrap();
+        assert_eq!(preview.trades.len(), settlement.trades.len());
+        assert_eq!(preview.clearing_prices, settlement.clearing_prices);
+        assert_eq!(preview.estimated_gas, settlement.estimate_gas());
+        assert_eq!(preview.surplus, engine.calculate_surplus(&orders, &settlement));
+    }
+
+    #[test]
+    fn test_match_missing_a_limit_is_skipped() {
+        let engine = SolverEngine::new(SolverConfig::default());
+
+        let token_x = Address::from_low_u64_be(1);
+        let token_y = Address::from_low_u64_be(2);
+
+        // A wants 2 Y per X but B only gives 1.99: no rate meets both, so
+        // neither order is paid past what the rate provides
+        let orders = vec![
+            order_with_id(1, token_x, token_y, E18, 2 * E18),
+            order_with_id(2, token_y, token_x, 199 * E18 / 100, E18),
+        ];
+
+        let preview = engine.plan_settlement(&orders, &[(0, 1)]).unwrap();
+        assert_eq!(preview.skipped_matches, vec![(0, 1)]);
+        assert!(preview.trades.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_clearing_price_maximizes_surplus() {
+        let engine = SolverEngine::new(SolverConfig::default());
+
+        let token_x = Address::from_low_u64_be(1);
+        let token_y = Address::from_low_u64_be(2);
+        let scale = U256::exp10(18);
+
+        // A buys 2 Y for at most 1.2 X, B sells 2 Y for at least 1 X. Valued
+        // in Y, total surplus rises with the price, so B's limit on what it
+        // swaps after its fee wins.
+        let mut orders = vec![
+            order_with_id(1, token_x, token_y, 12 * E18 / 10, 2 * E18),
+            order_with_id(2, token_y, token_x, 2 * E18, E18),
+        ];
+        orders[0].kind = OrderType::Buy;
+
+        let chosen = engine.calculate_clearing_price(&orders[0], &orders[1]);
+        assert_eq!(chosen, orders[1].sell_amount_after_fee() * scale / orders[1].buy_amount);
+        let at = |price: f64| SolverEngine::pair_surplus(&orders[0], &orders[1], price);
+        assert!(at(2.0) > at(1.8));
+        assert!(at(2.0) > at(2.0 / 1.2));
+
+        // The pair's limits are asymmetric around 1, so the geometric mean
+        // of the limits is a real alternative, and never does better
+        let lowest = orders[0].buy_amount * scale / orders[0].sell_amount_after_fee();
+        let geometric = U256::try_from(lowest.full_mul(chosen).integer_sqrt()).unwrap();
+        assert!(geometric > lowest && geometric < chosen);
+        assert!(at(u256_to_f64(chosen) / 1e18) >= at(u256_to_f64(geometric) / 1e18));
+
+        let settlement = engine.build_settlement(&orders, vec![(0, 1)]).await.unwrap();
+        assert!(settlement.check_balances(&orders).is_ok());
+
+        // Two sell orders of different sizes only balance at 4 Y per X, so
+        // the price stops at the nearest limit rather than an endpoint of
+        // the objective
+        let order_a = order_with_id(1, token_x, token_y, E18, 19 * E18 / 10);
+        let order_b = order_with_id(2, token_y, token_x, 4 * E18, 2 * E18);
+        let chosen = engine.calculate_clearing_price(&order_a, &order_b);
+        assert_eq!(chosen, order_b.sell_amount_after_fee() * scale / order_b.buy_amount);
+    }
+
+    #[test]
+    fn test_surplus_distribution_policies() {
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+
+        // A sells 10 for at least 10 (limit 1), B sells 2 for 1 (limit 2)
+        let order_a = create_test_order(token_a, token_b, 10 * E18, 10 * E18);
+        let order_b = create_test_order(token_b, token_a, 2 * E18, E18);
+        let surpluses = |distribution| {
+            let engine = SolverEngine::new(SolverConfig::builder().surplus_distribution(distribution).build().unwrap());
+            let price = u256_to_f64(engine.calculate_clearing_price(&order_a, &order_b)) / 1e18;
+            assert!((1.0..=2.0).contains(&price));
+            // In B tokens, for the larger A and the smaller B
+            (10.0 * price - 10.0, 2.0 - price)
+        };
+
+        let (proportional_a, proportional_b) = surpluses(SurplusDistribution::Proportional);
+        let (equal_a, equal_b) = surpluses(SurplusDistribution::EqualSplit);
+        assert!(proportional_a > equal_a);
+        assert!((proportional_a / 10.0 - proportional_b).abs() < 1e-9);
+        assert!((equal_a - equal_b).abs() < 1e-9);
+
+        let (favored_a, favored_b) = surpluses(SurplusDistribution::FavorSmaller);
+        assert!(favored_a.abs() < 1e-9);
+        assert!((favored_b - 1.0).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_clearing_price_flat_objective_uses_midpoint() {
+        let engine = SolverEngine::new(SolverConfig::default());
+
+        let token_x = Address::from_low_u64_be(1);
+        let token_y = Address::from_low_u64_be(2);
+
+        // A sells exactly the X that B wants to buy: any price moves Y
+        // surplus from one side to the other without changing the total
+        let order_a = create_test_order(token_x, token_y, E18, 19 * E18 / 10);
+        let mut order_b = create_test_order(token_y, token_x, 2 * E18, E18);
+        order_b.kind = OrderType::Buy;
+
+        let price = u256_to_f64(engine.calculate_clearing_price(&order_a, &order_b)) / 1e18;
+        assert!((price - 1.95).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_clearing_price_beyond_u128() {
+        let engine = SolverEngine::new(SolverConfig::default());
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+
+        // 1000 wei of A for 1e24 B against 1e25 B for 1000 wei of A: both
+        // limits, scaled by 1e18, are beyond u128
+        let mut order_a = create_test_order(token_a, token_b, 1000, 0);
+        order_a.buy_amount = U256::exp10(24);
+        order_a.fee_amount = U256::zero();
+        let mut order_b = create_test_order(token_b, token_a, 0, 1000);
+        order_b.sell_amount = U256::exp10(25);
+        order_b.fee_amount = U256::zero();
+        assert_eq!(engine.calculate_clearing_price(&order_a, &order_b), U256::exp10(40));
+
+        // Against a buy order the objective is linear and scored in floats:
+        // B buys twice what A sells, so the total falls as the price rises
+        order_a.buy_amount = U256::exp10(39);
+        order_b.sell_amount = U256::exp10(40);
+        order_b.buy_amount = U256::from(2000);
+        order_b.kind = OrderType::Buy;
+        assert_eq!(engine.calculate_clearing_price(&order_a, &order_b), U256::exp10(54));
     }
 }
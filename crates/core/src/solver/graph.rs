@@ -0,0 +1,178 @@
+use ethers::types::Address;
+use std::collections::HashMap;
+
+/// Directed multigraph over tokens, each edge labelled with what it came
+/// from (e.g. an order or pool index).
+///
+/// Shared by matching, which looks for cycles of orders, and routing, which
+/// looks for paths through pools. Searches walk distinct tokens, so parallel
+/// edges between the same tokens never multiply the results.
+#[derive(Debug, Clone)]
+pub struct TokenGraph<E = ()> {
+    /// Distinct tokens one edge away, in the order first connected
+    neighbors: HashMap<Address, Vec<Address>>,
+
+    /// Labels of the edges between each ordered pair of tokens
+    edges: HashMap<(Address, Address), Vec<E>>,
+}
+
+impl<E> Default for TokenGraph<E> {
+    fn default() -> Self {
+        Self {
+            neighbors: HashMap::new(),
+            edges: HashMap::new(),
+        }
+    }
+}
+
+impl<E> TokenGraph<E> {
+    /// Creates an empty graph
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an edge from `from` to `to`, keeping any parallel edges
+    pub fn add_edge(&mut self, from: Address, to: Address, label: E) {
+        let labels = self.edges.entry((from, to)).or_default();
+        if labels.is_empty() {
+            self.neighbors.entry(from).or_default().push(to);
+        }
+        labels.push(label);
+    }
+
+    /// Returns the distinct tokens one edge away from `token`
+    pub fn neighbors(&self, token: Address) -> &[Address] {
+        self.neighbors.get(&token).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Returns the labels of the edges from `from` to `to`
+    pub fn edges(&self, from: Address, to: Address) -> &[E] {
+        self.edges.get(&(from, to)).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Finds every path of 1 to `max_depth` edges from `start` to `end`
+    /// that visits no token twice, as the tokens it passes through
+    pub fn find_paths(&self, start: Address, end: Address, max_depth: usize) -> Vec<Vec<Address>> {
+        let mut paths = Vec::new();
+        let mut stack = vec![vec![start]];
+
+        while let Some(path) = stack.pop() {
+            let current = path[path.len() - 1];
+            if current == end && path.len() > 1 {
+                paths.push(path);
+                continue;
+            }
+
+            // A path of n edges visits n + 1 tokens
+            if path.len() > max_depth {
+                continue;
+            }
+
+            for &next in self.neighbors(current) {
+                if !path.contains(&next) {
+                    let mut extended = path.clone();
+                    extended.push(next);
+                    stack.push(extended);
+                }
+            }
+        }
+
+        paths
+    }
+
+    /// Finds every cycle of 2 to `max_size` distinct tokens, as the tokens
+    /// in edge order.
+    ///
+    /// Each cycle is reported once, starting from its smallest token.
+    pub fn find_cycles(&self, max_size: usize) -> Vec<Vec<Address>> {
+        let mut cycles = Vec::new();
+        let mut starts: Vec<Address> = self.neighbors.keys().copied().collect();
+        starts.sort();
+
+        for start in starts {
+            // Only tokens above the start, so no rotation is found twice
+            let mut stack = vec![vec![start]];
+            while let Some(path) = stack.pop() {
+                for &next in self.neighbors(path[path.len() - 1]) {
+                    if next == start && path.len() >= 2 {
+                        cycles.push(path.clone());
+                    } else if next > start && path.len() < max_size && !path.contains(&next) {
+                        let mut extended = path.clone();
+                        extended.push(next);
+                        stack.push(extended);
+                    }
+                }
+            }
+        }
+
+        cycles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(n: u64) -> Address {
+        Address::from_low_u64_be(n)
+    }
+
+    #[test]
+    fn test_parallel_edges_keep_labels_but_not_neighbors() {
+        let mut graph = TokenGraph::new();
+        graph.add_edge(token(1), token(2), 10);
+        graph.add_edge(token(1), token(2), 11);
+        graph.add_edge(token(1), token(3), 12);
+
+        assert_eq!(graph.neighbors(token(1)), &[token(2), token(3)]);
+        assert_eq!(graph.edges(token(1), token(2)), &[10, 11]);
+        assert!(graph.neighbors(token(2)).is_empty());
+        assert!(graph.edges(token(2), token(1)).is_empty());
+    }
+
+    #[test]
+    fn test_find_paths_within_depth() {
+        // 1 -> 2 -> 4, 1 -> 3 -> 4, 1 -> 4 twice, 4 -> 1
+        let mut graph = TokenGraph::new();
+        for (from, to) in [(1, 2), (2, 4), (1, 3), (3, 4), (1, 4), (1, 4), (4, 1)] {
+            graph.add_edge(token(from), token(to), ());
+        }
+
+        let mut paths = graph.find_paths(token(1), token(4), 2);
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![
+                vec![token(1), token(2), token(4)],
+                vec![token(1), token(3), token(4)],
+                vec![token(1), token(4)],
+            ]
+        );
+
+        assert_eq!(graph.find_paths(token(1), token(4), 1), vec![vec![token(1), token(4)]]);
+        // 2 -> 4 -> 1 -> 3 needs three edges
+        assert!(graph.find_paths(token(2), token(3), 2).is_empty());
+        assert_eq!(graph.find_paths(token(2), token(3), 3).len(), 1);
+        // Revisiting the start is never a path
+        assert!(graph.find_paths(token(1), token(1), 3).is_empty());
+    }
+
+    #[test]
+    fn test_find_cycles_reports_each_once() {
+        // Triangle 1 -> 2 -> 3 -> 1, a 2-cycle 3 <-> 4, and a dead end 4 -> 5
+        let mut graph = TokenGraph::new();
+        for (from, to) in [(1, 2), (2, 3), (3, 1), (3, 1), (3, 4), (4, 3), (4, 5)] {
+            graph.add_edge(token(from), token(to), ());
+        }
+
+        let mut cycles = graph.find_cycles(3);
+        cycles.sort();
+        assert_eq!(
+            cycles,
+            vec![vec![token(1), token(2), token(3)], vec![token(3), token(4)]]
+        );
+
+        assert_eq!(graph.find_cycles(2), vec![vec![token(3), token(4)]]);
+        assert!(graph.find_cycles(1).is_empty());
+    }
+}
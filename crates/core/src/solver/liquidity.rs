@@ -0,0 +1,122 @@
+use super::{LiquidityPool, RoutingEngine};
+use async_trait::async_trait;
+use ethers::types::Address;
+use tracing::debug;
+
+/// Source of AMM liquidity for an auction
+#[async_trait]
+pub trait LiquiditySource: Send + Sync {
+    /// Returns source name (matches `AuctionContext::liquidity_sources`)
+    fn name(&self) -> &str;
+
+    /// Fetches pools trading any of the given tokens
+    async fn fetch_pools(&self, tokens: &[Address]) -> crate::Result<Vec<LiquidityPool>>;
+}
+
+/// In-memory liquidity source, mainly for testing
+#[derive(Debug, Clone)]
+pub struct StaticLiquiditySource {
+    name: String,
+    pools: Vec<LiquidityPool>,
+}
+
+impl StaticLiquiditySource {
+    /// Creates a source serving a fixed set of pools
+    pub fn new(name: impl Into<String>, pools: Vec<LiquidityPool>) -> Self {
+        Self {
+            name: name.into(),
+            pools,
+        }
+    }
+}
+
+#[async_trait]
+impl LiquiditySource for StaticLiquiditySource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn fetch_pools(&self, tokens: &[Address]) -> crate::Result<Vec<LiquidityPool>> {
+        Ok(self
+            .pools
+            .iter()
+            .filter(|pool| tokens.contains(&pool.token_a) || tokens.contains(&pool.token_b))
+            .cloned()
+            .collect())
+    }
+}
+
+/// Builds a routing engine from pools fetched across `sources`
+pub async fn build_routing_engine(
+    sources: &[std::sync::Arc<dyn LiquiditySource>],
+    tokens: &[Address],
+) -> crate::Result<RoutingEngine> {
+    let mut routing = RoutingEngine::default();
+
+    for source in sources {
+        let pools = source.fetch_pools(tokens).await?;
+        debug!("Fetched {} pools from {}", pools.len(), source.name());
+        for pool in pools {
+            routing.add_pool(pool);
+        }
+    }
+
+    Ok(routing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::PoolType;
+    use ethers::types::U256;
+    use std::sync::Arc;
+
+    fn pool(address: u64, token_a: Address, token_b: Address) -> LiquidityPool {
+        LiquidityPool {
+            address: Address::from_low_u64_be(address),
+            pool_type: PoolType::UniswapV2,
+            token_a,
+            token_b,
+            reserve_a: U256::from(1000000),
+            reserve_b: U256::from(2000000),
+            fee_bps: 30,
+            gas_cost: 100000,
+            concentrated: None,
+            amplification: None,
+            fetched_at_block: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_static_source_filters_by_token() {
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let token_c = Address::from_low_u64_be(3);
+        let token_d = Address::from_low_u64_be(4);
+
+        let source = StaticLiquiditySource::new(
+            "static",
+            vec![pool(0x10, token_a, token_b), pool(0x20, token_c, token_d)],
+        );
+
+        let pools = source.fetch_pools(&[token_a]).await.unwrap();
+        assert_eq!(pools.len(), 1);
+        assert_eq!(pools[0].address, Address::from_low_u64_be(0x10));
+    }
+
+    #[tokio::test]
+    async fn test_routing_engine_from_sources() {
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let token_c = Address::from_low_u64_be(3);
+
+        let sources: Vec<Arc<dyn LiquiditySource>> = vec![
+            Arc::new(StaticLiquiditySource::new("first", vec![pool(0x10, token_a, token_b)])),
+            Arc::new(StaticLiquiditySource::new("second", vec![pool(0x20, token_b, token_c)])),
+        ];
+
+        let routing = build_routing_engine(&sources, &[token_a, token_c]).await.unwrap();
+        let route = routing.find_best_route(token_a, token_c, U256::from(1000)).unwrap();
+        assert_eq!(route.pools.len(), 2);
+    }
+}
@@ -1,4 +1,7 @@
 use crate::domain::{Order, OrderId};
+use crate::settlement::SettlementPlan;
+use super::graph::TokenGraph;
+use ethers::types::{Address, U256, U512};
 use std::collections::{HashMap, HashSet};
 use tracing::{debug, info};
 
@@ -16,6 +19,47 @@ pub struct OrderMatch {
     
     /// Estimated surplus generated
     pub estimated_surplus: f64,
+
+    /// Clearing prices for the match's tokens (empty for direct pairs,
+    /// which the settlement engine prices itself)
+    pub clearing_prices: HashMap<Address, U256>,
+}
+
+/// Uniform clearing of a ring match
+#[derive(Debug, Clone)]
+struct RingClearing {
+    /// Price per token, with the first order's sell token at 1e18
+    prices: HashMap<Address, U256>,
+
+    /// Factor by which every order's clearing rate beats its limit rate
+    improvement: f64,
+
+    /// Surplus in units of the first order's sell token
+    surplus: f64,
+}
+
+/// Price assigned to the reference token of a ring
+const RING_PRICE_SCALE: f64 = 1e18;
+
+/// Gas on top of each trade of a ring or batch match, for the extra token
+/// transfer and clearing price every additional token brings
+const RING_ORDER_GAS: u64 = 20000;
+
+impl OrderMatch {
+    /// Estimates the gas settling this match adds: a trade per order, plus
+    /// the overhead of clearing every token of a ring or batch
+    pub fn estimated_gas(&self) -> u64 {
+        let per_order = match self.match_type {
+            MatchType::DirectPair => SettlementPlan::TRADE_GAS,
+            MatchType::Ring | MatchType::Batch => SettlementPlan::TRADE_GAS + RING_ORDER_GAS,
+        };
+        self.orders.len() as u64 * per_order
+    }
+
+    /// Estimated surplus per unit of gas
+    pub fn surplus_per_gas(&self) -> f64 {
+        self.estimated_surplus / self.estimated_gas().max(1) as f64
+    }
 }
 
 /// Type of order match
@@ -38,6 +82,12 @@ pub struct MatchingEngine {
     
     /// Minimum quality score to accept
     min_quality_score: f64,
+
+    /// Minimum matchable volume, in reference token units
+    min_match_volume: U256,
+
+    /// Token prices in reference token units, scaled by 1e18
+    reference_prices: HashMap<Address, U256>,
 }
 
 impl MatchingEngine {
@@ -46,9 +96,40 @@ impl MatchingEngine {
         Self {
             max_ring_size,
             min_quality_score,
+            min_match_volume: U256::zero(),
+            reference_prices: HashMap::new(),
+        }
+    }
+
+    /// Skips matches trading less than `volume` of the reference token
+    pub fn with_min_match_volume(mut self, volume: U256) -> Self {
+        self.min_match_volume = volume;
+        self
+    }
+
+    /// Sets the price of `token` in reference token units (scaled by 1e18),
+    /// used to value match volume
+    pub fn with_reference_price(mut self, token: Address, price: U256) -> Self {
+        self.reference_prices.insert(token, price);
+        self
+    }
+
+    /// Values `amount` of `token` in reference token units.
+    ///
+    /// Tokens without a reference price are taken at their raw amount.
+    fn reference_volume(&self, token: Address, amount: U256) -> U256 {
+        match self.reference_prices.get(&token) {
+            Some(price) => U256::try_from(amount.full_mul(*price) / U512::from(U256::exp10(18)))
+                .unwrap_or(U256::MAX),
+            None => amount,
         }
     }
 
+    /// Checks if a match trading `amount` of `token` clears the minimum volume
+    fn meets_min_volume(&self, token: Address, amount: U256) -> bool {
+        self.reference_volume(token, amount) >= self.min_match_volume
+    }
+
     /// Finds all possible matches in a batch of orders
     pub fn find_matches(&self, orders: &[Order]) -> Vec<OrderMatch> {
         let mut matches = Vec::new();
@@ -73,35 +154,78 @@ impl MatchingEngine {
         matches
     }
 
-    /// Finds direct pair matches (A<->B)
+    /// Finds direct pair matches (A<->B).
+    ///
+    /// Orders are bucketed by token pair so each is only compared against
+    /// orders trading the opposite direction, in the same order an
+    /// exhaustive scan would visit them.
     fn find_direct_pairs(&self, orders: &[Order]) -> Vec<OrderMatch> {
         let mut matches = Vec::new();
 
-        for (i, order_a) in orders.iter().enumerate() {
-            for order_b in orders.iter().skip(i + 1) {
-                if self.is_direct_match(order_a, order_b) {
-                    let quality = self.calculate_pair_quality(order_a, order_b);
-                    let surplus = self.estimate_pair_surplus(order_a, order_b);
-
-                    matches.push(OrderMatch {
-                        orders: vec![order_a.id, order_b.id],
-                        match_type: MatchType::DirectPair,
-                        quality_score: quality,
-                        estimated_surplus: surplus,
-                    });
-
-                    debug!(
-                        "Direct pair match: {:?} <-> {:?}, quality={:.4}",
-                        order_a.id, order_b.id, quality
-                    );
-                }
+        Self::for_each_direct_pair_candidate(orders, |i, j| {
+            if let Some(pair) = self.direct_pair_match(&orders[i], &orders[j]) {
+                matches.push(pair);
             }
-        }
+        });
 
         info!("Found {} direct pair matches", matches.len());
         matches
     }
 
+    /// Calls `visit(i, j)` for each `i < j` where order `j` trades the
+    /// opposite direction of order `i`, ascending by `i` then `j`
+    fn for_each_direct_pair_candidate(orders: &[Order], mut visit: impl FnMut(usize, usize)) {
+        // (sell token, buy token) -> indices of orders, ascending
+        let mut buckets: HashMap<(Address, Address), Vec<usize>> = HashMap::new();
+        for (i, order) in orders.iter().enumerate() {
+            buckets.entry((order.sell_token, order.buy_token)).or_default().push(i);
+        }
+
+        for (i, order) in orders.iter().enumerate() {
+            let Some(counter) = buckets.get(&(order.buy_token, order.sell_token)) else {
+                continue;
+            };
+
+            let later = counter.partition_point(|&j| j <= i);
+            for &j in &counter[later..] {
+                visit(i, j);
+            }
+        }
+    }
+
+    /// Builds the match for two orders, if they form a direct pair of at
+    /// least the minimum volume
+    fn direct_pair_match(&self, order_a: &Order, order_b: &Order) -> Option<OrderMatch> {
+        if !self.is_direct_match(order_a, order_b) {
+            return None;
+        }
+
+        let volume = order_a.sell_amount.min(order_b.buy_amount);
+        if !self.meets_min_volume(order_a.sell_token, volume) {
+            debug!(
+                "Skipping dust pair {:?} <-> {:?}",
+                order_a.id, order_b.id
+            );
+            return None;
+        }
+
+        let quality = self.calculate_pair_quality(order_a, order_b);
+        let surplus = self.estimate_pair_surplus(order_a, order_b);
+
+        debug!(
+            "Direct pair match: {:?} <-> {:?}, quality={:.4}",
+            order_a.id, order_b.id, quality
+        );
+
+        Some(OrderMatch {
+            orders: vec![order_a.id, order_b.id],
+            match_type: MatchType::DirectPair,
+            quality_score: quality,
+            estimated_surplus: surplus,
+            clearing_prices: HashMap::new(),
+        })
+    }
+
     /// Checks if two orders form a direct match
     fn is_direct_match(&self, order_a: &Order, order_b: &Order) -> bool {
         // Orders match if:
@@ -163,7 +287,7 @@ impl MatchingEngine {
         // Weighted combination
         let quality = price_overlap * 0.4 + volume_score * 0.3 + balance_score * 0.3;
         
-        quality.max(0.0).min(1.0)
+        quality.clamp(0.0, 1.0)
     }
 
     /// Estimates surplus for a pair match
@@ -183,7 +307,10 @@ impl MatchingEngine {
         (volume * price_diff) / 1e18 // Convert from wei
     }
 
-    /// Finds ring matches (cycles of 3+ orders)
+    /// Finds ring matches (cycles of 3+ orders).
+    ///
+    /// At most one ring is tried per cycle of tokens, built from the order
+    /// offering the best rate on each hop.
     fn find_rings(&self, orders: &[Order]) -> Vec<OrderMatch> {
         let mut matches = Vec::new();
 
@@ -191,13 +318,26 @@ impl MatchingEngine {
             return matches;
         }
 
-        // Build token graph
-        let graph = self.build_token_graph(orders);
+        // Build token graph, each edge an order from its sell to buy token
+        let mut graph = TokenGraph::new();
+        for (idx, order) in orders.iter().enumerate() {
+            graph.add_edge(order.sell_token, order.buy_token, idx);
+        }
 
-        // Find cycles in the graph
-        let cycles = self.find_cycles(&graph, self.max_ring_size);
+        // Two-token cycles are direct pairs, found separately
+        let cycles = graph.find_cycles(self.max_ring_size);
+        for tokens in cycles.iter().filter(|tokens| tokens.len() >= 3) {
+            let cycle: Vec<usize> = (0..tokens.len())
+                .filter_map(|i| {
+                    let edges = graph.edges(tokens[i], tokens[(i + 1) % tokens.len()]);
+                    edges.iter().copied().min_by(|&a, &b| {
+                        // Lowest buy amount per sell amount offers the most
+                        let (a, b) = (&orders[a], &orders[b]);
+                        a.buy_amount.full_mul(b.sell_amount).cmp(&b.buy_amount.full_mul(a.sell_amount))
+                    })
+                })
+                .collect();
 
-        for cycle in cycles {
             if let Some(ring_match) = self.validate_ring(orders, &cycle) {
                 matches.push(ring_match);
             }
@@ -207,38 +347,6 @@ impl MatchingEngine {
         matches
     }
 
-    /// Builds a directed graph of token relationships
-    fn build_token_graph(&self, orders: &[Order]) -> HashMap<ethers::types::Address, Vec<usize>> {
-        let mut graph: HashMap<ethers::types::Address, Vec<usize>> = HashMap::new();
-
-        for (idx, order) in orders.iter().enumerate() {
-            graph
-                .entry(order.sell_token)
-                .or_insert_with(Vec::new)
-                .push(idx);
-        }
-
-        graph
-    }
-
-    /// Finds cycles in the token graph using DFS
-    fn find_cycles(
-        &self,
-        graph: &HashMap<ethers::types::Address, Vec<usize>>,
-        max_size: usize,
-    ) -> Vec<Vec<usize>> {
-        let mut cycles = Vec::new();
-        
-        // This is a simplified cycle detection
-        // A production implementation would use more sophisticated algorithms
-        // like Johnson's algorithm for finding all elementary cycles
-        
-        // For now, we'll just detect simple 3-cycles
-        // TODO: Implement full cycle detection algorithm
-        
-        cycles
-    }
-
     /// Validates and scores a ring match
     fn validate_ring(&self, orders: &[Order], cycle: &[usize]) -> Option<OrderMatch> {
         if cycle.len() < 3 {
@@ -256,64 +364,101 @@ impl MatchingEngine {
             }
         }
 
-        // Calculate quality score for the ring
-        let quality = self.calculate_ring_quality(orders, cycle);
-        let surplus = self.estimate_ring_surplus(orders, cycle);
+        // A ring trades no more than its smallest order
+        let ring_volume = cycle
+            .iter()
+            .map(|&i| self.reference_volume(orders[i].sell_token, orders[i].sell_amount))
+            .min()?;
+        if ring_volume < self.min_match_volume {
+            debug!("Skipping dust ring of {} orders", cycle.len());
+            return None;
+        }
+
+        let clearing = self.compute_ring_clearing(orders, cycle)?;
+        let quality = self.calculate_ring_quality(cycle, &clearing);
 
         Some(OrderMatch {
             orders: cycle.iter().map(|&i| orders[i].id).collect(),
             match_type: MatchType::Ring,
             quality_score: quality,
-            estimated_surplus: surplus,
+            estimated_surplus: clearing.surplus,
+            clearing_prices: clearing.prices,
+        })
+    }
+
+    /// Solves for uniform clearing prices around a ring.
+    ///
+    /// The ring's price product is what the orders offer relative to what
+    /// they ask for, `prod(sell_i / buy_i)`. Uniform prices make the
+    /// clearing rates multiply to exactly 1 around the cycle, so a product
+    /// below 1 cannot satisfy every limit. Above 1, the excess is split
+    /// evenly: each order clears at its limit rate times `product^(1/n)`.
+    /// Volume is bounded by the order with the smallest sell value.
+    fn compute_ring_clearing(&self, orders: &[Order], cycle: &[usize]) -> Option<RingClearing> {
+        let ring: Vec<&Order> = cycle.iter().map(|&i| &orders[i]).collect();
+
+        if ring.iter().any(|o| o.sell_amount.is_zero() || o.buy_amount.is_zero()) {
+            return None;
+        }
+
+        let limit_rates: Vec<f64> = ring
+            .iter()
+            .map(|o| o.buy_amount.as_u128() as f64 / o.sell_amount.as_u128() as f64)
+            .collect();
+
+        let price_product: f64 = limit_rates.iter().map(|rate| 1.0 / rate).product();
+        if !price_product.is_finite() || price_product < 1.0 {
+            debug!("Rejecting ring with price product {:.6}", price_product);
+            return None;
+        }
+
+        let improvement = price_product.powf(1.0 / ring.len() as f64);
+
+        // Walk the ring: p(buy) = p(sell) / clearing rate
+        let mut token_prices = Vec::with_capacity(ring.len());
+        let mut price = RING_PRICE_SCALE;
+        for (order, rate) in ring.iter().zip(&limit_rates) {
+            token_prices.push((order.sell_token, price));
+            price /= rate * improvement;
+        }
+
+        let flow_value = ring
+            .iter()
+            .zip(&token_prices)
+            .map(|(o, (_, price))| o.sell_amount.as_u128() as f64 * price)
+            .fold(f64::INFINITY, f64::min);
+
+        // Each order's surplus is the same share of the traded value
+        let surplus = ring.len() as f64 * (flow_value / RING_PRICE_SCALE) * (1.0 - 1.0 / improvement)
+            / 1e18; // Convert from wei
+
+        Some(RingClearing {
+            prices: token_prices
+                .into_iter()
+                .map(|(token, price)| (token, U256::from(price as u128)))
+                .collect(),
+            improvement,
+            surplus,
         })
     }
 
     /// Calculates quality score for a ring match
-    fn calculate_ring_quality(&self, orders: &[Order], cycle: &[usize]) -> f64 {
+    fn calculate_ring_quality(&self, cycle: &[usize], clearing: &RingClearing) -> f64 {
         // Ring quality based on:
-        // 1. Number of orders (more = better, up to a point)
-        // 2. Price consistency around the ring
-        // 3. Volume balance
-        
+        // 1. Number of orders (fewer = better, as rings are gas heavy)
+        // 2. Rate improvement each order gets over its limit
+
         let size_score = 1.0 / (cycle.len() as f64).sqrt(); // Prefer smaller rings
-        
-        // Calculate price product around the ring (should be >= 1 for valid ring)
-        let mut price_product = 1.0;
-        for &idx in cycle {
-            let order = &orders[idx];
-            let price = order.buy_amount.as_u128() as f64 / order.sell_amount.as_u128() as f64;
-            price_product *= price;
-        }
-        
-        let price_score = if price_product >= 1.0 {
-            (price_product - 1.0).min(1.0)
-        } else {
-            0.0
-        };
-        
-        (size_score + price_score) / 2.0
-    }
+        let price_score = (clearing.improvement - 1.0).clamp(0.0, 1.0);
 
-    /// Estimates surplus for a ring match
-    fn estimate_ring_surplus(&self, orders: &[Order], cycle: &[usize]) -> f64 {
-        // Simplified surplus calculation for rings
-        // Real implementation would solve for optimal clearing prices
-        
-        let mut total_surplus = 0.0;
-        
-        for &idx in cycle {
-            let order = &orders[idx];
-            // Estimate surplus as a fraction of order volume
-            total_surplus += (order.sell_amount.as_u128() as f64) * 0.001 / 1e18;
-        }
-        
-        total_surplus
+        (size_score + price_score) / 2.0
     }
 
     /// Selects non-overlapping matches to maximize total quality
     pub fn select_optimal_matches(&self, matches: Vec<OrderMatch>) -> Vec<OrderMatch> {
         let mut selected = Vec::new();
         let mut used_orders: HashSet<OrderId> = HashSet::new();
+        let candidate_count = matches.len();
 
         // Greedy selection: pick highest quality matches that don't overlap
         for match_candidate in matches {
@@ -335,11 +480,22 @@ impl MatchingEngine {
         info!(
             "Selected {} non-overlapping matches from {} candidates",
             selected.len(),
-            matches.len()
+            candidate_count
         );
 
         selected
     }
+
+    /// Selects non-overlapping matches, preferring those earning the most
+    /// surplus per unit of gas over the highest quality
+    pub fn select_by_surplus_per_gas(&self, mut matches: Vec<OrderMatch>) -> Vec<OrderMatch> {
+        matches.sort_by(|a, b| {
+            b.surplus_per_gas()
+                .partial_cmp(&a.surplus_per_gas())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        self.select_optimal_matches(matches)
+    }
 }
 
 impl Default for MatchingEngine {
@@ -351,8 +507,10 @@ impl Default for MatchingEngine {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::domain::{OrderKind, OrderStatus, ChainId};
-    use ethers::types::{Address, U256};
+    use crate::domain::{OrderStatus, OrderType};
+
+    /// One whole token at 18 decimals
+    const E18: u128 = 1_000_000_000_000_000_000;
 
     fn create_test_order(
         id: u8,
@@ -371,12 +529,16 @@ mod tests {
             buy_token,
             sell_amount: U256::from(sell_amount),
             buy_amount: U256::from(buy_amount),
-            valid_to: Some(u32::MAX),
+            valid_to: u32::MAX,
             fee_amount: U256::from(1000),
-            kind: OrderKind::Sell,
+            kind: OrderType::Sell,
             partially_fillable: false,
             status: OrderStatus::Open,
-            chain_id: ChainId::Mainnet,
+            source_chain: None,
+            destination_chain: None,
+            bridge_provider: None,
+            filled_amount: U256::zero(),
+            signature: vec![],
         }
     }
 
@@ -463,12 +625,14 @@ mod tests {
                 match_type: MatchType::DirectPair,
                 quality_score: 0.8,
                 estimated_surplus: 100.0,
+                clearing_prices: HashMap::new(),
             },
             OrderMatch {
                 orders: vec![OrderId(order_id_2), OrderId(order_id_3)],
                 match_type: MatchType::DirectPair,
                 quality_score: 0.6,
                 estimated_surplus: 80.0,
+                clearing_prices: HashMap::new(),
             },
         ];
 
@@ -478,4 +642,200 @@ mod tests {
         assert_eq!(selected.len(), 1);
         assert_eq!(selected[0].quality_score, 0.8);
     }
+
+    #[test]
+    fn test_surplus_per_gas_selection_prefers_cheap_pairs() {
+        let engine = MatchingEngine::default();
+        let id = |n: u8| OrderId([n; 32]);
+        let candidate = |orders: Vec<OrderId>, match_type, quality_score, estimated_surplus| OrderMatch {
+            orders,
+            match_type,
+            quality_score,
+            estimated_surplus,
+            clearing_prices: HashMap::new(),
+        };
+
+        // The ring outscores each pair but earns less than both together,
+        // at more gas than both together
+        let matches = vec![
+            candidate(vec![id(1), id(2), id(3)], MatchType::Ring, 0.9, 90.0),
+            candidate(vec![id(1), id(4)], MatchType::DirectPair, 0.5, 50.0),
+            candidate(vec![id(2), id(5)], MatchType::DirectPair, 0.5, 50.0),
+        ];
+        assert!(matches[0].estimated_gas() > matches[1].estimated_gas() + matches[2].estimated_gas());
+
+        let by_quality = engine.select_optimal_matches(matches.clone());
+        assert_eq!(by_quality.len(), 1);
+        assert_eq!(by_quality[0].match_type, MatchType::Ring);
+
+        let by_gas = engine.select_by_surplus_per_gas(matches);
+        assert_eq!(by_gas.len(), 2);
+        assert!(by_gas.iter().all(|m| m.match_type == MatchType::DirectPair));
+    }
+
+    #[test]
+    fn test_ring_clearing_prices_and_surplus() {
+        let engine = MatchingEngine::default();
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let token_c = Address::from_low_u64_be(3);
+
+        // Each order sells 100 for at least 90, so the price product is
+        // (100/90)^3 and every order clears at 1:1, gaining 10 tokens
+        let orders = vec![
+            create_test_order(1, token_a, token_b, 100_000_000_000_000_000_000, 90_000_000_000_000_000_000),
+            create_test_order(2, token_b, token_c, 100_000_000_000_000_000_000, 90_000_000_000_000_000_000),
+            create_test_order(3, token_c, token_a, 100_000_000_000_000_000_000, 90_000_000_000_000_000_000),
+        ];
+
+        let ring = engine.validate_ring(&orders, &[0, 1, 2]).unwrap();
+        assert_eq!(ring.match_type, MatchType::Ring);
+        assert!((ring.estimated_surplus - 30.0).abs() < 1e-6);
+
+        assert_eq!(ring.clearing_prices.len(), 3);
+        for token in [token_a, token_b, token_c] {
+            let price = ring.clearing_prices[&token];
+            let diff = if price > U256::exp10(18) { price - U256::exp10(18) } else { U256::exp10(18) - price };
+            assert!(diff < U256::exp10(6));
+        }
+    }
+
+    #[test]
+    fn test_rings_found_from_token_cycles() {
+        let engine = MatchingEngine::default();
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let token_c = Address::from_low_u64_be(3);
+
+        let orders = vec![
+            create_test_order(1, token_a, token_b, 100 * E18, 90 * E18),
+            // Asks more for the same hop, so the ring uses the order above
+            create_test_order(2, token_a, token_b, 100 * E18, 99 * E18),
+            create_test_order(3, token_b, token_c, 100 * E18, 90 * E18),
+            create_test_order(4, token_c, token_a, 100 * E18, 90 * E18),
+            // A direct pair isn't a ring
+            create_test_order(5, token_b, token_a, 100 * E18, 90 * E18),
+        ];
+
+        let rings = engine.find_rings(&orders);
+        assert_eq!(rings.len(), 1);
+        assert_eq!(rings[0].match_type, MatchType::Ring);
+        assert_eq!(rings[0].orders, vec![orders[0].id, orders[2].id, orders[3].id]);
+    }
+
+    #[test]
+    fn test_ring_without_arbitrage_rejected() {
+        let engine = MatchingEngine::default();
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let token_c = Address::from_low_u64_be(3);
+
+        // Every order asks for more than it offers: price product below 1
+        let orders = vec![
+            create_test_order(1, token_a, token_b, 1000, 1100),
+            create_test_order(2, token_b, token_c, 1000, 1100),
+            create_test_order(3, token_c, token_a, 1000, 1100),
+        ];
+
+        assert!(engine.validate_ring(&orders, &[0, 1, 2]).is_none());
+    }
+
+    #[test]
+    fn test_min_match_volume_filters_dust() {
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+
+        // Token A is worth 2 reference tokens, so the minimum is 5 A
+        let engine = MatchingEngine::default()
+            .with_min_match_volume(U256::from(10 * E18))
+            .with_reference_price(token_a, U256::from(2 * E18));
+
+        let dust = vec![
+            create_test_order(1, token_a, token_b, 4 * E18, 8 * E18),
+            create_test_order(2, token_b, token_a, 8 * E18, 4 * E18),
+        ];
+        assert!(engine.find_direct_pairs(&dust).is_empty());
+
+        let sizeable = vec![
+            create_test_order(1, token_a, token_b, 5 * E18, 10 * E18),
+            create_test_order(2, token_b, token_a, 10 * E18, 5 * E18),
+        ];
+        assert_eq!(engine.find_direct_pairs(&sizeable).len(), 1);
+
+        // Without a price, B volume counts at its raw amount: 9 < 10
+        let ring_orders = vec![
+            create_test_order(1, token_a, token_b, 100 * E18, 90 * E18),
+            create_test_order(2, token_b, Address::from_low_u64_be(3), 9 * E18, 8 * E18),
+            create_test_order(3, Address::from_low_u64_be(3), token_a, 100 * E18, 90 * E18),
+        ];
+        assert!(engine.validate_ring(&ring_orders, &[0, 1, 2]).is_none());
+    }
+
+    /// Random batch over a few tokens, with limit prices on both sides of
+    /// the counter orders'
+    fn random_batch(count: usize, tokens: u64, seed: u64) -> Vec<Order> {
+        let mut state = seed;
+        let mut next = move || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            state >> 33
+        };
+
+        (0..count)
+            .map(|i| {
+                let sell_token = next() % tokens + 1;
+                let buy_token = (sell_token + next() % (tokens - 1)) % tokens + 1;
+                let mut order = create_test_order(
+                    0,
+                    Address::from_low_u64_be(sell_token),
+                    Address::from_low_u64_be(buy_token),
+                    1000 + (next() % 1000) as u128,
+                    1000 + (next() % 1000) as u128,
+                );
+                // Unique however large the batch
+                order.id.0[..8].copy_from_slice(&(i as u64).to_be_bytes());
+                order
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_bucketed_pairs_match_exhaustive_scan() {
+        let engine = MatchingEngine::default();
+        let orders = random_batch(200, 5, 42);
+
+        let mut exhaustive = Vec::new();
+        for (i, order_a) in orders.iter().enumerate() {
+            for order_b in orders.iter().skip(i + 1) {
+                exhaustive.extend(engine.direct_pair_match(order_a, order_b));
+            }
+        }
+
+        let bucketed = engine.find_direct_pairs(&orders);
+        assert!(!bucketed.is_empty());
+        assert_eq!(bucketed.len(), exhaustive.len());
+        for (found, expected) in bucketed.iter().zip(&exhaustive) {
+            assert_eq!(found.orders, expected.orders);
+            assert_eq!(found.quality_score, expected.quality_score);
+        }
+    }
+
+    #[test]
+    fn test_large_batch_compares_few_candidates() {
+        let orders = random_batch(20_000, 1000, 7);
+
+        // An exhaustive scan would compare ~2e8 pairs; spread over ~1e6
+        // directed token pairs, bucketing leaves a few hundred
+        let mut candidates = 0usize;
+        MatchingEngine::for_each_direct_pair_candidate(&orders, |i, j| {
+            assert!(i < j);
+            assert_eq!(orders[i].sell_token, orders[j].buy_token);
+            assert_eq!(orders[i].buy_token, orders[j].sell_token);
+            candidates += 1;
+        });
+        assert!(candidates > 0);
+        assert!(candidates < orders.len());
+    }
 }
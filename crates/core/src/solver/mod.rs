@@ -1,19 +1,49 @@
 pub mod engine;
+pub mod graph;
 pub mod matching;
 pub mod routing;
 pub mod pricing;
+pub mod multi;
+pub mod baseline;
+pub mod liquidity;
+pub mod scoring;
+pub mod stats;
 
-use crate::domain::{Order, OrderId};
+use crate::domain::{ChainId, Order, OrderId};
 use crate::settlement::SettlementPlan;
 use async_trait::async_trait;
+use ethers::types::{Address, U256};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 // Re-export main types from submodules
-pub use engine::SolverEngine;
+pub use engine::{RejectReason, SettlementPreview, SolveRejectReason, SolveTrace, SolverEngine};
 pub use matching::{MatchingEngine, OrderMatch, MatchType};
-pub use routing::{RoutingEngine, LiquidityPool, PoolType, Route};
-pub use pricing::{PricingEngine, ClearingPrice, PricingStrategy};
+pub use routing::{
+    RoutingEngine, RoutingError, RoutingSnapshot, RouteScoringWeights, LiquidityPool, OutputModel, PoolType, Quote, Route,
+};
+pub use pricing::{PricingEngine, ClearingPrice, FeeModel, PricingStrategy, PriceFeed, PriceSource};
+pub use multi::MultiSolver;
+pub use baseline::BaselineSolver;
+pub use liquidity::{LiquiditySource, StaticLiquiditySource};
+pub use scoring::{ScoringPolicy, SurplusMinusGasPolicy, SurplusOnlyPolicy};
+pub use stats::AuctionStats;
+pub use graph::TokenGraph;
+
+/// Default gas budget for a settlement (Ethereum block gas limit)
+pub const DEFAULT_MAX_SETTLEMENT_GAS: u64 = 30_000_000;
+
+fn default_max_settlement_gas() -> u64 {
+    DEFAULT_MAX_SETTLEMENT_GAS
+}
+
+/// Default price tolerance for CoW matches (as percentage)
+pub const DEFAULT_MATCH_PRICE_TOLERANCE: f64 = 0.5;
+
+fn default_match_price_tolerance() -> f64 {
+    DEFAULT_MATCH_PRICE_TOLERANCE
+}
 
 /// Solver configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,9 +54,14 @@ pub struct SolverConfig {
     /// Minimum profit threshold for solutions
     pub min_profit_threshold: f64,
     
-    /// Maximum slippage tolerance (as percentage)
+    /// Maximum slippage tolerance for AMM routing (as percentage)
     pub max_slippage: f64,
     
+    /// How far apart two orders' limit prices may be and still match (as
+    /// percentage); zero requires the prices to overlap
+    #[serde(default = "default_match_price_tolerance")]
+    pub match_price_tolerance: f64,
+    
     /// Enable CoW matching
     pub enable_cow_matching: bool,
     
@@ -38,6 +73,89 @@ pub struct SolverConfig {
     
     /// Solver timeout in milliseconds
     pub timeout_ms: u64,
+    
+    /// What to do with valid orders that can't be matched or routed
+    #[serde(default)]
+    pub unroutable_policy: UnroutablePolicy,
+    
+    /// Sort orders by `valid_to` so those closest to expiry are matched first
+    #[serde(default)]
+    pub prioritize_expiring: bool,
+    
+    /// Maximum number of valid orders solved per batch (unbounded if
+    /// unset); the rest are reported in `Solution::overflow_orders`
+    #[serde(default)]
+    pub max_batch_size: Option<usize>,
+    
+    /// Token surplus is valued in, at the settlement's clearing prices
+    /// (zero to count raw surplus token amounts)
+    #[serde(default)]
+    pub reference_token: Address,
+    
+    /// Gas budget for a whole settlement; orders that don't fit are left out
+    #[serde(default = "default_max_settlement_gas")]
+    pub max_settlement_gas: u64,
+    
+    /// How a matched pair's surplus is split through its clearing price
+    #[serde(default)]
+    pub surplus_distribution: SurplusDistribution,
+    
+    /// How many blocks old a pool's reserves may be and still be routed
+    /// through (unbounded if unset)
+    #[serde(default)]
+    pub max_pool_staleness: Option<u64>,
+    
+    /// Reject orders whose signature doesn't recover to their owner
+    #[serde(default)]
+    pub verify_signatures: bool,
+}
+
+/// Handling of valid orders the solver can't settle
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UnroutablePolicy {
+    /// Settle the solvable subset and report the rest in `Solution::dropped_orders`
+    #[default]
+    DropAndContinue,
+    
+    /// Fail the whole batch if any order can't be settled
+    FailBatch,
+}
+
+impl UnroutablePolicy {
+    /// Applies the policy to orders left out of a solution
+    pub fn apply(self, dropped: &[OrderId]) -> crate::Result<()> {
+        match self {
+            UnroutablePolicy::FailBatch if !dropped.is_empty() => {
+                Err(crate::Error::InsufficientLiquidity(format!(
+                    "{} orders could not be matched or routed: {:?}",
+                    dropped.len(),
+                    dropped
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Policy for splitting a matched pair's surplus between its orders.
+///
+/// Every policy picks the clearing price between the two limit prices, so
+/// both orders' limits are always met.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SurplusDistribution {
+    /// Maximize the pair's combined surplus, however it falls
+    #[default]
+    MaxTotal,
+    
+    /// Surplus proportional to order size: the midpoint of the two limit
+    /// prices, so both orders gain the same surplus per unit traded
+    Proportional,
+    
+    /// The same surplus for both orders, whatever their size
+    EqualSplit,
+    
+    /// All surplus to the smaller order, e.g. to protect retail flow
+    FavorSmaller,
 }
 
 impl Default for SolverConfig {
@@ -46,11 +164,190 @@ impl Default for SolverConfig {
             max_gas_price: 100,
             min_profit_threshold: 0.01,
             max_slippage: 0.5,
+            match_price_tolerance: DEFAULT_MATCH_PRICE_TOLERANCE,
             enable_cow_matching: true,
             enable_amm_routing: true,
             enable_cross_chain: true,
             timeout_ms: 5000,
+            unroutable_policy: UnroutablePolicy::default(),
+            prioritize_expiring: false,
+            max_batch_size: None,
+            reference_token: Address::zero(),
+            max_settlement_gas: DEFAULT_MAX_SETTLEMENT_GAS,
+            surplus_distribution: SurplusDistribution::default(),
+            max_pool_staleness: None,
+            verify_signatures: false,
+        }
+    }
+}
+
+impl SolverConfig {
+    /// Starts building a configuration from defaults
+    pub fn builder() -> SolverConfigBuilder {
+        SolverConfigBuilder::default()
+    }
+    
+    /// Validates configuration values
+    pub fn validate(&self) -> crate::Result<()> {
+        if !self.max_slippage.is_finite() || self.max_slippage < 0.0 {
+            return Err(crate::Error::ConfigError(format!(
+                "max_slippage must be non-negative, got {}",
+                self.max_slippage
+            )));
+        }
+        
+        if self.max_slippage > 100.0 {
+            return Err(crate::Error::ConfigError(format!(
+                "max_slippage must not exceed 100%, got {}",
+                self.max_slippage
+            )));
+        }
+        
+        if !self.match_price_tolerance.is_finite() || self.match_price_tolerance < 0.0 {
+            return Err(crate::Error::ConfigError(format!(
+                "match_price_tolerance must be non-negative, got {}",
+                self.match_price_tolerance
+            )));
+        }
+        
+        if !self.min_profit_threshold.is_finite() {
+            return Err(crate::Error::ConfigError(format!(
+                "min_profit_threshold must be finite, got {}",
+                self.min_profit_threshold
+            )));
         }
+        
+        if self.timeout_ms == 0 {
+            return Err(crate::Error::ConfigError(
+                "timeout_ms must be greater than zero".to_string(),
+            ));
+        }
+        
+        if self.max_settlement_gas < SettlementPlan::BASE_GAS {
+            return Err(crate::Error::ConfigError(format!(
+                "max_settlement_gas must cover the base transaction gas ({}), got {}",
+                SettlementPlan::BASE_GAS,
+                self.max_settlement_gas
+            )));
+        }
+        
+        if self.max_batch_size == Some(0) {
+            return Err(crate::Error::ConfigError(
+                "max_batch_size must be greater than zero".to_string(),
+            ));
+        }
+        
+        Ok(())
+    }
+}
+
+/// Builder for `SolverConfig` with validation
+#[derive(Debug, Clone, Default)]
+pub struct SolverConfigBuilder {
+    config: SolverConfig,
+}
+
+impl SolverConfigBuilder {
+    /// Sets maximum gas price (in gwei)
+    pub fn max_gas_price(mut self, max_gas_price: u64) -> Self {
+        self.config.max_gas_price = max_gas_price;
+        self
+    }
+    
+    /// Sets minimum profit threshold
+    pub fn min_profit_threshold(mut self, min_profit_threshold: f64) -> Self {
+        self.config.min_profit_threshold = min_profit_threshold;
+        self
+    }
+    
+    /// Sets maximum slippage (as percentage)
+    pub fn max_slippage(mut self, max_slippage: f64) -> Self {
+        self.config.max_slippage = max_slippage;
+        self
+    }
+    
+    /// Sets the price tolerance for CoW matches (as percentage)
+    pub fn match_price_tolerance(mut self, match_price_tolerance: f64) -> Self {
+        self.config.match_price_tolerance = match_price_tolerance;
+        self
+    }
+    
+    /// Enables or disables CoW matching
+    pub fn enable_cow_matching(mut self, enable: bool) -> Self {
+        self.config.enable_cow_matching = enable;
+        self
+    }
+    
+    /// Enables or disables AMM routing
+    pub fn enable_amm_routing(mut self, enable: bool) -> Self {
+        self.config.enable_amm_routing = enable;
+        self
+    }
+    
+    /// Enables or disables cross-chain swaps
+    pub fn enable_cross_chain(mut self, enable: bool) -> Self {
+        self.config.enable_cross_chain = enable;
+        self
+    }
+    
+    /// Sets solver timeout in milliseconds
+    pub fn timeout_ms(mut self, timeout_ms: u64) -> Self {
+        self.config.timeout_ms = timeout_ms;
+        self
+    }
+    
+    /// Sets handling of unsettleable orders
+    pub fn unroutable_policy(mut self, policy: UnroutablePolicy) -> Self {
+        self.config.unroutable_policy = policy;
+        self
+    }
+    
+    /// Sets how matched pairs' surplus is split
+    pub fn surplus_distribution(mut self, distribution: SurplusDistribution) -> Self {
+        self.config.surplus_distribution = distribution;
+        self
+    }
+    
+    /// Enables or disables earliest-deadline-first matching
+    pub fn prioritize_expiring(mut self, enable: bool) -> Self {
+        self.config.prioritize_expiring = enable;
+        self
+    }
+    
+    /// Caps the number of orders solved per batch
+    pub fn max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.config.max_batch_size = Some(max_batch_size);
+        self
+    }
+    
+    /// Sets the token surplus is valued in
+    pub fn reference_token(mut self, token: Address) -> Self {
+        self.config.reference_token = token;
+        self
+    }
+    
+    /// Sets the gas budget for a whole settlement
+    pub fn max_settlement_gas(mut self, max_settlement_gas: u64) -> Self {
+        self.config.max_settlement_gas = max_settlement_gas;
+        self
+    }
+    
+    /// Sets how many blocks old pool reserves may be when routing
+    pub fn max_pool_staleness(mut self, max_pool_staleness: u64) -> Self {
+        self.config.max_pool_staleness = Some(max_pool_staleness);
+        self
+    }
+    
+    /// Enables or disables order signature verification
+    pub fn verify_signatures(mut self, enable: bool) -> Self {
+        self.config.verify_signatures = enable;
+        self
+    }
+    
+    /// Validates and returns the configuration
+    pub fn build(self) -> crate::Result<SolverConfig> {
+        self.config.validate()?;
+        Ok(self.config)
     }
 }
 
@@ -69,15 +366,67 @@ pub struct Solution {
     /// Total surplus generated
     pub surplus: f64,
     
+    /// Token `surplus` is valued in, or `None` for the settlement chain's
+    /// native token
+    #[serde(default)]
+    pub surplus_token: Option<Address>,
+    
+    /// Surplus generated per traded order
+    #[serde(default)]
+    pub order_surplus: Vec<(OrderId, f64)>,
+    
+    /// Valid orders left out because they couldn't be matched or routed
+    #[serde(default)]
+    pub dropped_orders: Vec<OrderId>,
+    
+    /// Settleable orders left out to keep within `max_settlement_gas`
+    #[serde(default)]
+    pub gas_excluded_orders: Vec<OrderId>,
+    
+    /// Valid orders left out because the batch exceeded `max_batch_size`
+    #[serde(default)]
+    pub overflow_orders: Vec<OrderId>,
+    
+    /// Chain the settlement executes on (Ethereum if unset)
+    #[serde(default)]
+    pub chain: Option<ChainId>,
+    
+    /// Block of the auction the solution was computed for
+    #[serde(default)]
+    pub valid_for_block: u64,
+    
     /// Solution quality score
     pub score: f64,
 }
 
+/// Execution result for a single order in a solution
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderFill {
+    /// Order that was filled
+    pub order_id: OrderId,
+    
+    /// Sell amount actually executed (less than the order's for partial fills)
+    pub executed_sell: U256,
+    
+    /// Buy amount actually executed
+    pub executed_buy: U256,
+    
+    /// Clearing price of the order's sell token, if the settlement priced it
+    pub clearing_price: Option<U256>,
+}
+
+/// Chain a batch settles on: the source chain of its first order that
+/// names one, or `None` for Ethereum
+pub(crate) fn settlement_chain(orders: &[Order]) -> Option<ChainId> {
+    orders.iter().find_map(|order| order.source_chain)
+}
+
 /// Solver trait for different solving strategies
 #[async_trait]
 pub trait Solver: Send + Sync {
-    /// Solves a batch of orders
-    async fn solve(&self, orders: Vec<Order>) -> crate::Result<Option<Solution>>;
+    /// Solves a batch of orders in the given auction, returning the best
+    /// solution found so far if its cancellation token fires
+    async fn solve(&self, orders: Vec<Order>, ctx: &AuctionContext) -> crate::Result<Option<Solution>>;
     
     /// Returns solver name
     fn name(&self) -> &str;
@@ -87,28 +436,146 @@ pub trait Solver: Send + Sync {
 }
 
 /// Batch auction context
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct AuctionContext {
     /// Current block number
     pub block_number: u64,
     
-    /// Current timestamp
+    /// Current timestamp (0 = use the system clock)
     pub timestamp: u32,
     
-    /// Current gas price
+    /// Current gas price in gwei (0 = assume 1 gwei)
     pub gas_price: u64,
     
     /// Available liquidity sources
     pub liquidity_sources: Vec<String>,
+    
+    /// Congestion multiplier applied to gas costs (1.0 = normal)
+    pub congestion_factor: Option<f64>,
+    
+    /// Cancels the solve, which then returns the best solution found so far
+    pub cancellation: CancellationToken,
+}
+
+/// Shared flag for cancelling an in-flight solve.
+///
+/// Clones share the flag, so the caller keeps one and passes another in
+/// the `AuctionContext`.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Creates a token that hasn't been cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels every solve holding a clone of this token
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Checks if the token has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl AuctionContext {
+    /// Gas price assumed when the auction doesn't set one (in gwei)
+    pub const DEFAULT_GAS_PRICE: u64 = 1;
+
+    /// Returns the time orders are checked for expiry against: the
+    /// auction's timestamp, or the system clock if it has none
+    pub fn now(&self) -> u32 {
+        if self.timestamp != 0 {
+            return self.timestamp;
+        }
+
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32
+    }
+
+    /// Returns the cost of `gas` units in ETH at the auction's gas price,
+    /// inflated by the congestion multiplier
+    pub fn gas_cost_eth(&self, gas: u64) -> f64 {
+        let gas_price = if self.gas_price == 0 {
+            Self::DEFAULT_GAS_PRICE
+        } else {
+            self.gas_price
+        };
+
+        gas as f64 * gas_price as f64 * 1e-9 * self.gas_multiplier()
+    }
+
+    /// Returns the gas cost multiplier for current congestion.
+    ///
+    /// Never below 1.0, so congestion can only make gas more expensive.
+    pub fn gas_multiplier(&self) -> f64 {
+        match self.congestion_factor {
+            Some(factor) if factor.is_finite() => factor.max(1.0),
+            _ => 1.0,
+        }
+    }
 }
 
 impl Solution {
+    /// Returns what each traded order executed and at which clearing price
+    pub fn fills(&self) -> Vec<OrderFill> {
+        self.settlement
+            .trades
+            .iter()
+            .map(|trade| OrderFill {
+                order_id: trade.order_id,
+                executed_sell: trade.executed_sell_amount,
+                executed_buy: trade.executed_buy_amount,
+                clearing_price: self.settlement.clearing_prices.get(&trade.sell_token).copied(),
+            })
+            .collect()
+    }
+    
+    /// Checks if the solution is more than `max_age_blocks` behind
+    /// `current_block`, so reserves may have moved since it was computed
+    pub fn is_stale(&self, current_block: u64, max_age_blocks: u64) -> bool {
+        current_block.saturating_sub(self.valid_for_block) > max_age_blocks
+    }
+    
     /// Calculates solution quality score
     pub fn calculate_score(&mut self) {
+        self.score = self.score_with_gas_multiplier(1.0);
+    }
+    
+    /// Calculates solution quality score, inflating the gas penalty by
+    /// the auction's congestion factor
+    pub fn calculate_score_with_context(&mut self, ctx: &AuctionContext) {
+        self.score = self.score_with_gas_multiplier(ctx.gas_multiplier());
+    }
+    
+    fn score_with_gas_multiplier(&self, multiplier: f64) -> f64 {
         // Score = surplus - gas_cost_in_eth
         // Higher surplus and lower gas cost = better score
         let gas_cost_eth = self.gas_cost as f64 * 1e-9; // Convert gwei to ETH
-        self.score = self.surplus - gas_cost_eth;
+        self.surplus - gas_cost_eth * multiplier
+    }
+    
+    /// Returns the score converted to the feed's reference currency.
+    ///
+    /// Surplus is priced in its `surplus_token`, or the native token of the
+    /// chain the solution settles on if unset, and gas in that native token.
+    /// The engine already nets bridge fees out of surplus, so this makes
+    /// solutions on different chains comparable. Returns `None` if the feed
+    /// is missing either price.
+    pub fn normalized_score(&self, feeds: &PriceFeed) -> Option<f64> {
+        let chain = self.chain.unwrap_or(ChainId::Ethereum);
+        let native_price = feeds.native_price(chain)?;
+        let surplus_price = match self.surplus_token {
+            Some(token) => feeds.token_price(token)?,
+            None => native_price,
+        };
+        let gas_cost_eth = self.gas_cost as f64 * 1e-9;
+        Some(self.surplus * surplus_price - gas_cost_eth * native_price)
     }
     
     /// Checks if solution is profitable
@@ -128,6 +595,84 @@ mod tests {
         assert!(config.enable_cow_matching);
     }
     
+    #[test]
+    fn test_config_builder_success() {
+        let config = SolverConfig::builder()
+            .max_slippage(1.0)
+            .timeout_ms(2000)
+            .enable_cross_chain(false)
+            .build()
+            .unwrap();
+        
+        assert_eq!(config.max_slippage, 1.0);
+        assert_eq!(config.timeout_ms, 2000);
+        assert!(!config.enable_cross_chain);
+    }
+    
+    #[test]
+    fn test_solution_serialization_is_deterministic() {
+        let tokens: Vec<Address> = (1..=16).map(Address::from_low_u64_be).collect();
+        let solution = |tokens: &mut dyn Iterator<Item = &Address>| {
+            let mut settlement = SettlementPlan::default();
+            for token in tokens {
+                settlement.set_clearing_price(*token, U256::from(token.to_low_u64_be() * 1000));
+            }
+            Solution {
+                orders: vec![],
+                settlement,
+                gas_cost: 0,
+                surplus: 0.0,
+                surplus_token: None,
+                order_surplus: vec![],
+                dropped_orders: vec![],
+                gas_excluded_orders: vec![],
+                overflow_orders: vec![],
+                chain: None,
+                valid_for_block: 0,
+                score: 0.0,
+            }
+        };
+        
+        let forward = serde_json::to_string(&solution(&mut tokens.iter())).unwrap();
+        let reverse = serde_json::to_string(&solution(&mut tokens.iter().rev())).unwrap();
+        assert_eq!(forward, reverse);
+        
+        let roundtrip: Solution = serde_json::from_str(&forward).unwrap();
+        assert_eq!(roundtrip.settlement.clearing_prices.len(), tokens.len());
+    }
+    
+    #[test]
+    fn test_config_builder_rejects_negative_slippage() {
+        let err = SolverConfig::builder().max_slippage(-0.1).build().unwrap_err();
+        assert!(matches!(err, crate::Error::ConfigError(ref msg) if msg.contains("max_slippage")));
+    }
+    
+    #[test]
+    fn test_config_builder_rejects_slippage_above_100() {
+        let err = SolverConfig::builder().max_slippage(100.5).build().unwrap_err();
+        assert!(matches!(err, crate::Error::ConfigError(ref msg) if msg.contains("100%")));
+    }
+    
+    #[test]
+    fn test_config_builder_rejects_zero_batch_size() {
+        let err = SolverConfig::builder().max_batch_size(0).build().unwrap_err();
+        assert!(matches!(err, crate::Error::ConfigError(ref msg) if msg.contains("max_batch_size")));
+    }
+    
+    #[test]
+    fn test_config_builder_rejects_zero_timeout() {
+        let err = SolverConfig::builder().timeout_ms(0).build().unwrap_err();
+        assert!(matches!(err, crate::Error::ConfigError(ref msg) if msg.contains("timeout_ms")));
+    }
+    
+    #[test]
+    fn test_config_builder_rejects_non_finite_threshold() {
+        let err = SolverConfig::builder().min_profit_threshold(f64::NAN).build().unwrap_err();
+        assert!(
+            matches!(err, crate::Error::ConfigError(ref msg) if msg.contains("min_profit_threshold"))
+        );
+    }
+    
     #[test]
     fn test_solution_scoring() {
         let mut solution = Solution {
@@ -135,6 +680,13 @@ mod tests {
             settlement: SettlementPlan::default(),
             gas_cost: 100_000,
             surplus: 0.5,
+            surplus_token: None,
+            order_surplus: vec![],
+            dropped_orders: vec![],
+            gas_excluded_orders: vec![],
+            overflow_orders: vec![],
+            chain: None,
+            valid_for_block: 0,
             score: 0.0,
         };
         
@@ -142,4 +694,194 @@ mod tests {
         assert!(solution.score > 0.0);
         assert!(solution.is_profitable(0.0));
     }
+    
+    #[test]
+    fn test_solution_goes_stale_after_max_age() {
+        let solution = Solution {
+            orders: vec![],
+            settlement: SettlementPlan::default(),
+            gas_cost: 0,
+            surplus: 0.0,
+            surplus_token: None,
+            order_surplus: vec![],
+            dropped_orders: vec![],
+            gas_excluded_orders: vec![],
+            overflow_orders: vec![],
+            chain: None,
+            valid_for_block: 100,
+            score: 0.0,
+        };
+        
+        assert!(!solution.is_stale(100, 2));
+        assert!(!solution.is_stale(102, 2));
+        assert!(solution.is_stale(103, 2));
+        assert!(solution.is_stale(101, 0));
+        // A submitter lagging behind the auction isn't stale
+        assert!(!solution.is_stale(99, 0));
+    }
+    
+    #[test]
+    fn test_normalized_score_ranks_across_chains() {
+        let solution = |chain, surplus| Solution {
+            orders: vec![],
+            settlement: SettlementPlan::default(),
+            gas_cost: 100_000,
+            surplus,
+            surplus_token: None,
+            order_surplus: vec![],
+            dropped_orders: vec![],
+            gas_excluded_orders: vec![],
+            overflow_orders: vec![],
+            chain: Some(chain),
+            valid_for_block: 0,
+            score: 0.0,
+        };
+        let mut on_polygon = solution(ChainId::Polygon, 10.0);
+        let mut on_ethereum = solution(ChainId::Ethereum, 0.01);
+        on_polygon.calculate_score();
+        on_ethereum.calculate_score();
+        
+        // Raw surplus favours Polygon, but 10 MATIC is worth far less than 0.01 ETH
+        assert!(on_polygon.score > on_ethereum.score);
+        
+        let feeds = PriceFeed::new()
+            .with_native_price(ChainId::Ethereum, 3000.0)
+            .with_native_price(ChainId::Polygon, 0.5);
+        let polygon_usd = on_polygon.normalized_score(&feeds).unwrap();
+        let ethereum_usd = on_ethereum.normalized_score(&feeds).unwrap();
+        assert!(ethereum_usd > polygon_usd);
+        
+        assert!(on_polygon.normalized_score(&PriceFeed::new()).is_none());
+    }
+    
+    #[test]
+    fn test_normalized_score_prices_surplus_in_its_token() {
+        let usdc = Address::from_low_u64_be(1);
+        let mut solution = Solution {
+            orders: vec![],
+            settlement: SettlementPlan::default(),
+            gas_cost: 100_000,
+            surplus: 30.0,
+            surplus_token: Some(usdc),
+            order_surplus: vec![],
+            dropped_orders: vec![],
+            gas_excluded_orders: vec![],
+            overflow_orders: vec![],
+            chain: Some(ChainId::Ethereum),
+            valid_for_block: 0,
+            score: 0.0,
+        };
+        
+        // 30 USDC of surplus, less 1e-4 ETH of gas
+        let feeds = PriceFeed::new()
+            .with_native_price(ChainId::Ethereum, 3000.0)
+            .with_token_price(usdc, 1.0);
+        let usd = solution.normalized_score(&feeds).unwrap();
+        assert!((usd - (30.0 - 0.3)).abs() < 1e-9);
+        
+        // Counted as ETH, the same surplus would be worth 3000 times more
+        solution.surplus_token = None;
+        assert!(solution.normalized_score(&feeds).unwrap() > 1000.0 * usd);
+        
+        solution.surplus_token = Some(usdc);
+        let unpriced = PriceFeed::new().with_native_price(ChainId::Ethereum, 3000.0);
+        assert!(solution.normalized_score(&unpriced).is_none());
+    }
+    
+    #[test]
+    fn test_congestion_makes_gas_heavy_solution_unprofitable() {
+        let mut solution = Solution {
+            orders: vec![],
+            settlement: SettlementPlan::default(),
+            gas_cost: 10_000_000,
+            surplus: 0.03,
+            surplus_token: None,
+            order_surplus: vec![],
+            dropped_orders: vec![],
+            gas_excluded_orders: vec![],
+            overflow_orders: vec![],
+            chain: None,
+            valid_for_block: 0,
+            score: 0.0,
+        };
+        let mut ctx = AuctionContext {
+            block_number: 1,
+            timestamp: 0,
+            gas_price: 30,
+            liquidity_sources: vec![],
+            congestion_factor: None,
+            cancellation: CancellationToken::default(),
+        };
+        
+        solution.calculate_score_with_context(&ctx);
+        assert!(solution.is_profitable(0.01));
+        
+        ctx.congestion_factor = Some(3.0);
+        solution.calculate_score_with_context(&ctx);
+        assert!(!solution.is_profitable(0.01));
+    }
+    
+    #[test]
+    fn test_fills_join_trades_with_prices() {
+        use crate::settlement::Trade;
+        use ethers::types::Address;
+        
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let e18 = U256::exp10(18);
+        
+        let mut settlement = SettlementPlan::default();
+        settlement.set_clearing_price(token_a, e18 * 2);
+        settlement.set_clearing_price(token_b, e18);
+        settlement.add_trade(Trade {
+            order_id: OrderId([1u8; 32]),
+            sell_token: token_a,
+            buy_token: token_b,
+            executed_sell_amount: e18,
+            executed_buy_amount: e18 * 2,
+            fee: U256::zero(),
+        });
+        // Partially filled: only 40% of a 5-unit order
+        settlement.add_trade(Trade {
+            order_id: OrderId([2u8; 32]),
+            sell_token: token_b,
+            buy_token: token_a,
+            executed_sell_amount: e18 * 2,
+            executed_buy_amount: e18,
+            fee: U256::zero(),
+        });
+        
+        let solution = Solution {
+            orders: vec![OrderId([1u8; 32]), OrderId([2u8; 32])],
+            settlement,
+            gas_cost: 0,
+            surplus: 0.0,
+            surplus_token: None,
+            order_surplus: vec![],
+            dropped_orders: vec![],
+            gas_excluded_orders: vec![],
+            overflow_orders: vec![],
+            chain: None,
+            valid_for_block: 0,
+            score: 0.0,
+        };
+        
+        assert_eq!(
+            solution.fills(),
+            vec![
+                OrderFill {
+                    order_id: OrderId([1u8; 32]),
+                    executed_sell: e18,
+                    executed_buy: e18 * 2,
+                    clearing_price: Some(e18 * 2),
+                },
+                OrderFill {
+                    order_id: OrderId([2u8; 32]),
+                    executed_sell: e18 * 2,
+                    executed_buy: e18,
+                    clearing_price: Some(e18),
+                },
+            ]
+        );
+    }
 }
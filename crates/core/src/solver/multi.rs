@@ -0,0 +1,216 @@
+use super::{AuctionContext, Solver, SolverConfig, Solution};
+use crate::domain::Order;
+use async_trait::async_trait;
+use futures::future::join_all;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Sentinel for "no winner yet"
+const NO_WINNER: usize = usize::MAX;
+
+/// Runs several solvers concurrently and keeps the best-scoring solution
+pub struct MultiSolver {
+    solvers: Vec<Box<dyn Solver>>,
+    config: SolverConfig,
+    winner: AtomicUsize,
+}
+
+impl MultiSolver {
+    /// Creates a multi-solver over the given solvers.
+    ///
+    /// Uses the first solver's configuration with the tightest `timeout_ms`
+    /// among all members, since the batch has to finish within every deadline.
+    pub fn new(solvers: Vec<Box<dyn Solver>>) -> Self {
+        let mut config = solvers
+            .first()
+            .map(|s| s.config().clone())
+            .unwrap_or_default();
+        if let Some(timeout_ms) = solvers.iter().map(|s| s.config().timeout_ms).min() {
+            config.timeout_ms = timeout_ms;
+        }
+
+        Self {
+            solvers,
+            config,
+            winner: AtomicUsize::new(NO_WINNER),
+        }
+    }
+
+    /// Returns number of inner solvers
+    pub fn len(&self) -> usize {
+        self.solvers.len()
+    }
+
+    /// Checks if there are no inner solvers
+    pub fn is_empty(&self) -> bool {
+        self.solvers.is_empty()
+    }
+}
+
+#[async_trait]
+impl Solver for MultiSolver {
+    async fn solve(&self, orders: Vec<Order>, ctx: &AuctionContext) -> crate::Result<Option<Solution>> {
+        let timeout = Duration::from_millis(self.config.timeout_ms);
+        info!(
+            "Racing {} solvers with {}ms timeout",
+            self.solvers.len(),
+            self.config.timeout_ms
+        );
+
+        let results = join_all(
+            self.solvers
+                .iter()
+                .map(|solver| tokio::time::timeout(timeout, solver.solve(orders.clone(), ctx))),
+        )
+        .await;
+
+        let mut best: Option<(usize, Solution)> = None;
+        let mut first_error = None;
+        let mut failures = 0;
+
+        for (idx, result) in results.into_iter().enumerate() {
+            let name = self.solvers[idx].name();
+            match result {
+                Ok(Ok(Some(solution))) => {
+                    debug!("{} produced score {:.6}", name, solution.score);
+                    let improves = match &best {
+                        Some((_, current)) => solution.score > current.score,
+                        None => true,
+                    };
+                    if improves {
+                        best = Some((idx, solution));
+                    }
+                }
+                Ok(Ok(None)) => debug!("{} found no solution", name),
+                Ok(Err(e)) => {
+                    warn!("{} failed: {}", name, e);
+                    failures += 1;
+                    first_error.get_or_insert(e);
+                }
+                Err(_) => {
+                    warn!("{} timed out", name);
+                    failures += 1;
+                }
+            }
+        }
+
+        match best {
+            Some((idx, solution)) => {
+                info!("Selected solution from {}", self.solvers[idx].name());
+                self.winner.store(idx, Ordering::Relaxed);
+                Ok(Some(solution))
+            }
+            None => {
+                self.winner.store(NO_WINNER, Ordering::Relaxed);
+                match first_error {
+                    Some(e) if failures == self.solvers.len() => Err(e),
+                    _ => Ok(None),
+                }
+            }
+        }
+    }
+
+    /// Returns the name of the solver that won the last solve
+    fn name(&self) -> &str {
+        match self.winner.load(Ordering::Relaxed) {
+            NO_WINNER => "MultiSolver",
+            idx => self.solvers[idx].name(),
+        }
+    }
+
+    fn config(&self) -> &SolverConfig {
+        &self.config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settlement::SettlementPlan;
+
+    struct MockSolver {
+        name: String,
+        score: Option<f64>,
+        fail: bool,
+        config: SolverConfig,
+    }
+
+    impl MockSolver {
+        fn boxed(name: &str, score: Option<f64>, fail: bool, timeout_ms: u64) -> Box<dyn Solver> {
+            Box::new(Self {
+                name: name.to_string(),
+                score,
+                fail,
+                config: SolverConfig {
+                    timeout_ms,
+                    ..SolverConfig::default()
+                },
+            })
+        }
+    }
+
+    #[async_trait]
+    impl Solver for MockSolver {
+        async fn solve(&self, _orders: Vec<Order>, _ctx: &AuctionContext) -> crate::Result<Option<Solution>> {
+            if self.fail {
+                return Err(crate::Error::InsufficientLiquidity(self.name.clone()));
+            }
+
+            Ok(self.score.map(|score| Solution {
+                orders: vec![],
+                settlement: SettlementPlan::default(),
+                gas_cost: 0,
+                surplus: score,
+                surplus_token: None,
+                order_surplus: vec![],
+                dropped_orders: vec![],
+                gas_excluded_orders: vec![],
+                overflow_orders: vec![],
+                chain: None,
+                valid_for_block: 0,
+                score,
+            }))
+        }
+
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn config(&self) -> &SolverConfig {
+            &self.config
+        }
+    }
+
+    #[tokio::test]
+    async fn test_selects_highest_score() {
+        let multi = MultiSolver::new(vec![
+            MockSolver::boxed("Low", Some(0.1), false, 5000),
+            MockSolver::boxed("High", Some(0.5), false, 3000),
+            MockSolver::boxed("Empty", None, false, 4000),
+        ]);
+
+        assert_eq!(multi.name(), "MultiSolver");
+        assert_eq!(multi.config().timeout_ms, 3000);
+
+        let solution = multi.solve(vec![], &AuctionContext::default()).await.unwrap().unwrap();
+        assert_eq!(solution.score, 0.5);
+        assert_eq!(multi.name(), "High");
+    }
+
+    #[tokio::test]
+    async fn test_error_only_when_all_fail() {
+        let partial = MultiSolver::new(vec![
+            MockSolver::boxed("Broken", None, true, 5000),
+            MockSolver::boxed("Works", Some(0.2), false, 5000),
+        ]);
+        assert!(partial.solve(vec![], &AuctionContext::default()).await.unwrap().is_some());
+
+        let all_failed = MultiSolver::new(vec![
+            MockSolver::boxed("First", None, true, 5000),
+            MockSolver::boxed("Second", None, true, 5000),
+        ]);
+        let err = all_failed.solve(vec![], &AuctionContext::default()).await.unwrap_err();
+        assert!(matches!(err, crate::Error::InsufficientLiquidity(ref name) if name == "First"));
+    }
+}
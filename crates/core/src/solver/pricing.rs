@@ -1,5 +1,5 @@
-use crate::domain::Order;
-use ethers::types::{Address, U256};
+use crate::domain::{ChainId, Order, OrderType, TokenRegistry};
+use ethers::types::{Address, U256, U512};
 use std::collections::HashMap;
 use tracing::{debug, info};
 
@@ -14,6 +14,117 @@ pub struct ClearingPrice {
     
     /// Confidence score (0-1)
     pub confidence: f64,
+
+    /// Strategy that produced the price
+    pub source: PriceSource,
+}
+
+/// Where a clearing price came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PriceSource {
+    /// Mid-point of limit prices
+    MidPoint,
+
+    /// Surplus-maximizing optimization
+    MaxSurplus,
+
+    /// External price oracle
+    Oracle,
+
+    /// Volume-weighted average of limit prices
+    VolumeWeighted,
+}
+
+/// Native token prices per chain, and token prices, in a common reference
+/// currency (e.g. USD)
+#[derive(Debug, Clone, Default)]
+pub struct PriceFeed {
+    /// Chain -> price of its native token
+    native_prices: HashMap<ChainId, f64>,
+
+    /// Token -> price of one whole token
+    token_prices: HashMap<Address, f64>,
+}
+
+impl PriceFeed {
+    /// Creates an empty price feed
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the reference price of `chain`'s native token
+    pub fn with_native_price(mut self, chain: ChainId, price: f64) -> Self {
+        self.native_prices.insert(chain, price);
+        self
+    }
+
+    /// Returns the reference price of `chain`'s native token
+    pub fn native_price(&self, chain: ChainId) -> Option<f64> {
+        self.native_prices.get(&chain).copied()
+    }
+
+    /// Sets the reference price of one whole `token`
+    pub fn with_token_price(mut self, token: Address, price: f64) -> Self {
+        self.token_prices.insert(token, price);
+        self
+    }
+
+    /// Returns the reference price of one whole `token`
+    pub fn token_price(&self, token: Address) -> Option<f64> {
+        self.token_prices.get(&token).copied()
+    }
+}
+
+/// Fee rates, as fractions of surplus, by chain with per-token overrides
+#[derive(Debug, Clone, Default)]
+pub struct FeeModel {
+    /// Rate for orders on chains without a base rate
+    default_rate: f64,
+
+    /// Chain -> base rate for orders settling there
+    chain_rates: HashMap<ChainId, f64>,
+
+    /// Token -> rate replacing the chain's base rate
+    token_rates: HashMap<Address, f64>,
+}
+
+impl FeeModel {
+    /// Creates a model charging `default_rate` everywhere
+    pub fn new(default_rate: f64) -> Self {
+        Self {
+            default_rate,
+            ..Self::default()
+        }
+    }
+
+    /// Sets the base rate for orders settling on `chain`
+    pub fn with_chain_rate(mut self, chain: ChainId, rate: f64) -> Self {
+        self.chain_rates.insert(chain, rate);
+        self
+    }
+
+    /// Sets the rate for `token`, e.g. lower for stablecoins
+    pub fn with_token_rate(mut self, token: Address, rate: f64) -> Self {
+        self.token_rates.insert(token, rate);
+        self
+    }
+
+    /// Returns the fee rate for `order`.
+    ///
+    /// Each token is charged its own rate, or else the base rate of the
+    /// order's source chain (the default rate if unknown); the order pays
+    /// the higher of its two tokens', so a stable/volatile pair is charged
+    /// as volatile.
+    pub fn rate(&self, order: &Order) -> f64 {
+        let base = order
+            .source_chain
+            .and_then(|chain| self.chain_rates.get(&chain))
+            .copied()
+            .unwrap_or(self.default_rate);
+        let token_rate = |token: Address| self.token_rates.get(&token).copied().unwrap_or(base);
+
+        token_rate(order.sell_token).max(token_rate(order.buy_token))
+    }
 }
 
 /// Pricing strategy
@@ -30,6 +141,10 @@ pub enum PricingStrategy {
     
     /// Volume-weighted average
     VolumeWeighted,
+
+    /// Per token, the highest-confidence price across oracle,
+    /// volume-weighted and mid-point pricing
+    Hybrid,
 }
 
 /// Pricing engine for calculating uniform clearing prices
@@ -42,6 +157,15 @@ pub struct PricingEngine {
     
     /// Minimum price confidence threshold
     min_confidence: f64,
+    
+    /// Minimum fee charged per order, covering its gas (in sell token units)
+    min_fee: U256,
+
+    /// Fee rates applied to order surplus
+    fee_model: FeeModel,
+
+    /// Token decimals for converting between raw and whole amounts
+    tokens: TokenRegistry,
 }
 
 impl PricingEngine {
@@ -51,14 +175,52 @@ impl PricingEngine {
             strategy,
             price_oracle: HashMap::new(),
             min_confidence,
+            min_fee: U256::zero(),
+            fee_model: FeeModel::default(),
+            tokens: TokenRegistry::default(),
         }
     }
 
+    /// Sets the minimum fee charged per order (in sell token units)
+    pub fn with_min_fee(mut self, min_fee: U256) -> Self {
+        self.min_fee = min_fee;
+        self
+    }
+
+    /// Sets the fee rates charged on order surplus
+    pub fn with_fee_model(mut self, fee_model: FeeModel) -> Self {
+        self.fee_model = fee_model;
+        self
+    }
+
+    /// Sets the token decimals surplus and fees are scaled by;
+    /// unregistered tokens are assumed to have 18
+    pub fn with_token_registry(mut self, tokens: TokenRegistry) -> Self {
+        self.tokens = tokens;
+        self
+    }
+
     /// Sets external price for a token
     pub fn set_external_price(&mut self, token: Address, price: U256) {
         self.price_oracle.insert(token, price);
     }
 
+    /// Sets external prices for many tokens at once (e.g. an oracle snapshot),
+    /// overwriting existing entries for the same tokens
+    pub fn set_external_prices(&mut self, prices: HashMap<Address, U256>) {
+        self.price_oracle.extend(prices);
+    }
+
+    /// Removes all external prices
+    pub fn clear_external_prices(&mut self) {
+        self.price_oracle.clear();
+    }
+
+    /// Returns external price for a token, if set
+    pub fn external_price(&self, token: Address) -> Option<U256> {
+        self.price_oracle.get(&token).copied()
+    }
+
     /// Calculates uniform clearing prices for a set of matched orders
     pub fn calculate_clearing_prices(
         &self,
@@ -71,6 +233,7 @@ impl PricingEngine {
             PricingStrategy::MaxSurplus => self.calculate_max_surplus_prices(orders),
             PricingStrategy::MarketPrice => self.calculate_market_prices(orders),
             PricingStrategy::VolumeWeighted => self.calculate_volume_weighted_prices(orders),
+            PricingStrategy::Hybrid => self.calculate_hybrid_prices(orders),
         }
     }
 
@@ -83,7 +246,7 @@ impl PricingEngine {
         for order in orders {
             token_pairs
                 .entry((order.sell_token, order.buy_token))
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(order);
         }
 
@@ -108,7 +271,7 @@ impl PricingEngine {
             let price_u256 = U256::from((mid_price * 1e18) as u128);
 
             // Calculate confidence based on price spread
-            let spread = (max_price - min_price) / mid_price;
+            let spread = Self::volume_weighted_spread(&pair_orders);
             let confidence = (1.0 - spread.min(1.0)).max(0.0);
 
             prices.insert(
@@ -117,8 +280,17 @@ impl PricingEngine {
                     token: sell_token,
                     price: price_u256,
                     confidence,
+                    source: PriceSource::MidPoint,
                 },
             );
+            // The sell token is priced in the buy token, which stays the unit
+            // unless another pair already priced it
+            prices.entry(buy_token).or_insert(ClearingPrice {
+                token: buy_token,
+                price: U256::exp10(18),
+                confidence,
+                source: PriceSource::MidPoint,
+            });
 
             debug!(
                 "Mid-point price for {:?}: {:.6}, confidence: {:.2}",
@@ -129,6 +301,33 @@ impl PricingEngine {
         prices
     }
 
+    /// Relative spread of limit prices, weighted by sell volume: twice the
+    /// volume-weighted mean deviation from the volume-weighted price, over
+    /// that price. Two equal orders give `(max - min) / mid`, while an order
+    /// with little volume barely moves it however far out it is.
+    fn volume_weighted_spread(orders: &[&Order]) -> f64 {
+        let weighted: Vec<(f64, f64)> = orders
+            .iter()
+            .map(|order| {
+                let sell = order.sell_amount.as_u128() as f64;
+                (order.buy_amount.as_u128() as f64 / sell, sell)
+            })
+            .collect();
+
+        let total_volume: f64 = weighted.iter().map(|(_, volume)| volume).sum();
+        let vwap = weighted.iter().map(|(price, volume)| price * volume).sum::<f64>() / total_volume;
+        if !vwap.is_finite() || vwap <= 0.0 {
+            return 0.0;
+        }
+
+        let deviation = weighted
+            .iter()
+            .map(|(price, volume)| (price - vwap).abs() * volume)
+            .sum::<f64>()
+            / total_volume;
+        2.0 * deviation / vwap
+    }
+
     /// Calculates prices that maximize total surplus
     fn calculate_max_surplus_prices(&self, orders: &[Order]) -> HashMap<Address, ClearingPrice> {
         // This is a simplified implementation
@@ -142,12 +341,12 @@ impl PricingEngine {
         for order in orders {
             token_orders
                 .entry(order.sell_token)
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(order);
             
             token_orders
                 .entry(order.buy_token)
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(order);
         }
 
@@ -182,6 +381,7 @@ impl PricingEngine {
                     token,
                     price: price_u256,
                     confidence: 0.8, // Medium confidence for optimization-based pricing
+                    source: PriceSource::MaxSurplus,
                 },
             );
 
@@ -214,6 +414,7 @@ impl PricingEngine {
                         token,
                         price: oracle_price,
                         confidence: 0.95, // High confidence for oracle prices
+                        source: PriceSource::Oracle,
                     },
                 );
 
@@ -266,6 +467,7 @@ impl PricingEngine {
                     token,
                     price: price_u256,
                     confidence: 0.85, // Good confidence for volume-weighted
+                    source: PriceSource::VolumeWeighted,
                 },
             );
 
@@ -278,6 +480,55 @@ impl PricingEngine {
         prices
     }
 
+    /// Calculates prices by taking, per token, the highest-confidence price
+    /// from the oracle, volume-weighted and mid-point strategies
+    fn calculate_hybrid_prices(&self, orders: &[Order]) -> HashMap<Address, ClearingPrice> {
+        // Oracle prices only; market pricing would add its own mid-point fallback
+        let mut prices: HashMap<Address, ClearingPrice> = self
+            .price_oracle
+            .iter()
+            .filter(|(token, _)| {
+                orders
+                    .iter()
+                    .any(|o| o.sell_token == **token || o.buy_token == **token)
+            })
+            .map(|(&token, &price)| {
+                (
+                    token,
+                    ClearingPrice {
+                        token,
+                        price,
+                        confidence: 0.95,
+                        source: PriceSource::Oracle,
+                    },
+                )
+            })
+            .collect();
+
+        let candidates = self
+            .calculate_volume_weighted_prices(orders)
+            .into_values()
+            .chain(self.calculate_midpoint_prices(orders).into_values());
+
+        for candidate in candidates {
+            match prices.get(&candidate.token) {
+                Some(best) if best.confidence >= candidate.confidence => {}
+                _ => {
+                    prices.insert(candidate.token, candidate);
+                }
+            }
+        }
+
+        for price in prices.values() {
+            debug!(
+                "Hybrid price for {:?}: {} from {:?}, confidence: {:.2}",
+                price.token, price.price, price.source, price.confidence
+            );
+        }
+
+        prices
+    }
+
     /// Validates clearing prices against orders
     pub fn validate_prices(
         &self,
@@ -325,7 +576,8 @@ impl PricingEngine {
         Ok(())
     }
 
-    /// Calculates total surplus generated by clearing prices
+    /// Calculates total surplus generated by clearing prices, summing each
+    /// order's surplus in whole units of its buy token
     pub fn calculate_total_surplus(
         &self,
         prices: &HashMap<Address, ClearingPrice>,
@@ -339,12 +591,14 @@ impl PricingEngine {
                 prices.get(&order.buy_token),
             ) {
                 // Surplus = (clearing_value - limit_value) for the order
-                let clearing_value = (order.sell_amount * sell_price.price).as_u128() as f64;
-                let limit_value = (order.buy_amount * buy_price.price).as_u128() as f64;
-
-                if clearing_value > limit_value {
-                    let surplus = (clearing_value - limit_value) / 1e18;
-                    total_surplus += surplus;
+                let clearing_value = order.sell_amount.full_mul(sell_price.price);
+                let limit_value = order.buy_amount.full_mul(buy_price.price);
+
+                if clearing_value > limit_value && !buy_price.price.is_zero() {
+                    // Excess value back in raw buy token, then whole tokens
+                    let surplus = U256::try_from((clearing_value - limit_value) / U512::from(buy_price.price))
+                        .unwrap_or(U256::MAX);
+                    total_surplus += self.tokens.to_units(order.buy_token, surplus);
                 }
             }
         }
@@ -353,11 +607,32 @@ impl PricingEngine {
         total_surplus
     }
 
-    /// Calculates fee for an order based on surplus
-    pub fn calculate_fee(&self, order: &Order, surplus: f64, fee_percentage: f64) -> U256 {
-        // Fee = surplus * fee_percentage
-        let fee = surplus * fee_percentage;
-        U256::from((fee * 1e18) as u128)
+    /// Calculates fee for an order based on surplus, in the order's sell token.
+    ///
+    /// `surplus` is in whole tokens of the order's surplus token: the buy
+    /// token for sell orders (converted at the order's limit price), the
+    /// sell token for buy orders. The fee model's rate for the order is
+    /// charged, raised to the minimum fee and then capped at the order's
+    /// signed `fee_amount`, which always wins.
+    pub fn calculate_fee(&self, order: &Order, surplus: f64) -> U256 {
+        let surplus_token = match order.kind {
+            OrderType::Sell => order.buy_token,
+            OrderType::Buy => order.sell_token,
+        };
+        let fee_in_surplus_token = surplus * self.fee_model.rate(order) * self.tokens.unit(surplus_token);
+        
+        let fee_in_sell_token = match order.kind {
+            OrderType::Sell if !order.buy_amount.is_zero() => {
+                fee_in_surplus_token * order.sell_amount.as_u128() as f64
+                    / order.buy_amount.as_u128() as f64
+            }
+            OrderType::Sell => 0.0,
+            OrderType::Buy => fee_in_surplus_token,
+        };
+        
+        // Negative or non-finite fees saturate to zero
+        let fee = U256::from(fee_in_sell_token as u128);
+        fee.max(self.min_fee).min(order.fee_amount)
     }
 }
 
@@ -370,7 +645,10 @@ impl Default for PricingEngine {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::domain::{OrderId, OrderKind, OrderStatus, ChainId};
+    use crate::domain::{OrderId, OrderStatus};
+
+    /// One whole token at 18 decimals
+    const E18: u128 = 1_000_000_000_000_000_000;
 
     fn create_test_order(
         sell_token: Address,
@@ -385,12 +663,16 @@ mod tests {
             buy_token,
             sell_amount: U256::from(sell_amount),
             buy_amount: U256::from(buy_amount),
-            valid_to: Some(u32::MAX),
+            valid_to: u32::MAX,
             fee_amount: U256::from(1000),
-            kind: OrderKind::Sell,
+            kind: OrderType::Sell,
             partially_fillable: false,
             status: OrderStatus::Open,
-            chain_id: ChainId::Mainnet,
+            source_chain: None,
+            destination_chain: None,
+            bridge_provider: None,
+            filled_amount: U256::zero(),
+            signature: vec![],
         }
     }
 
@@ -412,6 +694,30 @@ mod tests {
         assert!(prices.get(&token_a).unwrap().confidence > 0.0);
     }
 
+    #[test]
+    fn test_midpoint_confidence_weighted_by_volume() {
+        let engine = PricingEngine::default();
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let confidence = |orders: &[Order]| engine.calculate_clearing_prices(orders)[&token_a].confidence;
+
+        let book = vec![
+            create_test_order(token_a, token_b, E18, 2 * E18),
+            create_test_order(token_a, token_b, E18, 21 * E18 / 10),
+        ];
+        let base = confidence(&book);
+
+        // Both orders ask 5x the market, one for dust and one for size
+        let mut with_dust = book.clone();
+        with_dust.push(create_test_order(token_a, token_b, 1000, 10000));
+        let mut with_whale = book.clone();
+        with_whale.push(create_test_order(token_a, token_b, E18, 10 * E18));
+
+        assert!((base - confidence(&with_dust)).abs() < 0.001);
+        assert!(base - confidence(&with_whale) > 0.5);
+    }
+
     #[test]
     fn test_volume_weighted_pricing() {
         let engine = PricingEngine::new(PricingStrategy::VolumeWeighted, 0.5);
@@ -449,6 +755,74 @@ mod tests {
         assert_eq!(prices.get(&token_a).unwrap().confidence, 0.95);
     }
 
+    #[test]
+    fn test_hybrid_pricing_prefers_oracle() {
+        let mut engine = PricingEngine::new(PricingStrategy::Hybrid, 0.5);
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+
+        engine.set_external_price(token_a, U256::from(2000000000000000000u128));
+
+        // Spread between token_a limit prices keeps its mid-point confidence below the oracle's
+        let orders = vec![
+            create_test_order(token_a, token_b, 1000, 2000),
+            create_test_order(token_a, token_b, 1000, 3000),
+        ];
+
+        let prices = engine.calculate_clearing_prices(&orders);
+        let price_a = prices.get(&token_a).unwrap();
+
+        assert_eq!(price_a.source, PriceSource::Oracle);
+        assert_eq!(price_a.price, U256::from(2000000000000000000u128));
+        assert_eq!(price_a.confidence, 0.95);
+    }
+
+    #[test]
+    fn test_hybrid_pricing_falls_back_to_midpoint() {
+        let mut engine = PricingEngine::new(PricingStrategy::Hybrid, 0.5);
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+
+        engine.set_external_price(token_a, U256::from(2000000000000000000u128));
+
+        let orders = vec![
+            create_test_order(token_a, token_b, 1000, 2000),
+            create_test_order(token_a, token_b, 1000, 3000),
+            create_test_order(token_b, token_a, 2000, 1000),
+        ];
+
+        let prices = engine.calculate_clearing_prices(&orders);
+        let price_b = prices.get(&token_b).unwrap();
+
+        // No oracle price; a single limit price gives mid-point full confidence
+        assert_eq!(price_b.source, PriceSource::MidPoint);
+        assert!(price_b.confidence > 0.85);
+        assert_eq!(prices.get(&token_a).unwrap().source, PriceSource::Oracle);
+    }
+
+    #[test]
+    fn test_bulk_external_prices() {
+        let mut engine = PricingEngine::default();
+
+        let snapshot: HashMap<Address, U256> = (1..=3)
+            .map(|i| (Address::from_low_u64_be(i), U256::from(i * 1000)))
+            .collect();
+        engine.set_external_prices(snapshot);
+
+        for i in 1..=3 {
+            assert_eq!(
+                engine.external_price(Address::from_low_u64_be(i)),
+                Some(U256::from(i * 1000))
+            );
+        }
+        assert_eq!(engine.external_price(Address::from_low_u64_be(4)), None);
+
+        engine.clear_external_prices();
+        assert_eq!(engine.external_price(Address::from_low_u64_be(1)), None);
+    }
+
     #[test]
     fn test_price_validation() {
         let engine = PricingEngine::default();
@@ -484,18 +858,96 @@ mod tests {
     }
 
     #[test]
-    fn test_fee_calculation() {
+    fn test_surplus_of_large_orders() {
         let engine = PricingEngine::default();
 
         let token_a = Address::from_low_u64_be(1);
         let token_b = Address::from_low_u64_be(2);
 
-        let order = create_test_order(token_a, token_b, 1000, 2000);
+        // Amounts times prices are far beyond u128
+        let orders = vec![create_test_order(token_a, token_b, 1000 * E18, 1500 * E18)];
+        let price = |token: Address, price: U256| ClearingPrice {
+            token,
+            price,
+            confidence: 1.0,
+            source: PriceSource::MidPoint,
+        };
+        let prices = HashMap::from([
+            (token_a, price(token_a, U256::from(2 * E18))),
+            (token_b, price(token_b, U256::from(E18))),
+        ]);
+
+        let surplus = engine.calculate_total_surplus(&prices, &orders);
+        assert!((surplus - 500.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_fee_calculation() {
+        let engine = PricingEngine::default().with_fee_model(FeeModel::new(0.1)); // 10%
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+
+        let mut order = create_test_order(token_a, token_b, 1000, 2000);
+        order.fee_amount = U256::from(10000000000000000000u128); // 10.0 in wei
         let surplus = 100.0;
-        let fee_percentage = 0.1; // 10%
 
-        let fee = engine.calculate_fee(&order, surplus, fee_percentage);
+        let fee = engine.calculate_fee(&order, surplus);
+
+        // 10.0 of the buy token is 5.0 of the sell token at the limit price
+        assert_eq!(fee, U256::from(5000000000000000000u128));
+    }
+
+    #[test]
+    fn test_fee_capped_at_order_fee_amount() {
+        let engine = PricingEngine::default().with_fee_model(FeeModel::new(0.1));
+
+        let order = create_test_order(Address::from_low_u64_be(1), Address::from_low_u64_be(2), 1000, 2000);
 
-        assert_eq!(fee, U256::from(10000000000000000000u128)); // 10.0 in wei
+        let fee = engine.calculate_fee(&order, 100.0);
+        assert_eq!(fee, order.fee_amount);
+    }
+
+    #[test]
+    fn test_fee_raised_to_minimum() {
+        let engine = PricingEngine::default()
+            .with_min_fee(U256::from(400))
+            .with_fee_model(FeeModel::new(0.1));
+
+        let mut order = create_test_order(Address::from_low_u64_be(1), Address::from_low_u64_be(2), 1000, 2000);
+        order.kind = OrderType::Buy;
+
+        // Tiny surplus rounds below the floor
+        assert_eq!(engine.calculate_fee(&order, 1e-16), U256::from(400));
+
+        // Floor never exceeds what the user signed
+        order.fee_amount = U256::from(300);
+        assert_eq!(engine.calculate_fee(&order, 1e-16), U256::from(300));
+    }
+
+    #[test]
+    fn test_fee_model_rates_by_token_and_chain() {
+        let usdc = Address::from_low_u64_be(1);
+        let dai = Address::from_low_u64_be(2);
+        let weth = Address::from_low_u64_be(3);
+
+        let model = FeeModel::new(0.1)
+            .with_chain_rate(ChainId::Arbitrum, 0.05)
+            .with_token_rate(usdc, 0.01)
+            .with_token_rate(dai, 0.01);
+        let engine = PricingEngine::default().with_fee_model(model.clone());
+
+        let mut stable = create_test_order(usdc, dai, 1_000_000_000_000_000_000, 1_000_000_000_000_000_000);
+        stable.fee_amount = U256::MAX;
+        let mut volatile = create_test_order(usdc, weth, 1_000_000_000_000_000_000, 1_000_000_000_000_000_000);
+        volatile.fee_amount = U256::MAX;
+
+        assert_eq!(model.rate(&stable), 0.01);
+        assert_eq!(model.rate(&volatile), 0.1);
+        assert!(engine.calculate_fee(&stable, 10.0) < engine.calculate_fee(&volatile, 10.0));
+
+        // Tokens without an override pay their chain's base rate
+        volatile.source_chain = Some(ChainId::Arbitrum);
+        assert_eq!(model.rate(&volatile), 0.05);
     }
 }
@@ -1,9 +1,45 @@
-use crate::domain::{Order, Token};
-use ethers::types::{Address, U256};
-use std::collections::{HashMap, BinaryHeap};
+use crate::domain::orders::SETTLEMENT_CONTRACT;
+use crate::domain::{Order, OrderType, TokenRegistry};
+use crate::math::{calculate_amm_input, calculate_optimal_split, u256_to_f64};
+use crate::settlement::{Interaction, InteractionType, SettlementPlan, TokenFlow, Trade};
+use super::graph::TokenGraph;
+use ethers::abi::{self, Token};
+use ethers::types::{Address, Bytes, U256, U512};
+use ethers::utils::{id, keccak256};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
 use std::cmp::Ordering;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
 use tracing::{debug, info};
 
+/// StableSwap amplification assumed for Curve pools that don't report one
+const DEFAULT_CURVE_AMPLIFICATION: u64 = 100;
+
+/// Number of halvings of the largest reserve tried as arbitrage inputs
+const ARBITRAGE_SIZING_STEPS: u32 = 32;
+
+/// Uniswap V2 router used for V2-style swaps
+const UNISWAP_V2_ROUTER: [u8; 20] = [
+    0x7a, 0x25, 0x0d, 0x56, 0x30, 0xb4, 0xcf, 0x53, 0x97, 0x39,
+    0xdf, 0x2c, 0x5d, 0xac, 0xb4, 0xc6, 0x59, 0xf2, 0x48, 0x8d,
+];
+
+/// Encodes a single-hop `swapExactTokensForTokens` paying out to the
+/// settlement contract. The auction's own validity bounds execution time,
+/// so the deadline is left open.
+fn encode_v2_swap(amount_in: U256, amount_out_min: U256, token_in: Address, token_out: Address) -> Bytes {
+    let mut call_data = id("swapExactTokensForTokens(uint256,uint256,address[],address,uint256)").to_vec();
+    call_data.extend(abi::encode(&[
+        Token::Uint(amount_in),
+        Token::Uint(amount_out_min),
+        Token::Array(vec![Token::Address(token_in), Token::Address(token_out)]),
+        Token::Address(Address::from(SETTLEMENT_CONTRACT)),
+        Token::Uint(U256::MAX),
+    ]));
+    call_data.into()
+}
+
 /// Represents a liquidity pool
 #[derive(Debug, Clone)]
 pub struct LiquidityPool {
@@ -30,10 +66,93 @@ pub struct LiquidityPool {
     
     /// Gas cost to interact with this pool
     pub gas_cost: u64,
+    
+    /// Active-range state, for concentrated-liquidity pools
+    pub concentrated: Option<ConcentratedLiquidity>,
+    
+    /// StableSwap amplification coefficient `A`, for Curve pools
+    pub amplification: Option<u64>,
+    
+    /// Block the reserves were read at (0 = unknown, never stale)
+    pub fetched_at_block: u64,
 }
 
-/// Type of AMM pool
+impl LiquidityPool {
+    /// Creates a pool whose gas cost is the default for its type
+    pub fn with_defaults(
+        address: Address,
+        pool_type: PoolType,
+        token_a: Address,
+        token_b: Address,
+        reserve_a: U256,
+        reserve_b: U256,
+        fee_bps: u16,
+    ) -> Self {
+        Self {
+            address,
+            pool_type,
+            token_a,
+            token_b,
+            reserve_a,
+            reserve_b,
+            fee_bps,
+            gas_cost: Self::default_gas_cost(pool_type),
+            concentrated: None,
+            amplification: None,
+            fetched_at_block: 0,
+        }
+    }
+
+    /// Returns typical gas used by a swap through a pool of `pool_type`,
+    /// matching what `SettlementPlan::estimate_gas` charges for the swap
+    pub fn default_gas_cost(pool_type: PoolType) -> u64 {
+        match pool_type {
+            PoolType::UniswapV2 | PoolType::ConstantProduct => 100_000,
+            PoolType::UniswapV3 => 130_000,
+            PoolType::Balancer => 150_000,
+            PoolType::Curve => 180_000,
+            PoolType::Custom(_) => 100_000,
+        }
+    }
+
+    /// Checks if price impact through this pool can only be estimated from
+    /// reserves, as for V3 pools without concentrated-liquidity state
+    pub fn has_low_confidence_impact(&self) -> bool {
+        self.pool_type == PoolType::UniswapV3 && self.concentrated.is_none()
+    }
+}
+
+/// State of the active tick range of a concentrated-liquidity (V3) pool.
+///
+/// Prices are `sqrt(token_b / token_a)` in Q64.96 fixed point, as reported
+/// by the pool's `slot0`.
 #[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConcentratedLiquidity {
+    /// Current sqrt price
+    pub sqrt_price_x96: U256,
+    
+    /// Liquidity available in the active range
+    pub liquidity: u128,
+    
+    /// Sqrt price at the lower bound of the active range
+    pub sqrt_price_lower_x96: U256,
+    
+    /// Sqrt price at the upper bound of the active range
+    pub sqrt_price_upper_x96: U256,
+}
+
+/// Converts a Q64.96 fixed-point value to a float
+fn q96_to_f64(value: U256) -> f64 {
+    value
+        .0
+        .iter()
+        .rev()
+        .fold(0.0, |acc, &limb| acc * 18446744073709551616.0 + limb as f64)
+        / 2f64.powi(96)
+}
+
+/// Type of AMM pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PoolType {
     /// Uniswap V2 style (constant product)
     UniswapV2,
@@ -49,6 +168,18 @@ pub enum PoolType {
     
     /// Generic constant product
     ConstantProduct,
+    
+    /// Pool priced by the [`OutputModel`] registered under this id with
+    /// `RoutingEngine::with_output_model`
+    Custom(u16),
+}
+
+/// Swap math for `PoolType::Custom` pools, so new AMMs can be routed
+/// through without changing `PoolType`'s built-in handling
+pub trait OutputModel: Send + Sync {
+    /// Returns what `pool` pays out for `amount_in` of `token_in`, after
+    /// pool fees
+    fn output(&self, pool: &LiquidityPool, token_in: Address, amount_in: U256) -> U256;
 }
 
 /// Represents a route through AMM pools
@@ -60,19 +191,296 @@ pub struct Route {
     /// Tokens in the path (including start and end)
     pub path: Vec<Address>,
     
+    /// Input amount the route was evaluated for
+    pub amount_in: U256,
+    
     /// Expected output amount
     pub output_amount: U256,
     
+    /// Expected output of each hop, in path order (the last is `output_amount`)
+    pub hop_outputs: Vec<U256>,
+    
+    /// Extra pools sharing each hop's input with `pools[i]`, when hops are
+    /// split; empty for single-pool routes
+    pub split_pools: Vec<Vec<LiquidityPool>>,
+    
+    /// Minimum acceptable output after slippage, enforced on-chain
+    pub min_output_amount: U256,
+    
     /// Total gas cost
     pub gas_cost: u64,
     
     /// Price impact (as percentage)
     pub price_impact: f64,
     
+    /// Set if any hop's price impact is a reserve-based estimate
+    pub low_confidence_impact: bool,
+    
     /// Route quality score
     pub score: f64,
 }
 
+impl Route {
+    /// Returns the realized price (output per unit of `amount_in`), or 0
+    /// for a zero input.
+    ///
+    /// Read off [`Self::execution_price_fixed`], so amounts of any size
+    /// are safe.
+    pub fn execution_price(&self, amount_in: U256) -> f64 {
+        u256_to_f64(self.execution_price_fixed(amount_in)) / 1e18
+    }
+
+    /// Like [`Self::execution_price`], as a fixed-point value scaled by 1e18
+    /// and rounded down
+    pub fn execution_price_fixed(&self, amount_in: U256) -> U256 {
+        if amount_in.is_zero() {
+            return U256::zero();
+        }
+
+        let price = self.output_amount.full_mul(U256::exp10(18)) / U512::from(amount_in);
+        U256::try_from(price).unwrap_or(U256::MAX)
+    }
+
+    /// Checks if this route, quoted for `order`'s remaining sell amount,
+    /// satisfies the order's limit price
+    pub fn beats_limit(&self, order: &Order) -> bool {
+        let (remaining_sell, remaining_buy) = order.remaining_amounts();
+        if remaining_sell.is_zero() {
+            return false;
+        }
+
+        match order.kind {
+            // Compared exactly against buy_amount / sell_amount
+            OrderType::Sell => {
+                self.output_amount.full_mul(order.sell_amount) >= order.buy_amount.full_mul(remaining_sell)
+            }
+            // A buyer must receive the full remaining amount
+            OrderType::Buy => self.output_amount >= remaining_buy,
+        }
+    }
+}
+
+/// Expected result of a swap, quoted without building a settlement
+#[derive(Debug, Clone)]
+pub struct Quote {
+    /// Amount of the input token quoted for
+    pub amount_in: U256,
+    
+    /// Expected output amount
+    pub output_amount: U256,
+    
+    /// Minimum output after the engine's slippage tolerance
+    pub min_output_amount: U256,
+    
+    /// Output per unit of input
+    pub execution_price: f64,
+    
+    /// Price impact (as percentage)
+    pub price_impact: f64,
+    
+    /// Gas used by the route's swaps
+    pub gas_estimate: u64,
+    
+    /// Route the quote would execute through
+    pub route: Route,
+}
+
+/// Union-find over tokens, grouping those linked through any chain of pools
+#[derive(Debug, Default)]
+struct TokenComponents {
+    /// Token -> parent token; roots point to themselves
+    parent: HashMap<Address, Address>,
+
+    /// Root -> number of tokens in its component
+    size: HashMap<Address, usize>,
+}
+
+impl TokenComponents {
+    /// Returns the root of `token`'s component, if it is in any pool
+    fn find(&self, token: Address) -> Option<Address> {
+        let mut current = token;
+        loop {
+            let parent = *self.parent.get(&current)?;
+            if parent == current {
+                return Some(current);
+            }
+            current = parent;
+        }
+    }
+
+    /// Merges the components of `a` and `b`, the smaller under the larger
+    fn union(&mut self, a: Address, b: Address) {
+        for token in [a, b] {
+            if let Entry::Vacant(entry) = self.parent.entry(token) {
+                entry.insert(token);
+                self.size.insert(token, 1);
+            }
+        }
+
+        let (Some(root_a), Some(root_b)) = (self.find(a), self.find(b)) else {
+            return;
+        };
+        if root_a == root_b {
+            return;
+        }
+
+        let (small, large) = if self.size[&root_a] < self.size[&root_b] {
+            (root_a, root_b)
+        } else {
+            (root_b, root_a)
+        };
+        self.parent.insert(small, large);
+        let merged = self.size.remove(&small).unwrap_or(0);
+        *self.size.entry(large).or_insert(0) += merged;
+    }
+
+    /// Checks if some chain of pools links `a` and `b`
+    fn connected(&self, a: Address, b: Address) -> bool {
+        match (self.find(a), self.find(b)) {
+            (Some(root_a), Some(root_b)) => root_a == root_b,
+            _ => false,
+        }
+    }
+}
+
+/// Cache key: token pair, amount bucket and hop limit
+type RouteCacheKey = (Address, Address, U256, usize);
+
+/// Replay cache key: hash of the token pair, exact amount, hop limit and
+/// the reserves of every pool reachable from the input token
+type ReplayCacheKey = [u8; 32];
+
+/// Small LRU cache of best routes
+#[derive(Debug)]
+struct RouteCache<K = RouteCacheKey> {
+    /// Maximum number of cached routes
+    capacity: usize,
+    
+    /// Cached routes
+    entries: HashMap<K, Route>,
+    
+    /// Keys from least to most recently used
+    recency: VecDeque<K>,
+    
+    /// Lookups served from the cache
+    hits: u64,
+}
+
+impl<K: Copy + Eq + Hash> RouteCache<K> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            recency: VecDeque::new(),
+            hits: 0,
+        }
+    }
+    
+    fn get(&mut self, key: &K) -> Option<Route> {
+        let route = self.entries.get(key)?.clone();
+        self.touch(key);
+        self.hits += 1;
+        Some(route)
+    }
+    
+    fn insert(&mut self, key: K, route: Route) {
+        if self.capacity == 0 {
+            return;
+        }
+        
+        if self.entries.insert(key, route).is_some() {
+            self.touch(&key);
+            return;
+        }
+        
+        self.recency.push_back(key);
+        if self.recency.len() > self.capacity {
+            if let Some(evicted) = self.recency.pop_front() {
+                self.entries.remove(&evicted);
+            }
+        }
+    }
+    
+    fn touch(&mut self, key: &K) {
+        if let Some(pos) = self.recency.iter().position(|k| k == key) {
+            self.recency.remove(pos);
+            self.recency.push_back(*key);
+        }
+    }
+    
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+}
+
+/// Why no route could be found for a swap
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum RoutingError {
+    /// No pools connect the tokens within `max_hops`
+    #[error("No path between tokens")]
+    NoPath,
+    
+    /// Every route exceeds `max_price_impact`
+    #[error("All routes exceed the price impact cap (best: {best_impact:.2}%)")]
+    AllExceedPriceImpact {
+        /// Lowest price impact among the rejected routes (as percentage)
+        best_impact: f64,
+    },
+    
+    /// Pools connect the tokens but no route produces any output
+    #[error("All routes produce zero output")]
+    ZeroOutput,
+    
+    /// Every route's output is below the requested floor
+    #[error("All routes output less than the minimum (best: {best_output})")]
+    BelowMinOutput {
+        /// Highest output among the rejected routes
+        best_output: U256,
+    },
+    
+    /// The route would end at the token it starts from
+    #[error("Route starts and ends at the same token")]
+    CyclicRoute,
+    
+    /// Every route's gas is worth more than its output
+    #[error("No route's output covers its gas (best net value: {best_net_value:.6} ETH)")]
+    NoProfitableRoute {
+        /// Highest output value net of gas among the rejected routes (in ETH)
+        best_net_value: f64,
+    },
+}
+
+/// Relative weights of the terms in a route's score
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RouteScoringWeights {
+    /// Weight of the output amount (in units of 1e18)
+    pub output: f64,
+    
+    /// Weight of the gas penalty (per million gas)
+    pub gas: f64,
+    
+    /// Weight of the output lost to price impact
+    pub impact: f64,
+}
+
+impl Default for RouteScoringWeights {
+    fn default() -> Self {
+        Self {
+            output: 1.0,
+            gas: 1.0,
+            impact: 1.0,
+        }
+    }
+}
+
+/// Pool reserves captured by [`RoutingEngine::snapshot`]
+#[derive(Debug, Clone)]
+pub struct RoutingSnapshot {
+    /// (pool address, reserve_a, reserve_b), in pool order
+    reserves: Vec<(Address, U256, U256)>,
+}
+
 /// AMM routing engine
 pub struct RoutingEngine {
     /// Available liquidity pools
@@ -81,11 +489,58 @@ pub struct RoutingEngine {
     /// Pool lookup by token pair
     pool_index: HashMap<(Address, Address), Vec<usize>>,
     
+    /// Connected components of the token graph, for cheap reachability
+    components: TokenComponents,
+    
     /// Maximum number of hops
     max_hops: usize,
     
     /// Maximum price impact allowed (as percentage)
     max_price_impact: f64,
+    
+    /// Minimum number of pools backing each hop of a multi-hop route
+    min_pools_per_hop: usize,
+    
+    /// Optional best-route cache, invalidated on any pool mutation
+    route_cache: Option<Mutex<RouteCache>>,
+    
+    /// Amounts are divided by this before keying the route cache, so
+    /// nearby amounts share a cached path
+    cache_granularity: U256,
+    
+    /// Optional best-route cache keyed by the reserves it was found at,
+    /// which survives pool mutations
+    replay_cache: Option<Mutex<RouteCache<ReplayCacheKey>>>,
+    
+    /// Default slippage tolerance for minimum outputs (as percentage)
+    max_slippage: f64,
+    
+    /// Fee-on-transfer tokens and their fee (in basis points)
+    transfer_fees: HashMap<Address, u16>,
+    
+    /// Pools whose smaller reserve is below this are rejected by `add_pool`
+    min_liquidity: U256,
+    
+    /// Weights combining output, gas and price impact into a route score
+    scoring_weights: RouteScoringWeights,
+    
+    /// Whether a hop may split its input across all pools for its pair
+    split_hops: bool,
+    
+    /// Swap math for custom pool types, keyed by their id
+    output_models: HashMap<u16, Arc<dyn OutputModel>>,
+    
+    /// Pools read before this block are rejected as stale
+    min_pool_block: u64,
+    
+    /// Token decimals route outputs are normalized by when scoring
+    tokens: TokenRegistry,
+    
+    /// Gas price (in gwei) routes must cover out of their output, if any
+    gas_price: Option<u64>,
+    
+    /// Value of a whole token in ETH, for weighing outputs against gas
+    native_prices: HashMap<Address, f64>,
 }
 
 impl RoutingEngine {
@@ -94,483 +549,2660 @@ impl RoutingEngine {
         Self {
             pools: Vec::new(),
             pool_index: HashMap::new(),
+            components: TokenComponents::default(),
             max_hops,
             max_price_impact,
+            min_pools_per_hop: 1,
+            route_cache: None,
+            cache_granularity: U256::one(),
+            replay_cache: None,
+            max_slippage: 0.0,
+            transfer_fees: HashMap::new(),
+            min_liquidity: U256::zero(),
+            scoring_weights: RouteScoringWeights::default(),
+            split_hops: false,
+            output_models: HashMap::new(),
+            min_pool_block: 0,
+            tokens: TokenRegistry::default(),
+            gas_price: None,
+            native_prices: HashMap::new(),
         }
     }
 
-    /// Adds a liquidity pool to the routing engine
-    pub fn add_pool(&mut self, pool: LiquidityPool) {
-        let idx = self.pools.len();
-        
-        // Index by both token orderings
-        self.pool_index
-            .entry((pool.token_a, pool.token_b))
-            .or_insert_with(Vec::new)
-            .push(idx);
-        
-        self.pool_index
-            .entry((pool.token_b, pool.token_a))
-            .or_insert_with(Vec::new)
-            .push(idx);
-        
-        self.pools.push(pool);
+    /// Sets the default slippage tolerance (as percentage) applied to route
+    /// outputs to derive `min_output_amount`
+    pub fn with_max_slippage(mut self, max_slippage: f64) -> Self {
+        self.max_slippage = max_slippage;
+        self
     }
 
-    /// Finds the best route for a swap
-    pub fn find_best_route(
-        &self,
-        token_in: Address,
-        token_out: Address,
-        amount_in: U256,
-    ) -> Option<Route> {
-        info!(
-            "Finding route: {:?} -> {:?}, amount: {}",
-            token_in, token_out, amount_in
-        );
+    /// Registers the swap math for pools of type `PoolType::Custom(id)`.
+    /// Custom pools without a model quote no output and are never used.
+    pub fn with_output_model(mut self, id: u16, model: Arc<dyn OutputModel>) -> Self {
+        self.output_models.insert(id, model);
+        self
+    }
 
-        // Find all possible routes
-        let routes = self.find_all_routes(token_in, token_out, amount_in);
+    /// Marks `token` as charging `fee_bps` on every transfer, so swaps
+    /// through it account for the amounts lost in and out of pools.
+    /// Tokens without a fee (the default) are priced as before.
+    pub fn with_transfer_fee(mut self, token: Address, fee_bps: u16) -> Self {
+        self.transfer_fees.insert(token, fee_bps.min(10000));
+        self
+    }
 
-        if routes.is_empty() {
-            debug!("No routes found");
-            return None;
+    /// Deducts `token`'s transfer fee from `amount`
+    fn after_transfer_fee(&self, token: Address, amount: U256) -> U256 {
+        match self.transfer_fees.get(&token) {
+            Some(&fee_bps) => amount - amount * U256::from(fee_bps) / U256::from(10000),
+            None => amount,
         }
+    }
 
-        // Select best route by score
-        let best_route = routes
-            .into_iter()
-            .max_by(|a, b| {
-                a.score
-                    .partial_cmp(&b.score)
-                    .unwrap_or(Ordering::Equal)
-            })?;
+    /// Enables an LRU cache of best routes holding up to `capacity` entries
+    pub fn with_cache(mut self, capacity: usize) -> Self {
+        self.route_cache = Some(Mutex::new(RouteCache::new(capacity)));
+        self
+    }
 
-        info!(
-            "Best route: {} hops, output: {}, score: {:.4}",
-            best_route.pools.len(),
-            best_route.output_amount,
-            best_route.score
-        );
+    /// Enables a cache of up to `capacity` best routes keyed by exact
+    /// amount and the reserves of every pool that could be routed through,
+    /// so replaying identical inputs skips the search even after reserves
+    /// were changed and restored
+    pub fn with_replay_cache(mut self, capacity: usize) -> Self {
+        self.replay_cache = Some(Mutex::new(RouteCache::new(capacity)));
+        self
+    }
 
-        Some(best_route)
+    /// Returns how many lookups the replay cache has served
+    pub fn replay_cache_hits(&self) -> u64 {
+        self.replay_cache
+            .as_ref()
+            .map(|cache| cache.lock().unwrap().hits)
+            .unwrap_or(0)
     }
 
-    /// Finds all possible routes up to max_hops
-    fn find_all_routes(
-        &self,
-        token_in: Address,
-        token_out: Address,
-        amount_in: U256,
-    ) -> Vec<Route> {
-        let mut routes = Vec::new();
+    /// Sets the amount bucket size used for cache keys, so queries whose
+    /// amounts round to the same bucket share a cached path, re-quoted at
+    /// each query's own amount.
+    ///
+    /// Has no effect unless the cache is enabled, before or after this call.
+    pub fn with_cache_granularity(mut self, granularity: U256) -> Self {
+        self.cache_granularity = granularity.max(U256::one());
+        self.invalidate_cache();
+        self
+    }
 
-        // Try direct routes (1 hop)
-        if let Some(direct_route) = self.find_direct_route(token_in, token_out, amount_in) {
-            routes.push(direct_route);
-        }
+    /// Returns the route cache key for a query
+    fn cache_key(&self, token_in: Address, token_out: Address, amount_in: U256, max_hops: usize) -> RouteCacheKey {
+        (token_in, token_out, amount_in / self.cache_granularity, max_hops)
+    }
 
-        // Try multi-hop routes if enabled
-        if self.max_hops > 1 {
-            routes.extend(self.find_multi_hop_routes(token_in, token_out, amount_in));
+    /// Re-quotes a cached route's path at `amount_in`, which may differ
+    /// within its cache bucket. Returns `None` if the path no longer
+    /// qualifies at that amount, so a full search runs instead.
+    fn requote(&self, cached: Route, amount_in: U256) -> Option<Route> {
+        if cached.amount_in == amount_in {
+            return Some(cached);
         }
 
-        // Filter by price impact
-        routes.retain(|r| r.price_impact <= self.max_price_impact);
+        let route = self.evaluate_hops(&cached.path, amount_in)?;
+        let unprofitable = matches!(self.gas_price, Some(gas_price) if self.net_value(&route, gas_price) < 0.0);
+        (route.price_impact <= self.max_price_impact && !unprofitable).then_some(route)
+    }
 
-        routes
+    /// Requires every hop of a multi-hop route to be backed by at least
+    /// `min_pools_per_hop` pools, so a single thin pool can't carry a route
+    pub fn with_min_pools_per_hop(mut self, min_pools_per_hop: usize) -> Self {
+        self.min_pools_per_hop = min_pools_per_hop;
+        self
     }
 
-    /// Finds direct route (single pool)
-    fn find_direct_route(
-        &self,
-        token_in: Address,
-        token_out: Address,
-        amount_in: U256,
-    ) -> Option<Route> {
-        let pool_indices = self.pool_index.get(&(token_in, token_out))?;
+    /// Rejects pools whose smaller reserve is below `min_liquidity`, so
+    /// dust pools never quote
+    pub fn with_min_liquidity(mut self, min_liquidity: U256) -> Self {
+        self.min_liquidity = min_liquidity;
+        self
+    }
 
-        let mut best_route: Option<Route> = None;
+    /// Rejects pools read more than `max_staleness` blocks before
+    /// `current_block`, dropping any already added. Pools with an unknown
+    /// fetch block are kept.
+    pub fn with_max_pool_staleness(mut self, current_block: u64, max_staleness: u64) -> Self {
+        self.min_pool_block = current_block.saturating_sub(max_staleness);
+        let stale: Vec<Address> = self
+            .pools
+            .iter()
+            .filter(|pool| self.is_stale(pool))
+            .map(|pool| pool.address)
+            .collect();
+        for address in stale {
+            debug!("Dropping stale pool {:?}", address);
+            self.remove_pool(address);
+        }
+        self
+    }
 
-        for &pool_idx in pool_indices {
-            let pool = &self.pools[pool_idx];
-            
-            // Calculate output amount
-            let output_amount = self.calculate_output(pool, token_in, amount_in);
-            
-            if output_amount.is_zero() {
-                continue;
-            }
+    /// Checks if `pool`'s reserves were read before the staleness cutoff
+    fn is_stale(&self, pool: &LiquidityPool) -> bool {
+        pool.fetched_at_block != 0 && pool.fetched_at_block < self.min_pool_block
+    }
 
-            // Calculate price impact
-            let price_impact = self.calculate_price_impact(pool, token_in, amount_in);
+    /// Sets how output, gas and price impact are traded off when ranking
+    /// routes, e.g. down-weighting gas on cheap L2s
+    pub fn with_scoring_weights(mut self, weights: RouteScoringWeights) -> Self {
+        self.scoring_weights = weights;
+        self.invalidate_cache();
+        self.invalidate_replay_cache();
+        self
+    }
 
-            // Calculate route score
-            let score = self.calculate_route_score(output_amount, pool.gas_cost, price_impact);
+    /// Sets the token decimals route outputs are scored in, so routes into
+    /// tokens of different decimals compare by value rather than raw amount;
+    /// unregistered tokens are assumed to have 18
+    pub fn with_token_registry(mut self, tokens: TokenRegistry) -> Self {
+        self.tokens = tokens;
+        self.invalidate_cache();
+        self.invalidate_replay_cache();
+        self
+    }
 
-            let route = Route {
-                pools: vec![pool.clone()],
-                path: vec![token_in, token_out],
-                output_amount,
-                gas_cost: pool.gas_cost,
-                price_impact,
-                score,
-            };
+    /// Rejects routes whose output is worth less than their gas at
+    /// `gas_price` (in gwei), so a value-destroying route is never returned
+    pub fn with_gas_price(mut self, gas_price: u64) -> Self {
+        self.gas_price = Some(gas_price);
+        self.invalidate_cache();
+        self.invalidate_replay_cache();
+        self
+    }
 
-            // Keep best route
-            if best_route.is_none() || route.score > best_route.as_ref().unwrap().score {
-                best_route = Some(route);
-            }
-        }
+    /// Sets what a whole `token` is worth in ETH when weighing route output
+    /// against gas; tokens without a price count one for one
+    pub fn with_native_price(mut self, token: Address, price: f64) -> Self {
+        self.native_prices.insert(token, price);
+        self.invalidate_cache();
+        self.invalidate_replay_cache();
+        self
+    }
 
-        best_route
+    /// Returns `route`'s output value net of its gas at `gas_price` (in
+    /// gwei), in ETH
+    fn net_value(&self, route: &Route, gas_price: u64) -> f64 {
+        let token_out = route.path[route.path.len() - 1];
+        let price = self.native_prices.get(&token_out).copied().unwrap_or(1.0);
+        let output_value = self.tokens.to_units(token_out, route.output_amount) * price;
+        output_value - route.gas_cost as f64 * gas_price as f64 * 1e-9
     }
 
-    /// Finds multi-hop routes using graph search
-    fn find_multi_hop_routes(
-        &self,
-        token_in: Address,
-        token_out: Address,
-        amount_in: U256,
-    ) -> Vec<Route> {
-        // Use Dijkstra's algorithm to find best paths
-        // This is a simplified implementation
-        
-        let mut routes = Vec::new();
+    /// Lets each hop of a path split its input across every pool for its
+    /// pair, via `calculate_optimal_split`, when that beats the best single
+    /// pool
+    pub fn with_hop_splitting(mut self, split_hops: bool) -> Self {
+        self.split_hops = split_hops;
+        self.invalidate_cache();
+        self.invalidate_replay_cache();
+        self
+    }
+
+    /// Adds a liquidity pool to the routing engine.
+    ///
+    /// Returns false, leaving the engine unchanged, if the pool's smaller
+    /// reserve is below the minimum liquidity or its reserves are stale.
+    pub fn add_pool(&mut self, pool: LiquidityPool) -> bool {
+        if pool.reserve_a.min(pool.reserve_b) < self.min_liquidity {
+            debug!("Skipping pool {:?} below minimum liquidity", pool.address);
+            return false;
+        }
         
-        // Build token graph
-        let graph = self.build_token_graph();
+        if self.is_stale(&pool) {
+            debug!("Skipping pool {:?} fetched at stale block {}", pool.address, pool.fetched_at_block);
+            return false;
+        }
         
-        // Find paths using BFS with limited depth
-        let paths = self.find_paths_bfs(&graph, token_in, token_out, self.max_hops);
+        let idx = self.pools.len();
+        Self::index_pool(&mut self.pool_index, idx, &pool);
+        self.components.union(pool.token_a, pool.token_b);
+        self.pools.push(pool);
+        self.invalidate_cache();
+        true
+    }
+
+    /// Updates the reserves of the pool at `address`.
+    ///
+    /// Returns false if no such pool exists.
+    pub fn update_reserves(&mut self, address: Address, reserve_a: U256, reserve_b: U256) -> bool {
+        let Some(pool) = self.pools.iter_mut().find(|p| p.address == address) else {
+            return false;
+        };
         
-        // Evaluate each path
-        for path in paths {
-            if let Some(route) = self.evaluate_path(&path, amount_in) {
-                routes.push(route);
+        pool.reserve_a = reserve_a;
+        pool.reserve_b = reserve_b;
+        self.invalidate_cache();
+        true
+    }
+
+    /// Captures current pool reserves, e.g. before simulating trades with
+    /// `update_reserves`
+    pub fn snapshot(&self) -> RoutingSnapshot {
+        RoutingSnapshot {
+            reserves: self
+                .pools
+                .iter()
+                .map(|pool| (pool.address, pool.reserve_a, pool.reserve_b))
+                .collect(),
+        }
+    }
+
+    /// Restores reserves captured by `snapshot`.
+    ///
+    /// The pool index is left untouched. Pools added since the snapshot keep
+    /// their current reserves; removed pools are skipped.
+    pub fn restore(&mut self, snapshot: RoutingSnapshot) {
+        for (idx, (address, reserve_a, reserve_b)) in snapshot.reserves.into_iter().enumerate() {
+            // Pools keep their position unless one was removed in between
+            let pool = match self.pools.get_mut(idx) {
+                Some(pool) if pool.address == address => Some(pool),
+                _ => self.pools.iter_mut().find(|p| p.address == address),
+            };
+            
+            if let Some(pool) = pool {
+                pool.reserve_a = reserve_a;
+                pool.reserve_b = reserve_b;
             }
         }
         
-        routes
+        self.invalidate_cache();
     }
 
-    /// Builds a graph of token connections
-    fn build_token_graph(&self) -> HashMap<Address, Vec<Address>> {
-        let mut graph: HashMap<Address, Vec<Address>> = HashMap::new();
-
-        for pool in &self.pools {
-            graph
-                .entry(pool.token_a)
-                .or_insert_with(Vec::new)
-                .push(pool.token_b);
-            
-            graph
-                .entry(pool.token_b)
-                .or_insert_with(Vec::new)
-                .push(pool.token_a);
+    /// Removes the pool at `address`, returning it if present
+    pub fn remove_pool(&mut self, address: Address) -> Option<LiquidityPool> {
+        let idx = self.pools.iter().position(|p| p.address == address)?;
+        let pool = self.pools.remove(idx);
+        
+        // Indices past the removed pool shifted, so rebuild the index. The
+        // pool may have been the only link between two components, which
+        // union-find can't undo, so those are rebuilt too.
+        self.pool_index.clear();
+        self.components = TokenComponents::default();
+        for (idx, pool) in self.pools.iter().enumerate() {
+            Self::index_pool(&mut self.pool_index, idx, pool);
+            self.components.union(pool.token_a, pool.token_b);
         }
+        
+        self.invalidate_cache();
+        Some(pool)
+    }
 
-        graph
+    /// Checks if any chain of pools, of any length, links `token_a` and
+    /// `token_b`. Tokens in no pool are connected to nothing.
+    pub fn is_connected(&self, token_a: Address, token_b: Address) -> bool {
+        self.components.connected(token_a, token_b)
     }
 
-    /// Finds paths using breadth-first search
-    fn find_paths_bfs(
-        &self,
-        graph: &HashMap<Address, Vec<Address>>,
-        start: Address,
-        end: Address,
-        max_depth: usize,
-    ) -> Vec<Vec<Address>> {
-        let mut paths = Vec::new();
-        let mut queue = vec![(start, vec![start])];
+    /// Indexes a pool under both token orderings
+    fn index_pool(
+        pool_index: &mut HashMap<(Address, Address), Vec<usize>>,
+        idx: usize,
+        pool: &LiquidityPool,
+    ) {
+        pool_index
+            .entry((pool.token_a, pool.token_b))
+            .or_default()
+            .push(idx);
+        
+        pool_index
+            .entry((pool.token_b, pool.token_a))
+            .or_default()
+            .push(idx);
+    }
 
-        while let Some((current, path)) = queue.pop() {
-            if path.len() > max_depth {
-                continue;
-            }
+    /// Drops all cached routes
+    fn invalidate_cache(&mut self) {
+        if let Some(cache) = self.route_cache.as_mut() {
+            cache.get_mut().unwrap().clear();
+        }
+    }
 
-            if current == end && path.len() > 1 {
-                paths.push(path.clone());
-                continue;
-            }
+    /// Drops all replay-cached routes, for changes to how routes are
+    /// ranked that their keys don't capture
+    fn invalidate_replay_cache(&mut self) {
+        if let Some(cache) = self.replay_cache.as_mut() {
+            cache.get_mut().unwrap().clear();
+        }
+    }
 
-            if let Some(neighbors) = graph.get(&current) {
-                for &neighbor in neighbors {
-                    // Avoid cycles
-                    if !path.contains(&neighbor) {
-                        let mut new_path = path.clone();
-                        new_path.push(neighbor);
-                        queue.push((neighbor, new_path));
-                    }
-                }
-            }
+    /// Hashes a route query with the reserves of every pool in `token_in`'s
+    /// connected component, so the key changes whenever any reserve a route
+    /// could use does
+    fn replay_key(&self, token_in: Address, token_out: Address, amount_in: U256, max_hops: usize) -> ReplayCacheKey {
+        let component = self.components.find(token_in);
+        let mut tokens = vec![
+            Token::Address(token_in),
+            Token::Address(token_out),
+            Token::Uint(amount_in),
+            Token::Uint(U256::from(max_hops)),
+        ];
+        for pool in self.pools.iter().filter(|pool| self.components.find(pool.token_a) == component) {
+            tokens.push(Token::Address(pool.address));
+            tokens.push(Token::Uint(pool.reserve_a));
+            tokens.push(Token::Uint(pool.reserve_b));
         }
+        keccak256(abi::encode(&tokens))
+    }
 
-        paths
+    /// Returns the amount coming out of each hop when `amount_in` is sent
+    /// through `route`, using current pool reserves
+    pub fn hop_amounts(&self, route: &Route, amount_in: U256) -> Vec<U256> {
+        let mut amount = amount_in;
+        (0..route.pools.len())
+            .map(|hop| {
+                let token_in = route.path[hop];
+                amount = Self::split_hop(&Self::hop_pools(route, hop), amount)
+                    .into_iter()
+                    .map(|(pool, share)| self.calculate_output(pool, token_in, share))
+                    .fold(U256::zero(), |total, output| total + output);
+                amount
+            })
+            .collect()
     }
 
-    /// Evaluates a token path and creates a route
-    fn evaluate_path(&self, path: &[Address], amount_in: U256) -> Option<Route> {
-        if path.len() < 2 {
-            return None;
+    /// Returns the pools sharing hop `hop` of `route`, primary pool first
+    fn hop_pools(route: &Route, hop: usize) -> Vec<&LiquidityPool> {
+        std::iter::once(&route.pools[hop])
+            .chain(route.split_pools.get(hop).into_iter().flatten())
+            .collect()
+    }
+
+    /// Splits `amount` across `pools` with `calculate_optimal_split`, giving
+    /// any rounding remainder to the first pool
+    fn split_hop<'a>(pools: &[&'a LiquidityPool], amount: U256) -> Vec<(&'a LiquidityPool, U256)> {
+        let reserves = pools.iter().map(|pool| (pool.reserve_a, pool.reserve_b)).collect();
+        let mut shares = calculate_optimal_split(amount, reserves);
+        let allotted = shares.iter().fold(U256::zero(), |total, share| total + *share);
+        if let Some(first) = shares.first_mut() {
+            *first += amount.saturating_sub(allotted);
         }
+        pools.iter().copied().zip(shares).collect()
+    }
 
-        let mut pools = Vec::new();
-        let mut current_amount = amount_in;
-        let mut total_gas = 0u64;
-        let mut total_price_impact = 0.0;
+    /// Builds one swap interaction per pool in the route, with the token
+    /// flows of each hop when `amount_in` is sent through it.
+    ///
+    /// V2-style hops are encoded as router `swapExactTokensForTokens` calls
+    /// whose `amountOutMin` scales each hop's expected output by the route's
+    /// `min_output_amount / output_amount`, so the last hop enforces the
+    /// route minimum. Other pool types are encoded at submission.
+    pub fn build_interactions(&self, route: &Route, amount_in: U256) -> Vec<Interaction> {
+        let hop_outputs = self.hop_amounts(route, amount_in);
+        let hop_inputs = std::iter::once(amount_in).chain(hop_outputs.iter().copied());
 
-        // For each hop in the path
-        for i in 0..path.len() - 1 {
-            let token_in = path[i];
-            let token_out = path[i + 1];
+        // One swap per pool, split hops spreading their input across pools
+        let swaps: Vec<_> = route
+            .path
+            .windows(2)
+            .zip(hop_inputs)
+            .enumerate()
+            .flat_map(|(hop, (tokens, hop_in))| {
+                Self::split_hop(&Self::hop_pools(route, hop), hop_in)
+                    .into_iter()
+                    .map(move |(pool, share)| {
+                        (pool, tokens, share, self.calculate_output(pool, tokens[0], share))
+                    })
+            })
+            .collect();
 
-            // Find best pool for this hop
-            let pool_indices = self.pool_index.get(&(token_in, token_out))?;
-            
-            let mut best_pool: Option<&LiquidityPool> = None;
-            let mut best_output = U256::zero();
+        swaps
+            .into_iter()
+            .map(|(pool, tokens, hop_in, hop_out)| {
+                let hop_min_out = if route.output_amount.is_zero() {
+                    U256::zero()
+                } else {
+                    hop_out * route.min_output_amount / route.output_amount
+                };
 
-            for &pool_idx in pool_indices {
-                let pool = &self.pools[pool_idx];
-                let output = self.calculate_output(pool, token_in, current_amount);
-                
-                if output > best_output {
-                    best_output = output;
-                    best_pool = Some(pool);
+                let (target, call_data, interaction_type) = match pool.pool_type {
+                    PoolType::UniswapV2 | PoolType::ConstantProduct => (
+                        Address::from(UNISWAP_V2_ROUTER),
+                        encode_v2_swap(hop_in, hop_min_out, tokens[0], tokens[1]),
+                        InteractionType::UniswapV2Swap,
+                    ),
+                    PoolType::UniswapV3 => (pool.address, Bytes::default(), InteractionType::UniswapV3Swap),
+                    PoolType::Balancer => (pool.address, Bytes::default(), InteractionType::BalancerSwap),
+                    PoolType::Curve => (pool.address, Bytes::default(), InteractionType::CurveSwap),
+                    PoolType::Custom(_) => (pool.address, Bytes::default(), InteractionType::Custom),
+                };
+
+                Interaction {
+                    target,
+                    call_data,
+                    value: U256::zero(),
+                    interaction_type,
+                    flow: Some(TokenFlow {
+                        token_in: tokens[0],
+                        amount_in: hop_in,
+                        token_out: tokens[1],
+                        amount_out: hop_out,
+                    }),
+                    chain: None,
+                    allow_failure: false,
                 }
-            }
+            })
+            .collect()
+    }
 
-            let pool = best_pool?;
-            
-            if best_output.is_zero() {
+    /// Fills `order` through its best route, adding the trade and swap
+    /// interactions to `settlement`.
+    ///
+    /// Routes spend the sell amount after fee, which must still buy the
+    /// order's full buy amount. If the settlement already prices the pair,
+    /// the order executes at that rate. Returns the route used, or `None` if
+    /// no route meets the limit or the prices conflict.
+    pub fn fill_order(&self, order: &Order, settlement: &mut SettlementPlan) -> Option<Route> {
+        let swapped = order.sell_amount_after_fee();
+        let route = match self.find_best_route(order.sell_token, order.buy_token, swapped) {
+            Ok(route) => route,
+            Err(e) => {
+                debug!("No route for order {:?}: {}", order.id, e);
                 return None;
             }
+        };
 
-            pools.push(pool.clone());
-            total_gas += pool.gas_cost;
-            total_price_impact += self.calculate_price_impact(pool, token_in, current_amount);
-            current_amount = best_output;
+        if route.output_amount < order.buy_amount {
+            debug!(
+                "Route output {} below limit {} for order {:?}",
+                route.output_amount, order.buy_amount, order.id
+            );
+            return None;
         }
 
-        let score = self.calculate_route_score(current_amount, total_gas, total_price_impact);
-
-        Some(Route {
-            pools,
-            path: path.to_vec(),
-            output_amount: current_amount,
-            gas_cost: total_gas,
-            price_impact: total_price_impact,
-            score,
-        })
-    }
-
-    /// Calculates output amount for a swap through a pool
-    fn calculate_output(&self, pool: &LiquidityPool, token_in: Address, amount_in: U256) -> U256 {
-        // Determine which direction we're swapping
-        let (reserve_in, reserve_out) = if token_in == pool.token_a {
-            (pool.reserve_a, pool.reserve_b)
-        } else {
-            (pool.reserve_b, pool.reserve_a)
+        // Uniform price for the pair implied by the routed amounts, unless
+        // earlier trades already priced it
+        let Some(executed_buy) = settlement.reconcile_trade_price(
+            order.sell_token,
+            order.buy_token,
+            swapped,
+            order.buy_amount,
+            route.output_amount,
+        ) else {
+            debug!(
+                "Route for order {:?} conflicts with the settlement's clearing prices",
+                order.id
+            );
+            return None;
         };
 
-        match pool.pool_type {
-            PoolType::UniswapV2 | PoolType::ConstantProduct => {
-                self.calculate_constant_product_output(amount_in, reserve_in, reserve_out, pool.fee_bps)
-            }
-            PoolType::UniswapV3 => {
-                // Simplified - real implementation would use tick math
-                self.calculate_constant_product_output(amount_in, reserve_in, reserve_out, pool.fee_bps)
-            }
-            PoolType::Balancer => {
-                // Simplified - real implementation would use weighted math
-                self.calculate_constant_product_output(amount_in, reserve_in, reserve_out, pool.fee_bps)
-            }
-            PoolType::Curve => {
-                // Simplified - real implementation would use StableSwap invariant
-                self.calculate_stable_swap_output(amount_in, reserve_in, reserve_out, pool.fee_bps)
+        settlement.add_trade(Trade {
+            order_id: order.id,
+            sell_token: order.sell_token,
+            buy_token: order.buy_token,
+            executed_sell_amount: swapped,
+            executed_buy_amount: executed_buy,
+            fee: order.fee_amount,
+        });
+        for interaction in self.build_interactions(&route, swapped) {
+            // Every swap pulls its input from the settlement contract. Approve
+            // the full allowance once per (token, spender) so later orders
+            // through the same pool reuse it.
+            if let Some(flow) = &interaction.flow {
+                settlement.ensure_approval(flow.token_in, interaction.target, U256::MAX);
             }
+            settlement.add_interaction(interaction);
         }
+
+        Some(route)
     }
 
-    /// Calculates output for constant product formula (x * y = k)
-    fn calculate_constant_product_output(
+    /// Finds the best route for a swap, protected by the engine's default
+    /// slippage tolerance
+    pub fn find_best_route(
         &self,
+        token_in: Address,
+        token_out: Address,
         amount_in: U256,
-        reserve_in: U256,
-        reserve_out: U256,
-        fee_bps: u16,
-    ) -> U256 {
-        if amount_in.is_zero() || reserve_in.is_zero() || reserve_out.is_zero() {
-            return U256::zero();
-        }
+    ) -> Result<Route, RoutingError> {
+        self.find_best_route_with_slippage(token_in, token_out, amount_in, self.max_slippage)
+    }
 
-        // amount_in_with_fee = amount_in * (10000 - fee_bps)
-        let amount_in_with_fee = amount_in * U256::from(10000 - fee_bps);
-        
-        // numerator = amount_in_with_fee * reserve_out
-        let numerator = amount_in_with_fee * reserve_out;
-        
-        // denominator = reserve_in * 10000 + amount_in_with_fee
-        let denominator = reserve_in * U256::from(10000) + amount_in_with_fee;
+    /// Like [`Self::find_best_route`], searching routes of up to `max_hops`
+    /// pools instead of the engine's default for this call only
+    pub fn find_best_route_with_hops(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+        max_hops: usize,
+    ) -> Result<Route, RoutingError> {
+        let mut route = self.lookup_best_route(token_in, token_out, amount_in, max_hops)?;
+        route.min_output_amount = Self::apply_slippage(route.output_amount, self.max_slippage);
+        Ok(route)
+    }
+
+    /// Quotes swapping `amount_in` of `token_in` for `token_out` through the
+    /// best route, without producing trades or interactions
+    pub fn quote(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Result<Quote, RoutingError> {
+        let route = self.find_best_route(token_in, token_out, amount_in)?;
         
-        if denominator.is_zero() {
-            return U256::zero();
+        Ok(Quote {
+            amount_in,
+            output_amount: route.output_amount,
+            min_output_amount: route.min_output_amount,
+            execution_price: route.execution_price(amount_in),
+            price_impact: route.price_impact,
+            gas_estimate: route.gas_cost,
+            route,
+        })
+    }
+
+    /// Finds the route using the least gas whose output is at least
+    /// `min_output`, rather than the one with the best output, protected by
+    /// the engine's default slippage tolerance. Among routes using the same
+    /// gas, fewer hops and then more output win.
+    pub fn find_cheapest_route(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+        min_output: U256,
+    ) -> Result<Route, RoutingError> {
+        if token_in == token_out {
+            return Err(RoutingError::CyclicRoute);
+        }
+        if !self.is_connected(token_in, token_out) {
+            return Err(RoutingError::NoPath);
         }
 
-        numerator / denominator
+        // Routes come best-first, so ties keep the better output
+        let routes = self.find_all_routes(token_in, token_out, amount_in, self.max_hops)?;
+        let best_output = routes.iter().map(|r| r.output_amount).max().unwrap_or_default();
+        let mut route = routes
+            .into_iter()
+            .filter(|r| r.output_amount >= min_output)
+            .min_by_key(|r| (r.gas_cost, r.pools.len()))
+            .ok_or(RoutingError::BelowMinOutput { best_output })?;
+
+        route.min_output_amount = Self::apply_slippage(route.output_amount, self.max_slippage);
+        Ok(route)
     }
 
-    /// Calculates output for stable swap (simplified)
-    fn calculate_stable_swap_output(
+    /// Like [`Self::find_best_route`], discarding why no route was found
+    pub fn find_best_route_opt(
         &self,
+        token_in: Address,
+        token_out: Address,
         amount_in: U256,
-        reserve_in: U256,
-        reserve_out: U256,
-        fee_bps: u16,
-    ) -> U256 {
-        // Simplified stable swap - real implementation would use the full invariant
-        // For stable pairs, price impact is much lower
-        
-        let fee_multiplier = U256::from(10000 - fee_bps);
-        let amount_out = amount_in * fee_multiplier / U256::from(10000);
-        
-        // Cap at reserve
-        amount_out.min(reserve_out * U256::from(99) / U256::from(100))
+    ) -> Option<Route> {
+        self.find_best_route(token_in, token_out, amount_in).ok()
     }
 
-    /// Calculates price impact for a swap
-    fn calculate_price_impact(&self, pool: &LiquidityPool, token_in: Address, amount_in: U256) -> f64 {
-        let (reserve_in, reserve_out) = if token_in == pool.token_a {
-            (pool.reserve_a, pool.reserve_b)
-        } else {
-            (pool.reserve_b, pool.reserve_a)
-        };
+    /// Finds the best route for a swap, setting `min_output_amount` from
+    /// `max_slippage` (as percentage, clamped to 0-100)
+    pub fn find_best_route_with_slippage(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+        max_slippage: f64,
+    ) -> Result<Route, RoutingError> {
+        let mut route = self.lookup_best_route(token_in, token_out, amount_in, self.max_hops)?;
+        route.min_output_amount = Self::apply_slippage(route.output_amount, max_slippage);
+        Ok(route)
+    }
 
-        if reserve_in.is_zero() {
-            return 100.0; // Max impact
+    /// Finds the most profitable cycle of up to `max_hops` pools that starts
+    /// and ends at `start_token`: pure arbitrage, filling no order.
+    ///
+    /// Each cycle is tried at inputs halving down from the largest reserve
+    /// of `start_token` on its first hop, keeping the input that gains the
+    /// most. Gains are net of gas at the engine's gas price, if one is set,
+    /// so `None` means no cycle pays for itself.
+    pub fn find_arbitrage(&self, start_token: Address, max_hops: usize) -> Option<Route> {
+        let gas_price = self.gas_price.unwrap_or(0);
+        let price = self.native_prices.get(&start_token).copied().unwrap_or(1.0);
+        let mut best: Option<(f64, Route)> = None;
+
+        for cycle in self.build_token_graph().find_cycles(max_hops) {
+            // Rotate the cycle to start and end at `start_token`
+            let Some(start) = cycle.iter().position(|&token| token == start_token) else {
+                continue;
+            };
+            let mut path = cycle[start..].to_vec();
+            path.extend_from_slice(&cycle[..=start]);
+
+            let Some(largest_reserve) = self.pool_index.get(&(path[0], path[1])).and_then(|indices| {
+                indices
+                    .iter()
+                    .map(|&idx| {
+                        let pool = &self.pools[idx];
+                        if pool.token_a == start_token { pool.reserve_a } else { pool.reserve_b }
+                    })
+                    .max()
+            }) else {
+                continue;
+            };
+
+            for step in 1..=ARBITRAGE_SIZING_STEPS {
+                let amount_in = largest_reserve >> step;
+                if amount_in.is_zero() {
+                    break;
+                }
+                let Some(route) = self.evaluate_hops(&path, amount_in) else {
+                    continue;
+                };
+                if route.output_amount <= amount_in {
+                    continue;
+                }
+
+                let gain = self.tokens.to_units(start_token, route.output_amount - amount_in) * price
+                    - route.gas_cost as f64 * gas_price as f64 * 1e-9;
+                if gain > 0.0 && !matches!(&best, Some((best_gain, _)) if *best_gain >= gain) {
+                    best = Some((gain, route));
+                }
+            }
         }
 
-        // Price impact = (amount_in / reserve_in) * 100
-        let impact = (amount_in.as_u128() as f64 / reserve_in.as_u128() as f64) * 100.0;
-        
-        impact.min(100.0)
+        let (gain, mut route) = best?;
+        info!("Arbitrage: {:?}, input: {}, gain: {:.6} ETH", route.path, route.amount_in, gain);
+        route.min_output_amount = Self::apply_slippage(route.output_amount, self.max_slippage);
+        Some(route)
     }
 
-    /// Calculates route quality score
-    fn calculate_route_score(&self, output_amount: U256, gas_cost: u64, price_impact: f64) -> f64 {
-        // Score factors:
-        // 1. Output amount (higher is better)
-        // 2. Gas cost (lower is better)
-        // 3. Price impact (lower is better)
-        
-        let output_score = (output_amount.as_u128() as f64) / 1e18;
-        let gas_penalty = (gas_cost as f64) / 1e6; // Normalize gas cost
-        let impact_penalty = price_impact / 100.0;
-        
-        // Weighted score
-        output_score - gas_penalty - impact_penalty
+    /// Finds the route needing the least input to buy exactly `amount_out`
+    /// of `token_out`, as buy orders require.
+    ///
+    /// Inputs are computed backwards from `amount_out` hop by hop with the
+    /// constant-product inverse, rounded up, so pools holding no more than
+    /// `amount_out` are never used. Returns the required input and the route
+    /// evaluated forward at that input, whose `output_amount` is at least
+    /// `amount_out`.
+    pub fn find_best_route_for_exact_out(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_out: U256,
+    ) -> Result<(U256, Route), RoutingError> {
+        let mut paths = Vec::new();
+        if self.pool_index.contains_key(&(token_in, token_out)) {
+            paths.push(vec![token_in, token_out]);
+        }
+        if self.max_hops > 1 {
+            paths.extend(
+                self.build_token_graph()
+                    .find_paths(token_in, token_out, self.max_hops)
+                    .into_iter()
+                    .filter(|path| path.len() > 2),
+            );
+        }
+        if paths.is_empty() {
+            return Err(RoutingError::NoPath);
+        }
+
+        // Non-constant-product pools and transfer fees can make the inverse
+        // underestimate, so keep only paths that deliver when run forward
+        let mut candidates: Vec<(U256, Route)> = paths
+            .iter()
+            .filter_map(|path| {
+                let amount_in = self.required_input(path, amount_out)?;
+                let route = self.evaluate_path(path, amount_in)?;
+                (route.output_amount >= amount_out).then_some((amount_in, route))
+            })
+            .collect();
+        if candidates.is_empty() {
+            return Err(RoutingError::ZeroOutput);
+        }
+
+        let best_impact = candidates
+            .iter()
+            .map(|(_, route)| route.price_impact)
+            .fold(f64::INFINITY, f64::min);
+        candidates.retain(|(_, route)| route.price_impact <= self.max_price_impact);
+
+        let (amount_in, mut route) = candidates
+            .into_iter()
+            .min_by(|(a_in, a), (b_in, b)| {
+                a_in.cmp(b_in)
+                    .then_with(|| a.gas_cost.cmp(&b.gas_cost))
+                    .then_with(|| a.path.cmp(&b.path))
+            })
+            .ok_or(RoutingError::AllExceedPriceImpact { best_impact })?;
+
+        route.min_output_amount = amount_out;
+        Ok((amount_in, route))
     }
-}
 
-impl Default for RoutingEngine {
-    fn default() -> Self {
-        Self::new(3, 5.0) // Max 3 hops, 5% max price impact
+    /// Returns the input `path` needs to output `amount_out`, using the
+    /// cheapest pool at each hop
+    fn required_input(&self, path: &[Address], amount_out: U256) -> Option<U256> {
+        path.windows(2).rev().try_fold(amount_out, |required, hop| {
+            let (token_in, token_out) = (hop[0], hop[1]);
+            self.pool_index
+                .get(&(token_in, token_out))?
+                .iter()
+                .filter_map(|&idx| {
+                    let pool = &self.pools[idx];
+                    let (reserve_in, reserve_out) = if token_in == pool.token_a {
+                        (pool.reserve_a, pool.reserve_b)
+                    } else {
+                        (pool.reserve_b, pool.reserve_a)
+                    };
+                    // The inverse rounds down; one more wei guarantees the output
+                    calculate_amm_input(required, reserve_in, reserve_out, pool.fee_bps as u32)?
+                        .checked_add(U256::one())
+                })
+                .min()
+        })
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Reduces `amount` by `slippage` percent, rounding down
+    fn apply_slippage(amount: U256, slippage: f64) -> U256 {
+        let slippage = if slippage.is_finite() { slippage.clamp(0.0, 100.0) } else { 0.0 };
+        // Parts per million of the percentage, so sub-basis-point tolerances still bite
+        let slippage_ppm = (slippage * 10_000.0).round() as u64;
+        amount * U256::from(1_000_000 - slippage_ppm) / U256::from(1_000_000)
+    }
 
-    fn create_test_pool(
-        token_a: Address,
-        token_b: Address,
-        reserve_a: u128,
-        reserve_b: u128,
-    ) -> LiquidityPool {
-        LiquidityPool {
-            address: Address::zero(),
-            pool_type: PoolType::UniswapV2,
-            token_a,
-            token_b,
-            reserve_a: U256::from(reserve_a),
-            reserve_b: U256::from(reserve_b),
-            fee_bps: 30, // 0.3%
-            gas_cost: 100000,
+    /// Finds the best route of up to `max_hops` pools through the cache or a
+    /// full search; the cached route is independent of slippage
+    fn lookup_best_route(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+        max_hops: usize,
+    ) -> Result<Route, RoutingError> {
+        info!(
+            "Finding route: {:?} -> {:?}, amount: {}",
+            token_in, token_out, amount_in
+        );
+
+        if token_in == token_out {
+            return Err(RoutingError::CyclicRoute);
         }
-    }
 
-    #[test]
-    fn test_constant_product_calculation() {
-        let engine = RoutingEngine::default();
+        // No search can cross between disconnected components
+        if !self.is_connected(token_in, token_out) {
+            debug!("Tokens {:?} and {:?} are not connected", token_in, token_out);
+            return Err(RoutingError::NoPath);
+        }
+
+        let replay_key = self
+            .replay_cache
+            .as_ref()
+            .map(|_| self.replay_key(token_in, token_out, amount_in, max_hops));
+        if let (Some(cache), Some(key)) = (&self.replay_cache, &replay_key) {
+            if let Some(route) = cache.lock().unwrap().get(key) {
+                debug!("Replay cache hit");
+                return Ok(route);
+            }
+        }
+
+        if let Some(cache) = &self.route_cache {
+            let cached = cache.lock().unwrap().get(&self.cache_key(token_in, token_out, amount_in, max_hops));
+            if let Some(route) = cached.and_then(|route| self.requote(route, amount_in)) {
+                debug!("Route cache hit");
+                return Ok(route);
+            }
+        }
+
+        // Find all possible routes
+        let routes = self.find_all_routes(token_in, token_out, amount_in, max_hops).map_err(|e| {
+            debug!("No routes found: {}", e);
+            e
+        })?;
+
+        // Routes are sorted best-first and never empty
+        let best_route = routes.into_iter().next().ok_or(RoutingError::NoPath)?;
+
+        info!(
+            "Best route: {} hops, output: {}, score: {:.4}",
+            best_route.pools.len(),
+            best_route.output_amount,
+            best_route.score
+        );
+
+        if let Some(cache) = &self.route_cache {
+            let key = self.cache_key(token_in, token_out, amount_in, max_hops);
+            cache.lock().unwrap().insert(key, best_route.clone());
+        }
+
+        if let (Some(cache), Some(key)) = (&self.replay_cache, replay_key) {
+            cache.lock().unwrap().insert(key, best_route.clone());
+        }
+
+        Ok(best_route)
+    }
+
+    /// Finds all possible routes of up to `max_hops` pools, best first.
+    ///
+    /// Fails rather than returning an empty list, with the reason no route
+    /// qualified.
+    fn find_all_routes(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+        max_hops: usize,
+    ) -> Result<Vec<Route>, RoutingError> {
+        let mut routes = Vec::new();
+
+        // Try direct routes (1 hop)
+        if let Some(direct_route) = self.find_direct_route(token_in, token_out, amount_in) {
+            routes.push(direct_route);
+        }
+
+        // Try multi-hop routes if enabled
+        if max_hops > 1 {
+            routes.extend(self.find_multi_hop_routes(token_in, token_out, amount_in, max_hops));
+        }
+
+        if routes.is_empty() {
+            // Tell apart unconnected tokens from pools that can't fill the amount
+            let connected = self.pool_index.contains_key(&(token_in, token_out))
+                || (max_hops > 1
+                    && !self
+                        .build_token_graph()
+                        .find_paths(token_in, token_out, max_hops)
+                        .is_empty());
+            return Err(if connected { RoutingError::ZeroOutput } else { RoutingError::NoPath });
+        }
+
+        // Filter by price impact
+        let best_impact = routes.iter().map(|r| r.price_impact).fold(f64::INFINITY, f64::min);
+        routes.retain(|r| r.price_impact <= self.max_price_impact);
+        if routes.is_empty() {
+            return Err(RoutingError::AllExceedPriceImpact { best_impact });
+        }
+
+        // Not trading beats a route whose gas outweighs its output
+        if let Some(gas_price) = self.gas_price {
+            let best_net_value = routes
+                .iter()
+                .map(|r| self.net_value(r, gas_price))
+                .fold(f64::NEG_INFINITY, f64::max);
+            routes.retain(|r| self.net_value(r, gas_price) >= 0.0);
+            if routes.is_empty() {
+                return Err(RoutingError::NoProfitableRoute { best_net_value });
+            }
+        }
+
+        Self::prune_dominated(&mut routes);
+        Self::sort_routes(&mut routes);
+        Ok(routes)
+    }
+
+    /// Removes routes another route beats on output, gas and price impact
+    /// at once (no worse on any, better on at least one). A dominated route
+    /// can never score higher, so only the Pareto front is kept.
+    fn prune_dominated(routes: &mut Vec<Route>) {
+        let dominates = |a: &Route, b: &Route| {
+            a.output_amount >= b.output_amount
+                && a.gas_cost <= b.gas_cost
+                && a.price_impact <= b.price_impact
+                && (a.output_amount > b.output_amount
+                    || a.gas_cost < b.gas_cost
+                    || a.price_impact < b.price_impact)
+        };
+
+        let keep: Vec<bool> = routes
+            .iter()
+            .map(|route| !routes.iter().any(|other| dominates(other, route)))
+            .collect();
+        let mut keep = keep.into_iter();
+        routes.retain(|_| keep.next().unwrap_or(true));
+    }
+
+    /// Finds direct route (single pool)
+    fn find_direct_route(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Option<Route> {
+        let pool_indices = self.pool_index.get(&(token_in, token_out))?;
+
+        let mut best_route: Option<Route> = None;
+
+        for &pool_idx in pool_indices {
+            let pool = &self.pools[pool_idx];
+            
+            // Calculate output amount
+            let output_amount = self.calculate_output(pool, token_in, amount_in);
+            
+            if output_amount.is_zero() {
+                continue;
+            }
+
+            // Calculate price impact
+            let price_impact = self.calculate_price_impact(pool, token_in, amount_in);
+
+            // Calculate route score
+            let score = self.calculate_route_score(token_out, output_amount, pool.gas_cost, price_impact);
+
+            let route = Route {
+                pools: vec![pool.clone()],
+                path: vec![token_in, token_out],
+                amount_in,
+                output_amount,
+                hop_outputs: vec![output_amount],
+                split_pools: Vec::new(),
+                min_output_amount: output_amount, // Set once slippage is known
+                gas_cost: pool.gas_cost,
+                price_impact,
+                low_confidence_impact: pool.has_low_confidence_impact(),
+                score,
+            };
+
+            // Keep best route
+            if best_route.is_none() || route.score > best_route.as_ref().unwrap().score {
+                best_route = Some(route);
+            }
+        }
+
+        best_route
+    }
+
+    /// Finds multi-hop routes using graph search
+    fn find_multi_hop_routes(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+        max_hops: usize,
+    ) -> Vec<Route> {
+        // Use Dijkstra's algorithm to find best paths
+        // This is a simplified implementation
+        
+        // Build token graph
+        let graph = self.build_token_graph();
+        
+        // Find paths that visit no token twice, with limited depth
+        let paths = graph.find_paths(token_in, token_out, max_hops);
+        
+        // Evaluate each path
+        self.evaluate_paths(&paths, amount_in)
+    }
+
+    /// Evaluates candidate paths, in parallel when the `parallel` feature is enabled.
+    ///
+    /// Paths are independent and only read `self.pools`, so evaluation order
+    /// doesn't matter; callers sort the results.
+    fn evaluate_paths(&self, paths: &[Vec<Address>], amount_in: U256) -> Vec<Route> {
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::prelude::*;
+            paths
+                .par_iter()
+                .filter_map(|path| self.evaluate_path(path, amount_in))
+                .collect()
+        }
+        
+        #[cfg(not(feature = "parallel"))]
+        {
+            self.evaluate_paths_sequential(paths, amount_in)
+        }
+    }
+
+    /// Evaluates candidate paths one at a time. With `parallel` enabled
+    /// only tests use it, as the reference for the parallel results.
+    #[cfg(any(test, not(feature = "parallel")))]
+    fn evaluate_paths_sequential(&self, paths: &[Vec<Address>], amount_in: U256) -> Vec<Route> {
+        paths
+            .iter()
+            .filter_map(|path| self.evaluate_path(path, amount_in))
+            .collect()
+    }
+
+    /// Sorts routes best-first with deterministic tie-breaking, since path
+    /// discovery order depends on hash map iteration
+    fn sort_routes(routes: &mut [Route]) {
+        routes.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| b.output_amount.cmp(&a.output_amount))
+                .then_with(|| a.gas_cost.cmp(&b.gas_cost))
+                .then_with(|| a.path.cmp(&b.path))
+        });
+    }
+
+    /// Builds a graph of token connections, each edge labelled with the
+    /// index of its pool
+    fn build_token_graph(&self) -> TokenGraph<usize> {
+        let mut graph = TokenGraph::new();
+
+        for (idx, pool) in self.pools.iter().enumerate() {
+            graph.add_edge(pool.token_a, pool.token_b, idx);
+            graph.add_edge(pool.token_b, pool.token_a, idx);
+        }
+
+        graph
+    }
+
+    /// Evaluates a token path and creates a route
+    fn evaluate_path(&self, path: &[Address], amount_in: U256) -> Option<Route> {
+        if path.len() < 2 {
+            return None;
+        }
+        // Search never revisits a token, but a round trip must never be
+        // returned however the path was put together
+        if path[0] == path[path.len() - 1] {
+            debug!("Rejecting cyclic path {:?}", path);
+            return None;
+        }
+
+        self.evaluate_hops(path, amount_in)
+    }
+
+    /// Sends `amount_in` through the best pool (or split) of each hop of
+    /// `path`, which may end where it starts
+    fn evaluate_hops(&self, path: &[Address], amount_in: U256) -> Option<Route> {
+        if path.len() < 2 {
+            return None;
+        }
+
+        let mut pools = Vec::new();
+        let mut split_pools = Vec::new();
+        let mut hop_outputs = Vec::with_capacity(path.len() - 1);
+        let mut current_amount = amount_in;
+        let mut total_gas = 0u64;
+        // Fraction of the ideal (spot) output retained after each hop
+        let mut retained = 1.0;
+
+        // For each hop in the path
+        for i in 0..path.len() - 1 {
+            let token_in = path[i];
+            let token_out = path[i + 1];
+
+            // Find best pool for this hop
+            let pool_indices = self.pool_index.get(&(token_in, token_out))?;
+            
+            // Skip hops without enough fallback liquidity
+            if path.len() > 2 && pool_indices.len() < self.min_pools_per_hop {
+                debug!(
+                    "Hop {:?} -> {:?} has {} pools, need {}",
+                    token_in, token_out, pool_indices.len(), self.min_pools_per_hop
+                );
+                return None;
+            }
+            
+            let mut best_pool: Option<&LiquidityPool> = None;
+            let mut best_output = U256::zero();
+
+            for &pool_idx in pool_indices {
+                let pool = &self.pools[pool_idx];
+                let output = self.calculate_output(pool, token_in, current_amount);
+                
+                if output > best_output {
+                    best_output = output;
+                    best_pool = Some(pool);
+                }
+            }
+
+            let pool = best_pool?;
+            
+            if best_output.is_zero() {
+                return None;
+            }
+
+            // Spread the hop across all its pools if that pays out more
+            if self.split_hops && pool_indices.len() > 1 {
+                let mut candidates: Vec<&LiquidityPool> = vec![pool];
+                candidates.extend(
+                    pool_indices
+                        .iter()
+                        .map(|&idx| &self.pools[idx])
+                        .filter(|candidate| !std::ptr::eq(*candidate, pool)),
+                );
+                let shares = Self::split_hop(&candidates, current_amount);
+                let split_output = shares
+                    .iter()
+                    .map(|(candidate, share)| self.calculate_output(candidate, token_in, *share))
+                    .fold(U256::zero(), |total, output| total + output);
+
+                if split_output > best_output {
+                    // Impact of each pool on its share, weighted by share
+                    let total = current_amount.as_u128() as f64;
+                    let impact: f64 = shares
+                        .iter()
+                        .map(|(candidate, share)| {
+                            self.calculate_price_impact(candidate, token_in, *share) * share.as_u128() as f64
+                                / total
+                        })
+                        .sum();
+
+                    pools.push(pool.clone());
+                    split_pools.resize(pools.len() - 1, Vec::new());
+                    split_pools.push(candidates[1..].iter().map(|&c| c.clone()).collect());
+                    total_gas += candidates.iter().map(|c| c.gas_cost).sum::<u64>();
+                    retained *= 1.0 - impact / 100.0;
+                    current_amount = split_output;
+                    hop_outputs.push(split_output);
+                    continue;
+                }
+            }
+
+            pools.push(pool.clone());
+            total_gas += pool.gas_cost;
+            retained *= 1.0 - self.calculate_price_impact(pool, token_in, current_amount) / 100.0;
+            current_amount = best_output;
+            hop_outputs.push(best_output);
+        }
+
+        // Impacts compound multiplicatively, so summing them would overstate
+        let total_price_impact = (1.0 - retained) * 100.0;
+
+        let score = self.calculate_route_score(path[path.len() - 1], current_amount, total_gas, total_price_impact);
+        let low_confidence_impact = pools.iter().any(LiquidityPool::has_low_confidence_impact);
+
+        Some(Route {
+            pools,
+            path: path.to_vec(),
+            amount_in,
+            output_amount: current_amount,
+            hop_outputs,
+            split_pools,
+            min_output_amount: current_amount, // Set once slippage is known
+            gas_cost: total_gas,
+            price_impact: total_price_impact,
+            low_confidence_impact,
+            score,
+        })
+    }
+
+    /// Calculates output amount received from a swap through a pool, net
+    /// of any transfer fees on the input and output tokens
+    fn calculate_output(&self, pool: &LiquidityPool, token_in: Address, amount_in: U256) -> U256 {
+        let token_out = if token_in == pool.token_a { pool.token_b } else { pool.token_a };
+
+        let received_by_pool = self.after_transfer_fee(token_in, amount_in);
+        let sent_by_pool = self.pool_output(pool, token_in, received_by_pool);
+        self.after_transfer_fee(token_out, sent_by_pool)
+    }
+
+    /// Calculates output amount of the pool itself for the amount it receives
+    fn pool_output(&self, pool: &LiquidityPool, token_in: Address, amount_in: U256) -> U256 {
+        // Determine which direction we're swapping
+        let (reserve_in, reserve_out) = if token_in == pool.token_a {
+            (pool.reserve_a, pool.reserve_b)
+        } else {
+            (pool.reserve_b, pool.reserve_a)
+        };
+
+        match pool.pool_type {
+            PoolType::UniswapV2 | PoolType::ConstantProduct => {
+                self.calculate_constant_product_output(amount_in, reserve_in, reserve_out, pool.fee_bps)
+            }
+            PoolType::UniswapV3 => {
+                // Simplified - real implementation would use tick math
+                self.calculate_constant_product_output(amount_in, reserve_in, reserve_out, pool.fee_bps)
+            }
+            PoolType::Balancer => {
+                // Simplified - real implementation would use weighted math
+                self.calculate_constant_product_output(amount_in, reserve_in, reserve_out, pool.fee_bps)
+            }
+            PoolType::Curve => self.calculate_stable_swap_output(
+                amount_in,
+                reserve_in,
+                reserve_out,
+                pool.fee_bps,
+                pool.amplification.unwrap_or(DEFAULT_CURVE_AMPLIFICATION),
+            ),
+            PoolType::Custom(id) => match self.output_models.get(&id) {
+                Some(model) => model.output(pool, token_in, amount_in),
+                None => {
+                    debug!("No output model for custom pool type {}", id);
+                    U256::zero()
+                }
+            },
+        }
+    }
+
+    /// Calculates output for constant product formula (x * y = k)
+    fn calculate_constant_product_output(
+        &self,
+        amount_in: U256,
+        reserve_in: U256,
+        reserve_out: U256,
+        fee_bps: u16,
+    ) -> U256 {
+        if amount_in.is_zero() || reserve_in.is_zero() || reserve_out.is_zero() {
+            return U256::zero();
+        }
+
+        // amount_in_with_fee = amount_in * (10000 - fee_bps)
+        let amount_in_with_fee = amount_in * U256::from(10000 - fee_bps);
+        
+        // numerator = amount_in_with_fee * reserve_out
+        let numerator = amount_in_with_fee * reserve_out;
+        
+        // denominator = reserve_in * 10000 + amount_in_with_fee
+        let denominator = reserve_in * U256::from(10000) + amount_in_with_fee;
+        
+        if denominator.is_zero() {
+            return U256::zero();
+        }
+
+        numerator / denominator
+    }
+
+    /// Calculates output for a two-coin StableSwap pool.
+    ///
+    /// Solves the invariant `A·n^n·Σx + D = A·D·n^n + D^(n+1) / (n^n·Πx)`
+    /// for the new output balance with Newton's method, as Curve's
+    /// `get_D`/`get_y` do, then takes the fee from the output.
+    fn calculate_stable_swap_output(
+        &self,
+        amount_in: U256,
+        reserve_in: U256,
+        reserve_out: U256,
+        fee_bps: u16,
+        amplification: u64,
+    ) -> U256 {
+        if amount_in.is_zero() || reserve_in.is_zero() || reserve_out.is_zero() || amplification == 0 {
+            return U256::zero();
+        }
+
+        let n = U256::from(2);
+        // Curve stores A as A·n^(n-1), so A·n^n is A·n here
+        let ann = U256::from(amplification) * n;
+
+        // Invariant D for the current balances
+        let sum = reserve_in + reserve_out;
+        let mut d = sum;
+        for _ in 0..255 {
+            let d_p = d * d / (reserve_in * n) * d / (reserve_out * n);
+            let d_prev = d;
+            d = (ann * sum + d_p * n) * d / ((ann - U256::one()) * d + (n + U256::one()) * d_p);
+            if d.max(d_prev) - d.min(d_prev) <= U256::one() {
+                break;
+            }
+        }
+
+        // Output balance y keeping D constant after the input arrives
+        let x = reserve_in + amount_in;
+        let c = d * d / (x * n) * d / (ann * n);
+        let b = x + d / ann;
+        let mut y = d;
+        for _ in 0..255 {
+            let y_prev = y;
+            y = (y * y + c) / (y * n + b - d);
+            if y.max(y_prev) - y.min(y_prev) <= U256::one() {
+                break;
+            }
+        }
+
+        // Round against the trader, as the pool does
+        let amount_out = reserve_out.saturating_sub(y).saturating_sub(U256::one());
+        amount_out - amount_out * U256::from(fee_bps) / U256::from(10000)
+    }
+
+    /// Calculates price impact for a swap (as a percentage).
+    ///
+    /// Compares the actual output with the output at the pool's spot price
+    /// after fees, so the result is pure slippage and per-hop impacts can be
+    /// composed along a route. Curve pools take their spot price from the
+    /// StableSwap invariant, so balanced swaps report near-zero impact
+    /// rather than their share of the reserves. V3 pools with concentrated-liquidity state use
+    /// the sqrt price movement instead; without it they fall back to the
+    /// reserve estimate (see `LiquidityPool::has_low_confidence_impact`).
+    fn calculate_price_impact(&self, pool: &LiquidityPool, token_in: Address, amount_in: U256) -> f64 {
+        // Transfer fees aren't slippage; measure on what reaches the pool
+        let amount_in = self.after_transfer_fee(token_in, amount_in);
+
+        if pool.pool_type == PoolType::UniswapV3 {
+            match &pool.concentrated {
+                Some(state) => return self.calculate_concentrated_price_impact(pool, state, token_in, amount_in),
+                None => debug!("No V3 state for pool {:?}, estimating impact from reserves", pool.address),
+            }
+        }
+
+        let (reserve_in, reserve_out) = if token_in == pool.token_a {
+            (pool.reserve_a, pool.reserve_b)
+        } else {
+            (pool.reserve_b, pool.reserve_a)
+        };
+
+        if reserve_in.is_zero() || reserve_out.is_zero() {
+            return 100.0; // Max impact
+        }
+
+        if amount_in.is_zero() {
+            return 0.0;
+        }
+
+        let amount_after_fee = amount_in.as_u128() as f64 * (10000 - pool.fee_bps) as f64 / 10000.0;
+        let spot_output = match pool.pool_type {
+            // Stable and custom pools' marginal rate, from a swap too small
+            // to move it
+            PoolType::Curve | PoolType::Custom(_) => {
+                let probe = (reserve_in / U256::from(1_000_000)).max(U256::one());
+                let probe_output = self.pool_output(pool, token_in, probe).as_u128() as f64;
+                amount_in.as_u128() as f64 * probe_output / probe.as_u128() as f64
+            }
+            _ => amount_after_fee * reserve_out.as_u128() as f64 / reserve_in.as_u128() as f64,
+        };
+
+        if spot_output <= 0.0 {
+            return 100.0;
+        }
+
+        let output = self.pool_output(pool, token_in, amount_in).as_u128() as f64;
+        let impact = (1.0 - output / spot_output) * 100.0;
+
+        impact.clamp(0.0, 100.0)
+    }
+
+    /// Calculates price impact for a swap within a V3 pool's active range,
+    /// from the pre- and post-swap sqrt price.
+    ///
+    /// Swaps that would push the price out of the active range can't be
+    /// priced from the known liquidity and report maximum impact.
+    fn calculate_concentrated_price_impact(
+        &self,
+        pool: &LiquidityPool,
+        state: &ConcentratedLiquidity,
+        token_in: Address,
+        amount_in: U256,
+    ) -> f64 {
+        let sqrt_price = q96_to_f64(state.sqrt_price_x96);
+        let liquidity = state.liquidity as f64;
+
+        if sqrt_price <= 0.0 || liquidity <= 0.0 {
+            return 100.0; // Max impact
+        }
+
+        if amount_in.is_zero() {
+            return 0.0;
+        }
+
+        let amount_after_fee = amount_in.as_u128() as f64 * (10000 - pool.fee_bps) as f64 / 10000.0;
+
+        // Selling token_a lowers the price, selling token_b raises it
+        let sqrt_price_after = if token_in == pool.token_a {
+            liquidity * sqrt_price / (liquidity + amount_after_fee * sqrt_price)
+        } else {
+            sqrt_price + amount_after_fee / liquidity
+        };
+
+        let lower = q96_to_f64(state.sqrt_price_lower_x96);
+        let upper = q96_to_f64(state.sqrt_price_upper_x96);
+        if sqrt_price_after < lower || sqrt_price_after > upper {
+            debug!("Swap through pool {:?} crosses its active range", pool.address);
+            return 100.0;
+        }
+
+        // Price of the output token in terms of the input, before vs after
+        let price_ratio = (sqrt_price_after / sqrt_price).powi(2);
+        let retained = if token_in == pool.token_a { price_ratio } else { 1.0 / price_ratio };
+
+        ((1.0 - retained) * 100.0).clamp(0.0, 100.0)
+    }
+
+    /// Calculates route quality score
+    fn calculate_route_score(
+        &self,
+        token_out: Address,
+        output_amount: U256,
+        gas_cost: u64,
+        price_impact: f64,
+    ) -> f64 {
+        // Score factors:
+        // 1. Output amount (higher is better)
+        // 2. Gas cost (lower is better)
+        // 3. Price impact (lower is better)
+        
+        // In whole tokens, so a 6-decimal output isn't dwarfed by an
+        // 18-decimal one of equal value
+        let output_score = self.tokens.to_units(token_out, output_amount);
+        let gas_penalty = (gas_cost as f64) / 1e6; // Normalize gas cost
+        // Charged as the share of output lost to slippage, so it scales with
+        // trade size and near-zero-slippage stable routes keep their edge
+        let impact_penalty = output_score * price_impact / 100.0;
+        
+        // Weighted score
+        let weights = &self.scoring_weights;
+        weights.output * output_score - weights.gas * gas_penalty - weights.impact * impact_penalty
+    }
+}
+
+impl Default for RoutingEngine {
+    fn default() -> Self {
+        Self::new(3, 5.0) // Max 3 hops, 5% max price impact
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One whole token at 18 decimals
+    const E18: u128 = 1_000_000_000_000_000_000;
+
+    fn create_test_order(id: u8, sell_token: Address, buy_token: Address, sell_amount: U256, buy_amount: U256) -> Order {
+        Order {
+            id: crate::domain::OrderId([id; 32]),
+            owner: Address::zero(),
+            sell_token,
+            buy_token,
+            sell_amount,
+            buy_amount,
+            valid_to: u32::MAX,
+            fee_amount: U256::zero(),
+            kind: OrderType::Sell,
+            partially_fillable: false,
+            status: crate::domain::OrderStatus::Open,
+            source_chain: None,
+            destination_chain: None,
+            bridge_provider: None,
+            filled_amount: U256::zero(),
+            signature: vec![],
+        }
+    }
+
+    fn create_test_pool(
+        token_a: Address,
+        token_b: Address,
+        reserve_a: u128,
+        reserve_b: u128,
+    ) -> LiquidityPool {
+        LiquidityPool {
+            address: Address::zero(),
+            pool_type: PoolType::UniswapV2,
+            token_a,
+            token_b,
+            reserve_a: U256::from(reserve_a),
+            reserve_b: U256::from(reserve_b),
+            fee_bps: 30, // 0.3%
+            gas_cost: 100000,
+            concentrated: None,
+            amplification: None,
+            fetched_at_block: 0,
+        }
+    }
+
+    #[test]
+    fn test_constant_product_calculation() {
+        let engine = RoutingEngine::default();
+        
+        let amount_in = U256::from(1000);
+        let reserve_in = U256::from(100000);
+        let reserve_out = U256::from(200000);
+        let fee_bps = 30;
+        
+        let output = engine.calculate_constant_product_output(
+            amount_in,
+            reserve_in,
+            reserve_out,
+            fee_bps,
+        );
+        
+        assert!(output > U256::zero());
+        assert!(output < U256::from(2000)); // Should be less than 2x input
+    }
+
+    #[test]
+    fn test_direct_route() {
+        let mut engine = RoutingEngine::default();
+        
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        
+        let pool = create_test_pool(token_a, token_b, 1000000, 2000000);
+        engine.add_pool(pool);
+        
+        let route = engine.find_best_route(token_a, token_b, U256::from(1000));
+        
+        assert!(route.is_ok());
+        let route = route.unwrap();
+        assert_eq!(route.pools.len(), 1);
+        assert_eq!(route.path.len(), 2);
+    }
+
+    #[test]
+    fn test_multi_hop_route() {
+        let mut engine = RoutingEngine::new(3, 10.0);
+        
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let token_c = Address::from_low_u64_be(3);
+        
+        // Create path A -> B -> C
+        engine.add_pool(create_test_pool(token_a, token_b, 1000000, 2000000));
+        engine.add_pool(create_test_pool(token_b, token_c, 2000000, 3000000));
+        
+        let route = engine.find_best_route(token_a, token_c, U256::from(1000));
+        
+        assert!(route.is_ok());
+        let route = route.unwrap();
+        assert_eq!(route.pools.len(), 2);
+        assert_eq!(route.path.len(), 3);
+    }
+
+    #[test]
+    fn test_exact_out_direct_route() {
+        let mut engine = RoutingEngine::default();
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        engine.add_pool(create_test_pool(token_a, token_b, 1000000, 2000000));
+
+        let amount_out = U256::from(1000);
+        let (amount_in, route) = engine
+            .find_best_route_for_exact_out(token_a, token_b, amount_out)
+            .unwrap();
+        let expected = calculate_amm_input(amount_out, U256::from(1000000), U256::from(2000000), 30).unwrap();
+        assert_eq!(amount_in, expected + 1);
+        assert_eq!(route.path, vec![token_a, token_b]);
+        assert_eq!(route.min_output_amount, amount_out);
+
+        // Feeding the input back in buys at least the requested amount
+        let forward = engine.find_best_route(token_a, token_b, amount_in).unwrap();
+        assert!(forward.output_amount >= amount_out);
+
+        // The pool can't pay out its whole reserve
+        assert_eq!(
+            engine
+                .find_best_route_for_exact_out(token_a, token_b, U256::from(2000000))
+                .unwrap_err(),
+            RoutingError::ZeroOutput
+        );
+    }
+
+    #[test]
+    fn test_hop_splitting_increases_output() {
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let token_c = Address::from_low_u64_be(3);
+
+        let pools = [
+            create_test_pool(token_a, token_b, 1000000, 2000000),
+            create_test_pool(token_a, token_b, 1000000, 2000000),
+            create_test_pool(token_b, token_c, 100000000, 100000000),
+        ];
+        let build = |split_hops| {
+            let mut engine = RoutingEngine::new(3, 50.0).with_hop_splitting(split_hops);
+            for pool in &pools {
+                engine.add_pool(pool.clone());
+            }
+            engine
+        };
+        let amount = U256::from(100000);
+
+        let single = build(false).find_best_route(token_a, token_c, amount).unwrap();
+        assert!(single.split_pools.is_empty());
+
+        let engine = build(true);
+        let split = engine.find_best_route(token_a, token_c, amount).unwrap();
+        assert!(split.output_amount > single.output_amount);
+        assert_eq!(split.split_pools[0].len(), 1);
+        assert_eq!(split.gas_cost, 300000);
+        assert_eq!(engine.hop_amounts(&split, amount), split.hop_outputs);
+
+        // The split hop becomes one swap per pool
+        let interactions = engine.build_interactions(&split, amount);
+        assert_eq!(interactions.len(), 3);
+        let first_hop_in = interactions[..2]
+            .iter()
+            .map(|i| i.flow.as_ref().unwrap().amount_in)
+            .fold(U256::zero(), |total, amount| total + amount);
+        assert_eq!(first_hop_in, amount);
+    }
+
+    #[test]
+    fn test_route_beats_limit_at_the_margin() {
+        let mut engine = RoutingEngine::default();
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        engine.add_pool(create_test_pool(token_a, token_b, 1000000, 2000000));
+
+        let amount_in = U256::from(1000);
+        let route = engine.find_best_route(token_a, token_b, amount_in).unwrap();
+        let output = route.output_amount;
+        let expected = output.as_u128() as f64 / 1000.0;
+        assert!((route.execution_price(amount_in) - expected).abs() < 1e-12);
+        assert_eq!(route.execution_price_fixed(amount_in), output * U256::exp10(15));
+
+        // Far beyond u128 on both sides, the price is still the ratio
+        let huge = Route { output_amount: U256::exp10(60) * 3, ..route.clone() };
+        assert!((huge.execution_price(U256::exp10(59)) - 30.0).abs() < 1e-12);
+
+        let order = |kind, buy_amount: U256| Order {
+            kind,
+            ..create_test_order(1, token_a, token_b, amount_in, buy_amount)
+        };
+
+        for kind in [OrderType::Sell, OrderType::Buy] {
+            assert!(route.beats_limit(&order(kind, output)));
+            assert!(!route.beats_limit(&order(kind, output + 1)));
+        }
+    }
+
+    #[test]
+    fn test_max_hops_override_per_call() {
+        let mut engine = RoutingEngine::new(1, 10.0);
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let token_c = Address::from_low_u64_be(3);
+        engine.add_pool(create_test_pool(token_a, token_b, 1000000, 2000000));
+        engine.add_pool(create_test_pool(token_b, token_c, 2000000, 1000000));
+
+        let amount = U256::from(1000);
+        assert_eq!(engine.find_best_route(token_a, token_c, amount).unwrap_err(), RoutingError::NoPath);
+        assert_eq!(
+            engine.find_best_route_with_hops(token_a, token_c, amount, 1).unwrap_err(),
+            RoutingError::NoPath
+        );
+
+        let route = engine.find_best_route_with_hops(token_a, token_c, amount, 2).unwrap();
+        assert_eq!(route.path, vec![token_a, token_b, token_c]);
+
+        // Deeper searches still respect the price impact cap
+        let err = engine
+            .find_best_route_with_hops(token_a, token_c, U256::from(500000), 2)
+            .unwrap_err();
+        assert!(matches!(err, RoutingError::AllExceedPriceImpact { .. }));
+    }
+
+    #[test]
+    fn test_prune_dominated_routes() {
+        let route = |output: u64, gas_cost: u64, price_impact: f64| Route {
+            pools: vec![],
+            path: vec![],
+            amount_in: U256::zero(),
+            output_amount: U256::from(output),
+            hop_outputs: vec![],
+            split_pools: Vec::new(),
+            min_output_amount: U256::from(output),
+            gas_cost,
+            price_impact,
+            low_confidence_impact: false,
+            score: 0.0,
+        };
+
+        // Most output vs cheapest gas trade off; the last loses on all three
+        let mut routes = vec![
+            route(1000, 200000, 1.0),
+            route(900, 100000, 0.5),
+            route(800, 300000, 2.0),
+        ];
+        RoutingEngine::prune_dominated(&mut routes);
+
+        let remaining: Vec<u64> = routes.iter().map(|r| r.gas_cost).collect();
+        assert_eq!(remaining, vec![200000, 100000]);
+    }
+
+    #[test]
+    fn test_default_gas_cost_matches_settlement_estimate() {
+        assert_eq!(LiquidityPool::default_gas_cost(PoolType::UniswapV2), 100_000);
+        assert_eq!(LiquidityPool::default_gas_cost(PoolType::UniswapV3), 130_000);
+        assert_eq!(LiquidityPool::default_gas_cost(PoolType::Balancer), 150_000);
+        assert_eq!(LiquidityPool::default_gas_cost(PoolType::Curve), 180_000);
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let token_c = Address::from_low_u64_be(3);
+        let mut engine = RoutingEngine::default();
+        engine.add_pool(LiquidityPool::with_defaults(
+            Address::from_low_u64_be(0x100),
+            PoolType::UniswapV2,
+            token_a,
+            token_b,
+            U256::exp10(21),
+            U256::exp10(21),
+            30,
+        ));
+        engine.add_pool(LiquidityPool::with_defaults(
+            Address::from_low_u64_be(0x200),
+            PoolType::Curve,
+            token_b,
+            token_c,
+            U256::exp10(21),
+            U256::exp10(21),
+            4,
+        ));
+
+        let amount = U256::exp10(18);
+        let route = engine.find_best_route(token_a, token_c, amount).unwrap();
+        assert_eq!(route.gas_cost, 280_000);
+
+        let mut settlement = SettlementPlan::default();
+        for interaction in engine.build_interactions(&route, amount) {
+            settlement.add_interaction(interaction);
+        }
+        assert_eq!(settlement.estimate_gas() - SettlementPlan::BASE_GAS, route.gas_cost);
+    }
+
+    #[test]
+    fn test_hop_outputs_per_hop() {
+        let mut engine = RoutingEngine::new(3, 10.0);
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let token_c = Address::from_low_u64_be(3);
+
+        let first_pool = create_test_pool(token_a, token_b, 1000000, 2000000);
+        engine.add_pool(first_pool.clone());
+        engine.add_pool(create_test_pool(token_b, token_c, 2000000, 3000000));
+
+        let amount = U256::from(1000);
+        let route = engine.find_best_route(token_a, token_c, amount).unwrap();
+        assert_eq!(route.hop_outputs.len(), 2);
+        assert_eq!(route.hop_outputs[0], engine.calculate_output(&first_pool, token_a, amount));
+        assert_eq!(route.hop_outputs[1], route.output_amount);
+        assert_eq!(route.hop_outputs, engine.hop_amounts(&route, amount));
+
+        let direct = engine.find_best_route(token_a, token_b, amount).unwrap();
+        assert_eq!(direct.hop_outputs, vec![direct.output_amount]);
+    }
+
+    #[test]
+    fn test_routing_errors() {
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let token_c = Address::from_low_u64_be(3);
+
+        let mut engine = RoutingEngine::new(3, 1.0);
+        engine.add_pool(create_test_pool(token_a, token_b, 1000000, 2000000));
+        engine.add_pool(create_test_pool(token_b, token_c, 1000000, 1));
+
+        assert!(matches!(
+            engine.find_best_route(token_a, Address::from_low_u64_be(9), U256::from(1000)),
+            Err(RoutingError::NoPath)
+        ));
+
+        // A tenth of the pool moves the price far past the 1% cap
+        match engine.find_best_route(token_a, token_b, U256::from(100000)) {
+            Err(RoutingError::AllExceedPriceImpact { best_impact }) => assert!(best_impact > 1.0),
+            other => panic!("expected price impact error, got {:?}", other.map(|r| r.output_amount)),
+        }
+
+        // Connected, but the B/C pool has no C to give
+        assert!(matches!(
+            engine.find_best_route(token_b, token_c, U256::from(1)),
+            Err(RoutingError::ZeroOutput)
+        ));
+        assert!(engine.find_best_route_opt(token_b, token_c, U256::from(1)).is_none());
+        assert!(engine.find_best_route_opt(token_a, token_b, U256::from(1000)).is_some());
+    }
+
+    #[test]
+    fn test_min_liquidity_excludes_dust_pools() {
+        let mut engine = RoutingEngine::default().with_min_liquidity(U256::from(10000));
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+
+        // Dust pool quotes a far better rate than the deep one
+        let mut dust = create_test_pool(token_a, token_b, 100, 1000000);
+        dust.address = Address::from_low_u64_be(0x10);
+        let mut deep = create_test_pool(token_a, token_b, 1000000, 2000000);
+        deep.address = Address::from_low_u64_be(0x20);
+
+        assert!(!engine.add_pool(dust));
+        assert!(engine.add_pool(deep));
+
+        let route = engine.find_best_route(token_a, token_b, U256::from(10)).unwrap();
+        assert_eq!(route.pools[0].address, Address::from_low_u64_be(0x20));
+    }
+
+    #[test]
+    fn test_stale_pools_excluded() {
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+
+        // Stale pool quotes a far better rate than the fresh one
+        let mut stale = create_test_pool(token_a, token_b, 1000000, 4000000);
+        stale.address = Address::from_low_u64_be(0x10);
+        stale.fetched_at_block = 90;
+        let mut fresh = create_test_pool(token_a, token_b, 1000000, 2000000);
+        fresh.address = Address::from_low_u64_be(0x20);
+        fresh.fetched_at_block = 98;
+
+        // Pools already added are dropped once a cutoff is set
+        let mut engine = RoutingEngine::default();
+        engine.add_pool(stale.clone());
+        engine.add_pool(fresh.clone());
+        let engine = engine.with_max_pool_staleness(100, 3);
+        let route = engine.find_best_route(token_a, token_b, U256::from(1000)).unwrap();
+        assert_eq!(route.pools[0].address, Address::from_low_u64_be(0x20));
+
+        // And later stale pools are rejected outright
+        let mut engine = RoutingEngine::default().with_max_pool_staleness(100, 3);
+        assert!(!engine.add_pool(stale));
+        assert!(engine.add_pool(fresh));
+    }
+
+    #[test]
+    fn test_gas_weight_flips_route_choice() {
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+
+        // Slightly better price, slightly more gas
+        let mut deep = create_test_pool(token_a, token_b, 1000 * E18, 2000 * E18);
+        deep.gas_cost = 105_000;
+        let mut cheap = create_test_pool(token_a, token_b, 1000 * E18, 1990 * E18);
+        cheap.gas_cost = 100_000;
+
+        let mut engine = RoutingEngine::default();
+        engine.add_pool(deep.clone());
+        engine.add_pool(cheap.clone());
+        let route = engine.find_best_route(token_a, token_b, U256::from(E18)).unwrap();
+        assert_eq!(route.gas_cost, 105_000);
+
+        let mut engine = RoutingEngine::default().with_scoring_weights(RouteScoringWeights {
+            gas: 10.0,
+            ..RouteScoringWeights::default()
+        });
+        engine.add_pool(deep);
+        engine.add_pool(cheap);
+        let route = engine.find_best_route(token_a, token_b, U256::from(E18)).unwrap();
+        assert_eq!(route.gas_cost, 100_000);
+    }
+
+    #[test]
+    fn test_price_impact_calculation() {
+        let engine = RoutingEngine::default();
+        
+        let token_a = Address::from_low_u64_be(1);
+        let pool = create_test_pool(token_a, Address::from_low_u64_be(2), 1000000, 2000000);
+        
+        let small_impact = engine.calculate_price_impact(&pool, token_a, U256::from(1000));
+        let large_impact = engine.calculate_price_impact(&pool, token_a, U256::from(100000));
+        
+        assert!(small_impact < large_impact);
+        assert!(small_impact < 1.0); // Less than 1% for small trade
+        assert!(large_impact > 5.0); // More than 5% for large trade
+    }
+
+    #[test]
+    fn test_multi_hop_price_impact_composes() {
+        // Two hops of ~2.5% each: naive summation (> 5%) would breach the cap
+        let mut engine = RoutingEngine::new(3, 5.0);
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let token_c = Address::from_low_u64_be(3);
+
+        let first = create_test_pool(token_a, token_b, E18, E18);
+        let second = create_test_pool(token_b, token_c, 97 * E18 / 100, E18);
+        engine.add_pool(first.clone());
+        engine.add_pool(second.clone());
+
+        let amount_in = U256::from(26 * E18 / 1000);
+        let hop_one = engine.calculate_price_impact(&first, token_a, amount_in);
+        let mid_amount = engine.calculate_output(&first, token_a, amount_in);
+        let hop_two = engine.calculate_price_impact(&second, token_b, mid_amount);
+        assert!(hop_one + hop_two > 5.0);
+
+        let route = engine.find_best_route(token_a, token_c, amount_in).unwrap();
+        assert_eq!(route.pools.len(), 2);
+        assert!(route.price_impact <= 5.0);
+
+        let composed = (1.0 - (1.0 - hop_one / 100.0) * (1.0 - hop_two / 100.0)) * 100.0;
+        assert!((route.price_impact - composed).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_transfer_fee_reduces_output() {
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let pool = create_test_pool(token_a, token_b, 100_000_000, 100_000_000);
+        let amount_in = U256::from(1_000_000);
+        
+        let plain = RoutingEngine::default();
+        let taxed = RoutingEngine::default().with_transfer_fee(token_a, 100);
         
-        let amount_in = U256::from(1000);
-        let reserve_in = U256::from(100000);
-        let reserve_out = U256::from(200000);
-        let fee_bps = 30;
+        let plain_output = plain.calculate_output(&pool, token_a, amount_in);
+        let taxed_output = taxed.calculate_output(&pool, token_a, amount_in);
+        assert_eq!(
+            taxed_output,
+            plain.calculate_output(&pool, token_a, U256::from(990_000))
+        );
+        assert!(taxed_output < plain_output);
         
-        let output = engine.calculate_constant_product_output(
-            amount_in,
-            reserve_in,
-            reserve_out,
-            fee_bps,
+        // Fee on the output token is charged on the way out
+        let taxed_out = RoutingEngine::default().with_transfer_fee(token_b, 100);
+        assert_eq!(
+            taxed_out.calculate_output(&pool, token_a, amount_in),
+            plain_output - plain_output / 100
         );
         
-        assert!(output > U256::zero());
-        assert!(output < U256::from(2000)); // Should be less than 2x input
+        // Impact is pool slippage only, unaffected by the transfer fee
+        let plain_impact = plain.calculate_price_impact(&pool, token_a, U256::from(990_000));
+        assert_eq!(taxed.calculate_price_impact(&pool, token_a, amount_in), plain_impact);
     }
-
+    
     #[test]
-    fn test_direct_route() {
+    fn test_stable_swap_output_near_peg() {
+        let engine = RoutingEngine::default();
+        let e18 = U256::exp10(18);
+        
+        // Balanced pool: a small trade clears at ~1:1 less the fee
+        let output = engine.calculate_stable_swap_output(
+            U256::from(1000) * e18,
+            U256::from(1_000_000) * e18,
+            U256::from(1_000_000) * e18,
+            4,
+            200,
+        );
+        assert!(output < U256::from(1000) * e18);
+        assert!(output > U256::from(999) * e18);
+        
+        // Draining most of one side gets progressively worse rates
+        let large = engine.calculate_stable_swap_output(
+            U256::from(900_000) * e18,
+            U256::from(1_000_000) * e18,
+            U256::from(1_000_000) * e18,
+            4,
+            200,
+        );
+        assert!(large < U256::from(900_000) * e18);
+    }
+    
+    #[test]
+    fn test_curve_route_beats_shallow_v2_for_stable_pair() {
+        let mut engine = RoutingEngine::default();
+        
+        let usdc = Address::from_low_u64_be(1);
+        let usdt = Address::from_low_u64_be(2);
+        
+        let v2 = LiquidityPool {
+            address: Address::from_low_u64_be(0x10),
+            ..create_test_pool(usdc, usdt, 500_000 * E18, 500_000 * E18)
+        };
+        let curve = LiquidityPool {
+            address: Address::from_low_u64_be(0x20),
+            pool_type: PoolType::Curve,
+            fee_bps: 4,
+            gas_cost: 180000, // Pricier than the V2 swap
+            amplification: Some(200),
+            ..create_test_pool(usdc, usdt, 10_000_000 * E18, 10_000_000 * E18)
+        };
+        
+        let amount_in = U256::from(10_000 * E18);
+        let v2_impact = engine.calculate_price_impact(&v2, usdc, amount_in);
+        let curve_impact = engine.calculate_price_impact(&curve, usdc, amount_in);
+        assert!(curve_impact < 0.01);
+        assert!(v2_impact > 1.0);
+        
+        engine.add_pool(v2);
+        engine.add_pool(curve);
+        
+        let route = engine.find_best_route(usdc, usdt, amount_in).unwrap();
+        assert_eq!(route.pools[0].pool_type, PoolType::Curve);
+        assert!(route.output_amount > U256::from(9_990 * E18));
+    }
+    
+    #[test]
+    fn test_stable_pool_impact_far_below_reserve_share() {
+        let engine = RoutingEngine::default();
+        
+        let usdc = Address::from_low_u64_be(1);
+        let usdt = Address::from_low_u64_be(2);
+        let pool = LiquidityPool {
+            pool_type: PoolType::Curve,
+            fee_bps: 4,
+            amplification: Some(200),
+            ..create_test_pool(usdc, usdt, 1_000_000 * E18, 1_000_000 * E18)
+        };
+        
+        for amount in [1_000 * E18, 50_000 * E18, 200_000 * E18] {
+            // What the constant-product heuristic would charge (as percentage)
+            let reserve_share = amount as f64 / (1_000_000 * E18) as f64 * 100.0;
+            let impact = engine.calculate_price_impact(&pool, usdc, U256::from(amount));
+            assert!(impact < reserve_share / 10.0, "{}% vs {}%", impact, reserve_share);
+        }
+        
+        // The same pool priced as constant product takes the full hit
+        let v2 = LiquidityPool { pool_type: PoolType::UniswapV2, ..pool.clone() };
+        let amount = U256::from(50_000 * E18);
+        assert!(engine.calculate_price_impact(&v2, usdc, amount) > 4.0);
+        assert!(engine.calculate_price_impact(&pool, usdc, amount) < 0.1);
+    }
+    
+    #[test]
+    fn test_v3_price_impact_from_sqrt_price() {
+        let engine = RoutingEngine::default();
+        
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let q96 = U256::from(1u128 << 96);
+        
+        // Price 1.0 with the active range spanning roughly +-20%
+        let pool = LiquidityPool {
+            pool_type: PoolType::UniswapV3,
+            concentrated: Some(ConcentratedLiquidity {
+                sqrt_price_x96: q96,
+                liquidity: 1_000_000_000_000_000_000,
+                sqrt_price_lower_x96: q96 * 9 / 10,
+                sqrt_price_upper_x96: q96 * 11 / 10,
+            }),
+            ..create_test_pool(token_a, token_b, 1000, 1000)
+        };
+        assert!(!pool.has_low_confidence_impact());
+        
+        let small = engine.calculate_price_impact(&pool, token_a, U256::from(1_000_000_000_000_000u128));
+        let small_reverse = engine.calculate_price_impact(&pool, token_b, U256::from(1_000_000_000_000_000u128));
+        let crossing = engine.calculate_price_impact(&pool, token_a, U256::from(1_000_000_000_000_000_000u128));
+        
+        assert!(small > 0.0 && small < 1.0);
+        assert!(small_reverse > 0.0 && small_reverse < 1.0);
+        assert_eq!(crossing, 100.0);
+    }
+    
+    #[test]
+    fn test_v3_without_state_flags_low_confidence() {
         let mut engine = RoutingEngine::default();
         
         let token_a = Address::from_low_u64_be(1);
         let token_b = Address::from_low_u64_be(2);
+        engine.add_pool(LiquidityPool {
+            pool_type: PoolType::UniswapV3,
+            ..create_test_pool(token_a, token_b, 1000000, 2000000)
+        });
         
-        let pool = create_test_pool(token_a, token_b, 1000000, 2000000);
+        let route = engine.find_best_route(token_a, token_b, U256::from(1000)).unwrap();
+        assert!(route.low_confidence_impact);
+        assert!(route.price_impact > 0.0);
+    }
+    
+    #[test]
+    fn test_route_cache_hit_and_invalidation() {
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let pool_address = Address::from_low_u64_be(100);
+        
+        // Granularity applies whichever order the builders run in
+        let mut engine = RoutingEngine::default()
+            .with_cache_granularity(U256::from(100))
+            .with_cache(16);
+        let mut pool = create_test_pool(token_a, token_b, 1000000, 2000000);
+        pool.address = pool_address;
+        engine.add_pool(pool.clone());
+        let mut uncached = RoutingEngine::default();
+        uncached.add_pool(pool);
+        
+        let first = engine.find_best_route(token_a, token_b, U256::from(1000)).unwrap();
+        
+        // Same bucket: the cached path is re-quoted at the new amount
+        let second = engine.find_best_route(token_a, token_b, U256::from(1050)).unwrap();
+        let fresh = uncached.find_best_route(token_a, token_b, U256::from(1050)).unwrap();
+        assert_eq!(second.path, first.path);
+        assert_eq!(second.amount_in, U256::from(1050));
+        assert_eq!(second.output_amount, fresh.output_amount);
+        assert_eq!(second.min_output_amount, fresh.min_output_amount);
+        assert!(second.output_amount > first.output_amount);
+        
+        // Reserve update busts the cache
+        assert!(engine.update_reserves(pool_address, U256::from(1000000), U256::from(4000000)));
+        let third = engine.find_best_route(token_a, token_b, U256::from(1000)).unwrap();
+        assert!(third.output_amount > first.output_amount);
+    }
+    
+    #[test]
+    fn test_replay_cache_keyed_by_reserves() {
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let pool_address = Address::from_low_u64_be(100);
+        
+        let mut engine = RoutingEngine::default().with_replay_cache(16);
+        let mut pool = create_test_pool(token_a, token_b, 1000000, 2000000);
+        pool.address = pool_address;
         engine.add_pool(pool);
         
-        let route = engine.find_best_route(token_a, token_b, U256::from(1000));
+        let amount = U256::from(1000);
+        let first = engine.find_best_route(token_a, token_b, amount).unwrap();
+        assert_eq!(engine.replay_cache_hits(), 0);
         
-        assert!(route.is_some());
-        let route = route.unwrap();
-        assert_eq!(route.pools.len(), 1);
-        assert_eq!(route.path.len(), 2);
+        // Replaying the same query is a hit
+        let replayed = engine.find_best_route(token_a, token_b, amount).unwrap();
+        assert_eq!(replayed.output_amount, first.output_amount);
+        assert_eq!(engine.replay_cache_hits(), 1);
+        
+        // Any reserve change is a miss
+        engine.update_reserves(pool_address, U256::from(1000000), U256::from(4000000));
+        let updated = engine.find_best_route(token_a, token_b, amount).unwrap();
+        assert!(updated.output_amount > first.output_amount);
+        assert_eq!(engine.replay_cache_hits(), 1);
+        
+        // Restoring the reserves hits the original entry again
+        engine.update_reserves(pool_address, U256::from(1000000), U256::from(2000000));
+        engine.find_best_route(token_a, token_b, amount).unwrap();
+        assert_eq!(engine.replay_cache_hits(), 2);
+    }
+    
+    #[test]
+    fn test_route_cache_evicts_least_recently_used() {
+        let mut cache = RouteCache::new(2);
+        let route = Route {
+            pools: vec![],
+            path: vec![],
+            amount_in: U256::zero(),
+            output_amount: U256::zero(),
+            hop_outputs: vec![],
+            split_pools: Vec::new(),
+            min_output_amount: U256::zero(),
+            gas_cost: 0,
+            price_impact: 0.0,
+            low_confidence_impact: false,
+            score: 0.0,
+        };
+        let key = |n: u64| (Address::zero(), Address::zero(), U256::from(n), 3);
+        
+        cache.insert(key(1), route.clone());
+        cache.insert(key(2), route.clone());
+        assert!(cache.get(&key(1)).is_some()); // 1 is now most recent
+        cache.insert(key(3), route);
+        
+        assert!(cache.get(&key(1)).is_some());
+        assert!(cache.get(&key(2)).is_none());
+        assert!(cache.get(&key(3)).is_some());
+    }
+    
+    #[test]
+    fn test_snapshot_restore_reverts_simulated_trades() {
+        let mut engine = RoutingEngine::default().with_cache(8);
+
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        engine.add_pool(create_test_pool(token_a, token_b, 1000000, 2000000));
+        let pool = engine.pools[0].address;
+
+        let amount = U256::from(1000);
+        let original = engine.find_best_route(token_a, token_b, amount).unwrap();
+
+        let snapshot = engine.snapshot();
+        assert!(engine.update_reserves(pool, U256::from(2000000), U256::from(1000000)));
+        let simulated = engine.find_best_route(token_a, token_b, amount).unwrap();
+        assert!(simulated.output_amount < original.output_amount);
+
+        engine.restore(snapshot);
+        let restored = engine.find_best_route(token_a, token_b, amount).unwrap();
+        assert_eq!(restored.output_amount, original.output_amount);
+        assert_eq!(restored.pools.len(), original.pools.len());
     }
 
     #[test]
-    fn test_multi_hop_route() {
-        let mut engine = RoutingEngine::new(3, 10.0);
+    fn test_remove_pool_reindexes() {
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let token_c = Address::from_low_u64_be(3);
+        
+        let mut engine = RoutingEngine::default();
+        let mut first = create_test_pool(token_a, token_b, 1000000, 2000000);
+        first.address = Address::from_low_u64_be(100);
+        let mut second = create_test_pool(token_b, token_c, 1000000, 2000000);
+        second.address = Address::from_low_u64_be(101);
+        engine.add_pool(first);
+        engine.add_pool(second);
+        
+        assert!(engine.remove_pool(Address::from_low_u64_be(100)).is_some());
+        assert!(engine.remove_pool(Address::from_low_u64_be(100)).is_none());
+        assert!(matches!(
+            engine.find_best_route(token_a, token_b, U256::from(1000)),
+            Err(RoutingError::NoPath)
+        ));
+        assert!(engine.find_best_route(token_b, token_c, U256::from(1000)).is_ok());
+    }
+    
+    #[test]
+    fn test_parallel_and_sequential_agree_on_large_pool_set() {
+        let token_in = Address::from_low_u64_be(1);
+        let token_out = Address::from_low_u64_be(2);
+        let mut engine = RoutingEngine::new(3, 100.0);
+        
+        // A few direct pools plus 150 two-hop paths through distinct intermediates
+        for i in 0..4u128 {
+            engine.add_pool(create_test_pool(token_in, token_out, 1_000_000 + i * 1000, 2_000_000));
+        }
+        for i in 0..150u64 {
+            let mid = Address::from_low_u64_be(1000 + i);
+            let depth = 1_000_000 + (i as u128 * 7919) % 500_000;
+            engine.add_pool(create_test_pool(token_in, mid, depth, depth * 2));
+            engine.add_pool(create_test_pool(mid, token_out, depth * 2, depth * 2));
+        }
+        assert!(engine.pools.len() > 300);
+        
+        let amount_in = U256::from(10_000);
+        let paths = engine.build_token_graph().find_paths(token_in, token_out, engine.max_hops);
+        
+        let mut parallel = engine.evaluate_paths(&paths, amount_in);
+        let mut sequential = engine.evaluate_paths_sequential(&paths, amount_in);
+        RoutingEngine::sort_routes(&mut parallel);
+        RoutingEngine::sort_routes(&mut sequential);
         
+        assert_eq!(parallel.len(), sequential.len());
+        for (p, s) in parallel.iter().zip(&sequential) {
+            assert_eq!(p.path, s.path);
+            assert_eq!(p.output_amount, s.output_amount);
+        }
+        
+        // Best route is reproducible across calls
+        let first = engine.find_best_route(token_in, token_out, amount_in).unwrap();
+        let second = engine.find_best_route(token_in, token_out, amount_in).unwrap();
+        assert_eq!(first.path, second.path);
+        assert_eq!(first.output_amount, second.output_amount);
+    }
+    
+    #[test]
+    fn test_min_pools_per_hop() {
         let token_a = Address::from_low_u64_be(1);
         let token_b = Address::from_low_u64_be(2);
         let token_c = Address::from_low_u64_be(3);
         
-        // Create path A -> B -> C
+        // A -> B backed by two pools, B -> C by a single pool
+        let pools = vec![
+            create_test_pool(token_a, token_b, 1000000, 2000000),
+            create_test_pool(token_a, token_b, 500000, 1000000),
+            create_test_pool(token_b, token_c, 2000000, 3000000),
+        ];
+        
+        let mut strict = RoutingEngine::new(3, 10.0).with_min_pools_per_hop(2);
+        let mut lenient = RoutingEngine::new(3, 10.0).with_min_pools_per_hop(1);
+        for pool in pools {
+            strict.add_pool(pool.clone());
+            lenient.add_pool(pool);
+        }
+        
+        assert!(strict.find_best_route(token_a, token_c, U256::from(1000)).is_err());
+        
+        let route = lenient.find_best_route(token_a, token_c, U256::from(1000));
+        assert!(route.is_ok());
+        assert_eq!(route.unwrap().pools.len(), 2);
+    }
+
+    #[test]
+    fn test_min_output_applies_slippage() {
+        let mut engine = RoutingEngine::default();
+        
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        engine.add_pool(create_test_pool(token_a, token_b, 1000000, 2000000));
+        
+        let route = engine
+            .find_best_route_with_slippage(token_a, token_b, U256::from(1000), 0.5)
+            .unwrap();
+        assert!(route.min_output_amount < route.output_amount);
+        assert_eq!(route.min_output_amount, route.output_amount * 995 / 1000);
+        
+        let route = engine
+            .find_best_route_with_slippage(token_a, token_b, U256::from(1000), 0.0)
+            .unwrap();
+        assert_eq!(route.min_output_amount, route.output_amount);
+        
+        // Engine-wide slippage applies to plain lookups
+        let engine = engine.with_max_slippage(1.0);
+        let route = engine.find_best_route(token_a, token_b, U256::from(1000)).unwrap();
+        assert!(route.min_output_amount < route.output_amount);
+    }
+    
+    #[test]
+    fn test_v2_interaction_encodes_min_output() {
+        let mut engine = RoutingEngine::default();
+        
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        engine.add_pool(create_test_pool(token_a, token_b, 1000000, 2000000));
+        
+        let amount_in = U256::from(1000);
+        let route = engine
+            .find_best_route_with_slippage(token_a, token_b, amount_in, 1.0)
+            .unwrap();
+        let interactions = engine.build_interactions(&route, amount_in);
+        assert_eq!(interactions.len(), 1);
+        
+        let call_data = &interactions[0].call_data;
+        assert_eq!(interactions[0].target, Address::from(UNISWAP_V2_ROUTER));
+        assert_eq!(
+            &call_data[..4],
+            &id("swapExactTokensForTokens(uint256,uint256,address[],address,uint256)")[..]
+        );
+        assert_eq!(U256::from_big_endian(&call_data[4..36]), amount_in);
+        assert_eq!(U256::from_big_endian(&call_data[36..68]), route.min_output_amount);
+    }
+
+    #[test]
+    fn test_fill_order_adds_approvals_once() {
+        let mut engine = RoutingEngine::new(3, 10.0);
+        
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let token_c = Address::from_low_u64_be(3);
         engine.add_pool(create_test_pool(token_a, token_b, 1000000, 2000000));
         engine.add_pool(create_test_pool(token_b, token_c, 2000000, 3000000));
         
-        let route = engine.find_best_route(token_a, token_c, U256::from(1000));
+        let order = create_test_order(1, token_a, token_c, U256::from(1000), U256::one());
+        let second = create_test_order(2, token_a, token_c, U256::from(1000), U256::one());
         
-        assert!(route.is_some());
-        let route = route.unwrap();
+        let mut settlement = SettlementPlan::default();
+        let route = engine.fill_order(&order, &mut settlement).unwrap();
         assert_eq!(route.pools.len(), 2);
-        assert_eq!(route.path.len(), 3);
+        engine.fill_order(&second, &mut settlement).unwrap();
+        
+        let router = Address::from(UNISWAP_V2_ROUTER);
+        let approvals: Vec<_> = settlement
+            .interactions
+            .iter()
+            .filter(|i| i.interaction_type == InteractionType::Approval)
+            .collect();
+        assert_eq!(approvals.len(), 2);
+        assert!(approvals[0].approves(token_a, router));
+        assert!(approvals[1].approves(token_b, router));
+        
+        // Each approval precedes the swap spending it
+        assert_eq!(settlement.interactions[0].interaction_type, InteractionType::Approval);
+        assert_eq!(settlement.interactions.len(), 6);
+    }
+    
+    #[test]
+    fn test_fill_order_keeps_existing_clearing_prices() {
+        let mut engine = RoutingEngine::new(3, 10.0);
+        
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        engine.add_pool(create_test_pool(token_a, token_b, 1000000, 2000000));
+        
+        let order = create_test_order(1, token_a, token_b, U256::from(1000), U256::from(1500));
+        
+        // An earlier trade priced the pair one to one, below the limit
+        let mut settlement = SettlementPlan::default();
+        settlement.set_clearing_price(token_a, U256::one());
+        settlement.set_clearing_price(token_b, U256::one());
+        assert!(engine.fill_order(&order, &mut settlement).is_none());
+        assert!(settlement.trades.is_empty());
+        assert!(settlement.interactions.is_empty());
+        
+        // Within the limit, the order executes at the existing rate and
+        // the rest of the route output stays in the settlement
+        let order = Order { buy_amount: U256::from(900), ..order };
+        let route = engine.fill_order(&order, &mut settlement).unwrap();
+        assert!(route.output_amount > U256::from(1000));
+        assert_eq!(settlement.trades[0].executed_buy_amount, U256::from(1000));
+        assert_eq!(settlement.clearing_prices[&token_a], U256::one());
+        assert_eq!(settlement.clearing_prices[&token_b], U256::one());
+    }
+    
+    /// Fixed-rate AMM paying out at the reserve ratio until drained
+    struct LinearModel;
+    
+    impl OutputModel for LinearModel {
+        fn output(&self, pool: &LiquidityPool, token_in: Address, amount_in: U256) -> U256 {
+            let (reserve_in, reserve_out) = if token_in == pool.token_a {
+                (pool.reserve_a, pool.reserve_b)
+            } else {
+                (pool.reserve_b, pool.reserve_a)
+            };
+            (amount_in * reserve_out / reserve_in).min(reserve_out)
+        }
+    }
+    
+    #[test]
+    fn test_custom_pool_type_routed_through_registered_model() {
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let mut pool = create_test_pool(token_a, token_b, 1_000_000, 2_000_000);
+        pool.pool_type = PoolType::Custom(7);
+        
+        // Unknown custom types quote nothing
+        let mut engine = RoutingEngine::default();
+        engine.add_pool(pool.clone());
+        assert!(engine.find_best_route(token_a, token_b, U256::from(1000)).is_err());
+        
+        let mut engine = RoutingEngine::default().with_output_model(7, Arc::new(LinearModel));
+        engine.add_pool(pool);
+        let route = engine.find_best_route(token_a, token_b, U256::from(1000)).unwrap();
+        assert_eq!(route.output_amount, U256::from(2000));
+        assert_eq!(route.price_impact, 0.0);
+        
+        let interactions = engine.build_interactions(&route, U256::from(1000));
+        assert_eq!(interactions[0].interaction_type, InteractionType::Custom);
+        assert_eq!(interactions[0].estimate_gas(), LiquidityPool::default_gas_cost(PoolType::Custom(7)));
+    }
+    
+    #[test]
+    fn test_disconnected_clusters_short_circuit() {
+        let tokens: Vec<Address> = (1..=4).map(Address::from_low_u64_be).collect();
+        let mut pools = vec![
+            create_test_pool(tokens[0], tokens[1], 1_000_000, 1_000_000),
+            create_test_pool(tokens[2], tokens[3], 1_000_000, 1_000_000),
+        ];
+        pools[1].address = Address::from_low_u64_be(0x20);
+        
+        let mut engine = RoutingEngine::new(3, 10.0);
+        for pool in pools {
+            engine.add_pool(pool);
+        }
+        
+        assert!(engine.is_connected(tokens[0], tokens[1]));
+        assert!(!engine.is_connected(tokens[0], tokens[2]));
+        assert!(!engine.is_connected(tokens[0], Address::from_low_u64_be(99)));
+        assert!(matches!(
+            engine.find_best_route(tokens[0], tokens[3], U256::from(1000)),
+            Err(RoutingError::NoPath)
+        ));
+        
+        // Bridging the clusters connects them; removing the bridge splits them again
+        let mut bridge = create_test_pool(tokens[1], tokens[2], 1_000_000, 1_000_000);
+        bridge.address = Address::from_low_u64_be(0x30);
+        engine.add_pool(bridge);
+        assert!(engine.is_connected(tokens[0], tokens[3]));
+        assert!(engine.find_best_route(tokens[0], tokens[3], U256::from(1000)).is_ok());
+        
+        engine.remove_pool(Address::from_low_u64_be(0x30));
+        assert!(!engine.is_connected(tokens[0], tokens[3]));
+        assert!(engine.is_connected(tokens[2], tokens[3]));
+    }
+    
+    #[test]
+    fn test_cheapest_route_prefers_direct_pool_above_floor() {
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let token_c = Address::from_low_u64_be(3);
+        
+        // The direct pool pays ~0.9 C per A, the two-hop route ~1 C
+        let mut engine = RoutingEngine::default();
+        let mut direct = create_test_pool(token_a, token_c, 1000 * E18, 900 * E18);
+        direct.address = Address::from_low_u64_be(0x10);
+        engine.add_pool(direct);
+        let mut first = create_test_pool(token_a, token_b, 1000 * E18, 2000 * E18);
+        first.address = Address::from_low_u64_be(0x20);
+        engine.add_pool(first);
+        let mut second = create_test_pool(token_b, token_c, 2000 * E18, 1000 * E18);
+        second.address = Address::from_low_u64_be(0x30);
+        engine.add_pool(second);
+        
+        let amount_in = U256::from(10 * E18);
+        let best = engine.find_best_route(token_a, token_c, amount_in).unwrap();
+        assert_eq!(best.pools.len(), 2);
+        
+        let cheapest = engine.find_cheapest_route(token_a, token_c, amount_in, U256::from(85 * E18 / 10)).unwrap();
+        assert_eq!(cheapest.pools.len(), 1);
+        assert!(cheapest.gas_cost < best.gas_cost);
+        assert!(cheapest.output_amount < best.output_amount);
+        
+        // Above what the direct pool pays, only the two-hop route qualifies
+        let floor = U256::from(95 * E18 / 10);
+        assert_eq!(engine.find_cheapest_route(token_a, token_c, amount_in, floor).unwrap().pools.len(), 2);
+        
+        assert_eq!(
+            engine.find_cheapest_route(token_a, token_c, amount_in, U256::from(20 * E18)).unwrap_err(),
+            RoutingError::BelowMinOutput { best_output: best.output_amount }
+        );
+    }
+    
+    #[test]
+    fn test_quote_matches_filled_route() {
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let token_c = Address::from_low_u64_be(3);
+        
+        let mut engine = RoutingEngine::default().with_max_slippage(0.5);
+        engine.add_pool(create_test_pool(token_a, token_b, 1000 * E18, 2000 * E18));
+        let mut second = create_test_pool(token_b, token_c, 2000 * E18, 1000 * E18);
+        second.address = Address::from_low_u64_be(0x20);
+        engine.add_pool(second);
+        
+        let amount_in = U256::from(E18);
+        let quote = engine.quote(token_a, token_c, amount_in).unwrap();
+        let route = engine.find_best_route(token_a, token_c, amount_in).unwrap();
+        assert_eq!(quote.output_amount, route.output_amount);
+        assert_eq!(quote.min_output_amount, route.min_output_amount);
+        assert_eq!(quote.gas_estimate, route.gas_cost);
+        assert_eq!(quote.route.path, vec![token_a, token_b, token_c]);
+        assert!(quote.execution_price > 0.9 && quote.execution_price < 1.0);
+        
+        // Filling an order through the same route delivers the quoted output
+        let order = create_test_order(1, token_a, token_c, amount_in, U256::one());
+        let mut settlement = SettlementPlan::default();
+        engine.fill_order(&order, &mut settlement).unwrap();
+        assert_eq!(settlement.trades[0].executed_buy_amount, quote.output_amount);
+        
+        assert!(matches!(engine.quote(token_a, Address::from_low_u64_be(99), amount_in), Err(RoutingError::NoPath)));
     }
 
     #[test]
-    fn test_price_impact_calculation() {
-        let engine = RoutingEngine::default();
+    fn test_route_scores_normalized_by_decimals() {
+        let usdc = Address::from_low_u64_be(1);
+        let dai = Address::from_low_u64_be(2);
+        let wbtc = Address::from_low_u64_be(3);
+        
+        // Same depth in whole tokens on both pools, so 1000 USDC buys the
+        // same number of DAI and WBTC
+        let pools = [
+            create_test_pool(usdc, dai, 1_000_000 * 10u128.pow(6), 1_000_000 * 10u128.pow(18)),
+            create_test_pool(usdc, wbtc, 1_000_000 * 10u128.pow(6), 1_000_000 * 10u128.pow(8)),
+        ];
+        let amount_in = U256::from(1000 * 10u64.pow(6));
+        
+        let mut raw = RoutingEngine::default();
+        for pool in &pools {
+            raw.add_pool(pool.clone());
+        }
+        let raw_dai = raw.find_best_route(usdc, dai, amount_in).unwrap();
+        let raw_wbtc = raw.find_best_route(usdc, wbtc, amount_in).unwrap();
+        // Assumed to have 18 decimals, WBTC's output looks ten orders of
+        // magnitude smaller than DAI's
+        assert!(raw_dai.score > 900.0);
+        assert!(raw_wbtc.score < 0.0);
+        
+        let tokens = TokenRegistry::new()
+            .with_decimals(usdc, 6)
+            .with_decimals(dai, 18)
+            .with_decimals(wbtc, 8);
+        let mut engine = RoutingEngine::default().with_token_registry(tokens);
+        for pool in &pools {
+            engine.add_pool(pool.clone());
+        }
+        let dai_route = engine.find_best_route(usdc, dai, amount_in).unwrap();
+        let wbtc_route = engine.find_best_route(usdc, wbtc, amount_in).unwrap();
         
+        assert!(dai_route.score > 900.0);
+        assert!((dai_route.score - wbtc_route.score).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_cyclic_routes_rejected() {
         let token_a = Address::from_low_u64_be(1);
-        let pool = create_test_pool(token_a, Address::from_low_u64_be(2), 1000000, 2000000);
+        let token_b = Address::from_low_u64_be(2);
+        let amount_in = U256::from(1000);
         
-        let small_impact = engine.calculate_price_impact(&pool, token_a, U256::from(1000));
-        let large_impact = engine.calculate_price_impact(&pool, token_a, U256::from(100000));
+        for split_hops in [false, true] {
+            let mut engine = RoutingEngine::default().with_hop_splitting(split_hops);
+            engine.add_pool(create_test_pool(token_a, token_b, 1000000, 2000000));
+            engine.add_pool(create_test_pool(token_a, token_b, 3000000, 6000000));
+            
+            // A -> B -> A is a valid path hop by hop, but goes nowhere
+            assert!(engine.evaluate_path(&[token_a, token_b], amount_in).is_some());
+            assert!(engine.evaluate_path(&[token_a, token_b, token_a], amount_in).is_none());
+            
+            assert!(matches!(
+                engine.find_best_route(token_a, token_a, amount_in),
+                Err(RoutingError::CyclicRoute)
+            ));
+            assert!(matches!(
+                engine.find_cheapest_route(token_a, token_a, amount_in, U256::zero()),
+                Err(RoutingError::CyclicRoute)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_unprofitable_routes_rejected_at_gas_price() {
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
         
-        assert!(small_impact < large_impact);
-        assert!(small_impact < 1.0); // Less than 1% for small trade
-        assert!(large_impact > 5.0); // More than 5% for large trade
+        let pool = LiquidityPool {
+            gas_cost: 500_000,
+            ..create_test_pool(token_a, token_b, 1000 * E18, 2000 * E18)
+        };
+        let dust = U256::from(1000);
+        
+        // Without a gas price the best route is returned regardless
+        let mut engine = RoutingEngine::default();
+        engine.add_pool(pool.clone());
+        assert!(engine.find_best_route(token_a, token_b, dust).is_ok());
+        
+        // At 30 gwei the swap costs 0.015 ETH, far more than its output
+        let mut engine = RoutingEngine::default().with_gas_price(30);
+        engine.add_pool(pool);
+        match engine.find_best_route(token_a, token_b, dust) {
+            Err(RoutingError::NoProfitableRoute { best_net_value }) => {
+                assert!((best_net_value + 0.015).abs() < 1e-9);
+            }
+            other => panic!("expected NoProfitableRoute, got {:?}", other.map(|r| r.output_amount)),
+        }
+        assert!(engine.find_best_route(token_a, token_b, U256::from(E18)).is_ok());
+        
+        // Output priced low enough in ETH no longer covers the gas
+        let engine = engine.with_native_price(token_b, 0.005);
+        assert!(matches!(
+            engine.find_best_route(token_a, token_b, U256::from(E18)),
+            Err(RoutingError::NoProfitableRoute { .. })
+        ));
+    }
+    
+    #[test]
+    fn test_find_arbitrage_detects_mispriced_ring() {
+        let token_a = Address::from_low_u64_be(1);
+        let token_b = Address::from_low_u64_be(2);
+        let token_c = Address::from_low_u64_be(3);
+        
+        // A = B = C through the first two pools, but the third sells A for
+        // C at half price
+        let ring = |c_per_a: u128| {
+            let mut engine = RoutingEngine::new(3, 5.0).with_gas_price(30);
+            engine.add_pool(create_test_pool(token_a, token_b, 1000 * E18, 1000 * E18));
+            engine.add_pool(create_test_pool(token_b, token_c, 1000 * E18, 1000 * E18));
+            engine.add_pool(create_test_pool(token_c, token_a, 1000 * E18 * c_per_a, 1000 * E18));
+            engine
+        };
+        
+        let engine = ring(2);
+        let route = engine.find_arbitrage(token_a, 3).expect("arbitrage found");
+        assert_eq!(route.path.first(), Some(&token_a));
+        assert_eq!(route.path.last(), Some(&token_a));
+        assert_eq!(route.pools.len(), 3);
+        // A -> C -> B -> A buys C cheaply in the mispriced pool
+        assert_eq!(route.path, vec![token_a, token_c, token_b, token_a]);
+        assert!(route.output_amount > route.amount_in);
+        let gain = (route.output_amount - route.amount_in).as_u128() as f64 / 1e18;
+        assert!(gain > route.gas_cost as f64 * 30e-9);
+        assert_eq!(engine.hop_amounts(&route, route.amount_in).last(), Some(&route.output_amount));
+        
+        // Two hops can't close the ring
+        assert!(engine.find_arbitrage(token_a, 2).is_none());
+        // Fees make a fairly priced ring a loss
+        assert!(ring(1).find_arbitrage(token_a, 3).is_none());
+        // Lookups still refuse round trips
+        assert!(matches!(
+            engine.find_best_route(token_a, token_a, U256::from(E18)),
+            Err(RoutingError::CyclicRoute)
+        ));
     }
 }
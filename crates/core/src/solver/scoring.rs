@@ -0,0 +1,92 @@
+use super::{AuctionContext, Solution};
+
+/// Objective used to rank solutions
+pub trait ScoringPolicy: Send + Sync {
+    /// Returns the score of `solution` in `ctx` (higher is better)
+    fn score(&self, solution: &Solution, ctx: &AuctionContext) -> f64;
+}
+
+/// Surplus net of gas, with gas valued at the auction's gas price and
+/// inflated by its congestion factor
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SurplusMinusGasPolicy;
+
+impl ScoringPolicy for SurplusMinusGasPolicy {
+    fn score(&self, solution: &Solution, ctx: &AuctionContext) -> f64 {
+        solution.surplus - ctx.gas_cost_eth(solution.gas_cost)
+    }
+}
+
+/// Surplus alone, ignoring gas
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SurplusOnlyPolicy;
+
+impl ScoringPolicy for SurplusOnlyPolicy {
+    fn score(&self, solution: &Solution, _ctx: &AuctionContext) -> f64 {
+        solution.surplus
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settlement::SettlementPlan;
+
+    fn solution(surplus: f64, gas_cost: u64) -> Solution {
+        Solution {
+            orders: vec![],
+            settlement: SettlementPlan::default(),
+            gas_cost,
+            surplus,
+            surplus_token: None,
+            order_surplus: vec![],
+            dropped_orders: vec![],
+            gas_excluded_orders: vec![],
+            overflow_orders: vec![],
+            chain: None,
+            valid_for_block: 0,
+            score: 0.0,
+        }
+    }
+
+    fn select<'a>(policy: &dyn ScoringPolicy, candidates: &'a [Solution]) -> &'a Solution {
+        let ctx = AuctionContext::default();
+        candidates
+            .iter()
+            .max_by(|a, b| policy.score(a, &ctx).total_cmp(&policy.score(b, &ctx)))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_default_policy_matches_calculate_score() {
+        let mut expected = solution(0.5, 100_000);
+        expected.calculate_score();
+
+        let score = SurplusMinusGasPolicy.score(&expected, &AuctionContext::default());
+        assert_eq!(score, expected.score);
+    }
+
+    #[test]
+    fn test_swapping_policy_changes_selection() {
+        // Slightly more surplus, but far more gas
+        let gas_heavy = solution(0.06, 50_000_000);
+        let lean = solution(0.05, 200_000);
+        let candidates = [gas_heavy, lean];
+
+        assert_eq!(select(&SurplusMinusGasPolicy, &candidates).gas_cost, 200_000);
+        assert_eq!(select(&SurplusOnlyPolicy, &candidates).gas_cost, 50_000_000);
+    }
+
+    #[test]
+    fn test_gas_valued_at_auction_gas_price() {
+        let solution = solution(0.05, 1_000_000);
+        let cheap = AuctionContext::default();
+        let expensive = AuctionContext {
+            gas_price: 30,
+            ..AuctionContext::default()
+        };
+
+        assert!((SurplusMinusGasPolicy.score(&solution, &cheap) - 0.049).abs() < 1e-12);
+        assert!((SurplusMinusGasPolicy.score(&solution, &expensive) - 0.02).abs() < 1e-12);
+    }
+}
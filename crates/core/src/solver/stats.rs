@@ -0,0 +1,132 @@
+use super::Solution;
+
+/// Running totals over the results of many auctions, for backtesting and
+/// monitoring
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuctionStats {
+    auctions: usize,
+    solved: usize,
+    orders_filled: usize,
+    orders_left_out: usize,
+    total_surplus: f64,
+    total_score: f64,
+    total_gas: u64,
+}
+
+impl AuctionStats {
+    /// Creates an empty accumulator
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records one auction's result, `None` if it wasn't solved
+    pub fn record(&mut self, solution: Option<&Solution>) {
+        self.auctions += 1;
+
+        let Some(solution) = solution else {
+            return;
+        };
+        self.solved += 1;
+        self.orders_filled += solution.orders.len();
+        self.orders_left_out += solution.dropped_orders.len()
+            + solution.gas_excluded_orders.len()
+            + solution.overflow_orders.len();
+        self.total_surplus += solution.surplus;
+        self.total_score += solution.score;
+        self.total_gas += solution.gas_cost;
+    }
+
+    /// Returns the number of auctions recorded
+    pub fn auctions(&self) -> usize {
+        self.auctions
+    }
+
+    /// Returns the number of auctions that produced a solution
+    pub fn solved_auctions(&self) -> usize {
+        self.solved
+    }
+
+    /// Returns the number of orders settled across all auctions
+    pub fn orders_filled(&self) -> usize {
+        self.orders_filled
+    }
+
+    /// Returns the surplus summed across all solutions
+    pub fn total_surplus(&self) -> f64 {
+        self.total_surplus
+    }
+
+    /// Returns the estimated gas summed across all solutions
+    pub fn total_gas(&self) -> u64 {
+        self.total_gas
+    }
+
+    /// Returns the share of orders settled among those solutions either
+    /// settled or left out, from 0 to 1.
+    ///
+    /// Unsolved auctions don't report their orders, so don't count.
+    pub fn fill_rate(&self) -> f64 {
+        let total = self.orders_filled + self.orders_left_out;
+        if total == 0 {
+            return 0.0;
+        }
+        self.orders_filled as f64 / total as f64
+    }
+
+    /// Returns the average score of the solutions found, or 0 if none were
+    pub fn mean_score(&self) -> f64 {
+        if self.solved == 0 {
+            return 0.0;
+        }
+        self.total_score / self.solved as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::OrderId;
+    use crate::settlement::SettlementPlan;
+
+    fn solution(filled: u8, dropped: u8, surplus: f64, score: f64) -> Solution {
+        Solution {
+            orders: (0..filled).map(|n| OrderId([n; 32])).collect(),
+            settlement: SettlementPlan::default(),
+            gas_cost: 100_000,
+            surplus,
+            surplus_token: None,
+            order_surplus: vec![],
+            dropped_orders: (0..dropped).map(|n| OrderId([100 + n; 32])).collect(),
+            gas_excluded_orders: vec![],
+            overflow_orders: vec![],
+            chain: None,
+            valid_for_block: 0,
+            score,
+        }
+    }
+
+    #[test]
+    fn test_aggregates_sequence_of_auctions() {
+        let mut stats = AuctionStats::new();
+        assert_eq!(stats.fill_rate(), 0.0);
+        assert_eq!(stats.mean_score(), 0.0);
+
+        let results = [
+            Some(solution(4, 0, 1.5, 1.0)),
+            None,
+            Some(solution(2, 2, 0.5, 0.2)),
+            Some(solution(0, 0, 0.0, 0.0)),
+        ];
+        for result in &results {
+            stats.record(result.as_ref());
+        }
+
+        assert_eq!(stats.auctions(), 4);
+        assert_eq!(stats.solved_auctions(), 3);
+        assert_eq!(stats.orders_filled(), 6);
+        assert_eq!(stats.total_gas(), 300_000);
+        assert!((stats.total_surplus() - 2.0).abs() < 1e-12);
+        assert!((stats.fill_rate() - 0.75).abs() < 1e-12);
+        assert!((stats.mean_score() - 0.4).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,80 @@
+use ethers::types::{Address, U256};
+use solver_core::domain::{Order, OrderId, OrderStatus, OrderType};
+use solver_core::solver::{
+    AuctionContext, BaselineSolver, LiquidityPool, PoolType, RoutingEngine, SolverEngine,
+};
+use solver_core::{Solver, SolverConfig};
+
+const E18: u128 = 1_000_000_000_000_000_000;
+
+fn order(id: u8, sell_token: Address, buy_token: Address, sell_amount: u128, buy_amount: u128) -> Order {
+    Order {
+        id: OrderId([id; 32]),
+        owner: Address::from_low_u64_be(id as u64),
+        sell_token,
+        buy_token,
+        sell_amount: U256::from(sell_amount),
+        buy_amount: U256::from(buy_amount),
+        valid_to: u32::MAX,
+        fee_amount: U256::zero(),
+        kind: OrderType::Sell,
+        partially_fillable: false,
+        status: OrderStatus::Open,
+        source_chain: None,
+        destination_chain: None,
+        bridge_provider: None,
+        filled_amount: U256::zero(),
+        signature: vec![],
+    }
+}
+
+#[tokio::test]
+async fn test_cow_matching_scores_at_least_baseline() {
+    let token_a = Address::from_low_u64_be(1);
+    let token_b = Address::from_low_u64_be(2);
+
+    // Opposing orders whose limits cross, plus a pool priced between them
+    let orders = vec![
+        order(1, token_a, token_b, E18, 19 * E18 / 10),
+        order(2, token_b, token_a, 2 * E18, E18),
+    ];
+
+    let mut routing = RoutingEngine::default();
+    routing.add_pool(LiquidityPool {
+        address: Address::from_low_u64_be(0x100),
+        pool_type: PoolType::UniswapV2,
+        token_a,
+        token_b,
+        reserve_a: U256::from(1000 * E18),
+        reserve_b: U256::from(1950 * E18),
+        fee_bps: 30,
+        gas_cost: 100000,
+        concentrated: None,
+        amplification: None,
+        fetched_at_block: 0,
+    });
+
+    let baseline = BaselineSolver::new(SolverConfig::default(), routing);
+    let cow = SolverEngine::new(SolverConfig::default());
+    assert_eq!(baseline.name(), "Baseline");
+
+    let ctx = AuctionContext::default();
+    let baseline_solution = baseline.solve(orders.clone(), &ctx).await.unwrap().unwrap();
+    let cow_solution = cow.solve(orders, &ctx).await.unwrap().unwrap();
+
+    // Baseline routes the first order through the pool, paying fees and gas
+    // for a swap plus an approval. Swapping back the other way can't clear
+    // at the same uniform price, so the second order is dropped.
+    assert_eq!(baseline_solution.orders, vec![OrderId([1; 32])]);
+    assert_eq!(baseline_solution.dropped_orders, vec![OrderId([2; 32])]);
+    assert_eq!(baseline_solution.settlement.interactions.len(), 2);
+    assert!(cow_solution.settlement.interactions.is_empty());
+    assert!(baseline_solution.settlement.solver_inventory_delta().is_empty());
+
+    assert!(
+        cow_solution.score >= baseline_solution.score,
+        "CoW score {} below baseline {}",
+        cow_solution.score,
+        baseline_solution.score
+    );
+}